@@ -0,0 +1,280 @@
+// Derives of the conversion traits of `micheline::convert`.
+//
+// Structs are converted as right-comb pairs of their fields, annotated with
+// their names when there are several, and enums as right combs of `or` of
+// their variants, annotated with their names in snake case. Names can be
+// changed with `#[micheline(name = "...")]`, and the Michelson type of a
+// field with `#[micheline(ty = "...")]`, such as `nat` for an `i32`.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_quote, Data, DeriveInput, Error, Fields, Ident, LitStr, Member, Result, Type};
+
+use crate::node;
+
+struct Field {
+    member: Member,
+    binding: Ident,
+    ty: Type,
+    michelson: Option<LitStr>,
+    annotation: Option<String>,
+}
+
+struct Variant {
+    ident: Ident,
+    annotation: String,
+    fields: Vec<Field>,
+}
+
+enum Shape {
+    Struct(Vec<Field>),
+    Enum(Vec<Variant>),
+}
+
+struct Attributes {
+    name: Option<String>,
+    // Michelson type of a field, in place of the one of its Rust type.
+    ty: Option<LitStr>,
+}
+
+fn attributes(attrs: &[syn::Attribute]) -> Result<Attributes> {
+    let mut attributes = Attributes { name: None, ty: None };
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("micheline")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                attributes.name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else if meta.path.is_ident("ty") {
+                attributes.ty = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `name` or `ty`"))
+            }
+        })?;
+    }
+    Ok(attributes)
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if index > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+fn fields(fields: &Fields) -> Result<Vec<Field>> {
+    let annotated = fields.len() >= 2;
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let Attributes { name, ty } = attributes(&field.attrs)?;
+            let name = name.or_else(|| field.ident.as_ref().map(Ident::to_string));
+            Ok(Field {
+                member: match &field.ident {
+                    Some(ident) => Member::Named(ident.clone()),
+                    None => Member::Unnamed(index.into()),
+                },
+                binding: format_ident!("field{}", index),
+                ty: field.ty.clone(),
+                michelson: ty,
+                annotation: name.filter(|_| annotated).map(|name| format!("%{}", name)),
+            })
+        })
+        .collect()
+}
+
+fn shape(input: &DeriveInput) -> Result<Shape> {
+    match &input.data {
+        Data::Struct(data) => Ok(Shape::Struct(fields(&data.fields)?)),
+        Data::Enum(data) => {
+            let variants = data.variants.iter().map(|variant| {
+                let Attributes { name, ty } = attributes(&variant.attrs)?;
+                if let Some(ty) = ty {
+                    return Err(Error::new_spanned(ty, "`ty` applies to fields"));
+                }
+                let name = name.unwrap_or_else(|| snake_case(&variant.ident.to_string()));
+                Ok(Variant {
+                    ident: variant.ident.clone(),
+                    annotation: format!("%{}", name),
+                    fields: fields(&variant.fields)?,
+                })
+            });
+            Ok(Shape::Enum(variants.collect::<Result<_>>()?))
+        }
+        Data::Union(_) => Err(Error::new_spanned(&input.ident, "unions cannot be converted to Micheline")),
+    }
+}
+
+/// The generics of `input`, with its type parameters bound by `bound`.
+fn bounded(input: &DeriveInput, bound: TokenStream) -> syn::Generics {
+    let mut generics = input.generics.clone();
+    let params: Vec<_> = generics.type_params().map(|param| param.ident.clone()).collect();
+    let clause = generics.make_where_clause();
+    for param in params {
+        clause.predicates.push(parse_quote!(#param: #bound));
+    }
+    generics
+}
+
+fn pair_type(fields: &[Field]) -> Result<TokenStream> {
+    let types = fields.iter().map(|field| {
+        let ty = &field.ty;
+        let michelson = match &field.michelson {
+            Some(michelson) => {
+                let tokens = michelson.value().parse().map_err(|_| Error::new_spanned(michelson, "invalid Michelson type"))?;
+                node::expand(tokens).map_err(|error| Error::new_spanned(michelson, error))?
+            }
+            None => quote!(<#ty as ::micheline::convert::MichelsonType>::michelson_type()),
+        };
+        Ok(match &field.annotation {
+            Some(annotation) => quote!(::micheline::convert::annotate(#michelson, #annotation)),
+            None => michelson,
+        })
+    });
+    let types = types.collect::<Result<Vec<_>>>()?;
+    Ok(quote!(::micheline::convert::pair_type(::std::vec![#(#types),*])))
+}
+
+/// The pattern binding the fields of a struct or variant at `path`.
+fn pattern(path: TokenStream, fields: &[Field]) -> TokenStream {
+    let members = fields.iter().map(|field| &field.member);
+    let bindings = fields.iter().map(|field| &field.binding);
+    quote!(#path { #(#members: #bindings),* })
+}
+
+fn pair(fields: &[Field]) -> TokenStream {
+    let bindings = fields.iter().map(|field| &field.binding);
+    quote!(::micheline::convert::pair(::std::vec![#(::micheline::convert::ToMicheline::to_micheline(#bindings)),*]))
+}
+
+/// Builds the struct or variant at `path` from the pair `node`, found at
+/// `prefix` in the converted value.
+fn unpair(path: TokenStream, fields: &[Field], node: TokenStream, prefix: TokenStream) -> TokenStream {
+    let count = fields.len();
+    if count == 0 {
+        return quote! {{
+            ::micheline::convert::unpair::<Self>(#node, 0).map_err(|error| error.within(#prefix))?;
+            #path {}
+        }};
+    }
+    let members = fields.iter().map(|field| &field.member);
+    let indices = 0..count;
+    quote! {{
+        let fields = ::micheline::convert::unpair::<Self>(#node, #count).map_err(|error| error.within(#prefix))?;
+        #path {
+            #(#members: ::micheline::convert::convert_at(&[#prefix, &fields[#indices].0[..]].concat(), fields[#indices].1)?),*
+        }
+    }}
+}
+
+pub fn michelson_type(input: &DeriveInput) -> Result<TokenStream> {
+    let generics = bounded(input, quote!(::micheline::convert::MichelsonType));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let ty = &input.ident;
+    let body = match shape(input)? {
+        Shape::Struct(fields) => pair_type(&fields)?,
+        Shape::Enum(variants) => {
+            let types = variants.iter().map(|variant| {
+                let payload = pair_type(&variant.fields)?;
+                let annotation = &variant.annotation;
+                Ok(quote!(::micheline::convert::annotate(#payload, #annotation)))
+            });
+            let types = types.collect::<Result<Vec<_>>>()?;
+            quote!(::micheline::convert::or_type(::std::vec![#(#types),*]))
+        }
+    };
+    Ok(quote! {
+        impl #impl_generics ::micheline::convert::MichelsonType for #ty #ty_generics #where_clause {
+            fn michelson_type() -> ::micheline::Node<::micheline::michelson_v1_primitives::Primitive> {
+                #body
+            }
+        }
+    })
+}
+
+pub fn to_micheline(input: &DeriveInput) -> Result<TokenStream> {
+    let generics = bounded(input, quote!(::micheline::convert::ToMicheline));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let ty = &input.ident;
+    let body = match shape(input)? {
+        Shape::Struct(fields) => {
+            let pattern = pattern(quote!(#ty), &fields);
+            let pair = pair(&fields);
+            quote! {
+                let #pattern = self;
+                #pair
+            }
+        }
+        Shape::Enum(variants) => {
+            let count = variants.len();
+            let arms = variants.iter().enumerate().map(|(index, variant)| {
+                let ident = &variant.ident;
+                let pattern = pattern(quote!(#ty::#ident), &variant.fields);
+                let pair = pair(&variant.fields);
+                quote!(#pattern => ::micheline::convert::or(#index, #count, #pair))
+            });
+            quote! {
+                match self {
+                    #(#arms,)*
+                }
+            }
+        }
+    };
+    Ok(quote! {
+        impl #impl_generics ::micheline::convert::ToMicheline for #ty #ty_generics #where_clause {
+            fn to_micheline(
+                &self,
+            ) -> ::std::result::Result<
+                ::micheline::Node<::micheline::michelson_v1_primitives::Primitive>,
+                ::micheline::convert::ConversionError,
+            > {
+                #body
+            }
+        }
+    })
+}
+
+pub fn from_micheline(input: &DeriveInput) -> Result<TokenStream> {
+    let generics = bounded(input, quote!(::micheline::convert::FromMicheline));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let ty = &input.ident;
+    let body = match shape(input)? {
+        Shape::Struct(fields) => {
+            let value = unpair(quote!(#ty), &fields, quote!(node), quote!(&[]));
+            quote!(::std::result::Result::Ok(#value))
+        }
+        Shape::Enum(variants) => {
+            let count = variants.len();
+            let arms = variants.iter().enumerate().map(|(index, variant)| {
+                let ident = &variant.ident;
+                let value = unpair(quote!(#ty::#ident), &variant.fields, quote!(value), quote!(&path[..]));
+                quote!(#index => ::std::result::Result::Ok(#value))
+            });
+            quote! {
+                let (index, path, value) = ::micheline::convert::unor::<Self>(node, #count)?;
+                match index {
+                    #(#arms,)*
+                    _ => ::std::unreachable!(),
+                }
+            }
+        }
+    };
+    Ok(quote! {
+        impl #impl_generics ::micheline::convert::FromMicheline for #ty #ty_generics #where_clause {
+            fn from_micheline(
+                node: &::micheline::Node<::micheline::michelson_v1_primitives::Primitive>,
+            ) -> ::std::result::Result<Self, ::micheline::convert::ConversionError> {
+                #body
+            }
+        }
+    })
+}
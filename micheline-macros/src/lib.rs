@@ -0,0 +1,171 @@
+// Procedural macros for the `micheline` crate, re-exported by it under the
+// `derive` feature.
+
+mod convert;
+mod node;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Ident, LitInt, LitStr};
+
+struct Primitive {
+    ident: Ident,
+    tag: u8,
+    name: String,
+}
+
+fn attributes(variant: &syn::Variant) -> syn::Result<(Option<u8>, Option<String>)> {
+    let mut tag = None;
+    let mut name = None;
+    for attr in variant.attrs.iter().filter(|attr| attr.path().is_ident("micheline")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                tag = Some(meta.value()?.parse::<LitInt>()?.base10_parse::<u8>()?);
+                Ok(())
+            } else if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `tag` or `name`"))
+            }
+        })?;
+    }
+    Ok((tag, name))
+}
+
+fn primitives(input: &DeriveInput) -> syn::Result<Vec<Primitive>> {
+    let data = match &input.data {
+        Data::Enum(data) if !data.variants.is_empty() => data,
+        _ => return Err(Error::new_spanned(&input.ident, "`Encodable` can only be derived for non-empty enums")),
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "primitive enums cannot be generic"));
+    }
+
+    let mut primitives: Vec<Primitive> = Vec::new();
+    // Tags follow the previous one unless given, as enum discriminants do.
+    let mut next = 0u16;
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(Error::new_spanned(variant, "primitives cannot have fields"));
+        }
+        let (tag, name) = attributes(variant)?;
+        let tag = match tag {
+            Some(tag) => tag,
+            None => u8::try_from(next).map_err(|_| Error::new_spanned(variant, "tags must fit in a byte"))?,
+        };
+        let name = name.unwrap_or_else(|| variant.ident.to_string());
+        if let Some(other) = primitives.iter().find(|other| other.tag == tag) {
+            return Err(Error::new_spanned(variant, format!("tag {} is already used by `{}`", tag, other.ident)));
+        }
+        if let Some(other) = primitives.iter().find(|other| other.name == name) {
+            return Err(Error::new_spanned(variant, format!("name `{}` is already used by `{}`", name, other.ident)));
+        }
+        next = tag as u16 + 1;
+        primitives.push(Primitive { ident: variant.ident.clone(), tag, name });
+    }
+    Ok(primitives)
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let primitives = primitives(input)?;
+    let ty = &input.ident;
+    let idents: Vec<_> = primitives.iter().map(|primitive| &primitive.ident).collect();
+    let tags: Vec<_> = primitives.iter().map(|primitive| primitive.tag).collect();
+    let names: Vec<_> = primitives.iter().map(|primitive| primitive.name.as_str()).collect();
+
+    Ok(quote! {
+        impl ::micheline::Encodable for #ty {
+            fn encode_to_buffer(&self, buffer: &mut ::std::vec::Vec<u8>) -> usize {
+                buffer.push(match self {
+                    #(#ty::#idents => #tags,)*
+                });
+                1
+            }
+
+            fn decode_from_buffer(buffer: &[u8]) -> ::std::option::Option<(Self, usize)> {
+                let value = match buffer.first()? {
+                    #(#tags => #ty::#idents,)*
+                    _ => return ::std::option::Option::None,
+                };
+                ::std::option::Option::Some((value, 1))
+            }
+        }
+
+        impl #ty {
+            /// The name of the primitive in concrete syntax.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    #(#ty::#idents => #names,)*
+                }
+            }
+        }
+
+        impl ::std::fmt::Display for #ty {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+
+        impl ::std::str::FromStr for #ty {
+            type Err = ::micheline::Error;
+
+            fn from_str(name: &str) -> ::std::result::Result<Self, Self::Err> {
+                match name {
+                    #(#names => ::std::result::Result::Ok(#ty::#idents),)*
+                    _ => ::std::result::Result::Err(::micheline::Error::InvalidPrimitive),
+                }
+            }
+        }
+    })
+}
+
+/// Derives `Encodable` for an enum of unit variants, along with its name
+/// mapping: an inherent `name` method, `Display` and `FromStr`.
+///
+/// Variants are tagged in declaration order from 0, or from the tag given by
+/// `#[micheline(tag = N)]`, and named after their identifier unless renamed
+/// with `#[micheline(name = "...")]`.
+#[proc_macro_derive(Encodable, attributes(micheline))]
+pub fn derive_encodable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Builds a `Node<Primitive>` from Michelson, such as
+/// `micheline!{ { PUSH %one nat 1 ; ADD } }`. Unknown primitives are
+/// rejected at compile time.
+///
+/// `#name` and `#(expression)` insert the value of a Rust expression of type
+/// `Node<Primitive>` in argument position, as in `micheline!(Pair #key 1)`.
+#[proc_macro]
+pub fn micheline(input: TokenStream) -> TokenStream {
+    node::expand(input.into()).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Derives `micheline::convert::MichelsonType`: structs are right-comb pairs
+/// of their fields, annotated with their names when there are several, and
+/// enums right combs of `or` of their variants, annotated with their names
+/// in snake case. `#[micheline(name = "...")]` renames a field or variant,
+/// and `#[micheline(ty = "...")]` gives the Michelson type of a field.
+#[proc_macro_derive(MichelsonType, attributes(micheline))]
+pub fn derive_michelson_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    convert::michelson_type(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Derives `micheline::convert::ToMicheline`, and so `IntoMicheline`, for
+/// values of the type derived by `MichelsonType`.
+#[proc_macro_derive(IntoMicheline, attributes(micheline))]
+pub fn derive_into_micheline(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    convert::to_micheline(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Derives `micheline::convert::FromMicheline`, for values of the type
+/// derived by `MichelsonType`.
+#[proc_macro_derive(FromMicheline, attributes(micheline))]
+pub fn derive_from_micheline(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    convert::from_micheline(&input).unwrap_or_else(Error::into_compile_error).into()
+}
@@ -0,0 +1,256 @@
+// Expansion of `micheline!`, which builds a `Node<Primitive>` from Michelson
+// written inline.
+//
+// The input follows the grammar of `syntax::parse` over Rust tokens: a
+// primitive takes all the arguments that follow it unless it is itself an
+// argument, parentheses group an application and braces hold a sequence.
+// `#name` and `#(expression)` interpolate Rust values of type
+// `Node<Primitive>`. Empty bytes `0x` are not a valid Rust token and must be
+// interpolated.
+//
+// Primitives are resolved here, so that unknown names are reported at their
+// span. Annotations span several Rust tokens, such as `%` and `0` for `%0`,
+// which are told apart from the next argument by being written next to each
+// other.
+
+use proc_macro2::{Delimiter, Ident, Literal, Span, TokenStream, TokenTree};
+use quote::{format_ident, quote};
+use syn::{Error, Result};
+
+/// The variants of `Primitive`, named after their namespace and the name of
+/// the primitive in Michelson source.
+const PRIMITIVES: &[&str] = &[
+    "K_parameter", "K_storage", "K_code", "D_False", "D_Elt", "D_Left", "D_None", "D_Pair", "D_Right", "D_Some",
+    "D_True", "D_Unit", "I_PACK", "I_UNPACK", "I_BLAKE2B", "I_SHA256", "I_SHA512", "I_ABS", "I_ADD", "I_AMOUNT",
+    "I_AND", "I_BALANCE", "I_CAR", "I_CDR", "I_CHECK_SIGNATURE", "I_COMPARE", "I_CONCAT", "I_CONS",
+    "I_CREATE_ACCOUNT", "I_CREATE_CONTRACT", "I_IMPLICIT_ACCOUNT", "I_DIP", "I_DROP", "I_DUP", "I_EDIV",
+    "I_EMPTY_MAP", "I_EMPTY_SET", "I_EQ", "I_EXEC", "I_FAILWITH", "I_GE", "I_GET", "I_GT", "I_HASH_KEY", "I_IF",
+    "I_IF_CONS", "I_IF_LEFT", "I_IF_NONE", "I_INT", "I_LAMBDA", "I_LE", "I_LEFT", "I_LOOP", "I_LSL", "I_LSR", "I_LT",
+    "I_MAP", "I_MEM", "I_MUL", "I_NEG", "I_NEQ", "I_NIL", "I_NONE", "I_NOT", "I_NOW", "I_OR", "I_PAIR", "I_PUSH",
+    "I_RIGHT", "I_SIZE", "I_SOME", "I_SOURCE", "I_SENDER", "I_SELF", "I_STEPS_TO_QUOTA", "I_SUB", "I_SWAP",
+    "I_TRANSFER_TOKENS", "I_SET_DELEGATE", "I_UNIT", "I_UPDATE", "I_XOR", "I_ITER", "I_LOOP_LEFT", "I_ADDRESS",
+    "I_CONTRACT", "I_ISNAT", "I_CAST", "I_RENAME", "T_bool", "T_contract", "T_int", "T_key", "T_key_hash", "T_lambda",
+    "T_list", "T_map", "T_big_map", "T_nat", "T_option", "T_or", "T_pair", "T_set", "T_signature", "T_string",
+    "T_bytes", "T_mutez", "T_timestamp", "T_unit", "T_operation", "T_address", "I_SLICE", "I_DIG", "I_DUG",
+    "I_EMPTY_BIG_MAP", "I_APPLY", "T_chain_id", "I_CHAIN_ID", "I_LEVEL", "I_SELF_ADDRESS", "T_never", "I_NEVER",
+    "I_UNPAIR", "I_VOTING_POWER", "I_TOTAL_VOTING_POWER", "I_KECCAK", "I_SHA3", "I_PAIRING_CHECK", "T_bls12_381_g1",
+    "T_bls12_381_g2", "T_bls12_381_fr", "T_sapling_state", "T_sapling_transaction_deprecated",
+    "I_SAPLING_EMPTY_STATE", "I_SAPLING_VERIFY_UPDATE", "T_ticket", "I_TICKET_DEPRECATED", "I_READ_TICKET",
+    "I_SPLIT_TICKET", "I_JOIN_TICKETS", "I_GET_AND_UPDATE", "T_chest", "T_chest_key", "I_OPEN_CHEST", "I_VIEW",
+    "K_view", "H_constant", "I_SUB_MUTEZ", "T_tx_rollup_l2_address", "I_MIN_BLOCK_TIME", "T_sapling_transaction",
+    "I_EMIT", "D_Lambda_rec", "I_LAMBDA_REC", "I_TICKET", "I_BYTES", "I_NAT", "D_Ticket", "I_IS_IMPLICIT_ACCOUNT",
+    "I_INDEX_ADDRESS", "I_GET_ADDRESS_INDEX",
+];
+
+struct Parser {
+    tokens: Vec<TokenTree>,
+    index: usize,
+    // Span reported when the input ends early.
+    end: Span,
+}
+
+fn is_punct(token: Option<&TokenTree>, c: char) -> bool {
+    matches!(token, Some(TokenTree::Punct(punct)) if punct.as_char() == c)
+}
+
+fn int(literal: &Literal, negative: bool) -> Result<TokenStream> {
+    let text = literal.to_string();
+    let digits = if negative { format!("-{}", text) } else { text };
+    match digits.parse::<i32>() {
+        Ok(value) => Ok(quote!(::micheline::Node::Int(#value))),
+        Err(_) => Err(Error::new(literal.span(), "expected an integer fitting in 32 bits")),
+    }
+}
+
+fn bytes(literal: &Literal, hex: &str) -> Result<TokenStream> {
+    let invalid = || Error::new(literal.span(), "expected an even number of hexadecimal digits");
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| invalid()))
+        .collect::<Result<Vec<u8>>>()?;
+    Ok(quote!(::micheline::Node::Bytes(::std::vec![#(#bytes),*])))
+}
+
+fn literal(literal: &Literal) -> Result<TokenStream> {
+    let text = literal.to_string();
+    if let Some(hex) = text.strip_prefix("0x") {
+        return bytes(literal, hex);
+    }
+    if text.starts_with('"') {
+        let value = syn::parse_str::<syn::LitStr>(&text)?.value();
+        return Ok(quote!(::micheline::Node::String(::std::string::String::from(#value))));
+    }
+    int(literal, false)
+}
+
+fn primitive(ident: &Ident, args: Vec<TokenStream>, annots: Vec<String>) -> Result<TokenStream> {
+    let name = ident.to_string();
+    let variant = match PRIMITIVES.iter().find(|variant| variant[2..] == name) {
+        Some(variant) => format_ident!("{}", variant, span = ident.span()),
+        None => return Err(Error::new(ident.span(), format!("unknown primitive `{}`", name))),
+    };
+    Ok(quote! {
+        ::micheline::Node::Prim(
+            ::micheline::michelson_v1_primitives::Primitive::#variant,
+            ::std::vec![#(#args),*],
+            ::std::vec![#(::std::string::String::from(#annots)),*],
+        )
+    })
+}
+
+/// Whether `next` starts where `previous` ends, without whitespace between
+/// them.
+fn adjacent(previous: &TokenTree, next: &TokenTree) -> bool {
+    previous.span().end() == next.span().start()
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenTree> {
+        self.tokens.get(self.index)
+    }
+
+    fn span(&self) -> Span {
+        self.peek().map(TokenTree::span).unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Result<TokenTree> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token.ok_or_else(|| Error::new(self.end, "unexpected end of input"))
+    }
+
+    /// Parses the annotations that follow a primitive. An annotation is a
+    /// prefix, `@`, `:` or `%`, followed by the letters, digits, `_`, `.`, `%`
+    /// and `@` written next to it, as in `%0`, `@%%` or `%a.b`.
+    fn annotations(&mut self) -> Result<Vec<String>> {
+        let mut annots = Vec::new();
+        while is_punct(self.peek(), '%') || is_punct(self.peek(), '@') || is_punct(self.peek(), ':') {
+            let mut previous = self.next()?;
+            let mut annot = previous.to_string();
+            while let Some(token) = self.peek().filter(|token| adjacent(&previous, token)) {
+                let text = token.to_string();
+                let valid = match token {
+                    TokenTree::Punct(punct) => matches!(punct.as_char(), '.' | '%' | '@'),
+                    TokenTree::Ident(_) | TokenTree::Literal(_) => {
+                        text.bytes().all(|c| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'.'))
+                    }
+                    TokenTree::Group(_) => false,
+                };
+                if !valid {
+                    return Err(Error::new(token.span(), "expected an annotation"));
+                }
+                annot.push_str(&text);
+                previous = self.next()?;
+            }
+            annots.push(annot);
+        }
+        Ok(annots)
+    }
+
+    /// Parses an expression in argument position, where primitives do not
+    /// take arguments unless parenthesized.
+    fn argument(&mut self) -> Result<TokenStream> {
+        match self.next()? {
+            TokenTree::Literal(value) => literal(&value),
+            TokenTree::Punct(punct) if punct.as_char() == '-' => match self.next()? {
+                TokenTree::Literal(value) => int(&value, true),
+                token => Err(Error::new(token.span(), "expected an integer")),
+            },
+            TokenTree::Punct(punct) if punct.as_char() == '#' => match self.next()? {
+                TokenTree::Ident(ident) => Ok(quote!(#ident)),
+                TokenTree::Group(group) if group.delimiter() == Delimiter::Parenthesis => Ok(quote!(#group)),
+                token => Err(Error::new(token.span(), "expected a name or a parenthesized expression")),
+            },
+            TokenTree::Ident(ident) => primitive(&ident, Vec::new(), self.annotations()?),
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                let items = parser(group.stream(), group.span_close()).sequence()?;
+                Ok(quote!(::micheline::Node::Seq(::std::vec![#(#items),*])))
+            }
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Parenthesis => {
+                let mut inner = parser(group.stream(), group.span_close());
+                let node = inner.application()?;
+                inner.finish()?;
+                Ok(node)
+            }
+            token => Err(Error::new(token.span(), "unexpected token")),
+        }
+    }
+
+    /// Parses an expression in which a primitive takes all the arguments
+    /// that follow it.
+    fn application(&mut self) -> Result<TokenStream> {
+        let ident = match self.peek() {
+            Some(TokenTree::Ident(ident)) => ident.clone(),
+            _ => return self.argument(),
+        };
+        self.index += 1;
+        let annots = self.annotations()?;
+        let mut args = Vec::new();
+        while self.peek().is_some() && !is_punct(self.peek(), ';') {
+            args.push(self.argument()?);
+        }
+        primitive(&ident, args, annots)
+    }
+
+    /// Parses `;`-separated expressions up to the end.
+    fn sequence(&mut self) -> Result<Vec<TokenStream>> {
+        let mut items = Vec::new();
+        while self.peek().is_some() {
+            items.push(self.application()?);
+            match self.peek() {
+                None => break,
+                Some(TokenTree::Punct(punct)) if punct.as_char() == ';' => self.index += 1,
+                Some(token) => return Err(Error::new(token.span(), "expected `;`")),
+            }
+        }
+        Ok(items)
+    }
+
+    fn finish(&self) -> Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(Error::new(self.span(), "unexpected token")),
+        }
+    }
+}
+
+fn parser(input: TokenStream, end: Span) -> Parser {
+    Parser { tokens: input.into_iter().collect(), index: 0, end }
+}
+
+pub fn expand(input: TokenStream) -> Result<TokenStream> {
+    let mut parser = parser(input, Span::call_site());
+    if parser.peek().is_none() {
+        return Err(Error::new(Span::call_site(), "expected an expression"));
+    }
+    let node = parser.application()?;
+    parser.finish()?;
+    Ok(quote! {{
+        let node: ::micheline::Node<::micheline::michelson_v1_primitives::Primitive> = #node;
+        node
+    }})
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::node::*;
+    use micheline::michelson_v1_primitives::Primitive;
+
+    #[test]
+    fn primitives() {
+        let variants = (0..=u8::MAX).filter_map(Primitive::from_int_enum).map(|prim| format!("{:?}", prim)).collect::<Vec<_>>();
+        assert_eq!(PRIMITIVES, variants);
+    }
+
+    #[test]
+    fn errors() {
+        let message = |source: &str| expand(source.parse().unwrap()).unwrap_err().to_string();
+        assert_eq!(message("Pair 1 (Sume 2)"), "unknown primitive `Sume`");
+        assert_eq!(message("CAR %\"a\""), "expected an annotation");
+        assert_eq!(message("Pair 1 %a"), "unexpected token");
+        assert_eq!(message("Pair -x 1"), "expected an integer");
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use micheline::codegen::{self, rust, schema, typescript};
+use micheline::convert::{BigMap, ConversionError, ConversionErrorKind, FromMicheline, MichelsonType, ToMicheline};
+use micheline::typecheck::normalize_type;
+use micheline::{micheline, syntax};
+
+mod token {
+    include!("codegen/token.rs");
+}
+
+const SCRIPT: &str = include_str!("codegen/token.tz");
+
+fn interface() -> codegen::Interface {
+    let node = syntax::to_node(&syntax::parse_script(SCRIPT).unwrap()).unwrap();
+    codegen::interface(&node).unwrap()
+}
+
+#[test]
+fn generated() {
+    assert_eq!(rust::generate_interface(&interface()).unwrap(), include_str!("codegen/token.rs"));
+    assert_eq!(typescript::generate_interface(&interface()).unwrap(), include_str!("codegen/token.ts"));
+    assert_eq!(schema::generate_interface(&interface()).unwrap(), include_str!("codegen/token.json"));
+}
+
+#[test]
+fn types() {
+    let interface = interface();
+    // Combs are nested in generated types and may be flat in scripts.
+    assert_eq!(normalize_type(&token::Storage::michelson_type()), normalize_type(&interface.storage.ty));
+    let types = [
+        token::Transfer::michelson_type(),
+        token::Pause::michelson_type(),
+        token::SetOwner::michelson_type(),
+        token::Default::michelson_type(),
+    ];
+    assert_eq!(types.len(), interface.entrypoints.len());
+    for (ty, entrypoint) in types.iter().zip(&interface.entrypoints) {
+        assert_eq!(normalize_type(ty), normalize_type(&entrypoint.ty));
+    }
+}
+
+#[test]
+fn values() {
+    let storage = token::Storage {
+        owner: String::from("tz1owner"),
+        ledger: BigMap::Map(BTreeMap::from([(String::from("tz1owner"), 10)])),
+        config: token::Config { paused: false, admins: vec![String::from("tz1admin")] },
+    };
+    let node = micheline!(Pair "tz1owner" (Pair {Elt "tz1owner" 10} (Pair False {"tz1admin"})));
+    assert_eq!(storage.to_micheline().as_ref(), Ok(&node));
+    assert_eq!(token::Storage::from_micheline(&node).as_ref(), Ok(&storage));
+    let flat = micheline!(Pair "tz1owner" {Elt "tz1owner" 10} (Pair False {"tz1admin"}));
+    assert_eq!(token::Storage::from_micheline(&flat), Ok(storage.clone()));
+    // Storage read from the chain holds the id of the big map.
+    let node = micheline!(Pair "tz1owner" (Pair 42 (Pair False {"tz1admin"})));
+    let stored = token::Storage { ledger: BigMap::Id(42), ..storage };
+    assert_eq!(stored.to_micheline().as_ref(), Ok(&node));
+    assert_eq!(token::Storage::from_micheline(&node), Ok(stored));
+
+    let parameter = token::Default::Transfer { to: String::from("tz1to"), amount: 5 };
+    let node = micheline!(Left (Pair "tz1to" 5));
+    assert_eq!(parameter.to_micheline().as_ref(), Ok(&node));
+    assert_eq!(token::Default::from_micheline(&node), Ok(parameter));
+    // Naturals are unsigned.
+    assert_eq!(
+        token::Default::from_micheline(&micheline!(Left (Pair "tz1to" (-5)))),
+        Err(ConversionError { path: vec![0, 1].into(), kind: ConversionErrorKind::OutOfRange })
+    );
+    assert_eq!(token::Default::SetOwner(None).to_micheline(), Ok(micheline!(Right (Right None))));
+    assert_eq!(token::Pause.to_micheline(), Ok(micheline!(Unit)));
+}
@@ -0,0 +1,48 @@
+// Generated by `micheline::codegen::rust`.
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ::micheline::convert::MichelsonType, ::micheline::convert::IntoMicheline, ::micheline::convert::FromMicheline)]
+pub struct Storage {
+    #[micheline(name = "owner", ty = "address")]
+    pub owner: ::std::string::String,
+    #[micheline(name = "ledger", ty = "big_map address nat")]
+    pub ledger: ::micheline::convert::BigMap<::std::string::String, u32>,
+    #[micheline(name = "config")]
+    pub config: Config,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ::micheline::convert::MichelsonType, ::micheline::convert::IntoMicheline, ::micheline::convert::FromMicheline)]
+pub struct Config {
+    #[micheline(name = "paused")]
+    pub paused: bool,
+    #[micheline(name = "admins", ty = "list address")]
+    pub admins: ::std::vec::Vec<::std::string::String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ::micheline::convert::MichelsonType, ::micheline::convert::IntoMicheline, ::micheline::convert::FromMicheline)]
+pub struct Transfer {
+    #[micheline(name = "to", ty = "address")]
+    pub to: ::std::string::String,
+    #[micheline(name = "amount")]
+    pub amount: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ::micheline::convert::MichelsonType, ::micheline::convert::IntoMicheline, ::micheline::convert::FromMicheline)]
+pub struct Pause;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ::micheline::convert::MichelsonType, ::micheline::convert::IntoMicheline, ::micheline::convert::FromMicheline)]
+pub struct SetOwner(#[micheline(ty = "option address")] pub ::std::option::Option<::std::string::String>);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ::micheline::convert::MichelsonType, ::micheline::convert::IntoMicheline, ::micheline::convert::FromMicheline)]
+pub enum Default {
+    #[micheline(name = "transfer")]
+    Transfer {
+        #[micheline(name = "to", ty = "address")]
+        to: ::std::string::String,
+        #[micheline(name = "amount")]
+        amount: u32,
+    },
+    #[micheline(name = "pause")]
+    Pause,
+    #[micheline(name = "set_owner")]
+    SetOwner(#[micheline(ty = "option address")] ::std::option::Option<::std::string::String>),
+}
@@ -0,0 +1,269 @@
+// Generated by `micheline::codegen::typescript`.
+
+export type Micheline =
+  | { int: string }
+  | { string: string }
+  | { bytes: string }
+  | { prim: string; args?: Micheline[]; annots?: string[] }
+  | Micheline[];
+
+type Encoder<T> = (value: T) => Micheline;
+type Decoder<T> = (node: Micheline) => T;
+
+function invalid(expected: string, node: Micheline): never {
+  throw new Error(`expected ${expected}, got ${JSON.stringify(node)}`);
+}
+
+function prim(name: string, ...args: Micheline[]): Micheline {
+  return args.length === 0 ? { prim: name } : { prim: name, args };
+}
+
+function isPrim(node: Micheline, name: string): boolean {
+  return !Array.isArray(node) && "prim" in node && node.prim === name;
+}
+
+function args(node: Micheline, name: string, arity: number): Micheline[] {
+  if (Array.isArray(node) || !("prim" in node) || node.prim !== name || (node.args ?? []).length !== arity) {
+    return invalid(name, node);
+  }
+  return node.args ?? [];
+}
+
+function pair(...values: Micheline[]): Micheline {
+  return values.reduceRight((right, left) => prim("Pair", left, right));
+}
+
+function unpair(node: Micheline, count: number): Micheline[] {
+  const fields: Micheline[] = [];
+  while (fields.length + 1 < count) {
+    const values = !Array.isArray(node) && "prim" in node && node.prim === "Pair" ? node.args ?? [] : [];
+    if (values.length < 2 || values.length > count - fields.length) {
+      return invalid(`a pair of ${count - fields.length} values`, node);
+    }
+    fields.push(...values.slice(0, -1));
+    node = values[values.length - 1];
+  }
+  fields.push(node);
+  return fields;
+}
+
+function or(index: number, count: number, value: Micheline): Micheline {
+  let node = index + 1 < count ? prim("Left", value) : value;
+  for (let depth = 0; depth < index; depth++) {
+    node = prim("Right", node);
+  }
+  return node;
+}
+
+function unor(node: Micheline, count: number): [number, Micheline] {
+  for (let index = 0; index + 1 < count; index++) {
+    if (isPrim(node, "Left")) {
+      return [index, args(node, "Left", 1)[0]];
+    }
+    node = args(node, "Right", 1)[0];
+  }
+  return [count - 1, node];
+}
+
+function encodeUnit(_value: undefined): Micheline {
+  return prim("Unit");
+}
+
+function decodeUnit(node: Micheline): undefined {
+  args(node, "Unit", 0);
+  return undefined;
+}
+
+function encodeBool(value: boolean): Micheline {
+  return prim(value ? "True" : "False");
+}
+
+function decodeBool(node: Micheline): boolean {
+  const value = isPrim(node, "True");
+  args(node, value ? "True" : "False", 0);
+  return value;
+}
+
+function encodeInt(value: bigint): Micheline {
+  return { int: value.toString() };
+}
+
+function decodeInt(node: Micheline): bigint {
+  return !Array.isArray(node) && "int" in node ? BigInt(node.int) : invalid("an integer", node);
+}
+
+function encodeString(value: string): Micheline {
+  return { string: value };
+}
+
+function decodeString(node: Micheline): string {
+  return !Array.isArray(node) && "string" in node ? node.string : invalid("a string", node);
+}
+
+function encodeBytes(value: string): Micheline {
+  return { bytes: value };
+}
+
+function decodeBytes(node: Micheline): string {
+  return !Array.isArray(node) && "bytes" in node ? node.bytes : invalid("bytes", node);
+}
+
+function encodeOption<T>(encode: Encoder<T>): Encoder<T | null> {
+  return (value) => (value === null ? prim("None") : prim("Some", encode(value)));
+}
+
+function decodeOption<T>(decode: Decoder<T>): Decoder<T | null> {
+  return (node) => {
+    if (isPrim(node, "None")) {
+      args(node, "None", 0);
+      return null;
+    }
+    return decode(args(node, "Some", 1)[0]);
+  };
+}
+
+function encodeList<T>(encode: Encoder<T>): Encoder<T[]> {
+  return (values) => values.map((value) => encode(value));
+}
+
+function decodeList<T>(decode: Decoder<T>): Decoder<T[]> {
+  return (node) => (Array.isArray(node) ? node.map((item) => decode(item)) : invalid("a sequence", node));
+}
+
+function encodeMap<K, V>(encodeKey: Encoder<K>, encodeValue: Encoder<V>): Encoder<Map<K, V>> {
+  return (map) => Array.from(map, ([key, value]) => prim("Elt", encodeKey(key), encodeValue(value)));
+}
+
+function decodeMap<K, V>(decodeKey: Decoder<K>, decodeValue: Decoder<V>): Decoder<Map<K, V>> {
+  return (node) => {
+    const entries = decodeList((elt) => {
+      const [key, value] = args(elt, "Elt", 2);
+      return [decodeKey(key), decodeValue(value)] as [K, V];
+    })(node);
+    return new Map(entries);
+  };
+}
+
+export type BigMap<K, V> = { id: bigint } | Map<K, V>;
+
+function encodeBigMap<K, V>(encodeKey: Encoder<K>, encodeValue: Encoder<V>): Encoder<BigMap<K, V>> {
+  const encode = encodeMap(encodeKey, encodeValue);
+  return (value) => (value instanceof Map ? encode(value) : encodeInt(value.id));
+}
+
+function decodeBigMap<K, V>(decodeKey: Decoder<K>, decodeValue: Decoder<V>): Decoder<BigMap<K, V>> {
+  const decode = decodeMap(decodeKey, decodeValue);
+  return (node) => (!Array.isArray(node) && "int" in node ? { id: decodeInt(node) } : decode(node));
+}
+
+export interface Storage {
+  owner: string;
+  ledger: BigMap<string, bigint>;
+  config: Config;
+}
+
+export function encodeStorage(value: Storage): Micheline {
+  return pair(
+    encodeString(value.owner),
+    encodeBigMap(encodeString, encodeInt)(value.ledger),
+    encodeConfig(value.config),
+  );
+}
+
+export function decodeStorage(node: Micheline): Storage {
+  const fields = unpair(node, 3);
+  return {
+    owner: decodeString(fields[0]),
+    ledger: decodeBigMap(decodeString, decodeInt)(fields[1]),
+    config: decodeConfig(fields[2]),
+  };
+}
+
+export interface Config {
+  paused: boolean;
+  admins: Array<string>;
+}
+
+export function encodeConfig(value: Config): Micheline {
+  return pair(
+    encodeBool(value.paused),
+    encodeList(encodeString)(value.admins),
+  );
+}
+
+export function decodeConfig(node: Micheline): Config {
+  const fields = unpair(node, 2);
+  return {
+    paused: decodeBool(fields[0]),
+    admins: decodeList(decodeString)(fields[1]),
+  };
+}
+
+export interface Transfer {
+  to: string;
+  amount: bigint;
+}
+
+export function encodeTransfer(value: Transfer): Micheline {
+  return pair(
+    encodeString(value.to),
+    encodeInt(value.amount),
+  );
+}
+
+export function decodeTransfer(node: Micheline): Transfer {
+  const fields = unpair(node, 2);
+  return {
+    to: decodeString(fields[0]),
+    amount: decodeInt(fields[1]),
+  };
+}
+
+export type Pause = undefined;
+
+export function encodePause(value: Pause): Micheline {
+  return encodeUnit(value);
+}
+
+export function decodePause(node: Micheline): Pause {
+  return decodeUnit(node);
+}
+
+export type SetOwner = string | null;
+
+export function encodeSetOwner(value: SetOwner): Micheline {
+  return encodeOption(encodeString)(value);
+}
+
+export function decodeSetOwner(node: Micheline): SetOwner {
+  return decodeOption(decodeString)(node);
+}
+
+export type Default =
+  | { kind: "transfer"; value: Transfer }
+  | { kind: "pause" }
+  | { kind: "set_owner"; value: string | null };
+
+export function encodeDefault(value: Default): Micheline {
+  switch (value.kind) {
+    case "transfer":
+      return or(0, 3, encodeTransfer(value.value));
+    case "pause":
+      return or(1, 3, encodeUnit(undefined));
+    case "set_owner":
+      return or(2, 3, encodeOption(encodeString)(value.value));
+  }
+}
+
+export function decodeDefault(node: Micheline): Default {
+  const [index, value] = unor(node, 3);
+  switch (index) {
+    case 0:
+      return { kind: "transfer", value: decodeTransfer(value) };
+    case 1:
+      decodeUnit(value);
+      return { kind: "pause" };
+    default:
+      return { kind: "set_owner", value: decodeOption(decodeString)(value) };
+  }
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+
+use micheline::convert::{ConversionError, ConversionErrorKind, FromMicheline, IntoMicheline, MichelsonType, ToMicheline};
+use micheline::path::Path;
+use micheline::{micheline, Node};
+
+#[derive(Debug, PartialEq, Clone, MichelsonType, IntoMicheline, FromMicheline)]
+struct Storage {
+    owner: String,
+    #[micheline(name = "balances")]
+    ledger: BTreeMap<String, i32>,
+    paused: bool,
+    metadata: Option<Metadata>,
+}
+
+#[derive(Debug, PartialEq, Clone, MichelsonType, IntoMicheline, FromMicheline)]
+struct Metadata(String);
+
+#[derive(Debug, PartialEq, Clone, MichelsonType, IntoMicheline, FromMicheline)]
+struct Account {
+    #[micheline(ty = "address")]
+    owner: String,
+    #[micheline(ty = "nat", name = "balance")]
+    amount: i32,
+}
+
+#[derive(Debug, PartialEq, Clone, MichelsonType, IntoMicheline, FromMicheline)]
+enum Parameter {
+    Pause,
+    Transfer { to: String, amount: i32 },
+    SetOwner(String),
+    #[micheline(name = "default")]
+    Batch(Vec<String>),
+}
+
+#[derive(Debug, PartialEq, Clone, MichelsonType, IntoMicheline, FromMicheline)]
+struct Wrapper<T> {
+    value: T,
+    count: i32,
+}
+
+fn storage() -> Storage {
+    Storage {
+        owner: String::from("tz1"),
+        ledger: BTreeMap::from([(String::from("tz2"), 10)]),
+        paused: false,
+        metadata: Some(Metadata(String::from("ipfs://"))),
+    }
+}
+
+#[test]
+fn structs() {
+    assert_eq!(
+        Storage::michelson_type(),
+        micheline!(pair (string %owner) (pair (map %balances string int) (pair (bool %paused) (option %metadata string))))
+    );
+    let value = micheline!(Pair "tz1" (Pair { Elt "tz2" 10 } (Pair False (Some "ipfs://"))));
+    assert_eq!(storage().into_micheline(), Ok(value.clone()));
+    assert_eq!(Storage::from_micheline(&value), Ok(storage()));
+    assert_eq!(Storage::from_micheline(&micheline!(Pair "tz1" { Elt "tz2" 10 } False (Some "ipfs://"))), Ok(storage()));
+
+    assert_eq!(
+        Storage::from_micheline(&micheline!(Pair "tz1" { Elt "tz2" 10 } False (Some 1))),
+        Err(ConversionError { path: Path(vec![3, 0]), kind: ConversionErrorKind::UnexpectedValue(micheline!(string)) })
+    );
+    assert_eq!(
+        Storage::from_micheline(&micheline!(Pair "tz1" { Elt "tz2" 10 })),
+        Err(ConversionError { path: Path(vec![]), kind: ConversionErrorKind::UnexpectedValue(Storage::michelson_type()) })
+    );
+
+    assert_eq!(
+        Wrapper::<bool>::michelson_type(),
+        micheline!(pair (bool %value) (int %count))
+    );
+    let wrapper = Wrapper { value: vec![true], count: 1 };
+    assert_eq!(Wrapper::from_micheline(&wrapper.clone().into_micheline().unwrap()), Ok(wrapper));
+    assert_eq!(
+        Wrapper { value: u64::MAX, count: 1 }.into_micheline(),
+        Err(ConversionError { path: Path(vec![0]), kind: ConversionErrorKind::OutOfRange })
+    );
+}
+
+#[test]
+fn enums() {
+    assert_eq!(
+        Parameter::michelson_type(),
+        micheline!(or (unit %pause) (or (pair %transfer (string %to) (int %amount)) (or (string %set_owner) (list %default string))))
+    );
+    let cases = [
+        (Parameter::Pause, micheline!(Left Unit)),
+        (Parameter::Transfer { to: String::from("tz1"), amount: 5 }, micheline!(Right (Left (Pair "tz1" 5)))),
+        (Parameter::SetOwner(String::from("tz2")), micheline!(Right (Right (Left "tz2")))),
+        (Parameter::Batch(vec![String::from("a")]), micheline!(Right (Right (Right { "a" })))),
+    ];
+    for (parameter, value) in cases {
+        assert_eq!(parameter.clone().into_micheline(), Ok(value.clone()));
+        assert_eq!(Parameter::from_micheline(&value), Ok(parameter));
+    }
+
+    assert_eq!(
+        Parameter::from_micheline(&micheline!(Right (Left (Pair "tz1" "5")))),
+        Err(ConversionError { path: Path(vec![0, 0, 1]), kind: ConversionErrorKind::UnexpectedValue(micheline!(int)) })
+    );
+    assert_eq!(
+        Parameter::from_micheline(&micheline!(Right (Left "tz1"))),
+        Err(ConversionError { path: Path(vec![0, 0]), kind: ConversionErrorKind::UnexpectedValue(Parameter::michelson_type()) })
+    );
+    assert_eq!(
+        Parameter::from_micheline(&micheline!(Left 1)),
+        Err(ConversionError { path: Path(vec![0]), kind: ConversionErrorKind::UnexpectedValue(Parameter::michelson_type()) })
+    );
+}
+
+#[test]
+fn newtypes() {
+    // Single fields are not annotated, so that newtypes have the type of
+    // their field.
+    assert_eq!(Option::<Metadata>::michelson_type(), micheline!(option string));
+    assert_eq!(Metadata(String::from("a")).into_micheline(), Ok(Node::String(String::from("a"))));
+}
+
+#[test]
+fn michelson_types() {
+    assert_eq!(Account::michelson_type(), micheline!(pair (address %owner) (nat %balance)));
+    let account = Account { owner: String::from("tz1"), amount: 3 };
+    assert_eq!(account.to_micheline(), Ok(micheline!(Pair "tz1" 3)));
+    assert_eq!(account.clone().into_micheline(), Ok(micheline!(Pair "tz1" 3)));
+    assert_eq!(vec![account.clone()].into_micheline(), Ok(micheline!({ Pair "tz1" 3 })));
+    assert_eq!(BTreeMap::from([(1, Some(account.clone()))]).into_micheline(), Ok(micheline!({ Elt 1 (Some (Pair "tz1" 3)) })));
+    assert_eq!(Account::from_micheline(&micheline!(Pair "tz1" 3)), Ok(account));
+}
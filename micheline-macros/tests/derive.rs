@@ -0,0 +1,32 @@
+use micheline::{Encodable, Error, Node};
+
+#[derive(Debug, PartialEq, Clone, Encodable)]
+enum Rollup {
+    Deposit,
+    Withdraw,
+    #[micheline(tag = 10, name = "transfer")]
+    Transfer,
+    Burn,
+}
+
+#[test]
+fn tags() {
+    assert_eq!(Node::Prim(Rollup::Deposit, vec![], vec![]).encode(), b"\x03\x00");
+    assert_eq!(Node::Prim(Rollup::Withdraw, vec![], vec![]).encode(), b"\x03\x01");
+    assert_eq!(Node::Prim(Rollup::Transfer, vec![], vec![]).encode(), b"\x03\x0a");
+    assert_eq!(Node::Prim(Rollup::Burn, vec![], vec![]).encode(), b"\x03\x0b");
+
+    assert_eq!(
+        Node::from(b"\x05\x0a\x00\x01"),
+        Ok(Node::Prim(Rollup::Transfer, vec![Node::Int(1)], vec![]))
+    );
+    assert_eq!(Node::<Rollup>::from(b"\x03\x02"), Err(Error::InvalidPrimitive));
+}
+
+#[test]
+fn names() {
+    assert_eq!(Rollup::Deposit.name(), "Deposit");
+    assert_eq!(Rollup::Transfer.to_string(), "transfer");
+    assert_eq!("transfer".parse(), Ok(Rollup::Transfer));
+    assert_eq!("Transfer".parse::<Rollup>(), Err(Error::InvalidPrimitive));
+}
@@ -0,0 +1,61 @@
+use micheline::michelson_v1_primitives::Primitive;
+use micheline::michelson_v1_primitives::Primitive::*;
+use micheline::{micheline, syntax, Node};
+
+fn prim(primitive: Primitive, args: Vec<Node<Primitive>>) -> Node<Primitive> {
+    Node::Prim(primitive, args, vec![])
+}
+
+fn parse(source: &str) -> Node<Primitive> {
+    syntax::to_node(&syntax::parse(source).unwrap()).unwrap()
+}
+
+#[test]
+fn literals() {
+    assert_eq!(
+        micheline!{ { PUSH %one nat 1 ; ADD } },
+        Node::Seq(vec![
+            Node::Prim(I_PUSH, vec![prim(T_nat, vec![]), Node::Int(1)], vec![String::from("%one")]),
+            prim(I_ADD, vec![]),
+        ])
+    );
+    assert_eq!(micheline!(-42), Node::Int(-42));
+    assert_eq!(micheline!(0xcafe), Node::Bytes(vec![0xca, 0xfe]));
+    assert_eq!(micheline!("a \"quoted\" string"), Node::String(String::from("a \"quoted\" string")));
+    assert_eq!(micheline!({}), Node::Seq(vec![]));
+}
+
+#[test]
+fn matches_parser() {
+    assert_eq!(
+        micheline!(Pair (Some @x :t "a") { Elt 1 0x01 ; Elt -2 0x00 } Unit),
+        parse("Pair (Some @x :t \"a\") { Elt 1 0x01 ; Elt -2 0x00 } Unit")
+    );
+    assert_eq!(
+        micheline!{ {
+            parameter (or (unit %increment) (int %set)) ;
+            storage int ;
+            code { UNPAIR ; IF_LEFT { DROP ; PUSH int 1 ; ADD } { SWAP ; DROP } ; NIL operation ; PAIR }
+        } },
+        parse("{ parameter (or (unit %increment) (int %set)) ; storage int ; code { UNPAIR ; IF_LEFT { DROP ; PUSH int 1 ; ADD } { SWAP ; DROP } ; NIL operation ; PAIR } }")
+    );
+}
+
+#[test]
+fn annotations() {
+    assert_eq!(micheline!(CAR @%% %@ :t.u), parse("CAR @%% %@ :t.u"));
+    assert_eq!(micheline!(PAIR %0 %_ %self %a.b %1.5), parse("PAIR %0 %_ %self %a.b %1.5"));
+    assert_eq!(micheline!(UNPAIR % %x nat), parse("UNPAIR % %x nat"));
+}
+
+#[test]
+fn interpolation() {
+    let key = Node::String(String::from("tz1"));
+    let values = (1..=3).map(Node::Int).collect::<Vec<_>>();
+    assert_eq!(
+        micheline!(Elt #(key.clone()) #(Node::Seq(values.clone()))),
+        prim(D_Elt, vec![key.clone(), Node::Seq(values)])
+    );
+    let expected = Node::Seq(vec![key.clone(), prim(D_Unit, vec![])]);
+    assert_eq!(micheline!({ #key ; Unit }), expected);
+}
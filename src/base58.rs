@@ -0,0 +1,127 @@
+// Base58Check encoding, as used by Tezos for the readable representation
+// of addresses, keys, signatures and chain ids.
+
+use crate::hash::sha256;
+
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+pub const TZ1: &[u8] = &[6, 161, 159];
+pub const TZ2: &[u8] = &[6, 161, 161];
+pub const TZ3: &[u8] = &[6, 161, 164];
+pub const TZ4: &[u8] = &[6, 161, 166];
+pub const KT1: &[u8] = &[2, 90, 121];
+pub const TXR1: &[u8] = &[1, 128, 120, 31];
+pub const SR1: &[u8] = &[6, 124, 117];
+pub const EDPK: &[u8] = &[13, 15, 37, 217];
+pub const SPPK: &[u8] = &[3, 254, 226, 86];
+pub const P2PK: &[u8] = &[3, 178, 139, 127];
+pub const BLPK: &[u8] = &[6, 149, 135, 204];
+pub const EDSIG: &[u8] = &[9, 245, 205, 134, 18];
+pub const SPSIG: &[u8] = &[13, 115, 101, 19, 63];
+pub const P2SIG: &[u8] = &[54, 240, 44, 52];
+pub const BLSIG: &[u8] = &[40, 171, 64, 207];
+pub const SIG: &[u8] = &[4, 130, 43];
+pub const NET: &[u8] = &[87, 82, 0];
+
+pub fn encode(data: &[u8]) -> String {
+    let zeros = data.iter().take_while(|byte| **byte == 0).count();
+    let mut digits: Vec<u8> = Vec::new();
+
+    for byte in &data[zeros..] {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let mut result = String::with_capacity(zeros + digits.len());
+    for _ in 0..zeros {
+        result.push('1');
+    }
+    for digit in digits.iter().rev() {
+        result.push(ALPHABET[*digit as usize] as char);
+    }
+    result
+}
+
+pub fn decode(value: &str) -> Option<Vec<u8>> {
+    let zeros = value.bytes().take_while(|byte| *byte == b'1').count();
+    let mut bytes: Vec<u8> = Vec::new();
+
+    for character in value.bytes().skip(zeros) {
+        let mut carry = ALPHABET.iter().position(|c| *c == character)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += (*byte as u32) * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+
+    let mut result = vec![0; zeros];
+    result.extend(bytes.iter().rev());
+    Some(result)
+}
+
+fn checksum(data: &[u8]) -> [u8; 4] {
+    let hash = sha256(&sha256(data));
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+pub fn encode_check(prefix: &[u8], payload: &[u8]) -> String {
+    let mut data = prefix.to_vec();
+    data.extend_from_slice(payload);
+    let checksum = checksum(&data);
+    data.extend_from_slice(&checksum);
+    encode(&data)
+}
+
+/// Decodes a Base58Check string, verifying the checksum and that it starts
+/// with `prefix`, and returns the payload if it is `length` bytes long.
+pub fn decode_check(value: &str, prefix: &[u8], length: usize) -> Option<Vec<u8>> {
+    let data = decode(value)?;
+    if data.len() != prefix.len() + length + 4 || !data.starts_with(prefix) {
+        return None;
+    }
+
+    let (body, check) = data.split_at(data.len() - 4);
+    if checksum(body) != check {
+        return None;
+    }
+
+    Some(body[prefix.len()..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base58::*;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"\x00\x00\x01"), "112");
+        assert_eq!(decode("112"), Some(b"\x00\x00\x01".to_vec()));
+        assert_eq!(encode(b"Hello World!"), "2NEpo7TZRRrLZSi2U");
+        assert_eq!(decode("2NEpo7TZRRrLZSi2U"), Some(b"Hello World!".to_vec()));
+        assert_eq!(decode("0OIl"), None);
+    }
+
+    #[test]
+    fn check() {
+        let payload = decode_check("KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc", KT1, 20).unwrap();
+        assert_eq!(encode_check(KT1, &payload), "KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc");
+
+        assert_eq!(decode_check("KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesd", KT1, 20), None);
+        assert_eq!(decode_check("KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc", TZ1, 20), None);
+        assert_eq!(decode_check("KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc", KT1, 21), None);
+    }
+}
@@ -0,0 +1,298 @@
+// Generation of bindings for contracts, from the types of their storage and
+// of the arguments of their entrypoints.
+//
+// Generated types and fields are named from field annotations, and types
+// sharing a name get numbered suffixes, as in `Config` and `Config2`.
+
+use std::collections::HashSet;
+
+use crate::Node;
+use crate::path::Path;
+use crate::michelson_v1_primitives::Primitive;
+use crate::michelson_v1_primitives::Primitive::*;
+use crate::typecheck::script_section;
+
+pub mod rust;
+pub mod schema;
+pub mod typescript;
+
+#[derive(Debug, PartialEq)]
+pub enum CodegenErrorKind {
+    /// A script is not a sequence of sections with `parameter` and `storage`.
+    InvalidScript,
+    /// The type expression is malformed.
+    InvalidType,
+    /// Values of the type have no representation in the generated code,
+    /// such as `lambda` or `operation`.
+    UnsupportedType(Primitive),
+    /// Two fields, variants or definitions would have the same name where
+    /// the output requires distinct ones, such as the keys of a JSON object.
+    DuplicateName(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CodegenError {
+    pub path: Path,
+    pub kind: CodegenErrorKind,
+}
+
+pub(crate) fn error<T>(path: &[usize], kind: CodegenErrorKind) -> Result<T, CodegenError> {
+    Err(CodegenError { path: Path::from(path), kind })
+}
+
+/// A type to generate bindings for, named `name`, found at `path` in the
+/// script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub ty: Node<Primitive>,
+    pub path: Path,
+}
+
+/// The types of a contract: its storage, named `storage`, and the argument
+/// of each entrypoint, named after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+    pub storage: Definition,
+    pub entrypoints: Vec<Definition>,
+}
+
+pub(crate) fn field_annotation(ty: &Node<Primitive>) -> Option<&str> {
+    match ty {
+        Node::Prim(_, _, annots) => annots.iter().find_map(|annot| annot.strip_prefix('%')),
+        _ => None,
+    }
+}
+
+/// The entrypoints of a parameter type at `path`: the branches of its `or`
+/// with a field annotation, and `default`, the whole parameter, unless a
+/// branch has that name.
+pub fn entrypoints(parameter: &Node<Primitive>, path: &[usize]) -> Vec<Definition> {
+    let mut entrypoints = Vec::new();
+    // Branches still to look at, last first so that entrypoints come in
+    // order.
+    let mut pending = vec![(parameter, Path::from(path))];
+    while let Some((ty, path)) = pending.pop() {
+        match (field_annotation(ty), ty) {
+            (Some(name), _) if !name.is_empty() => {
+                entrypoints.push(Definition { name: String::from(name), ty: ty.clone(), path });
+            }
+            (_, Node::Prim(T_or, args, _)) => {
+                for (index, arg) in args.iter().enumerate().rev() {
+                    pending.push((arg, path.child(index)));
+                }
+            }
+            _ => {}
+        }
+    }
+    if !entrypoints.iter().any(|entrypoint| entrypoint.name == "default") {
+        entrypoints.push(Definition { name: String::from("default"), ty: parameter.clone(), path: Path::from(path) });
+    }
+    entrypoints
+}
+
+/// The interface of a script, such as `{ parameter ... ; storage ... ;
+/// code ... }`.
+pub fn interface(script: &Node<Primitive>) -> Result<Interface, CodegenError> {
+    let sections = match script {
+        Node::Seq(sections) => sections,
+        _ => return error(&[], CodegenErrorKind::InvalidScript),
+    };
+    let (parameter_index, parameter) = match script_section(sections, K_parameter) {
+        Some(section) => section,
+        None => return error(&[], CodegenErrorKind::InvalidScript),
+    };
+    let (storage_index, storage) = match script_section(sections, K_storage) {
+        Some(section) => section,
+        None => return error(&[], CodegenErrorKind::InvalidScript),
+    };
+    Ok(Interface {
+        storage: Definition { name: String::from("storage"), ty: storage.clone(), path: Path(vec![storage_index, 0]) },
+        entrypoints: entrypoints(parameter, &[parameter_index, 0]),
+    })
+}
+
+pub(crate) fn without_annotations(ty: &Node<Primitive>) -> Node<Primitive> {
+    match ty {
+        Node::Prim(prim, args, _) => Node::Prim(*prim, args.clone(), vec![]),
+        other => other.clone(),
+    }
+}
+
+/// A field of a comb or a branch of an `or`, with its path.
+pub(crate) type Member<'a> = (&'a Node<Primitive>, Path);
+
+/// The fields of a right comb of pairs at `path`, with their paths. Pairs
+/// in last position are part of the comb unless annotated.
+pub(crate) fn pair_fields<'a>(ty: &'a Node<Primitive>, path: &[usize]) -> Result<Vec<Member<'a>>, CodegenError> {
+    let mut fields = Vec::new();
+    let (mut ty, mut path) = (ty, Path::from(path));
+    loop {
+        let args = match ty {
+            Node::Prim(T_pair, args, _) if args.len() >= 2 => args,
+            _ => return error(&path, CodegenErrorKind::InvalidType),
+        };
+        let (last, init) = args.split_last().expect("pair arguments");
+        for (index, arg) in init.iter().enumerate() {
+            fields.push((arg, path.child(index)));
+        }
+        path.0.push(init.len());
+        match last {
+            Node::Prim(T_pair, _, _) if field_annotation(last).is_none() => ty = last,
+            _ => {
+                fields.push((last, path));
+                return Ok(fields);
+            }
+        }
+    }
+}
+
+/// The variants of a right comb of `or` at `path`, with their paths.
+pub(crate) fn or_variants<'a>(ty: &'a Node<Primitive>, path: &[usize]) -> Result<Vec<Member<'a>>, CodegenError> {
+    let mut variants = Vec::new();
+    let (mut ty, mut path) = (ty, Path::from(path));
+    loop {
+        let (left, right) = match ty {
+            Node::Prim(T_or, args, _) if args.len() == 2 => (&args[0], &args[1]),
+            _ => return error(&path, CodegenErrorKind::InvalidType),
+        };
+        variants.push((left, path.child(0)));
+        path.0.push(1);
+        match right {
+            Node::Prim(T_or, _, _) if field_annotation(right).is_none() => ty = right,
+            _ => {
+                variants.push((right, path));
+                return Ok(variants);
+            }
+        }
+    }
+}
+
+/// Rejects the first of `names` that repeats an earlier one, at its path in
+/// `paths`.
+pub(crate) fn distinct(names: &[String], paths: &[Path]) -> Result<(), CodegenError> {
+    let mut seen = HashSet::new();
+    for (name, path) in names.iter().zip(paths) {
+        if !seen.insert(name) {
+            return error(path, CodegenErrorKind::DuplicateName(name.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// The names of the types generated so far, and the types they stand for.
+#[derive(Default)]
+pub(crate) struct Names {
+    names: HashSet<String>,
+    types: Vec<(String, Node<Primitive>, String)>,
+}
+
+impl Names {
+    /// Names where `reserved` are taken, as by names the output defines
+    /// itself.
+    pub(crate) fn reserving(reserved: &[&str]) -> Names {
+        Names { names: reserved.iter().map(|name| String::from(*name)).collect(), types: Vec::new() }
+    }
+
+    /// The name of the type generated for `ty` from `hint`, if any.
+    pub(crate) fn find(&self, hint: &str, ty: &Node<Primitive>) -> Option<String> {
+        let ty = without_annotations(ty);
+        self.types.iter().find(|(other, other_ty, _)| other == hint && *other_ty == ty).map(|(_, _, name)| name.clone())
+    }
+
+    /// A name not used yet for the type `ty`, from `hint`.
+    pub(crate) fn name(&mut self, hint: &str, ty: &Node<Primitive>) -> String {
+        let base = match camel_case(hint) {
+            name if name.starts_with(|c: char| c.is_ascii_alphabetic()) => name,
+            name => format!("T{}", name),
+        };
+        let mut name = base.clone();
+        let mut suffix = 1;
+        while !self.names.insert(name.clone()) {
+            suffix += 1;
+            name = format!("{}{}", base, suffix);
+        }
+        self.types.push((String::from(hint), without_annotations(ty), name.clone()));
+        name
+    }
+}
+
+/// Splits a name such as `set_owner` or `setOwner` into lowercase words.
+pub(crate) fn words(name: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut previous = None;
+    for c in name.chars() {
+        if !c.is_alphanumeric() {
+            previous = None;
+            continue;
+        }
+        let boundary = match previous {
+            None => true,
+            Some(previous) => c.is_uppercase() && !char::is_uppercase(previous),
+        };
+        if boundary {
+            words.push(String::new());
+        }
+        words.last_mut().expect("word").extend(c.to_lowercase());
+        previous = Some(c);
+    }
+    words
+}
+
+/// `set_owner` as `SetOwner`.
+pub(crate) fn camel_case(name: &str) -> String {
+    let mut camel = String::new();
+    for word in words(name) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.extend(chars);
+        }
+    }
+    camel
+}
+
+/// `setOwner` as `set_owner`.
+pub(crate) fn snake_case(name: &str) -> String {
+    words(name).join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::*;
+    use crate::syntax;
+
+    fn node(source: &str) -> Node<Primitive> {
+        syntax::to_node(&syntax::parse(source).unwrap()).unwrap()
+    }
+
+    fn names(entrypoints: &[Definition]) -> Vec<(&str, Vec<usize>)> {
+        entrypoints.iter().map(|entrypoint| (entrypoint.name.as_str(), entrypoint.path.0.clone())).collect()
+    }
+
+    #[test]
+    fn interfaces() {
+        let script = node("{ storage nat ; parameter (or (or (nat %a) (unit %b)) (or %c (int %d) string)) ; code {} }");
+        let contract = interface(&script).unwrap();
+        assert_eq!(contract.storage, Definition { name: String::from("storage"), ty: node("nat"), path: Path(vec![0, 0]) });
+        assert_eq!(
+            names(&contract.entrypoints),
+            vec![("a", vec![1, 0, 0, 0]), ("b", vec![1, 0, 0, 1]), ("c", vec![1, 0, 1]), ("default", vec![1, 0])]
+        );
+
+        let parameter = node("or (nat %default) (unit %other)");
+        assert_eq!(names(&entrypoints(&parameter, &[])), vec![("default", vec![0]), ("other", vec![1])]);
+        assert_eq!(names(&entrypoints(&node("nat"), &[])), vec![("default", vec![])]);
+
+        assert_eq!(interface(&node("{ storage nat }")), Err(CodegenError { path: Path(vec![]), kind: CodegenErrorKind::InvalidScript }));
+    }
+
+    #[test]
+    fn naming() {
+        assert_eq!(camel_case("set_owner"), "SetOwner");
+        assert_eq!(camel_case("setOwner"), "SetOwner");
+        assert_eq!(camel_case("NFT_metadata"), "NftMetadata");
+        assert_eq!(snake_case("setOwner"), "set_owner");
+        assert_eq!(snake_case("token-id"), "token_id");
+    }
+}
@@ -0,0 +1,395 @@
+// Rust bindings: structs for pairs and enums for `or`, deriving the traits
+// of `convert`.
+//
+// Integers are `i32`, the range of `Node::Int`, or `u32` for naturals, and
+// values with several representations, such as addresses, keys or
+// timestamps, are the `String` of their readable form. A `big_map` is a
+// `convert::BigMap`, which holds the id of the map in storage. Paths to
+// standard types are absolute, as generated types may be named like them.
+// The Michelson type of fields whose Rust type does not determine it, such
+// as `mutez` for `u32`, is given with `#[micheline(ty = "...")]`.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+
+use crate::Node;
+use crate::codegen::{
+    camel_case, error, field_annotation, interface, or_variants, pair_fields, snake_case, without_annotations,
+    CodegenError, CodegenErrorKind, Definition, Interface, Names,
+};
+use crate::macros::{self, MacroError};
+use crate::path::Path;
+use crate::michelson_v1_primitives::Primitive;
+use crate::michelson_v1_primitives::Primitive::*;
+use crate::syntax::{self, ParseError, UnknownPrimitive};
+
+const DERIVES: &str = "#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, \
+    ::micheline::convert::MichelsonType, ::micheline::convert::IntoMicheline, ::micheline::convert::FromMicheline)]";
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct",
+    "trait", "true", "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "gen",
+    "macro", "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+// Keywords that cannot be raw identifiers.
+const RESERVED: &[&str] = &["crate", "self", "Self", "super"];
+
+/// A struct field or variant argument.
+struct Field {
+    // Annotation naming the field, if any.
+    name: Option<String>,
+    rust: String,
+    // Michelson type, when the Rust type does not determine it.
+    michelson: Option<String>,
+}
+
+struct Generator {
+    items: Vec<String>,
+    names: Names,
+}
+
+/// The field named by the annotation `name`, or `fallback` if it has no
+/// letters or digits.
+fn identifier(name: &str, fallback: String) -> String {
+    let name = snake_case(name);
+    match name.chars().next() {
+        None => fallback,
+        Some(first) if first.is_ascii_digit() => format!("_{}", name),
+        Some(_) if RESERVED.contains(&name.as_str()) => format!("{}_", name),
+        Some(_) if KEYWORDS.contains(&name.as_str()) => format!("r#{}", name),
+        Some(_) => name,
+    }
+}
+
+/// The variant named by the annotation `name`, or `fallback` if it has no
+/// letters or digits.
+fn variant_identifier(name: &str, fallback: String) -> String {
+    let name = camel_case(name);
+    match name.chars().next() {
+        None => fallback,
+        Some(first) if first.is_ascii_digit() => format!("V{}", name),
+        Some(_) if RESERVED.contains(&name.as_str()) => format!("{}_", name),
+        Some(_) => name,
+    }
+}
+
+/// `idents` with a numeric suffix on those already used, as `a_b` and `aB`
+/// both name `a_b`.
+fn unique(idents: Vec<String>) -> Vec<String> {
+    let mut used = HashSet::new();
+    idents
+        .into_iter()
+        .map(|ident| {
+            // A suffixed keyword is no longer a keyword, so drop its escape.
+            let stem = match ident.strip_suffix('_') {
+                Some(stem) if RESERVED.contains(&stem) => stem,
+                _ => ident.strip_prefix("r#").unwrap_or(&ident),
+            }
+            .to_owned();
+            let mut unique = ident;
+            let mut suffix = 1;
+            while !used.insert(unique.clone()) {
+                suffix += 1;
+                unique = format!("{}{}", stem, suffix);
+            }
+            unique
+        })
+        .collect()
+}
+
+impl Generator {
+    /// The Rust type of values of `ty`, and whether it determines `ty`.
+    fn rust_type(&mut self, ty: &Node<Primitive>, hint: &str, path: &Path) -> Result<(String, bool), CodegenError> {
+        let (prim, args) = match ty {
+            Node::Prim(prim, args, _) => (*prim, args),
+            _ => return error(path, CodegenErrorKind::InvalidType),
+        };
+        let arg = |generator: &mut Generator, index: usize| match args.get(index) {
+            Some(arg) => generator.rust_type(arg, hint, &path.child(index)),
+            None => error(path, CodegenErrorKind::InvalidType),
+        };
+        Ok(match prim {
+            T_unit => (String::from("()"), true),
+            T_bool => (String::from("bool"), true),
+            T_int => (String::from("i32"), true),
+            T_string => (String::from("::std::string::String"), true),
+            T_bytes => (String::from("::std::vec::Vec<u8>"), true),
+            T_nat => (String::from("u32"), true),
+            T_mutez => (String::from("u32"), false),
+            T_address | T_key | T_key_hash | T_signature | T_chain_id | T_timestamp | T_contract | T_tx_rollup_l2_address => {
+                (String::from("::std::string::String"), false)
+            }
+            T_bls12_381_g1 | T_bls12_381_g2 | T_bls12_381_fr | T_chest | T_chest_key => (String::from("::std::vec::Vec<u8>"), false),
+            T_option => {
+                let (inner, exact) = arg(self, 0)?;
+                (format!("::std::option::Option<{}>", inner), exact)
+            }
+            T_list => {
+                let (inner, exact) = arg(self, 0)?;
+                (format!("::std::vec::Vec<{}>", inner), exact)
+            }
+            T_set => {
+                let (inner, exact) = arg(self, 0)?;
+                (format!("::std::collections::BTreeSet<{}>", inner), exact)
+            }
+            T_map | T_big_map => {
+                let (key, key_exact) = arg(self, 0)?;
+                let (value, value_exact) = arg(self, 1)?;
+                let map = if prim == T_map { "::std::collections::BTreeMap" } else { "::micheline::convert::BigMap" };
+                (format!("{}<{}, {}>", map, key, value), key_exact && value_exact)
+            }
+            T_pair | T_or => {
+                let hint = field_annotation(ty).unwrap_or(hint);
+                (self.definition(&Definition { name: String::from(hint), ty: ty.clone(), path: path.clone() })?, true)
+            }
+            prim => return error(path, CodegenErrorKind::UnsupportedType(prim)),
+        })
+    }
+
+    fn field(&mut self, ty: &Node<Primitive>, hint: &str, path: &Path) -> Result<Field, CodegenError> {
+        let name = field_annotation(ty).filter(|name| !name.is_empty()).map(String::from);
+        let (rust, exact) = self.rust_type(ty, name.as_deref().unwrap_or(hint), path)?;
+        let michelson = if exact { None } else { Some(syntax::print(&syntax::from_node(&without_annotations(ty)))) };
+        Ok(Field { name, rust, michelson })
+    }
+
+    fn fields(&mut self, ty: &Node<Primitive>, hint: &str, path: &Path) -> Result<Vec<Field>, CodegenError> {
+        let fields = pair_fields(ty, path)?;
+        fields
+            .into_iter()
+            .enumerate()
+            .map(|(index, (field, path))| self.field(field, &format!("{}_{}", hint, index), &path))
+            .collect()
+    }
+
+    /// Defines the type of `definition` and returns its name.
+    fn definition(&mut self, definition: &Definition) -> Result<String, CodegenError> {
+        if let Some(name) = self.names.find(&definition.name, &definition.ty) {
+            return Ok(name);
+        }
+        let name = self.names.name(&definition.name, &definition.ty);
+        // Reserved so that the type comes before the nested ones it uses.
+        let slot = self.items.len();
+        self.items.push(String::new());
+        let mut item = String::new();
+        let body = match &definition.ty {
+            Node::Prim(T_pair, _, _) => {
+                let fields = self.fields(&definition.ty, &definition.name, &definition.path)?;
+                let end = if fields.iter().any(|field| field.name.is_some()) { "" } else { ";" };
+                format!("pub struct {}{}{}", name, Fields(&fields, true), end)
+            }
+            Node::Prim(T_or, _, _) => {
+                let mut variants = Vec::new();
+                for (index, (ty, path)) in or_variants(&definition.ty, &definition.path)?.into_iter().enumerate() {
+                    let annotation = field_annotation(ty).filter(|name| !name.is_empty());
+                    let hint = annotation.map(String::from).unwrap_or_else(|| format!("{}_{}", definition.name, index));
+                    let fields = match ty {
+                        Node::Prim(T_unit, _, _) => Vec::new(),
+                        Node::Prim(T_pair, _, _) => self.fields(ty, &hint, &path)?,
+                        _ => vec![Field { name: None, ..self.field(&without_annotations(ty), &hint, &path)? }],
+                    };
+                    variants.push((annotation, fields));
+                }
+                let idents = variants
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (annotation, _))| variant_identifier(annotation.unwrap_or(""), format!("Variant{}", index)));
+                let idents = unique(idents.collect());
+                let variants = variants.iter().zip(idents).map(|((annotation, fields), ident)| {
+                    format!(
+                        "    #[micheline(name = {:?})]\n    {}{},\n",
+                        annotation.map(String::from).unwrap_or_else(|| snake_case(&ident)),
+                        ident,
+                        Fields(fields, false),
+                    )
+                });
+                format!("pub enum {} {{\n{}}}", name, variants.collect::<String>())
+            }
+            Node::Prim(T_unit, _, _) => format!("pub struct {};", name),
+            ty => {
+                let field = Field { name: None, ..self.field(&without_annotations(ty), &definition.name, &definition.path)? };
+                format!("pub struct {}{};", name, Fields(&[field], true))
+            }
+        };
+        item.push_str(DERIVES);
+        item.push('\n');
+        item.push_str(&body);
+        item.push('\n');
+        self.items[slot] = item;
+        Ok(name)
+    }
+}
+
+/// Fields of a struct, public, or of a variant, as `(...)` if none is named
+/// and `{ ... }` otherwise.
+struct Fields<'a>(&'a [Field], bool);
+
+impl fmt::Display for Fields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Fields(fields, public) = *self;
+        if fields.is_empty() {
+            return Ok(());
+        }
+        let named = fields.iter().any(|field| field.name.is_some());
+        let (indent, visibility) = match (named, public) {
+            (true, true) => ("    ", "pub "),
+            (true, false) => ("        ", ""),
+            (false, true) => ("", "pub "),
+            (false, false) => ("", ""),
+        };
+        let idents = fields
+            .iter()
+            .enumerate()
+            .map(|(index, field)| identifier(field.name.as_deref().unwrap_or(""), format!("field{}", index)));
+        let idents = unique(idents.collect());
+        f.write_str(if named { " {\n" } else { "(" })?;
+        for (index, (field, ident)) in fields.iter().zip(idents).enumerate() {
+            let mut attributes = Vec::new();
+            if let Some(name) = &field.name {
+                attributes.push(format!("name = {:?}", name));
+            }
+            if let Some(michelson) = &field.michelson {
+                attributes.push(format!("ty = {:?}", michelson));
+            }
+            if !attributes.is_empty() {
+                write!(f, "{}#[micheline({})]", indent, attributes.join(", "))?;
+                f.write_str(if named { "\n" } else { " " })?;
+            }
+            if named {
+                writeln!(f, "{}{}{}: {},", indent, visibility, ident, field.rust)?;
+            } else {
+                let separator = if index + 1 < fields.len() { ", " } else { "" };
+                write!(f, "{}{}{}", visibility, field.rust, separator)?;
+            }
+        }
+        match (named, public) {
+            (true, true) => f.write_str("}"),
+            (true, false) => f.write_str("    }"),
+            (false, _) => f.write_str(")"),
+        }
+    }
+}
+
+/// Rust source defining the types of `definitions`, in order, along with
+/// the types of their pairs and `or` named by annotations.
+pub fn generate(definitions: &[Definition]) -> Result<String, CodegenError> {
+    let mut generator = Generator { items: Vec::new(), names: Names::default() };
+    let mut output = String::from("// Generated by `micheline::codegen::rust`.\n");
+    for definition in definitions {
+        generator.definition(definition)?;
+    }
+    for item in &generator.items {
+        output.push('\n');
+        output.push_str(item);
+    }
+    Ok(output)
+}
+
+/// Rust source for the storage and entrypoints of a contract.
+pub fn generate_interface(interface: &Interface) -> Result<String, CodegenError> {
+    let mut definitions = vec![interface.storage.clone()];
+    definitions.extend(interface.entrypoints.iter().cloned());
+    generate(&definitions)
+}
+
+#[derive(Debug)]
+pub enum BuildError {
+    Io(io::Error),
+    Parse(ParseError),
+    Macro(MacroError),
+    UnknownPrimitive(UnknownPrimitive),
+    Codegen(CodegenError),
+}
+
+/// Generates bindings for the script in the `.tz` file at `script` into
+/// `output` in the `OUT_DIR` of a build script, to be included with
+/// `include!(concat!(env!("OUT_DIR"), "/<output>"))`.
+pub fn build(script: impl AsRef<std::path::Path>, output: impl AsRef<std::path::Path>) -> Result<(), BuildError> {
+    let script = script.as_ref();
+    println!("cargo:rerun-if-changed={}", script.display());
+    let out_dir = std::env::var_os("OUT_DIR")
+        .ok_or_else(|| BuildError::Io(io::Error::new(io::ErrorKind::NotFound, "OUT_DIR is not set")))?;
+
+    let source = std::fs::read_to_string(script).map_err(BuildError::Io)?;
+    let expr = syntax::parse_script(&source).map_err(BuildError::Parse)?;
+    let expr = macros::expand(&expr).map_err(BuildError::Macro)?;
+    let node = syntax::to_node(&expr).map_err(BuildError::UnknownPrimitive)?;
+    let interface = interface(&node).map_err(BuildError::Codegen)?;
+    let code = generate_interface(&interface).map_err(BuildError::Codegen)?;
+    std::fs::write(std::path::Path::new(&out_dir).join(output), code).map_err(BuildError::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::rust::*;
+
+    fn definition(name: &str, source: &str) -> Definition {
+        let ty = syntax::to_node(&syntax::parse(source).unwrap()).unwrap();
+        Definition { name: String::from(name), ty, path: Path(vec![]) }
+    }
+
+    #[test]
+    fn items() {
+        let code = generate(&[definition("storage", "pair (mutez %type) (or %kind (unit %a) (pair %b int (bytes %c)))")]).unwrap();
+        let items: Vec<&str> = code.split("\n\n").skip(1).map(|item| item.split_once('\n').unwrap().1).collect();
+        assert_eq!(
+            items,
+            vec![
+                "pub struct Storage {\n    #[micheline(name = \"type\", ty = \"mutez\")]\n    pub r#type: u32,\n    \
+                 #[micheline(name = \"kind\")]\n    pub kind: Kind,\n}",
+                "pub enum Kind {\n    #[micheline(name = \"a\")]\n    A,\n    #[micheline(name = \"b\")]\n    B {\n        \
+                 field0: i32,\n        #[micheline(name = \"c\")]\n        c: ::std::vec::Vec<u8>,\n    },\n}\n",
+            ]
+        );
+
+        let code = generate(&[definition("default", "option (pair int int)"), definition("Default", "unit")]).unwrap();
+        assert!(code.contains("pub struct Default(pub ::std::option::Option<Default2>);"));
+        assert!(code.contains("pub struct Default2(pub i32, pub i32);"));
+        assert!(code.contains("pub struct Default3;"));
+
+        // A type met again under the same name is reused, and another type
+        // with that name is suffixed.
+        let code = generate(&[
+            definition("a", "pair (int %x) (int %y)"),
+            definition("b", "pair (pair %a (int %x) (int %y)) (pair %c (int %x) (int %y))"),
+            definition("c", "pair (bool %x) (bool %y)"),
+        ])
+        .unwrap();
+        assert_eq!(code.matches("pub struct A ").count(), 1);
+        assert_eq!(code.matches("pub struct C").count(), 2);
+        assert!(code.contains("    #[micheline(name = \"a\")]\n    pub a: A,\n    #[micheline(name = \"c\")]\n    pub c: C,\n}"));
+        assert!(code.contains("pub struct C2 {\n    #[micheline(name = \"x\")]\n    pub x: bool,"));
+    }
+
+    #[test]
+    fn identifiers() {
+        let code = generate(&[definition("parameter", "or (nat %0) (or (unit %_) (or (unit %self) (unit %Self)))")]).unwrap();
+        assert!(code.contains("    #[micheline(name = \"0\")]\n    V0(u32),\n"));
+        assert!(code.contains("    #[micheline(name = \"_\")]\n    Variant1,\n"));
+        assert!(code.contains("    #[micheline(name = \"self\")]\n    Self_,\n"));
+        assert!(code.contains("    #[micheline(name = \"Self\")]\n    Self2,\n"));
+
+        let code = generate(&[definition("storage", "pair (nat %a_b) (nat %aB) (unit %_) (unit %self) (unit %crate)")]).unwrap();
+        for field in ["pub a_b: u32,", "pub a_b2: u32,", "pub field2: (),", "pub self_: (),", "pub crate_: (),"] {
+            assert!(code.contains(field), "{}", field);
+        }
+
+        let code = generate(&[definition("parameter", "or (unit %set_owner) (unit %setOwner)")]).unwrap();
+        assert!(code.contains("    SetOwner,\n") && code.contains("    SetOwner2,\n"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            generate(&[definition("storage", "pair nat (list (lambda int int))")]),
+            Err(CodegenError { path: Path(vec![1, 0]), kind: CodegenErrorKind::UnsupportedType(T_lambda) })
+        );
+        assert_eq!(
+            generate(&[definition("storage", "option")]),
+            Err(CodegenError { path: Path(vec![]), kind: CodegenErrorKind::InvalidType })
+        );
+    }
+}
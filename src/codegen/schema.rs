@@ -0,0 +1,314 @@
+// JSON Schemas (draft 2020-12) of the human-readable JSON representation of
+// Michelson values, to validate values before they are converted to
+// Micheline.
+//
+// Pairs with an annotated field are objects keyed by the annotations of
+// their fields, other pairs arrays. A value of an `or` is an object with the
+// annotation of its branch as only key, and `unit` an empty object. Numbers
+// are decimal strings, as Michelson integers are unbounded, bytes
+// hexadecimal strings and absent options `null`. Maps are objects when their
+// keys are strings, and arrays of `[key, value]` otherwise. Names that would
+// key an object twice are rejected.
+
+use std::fmt;
+
+use crate::Node;
+use crate::codegen::{distinct, error, field_annotation, or_variants, pair_fields, CodegenError, CodegenErrorKind, Definition, Interface};
+use crate::michelson_v1_primitives::Primitive;
+use crate::michelson_v1_primitives::Primitive::*;
+use crate::path::Path;
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+const BASE58: &str = "[1-9A-HJ-NP-Za-km-z]";
+
+enum Json {
+    Bool(bool),
+    Number(usize),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn object(properties: Vec<(&str, Json)>) -> Json {
+    Json::Object(properties.into_iter().map(|(key, value)| (String::from(key), value)).collect())
+}
+
+fn string(value: &str) -> Json {
+    Json::String(String::from(value))
+}
+
+/// The schema of strings matching `pattern`.
+fn pattern(pattern: &str) -> Json {
+    object(vec![("type", string("string")), ("pattern", string(pattern))])
+}
+
+fn quote(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl Json {
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let (open, close, len) = match self {
+            Json::Bool(value) => return write!(f, "{}", value),
+            Json::Number(value) => return write!(f, "{}", value),
+            Json::String(value) => return quote(f, value),
+            Json::Array(items) => ("[", "]", items.len()),
+            Json::Object(properties) => ("{", "}", properties.len()),
+        };
+        if len == 0 {
+            return write!(f, "{}{}", open, close);
+        }
+        writeln!(f, "{}", open)?;
+        for index in 0..len {
+            write!(f, "{:1$}", "", indent + 2)?;
+            match self {
+                Json::Array(items) => items[index].write(f, indent + 2)?,
+                Json::Object(properties) => {
+                    quote(f, &properties[index].0)?;
+                    f.write_str(": ")?;
+                    properties[index].1.write(f, indent + 2)?;
+                }
+                _ => unreachable!(),
+            }
+            writeln!(f, "{}", if index + 1 < len { "," } else { "" })?;
+        }
+        write!(f, "{:1$}{2}", "", indent, close)
+    }
+
+    /// The object `self` with `properties` first.
+    fn prepend(self, mut properties: Vec<(String, Json)>) -> Json {
+        match self {
+            Json::Object(rest) => properties.extend(rest),
+            _ => unreachable!("schemas are objects"),
+        }
+        Json::Object(properties)
+    }
+
+    /// Whether the values of this schema are strings.
+    fn is_string(&self) -> bool {
+        match self {
+            Json::Object(properties) => properties.iter().any(|(key, value)| key == "type" && matches!(value, Json::String(ty) if ty == "string")),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// The schema of the values of `ty`, found at `path`.
+fn schema(ty: &Node<Primitive>, path: &Path) -> Result<Json, CodegenError> {
+    let (prim, args) = match ty {
+        Node::Prim(prim, args, _) => (*prim, args),
+        _ => return error(path, CodegenErrorKind::InvalidType),
+    };
+    let arg = |index: usize| match args.get(index) {
+        Some(arg) => schema(arg, &path.child(index)),
+        None => error(path, CodegenErrorKind::InvalidType),
+    };
+    Ok(match prim {
+        T_unit => object(vec![("type", string("object")), ("maxProperties", Json::Number(0))]),
+        T_bool => object(vec![("type", string("boolean"))]),
+        T_int => pattern("^-?[0-9]+$"),
+        T_nat | T_mutez => pattern("^[0-9]+$"),
+        T_string | T_key | T_signature | T_chain_id | T_tx_rollup_l2_address => object(vec![("type", string("string"))]),
+        T_address | T_contract => pattern(&format!("^(tz[1-4]|KT1|txr1|sr1){}{{33}}(%.+)?$", BASE58)),
+        T_key_hash => pattern(&format!("^tz[1-4]{}{{33}}$", BASE58)),
+        T_timestamp => object(vec![("type", string("string")), ("format", string("date-time"))]),
+        T_bytes | T_bls12_381_g1 | T_bls12_381_g2 | T_bls12_381_fr | T_chest | T_chest_key => {
+            pattern("^([0-9a-fA-F]{2})*$")
+        }
+        T_option => {
+            // `null` would stand for both `None` and `Some None`.
+            if let Some(Node::Prim(T_option, _, _)) = args.first() {
+                return error(&path.child(0), CodegenErrorKind::UnsupportedType(T_option));
+            }
+            object(vec![("anyOf", Json::Array(vec![object(vec![("type", string("null"))]), arg(0)?]))])
+        }
+        T_list => object(vec![("type", string("array")), ("items", arg(0)?)]),
+        T_set => object(vec![("type", string("array")), ("items", arg(0)?), ("uniqueItems", Json::Bool(true))]),
+        T_map | T_big_map => {
+            let (key, value) = (arg(0)?, arg(1)?);
+            if key.is_string() {
+                object(vec![("type", string("object")), ("propertyNames", key), ("additionalProperties", value)])
+            } else {
+                let entry = object(vec![
+                    ("type", string("array")),
+                    ("prefixItems", Json::Array(vec![key, value])),
+                    ("items", Json::Bool(false)),
+                    ("minItems", Json::Number(2)),
+                ]);
+                object(vec![("type", string("array")), ("items", entry)])
+            }
+        }
+        T_pair => {
+            let fields = pair_fields(ty, path)?;
+            let count = fields.len();
+            let named = fields.iter().any(|(field, _)| field_annotation(field).is_some_and(|name| !name.is_empty()));
+            let mut names = Vec::new();
+            let mut paths = Vec::new();
+            let mut schemas = Vec::new();
+            for (index, (field, path)) in fields.into_iter().enumerate() {
+                let name = field_annotation(field).filter(|name| !name.is_empty());
+                names.push(name.map(String::from).unwrap_or_else(|| format!("field{}", index)));
+                schemas.push(schema(field, &path)?);
+                paths.push(path);
+            }
+            if named {
+                distinct(&names, &paths)?;
+                object(vec![
+                    ("type", string("object")),
+                    ("properties", Json::Object(names.iter().cloned().zip(schemas).collect())),
+                    ("required", Json::Array(names.into_iter().map(Json::String).collect())),
+                    ("additionalProperties", Json::Bool(false)),
+                ])
+            } else {
+                object(vec![
+                    ("type", string("array")),
+                    ("prefixItems", Json::Array(schemas)),
+                    ("items", Json::Bool(false)),
+                    ("minItems", Json::Number(count)),
+                ])
+            }
+        }
+        T_or => {
+            let mut names = Vec::new();
+            let mut paths = Vec::new();
+            let mut branches = Vec::new();
+            for (index, (variant, path)) in or_variants(ty, path)?.into_iter().enumerate() {
+                let name = field_annotation(variant).filter(|name| !name.is_empty());
+                let name = name.map(String::from).unwrap_or_else(|| format!("variant{}", index));
+                names.push(name.clone());
+                branches.push(object(vec![
+                    ("type", string("object")),
+                    ("properties", Json::Object(vec![(name.clone(), schema(variant, &path)?)])),
+                    ("required", Json::Array(vec![Json::String(name)])),
+                    ("additionalProperties", Json::Bool(false)),
+                ]));
+                paths.push(path);
+            }
+            // Branches with the same key would both match their values.
+            distinct(&names, &paths)?;
+            object(vec![("oneOf", Json::Array(branches))])
+        }
+        prim => return error(path, CodegenErrorKind::UnsupportedType(prim)),
+    })
+}
+
+/// The schema of the values of `definition`, titled with its name.
+fn titled(definition: &Definition) -> Result<Json, CodegenError> {
+    Ok(schema(&definition.ty, &definition.path)?.prepend(vec![(String::from("title"), string(&definition.name))]))
+}
+
+/// A JSON Schema document defining the values of `definitions`, in order,
+/// referenced as `#/$defs/<name>`.
+pub fn generate(definitions: &[Definition]) -> Result<String, CodegenError> {
+    let names: Vec<String> = definitions.iter().map(|definition| definition.name.clone()).collect();
+    let paths: Vec<Path> = definitions.iter().map(|definition| definition.path.clone()).collect();
+    distinct(&names, &paths)?;
+    let schemas = definitions.iter().map(titled).collect::<Result<Vec<_>, _>>()?;
+    let document = Json::Object(vec![
+        (String::from("$schema"), string(DRAFT)),
+        (String::from("$defs"), Json::Object(names.into_iter().zip(schemas).collect())),
+    ]);
+    Ok(format!("{}\n", document))
+}
+
+/// A JSON Schema document defining the storage and the argument of each
+/// entrypoint of a contract.
+pub fn generate_interface(interface: &Interface) -> Result<String, CodegenError> {
+    let mut definitions = vec![interface.storage.clone()];
+    definitions.extend(interface.entrypoints.iter().cloned());
+    generate(&definitions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::schema::*;
+    use crate::syntax;
+
+    fn definition(name: &str, source: &str) -> Definition {
+        let ty = syntax::to_node(&syntax::parse(source).unwrap()).unwrap();
+        Definition { name: String::from(name), ty, path: Path(vec![]) }
+    }
+
+    /// `json` without whitespace outside of strings.
+    fn compact(json: &str) -> String {
+        let mut compact = String::new();
+        let (mut quoted, mut escaped) = (false, false);
+        for c in json.chars() {
+            if quoted || !c.is_whitespace() {
+                compact.push(c);
+            }
+            if c == '"' && !escaped {
+                quoted = !quoted;
+            }
+            escaped = quoted && !escaped && c == '\\';
+        }
+        compact
+    }
+
+    #[test]
+    fn schemas() {
+        let schema = generate(&[definition("a \"b\"", "pair int (map (pair nat nat) unit)")]).unwrap();
+        assert_eq!(
+            compact(&schema),
+            concat!(
+                r#"{"$schema":"https://json-schema.org/draft/2020-12/schema","$defs":{"a \"b\"":{"title":"a \"b\"","#,
+                r#""type":"array","#,
+                r#""prefixItems":[{"type":"string","pattern":"^-?[0-9]+$"},{"type":"array","items":{"type":"array","#,
+                r#""prefixItems":[{"type":"array","prefixItems":[{"type":"string","pattern":"^[0-9]+$"},"#,
+                r#"{"type":"string","pattern":"^[0-9]+$"}],"items":false,"minItems":2},"#,
+                r#"{"type":"object","maxProperties":0}],"items":false,"minItems":2}}],"items":false,"minItems":2}}}"#,
+            )
+        );
+
+        let schema = generate(&[definition("storage", "pair (set %tags string) (or %state (unit %on) (bytes %off)) bool")]).unwrap();
+        assert_eq!(
+            compact(&schema),
+            concat!(
+                r#"{"$schema":"https://json-schema.org/draft/2020-12/schema","$defs":{"storage":{"title":"storage","#,
+                r#""type":"object","properties":{"#,
+                r#""tags":{"type":"array","items":{"type":"string"},"uniqueItems":true},"#,
+                r#""state":{"oneOf":[{"type":"object","properties":{"on":{"type":"object","maxProperties":0}},"#,
+                r#""required":["on"],"additionalProperties":false},{"type":"object","properties":{"off":{"type":"string","#,
+                r#""pattern":"^([0-9a-fA-F]{2})*$"}},"required":["off"],"additionalProperties":false}]},"#,
+                r#""field2":{"type":"boolean"}},"required":["tags","state","field2"],"additionalProperties":false}}}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            generate(&[definition("storage", "list (option (option int))")]),
+            Err(CodegenError { path: Path(vec![0, 0]), kind: CodegenErrorKind::UnsupportedType(T_option) })
+        );
+        assert_eq!(
+            generate(&[definition("storage", "pair int (lambda int int)")]),
+            Err(CodegenError { path: Path(vec![1]), kind: CodegenErrorKind::UnsupportedType(T_lambda) })
+        );
+
+        let duplicate = |path: Vec<usize>, name: &str| {
+            Err(CodegenError { path: Path(path), kind: CodegenErrorKind::DuplicateName(String::from(name)) })
+        };
+        assert_eq!(generate(&[definition("storage", "pair (int %a) (pair nat (int %a))")]), duplicate(vec![1, 1], "a"));
+        assert_eq!(generate(&[definition("storage", "pair (int %field1) int")]), duplicate(vec![1], "field1"));
+        assert_eq!(generate(&[definition("storage", "or (int %a) (nat %a)")]), duplicate(vec![1], "a"));
+        assert_eq!(generate(&[definition("a", "int"), definition("a", "nat")]), duplicate(vec![], "a"));
+    }
+}
@@ -0,0 +1,477 @@
+// TypeScript bindings: interfaces or tuples for pairs and tagged unions for
+// `or`, with functions encoding values to, and decoding them from, the JSON
+// representation of Micheline used by Tezos nodes.
+//
+// Numbers are `bigint`, as Michelson integers are unbounded, and values with
+// several representations, such as addresses or timestamps, are the `string`
+// of their readable form. Bytes are hexadecimal strings, absent options
+// `null` and maps `Map`s. Big maps are either the `{ id }` nodes return for
+// them or a `Map` of their bindings. The branches of an `or` are told apart by their
+// `kind`, the annotation of the branch. Encoders keep the order of sets and
+// maps, which nodes require to be sorted.
+
+use crate::Node;
+use crate::codegen::{
+    distinct, error, field_annotation, or_variants, pair_fields, without_annotations, CodegenError, CodegenErrorKind, Definition,
+    Interface, Names,
+};
+use crate::michelson_v1_primitives::Primitive;
+use crate::michelson_v1_primitives::Primitive::*;
+use crate::path::Path;
+
+const PRELUDE: &str = r#"export type Micheline =
+  | { int: string }
+  | { string: string }
+  | { bytes: string }
+  | { prim: string; args?: Micheline[]; annots?: string[] }
+  | Micheline[];
+
+type Encoder<T> = (value: T) => Micheline;
+type Decoder<T> = (node: Micheline) => T;
+
+function invalid(expected: string, node: Micheline): never {
+  throw new Error(`expected ${expected}, got ${JSON.stringify(node)}`);
+}
+
+function prim(name: string, ...args: Micheline[]): Micheline {
+  return args.length === 0 ? { prim: name } : { prim: name, args };
+}
+
+function isPrim(node: Micheline, name: string): boolean {
+  return !Array.isArray(node) && "prim" in node && node.prim === name;
+}
+
+function args(node: Micheline, name: string, arity: number): Micheline[] {
+  if (Array.isArray(node) || !("prim" in node) || node.prim !== name || (node.args ?? []).length !== arity) {
+    return invalid(name, node);
+  }
+  return node.args ?? [];
+}
+
+function pair(...values: Micheline[]): Micheline {
+  return values.reduceRight((right, left) => prim("Pair", left, right));
+}
+
+function unpair(node: Micheline, count: number): Micheline[] {
+  const fields: Micheline[] = [];
+  while (fields.length + 1 < count) {
+    const values = !Array.isArray(node) && "prim" in node && node.prim === "Pair" ? node.args ?? [] : [];
+    if (values.length < 2 || values.length > count - fields.length) {
+      return invalid(`a pair of ${count - fields.length} values`, node);
+    }
+    fields.push(...values.slice(0, -1));
+    node = values[values.length - 1];
+  }
+  fields.push(node);
+  return fields;
+}
+
+function or(index: number, count: number, value: Micheline): Micheline {
+  let node = index + 1 < count ? prim("Left", value) : value;
+  for (let depth = 0; depth < index; depth++) {
+    node = prim("Right", node);
+  }
+  return node;
+}
+
+function unor(node: Micheline, count: number): [number, Micheline] {
+  for (let index = 0; index + 1 < count; index++) {
+    if (isPrim(node, "Left")) {
+      return [index, args(node, "Left", 1)[0]];
+    }
+    node = args(node, "Right", 1)[0];
+  }
+  return [count - 1, node];
+}
+
+function encodeUnit(_value: undefined): Micheline {
+  return prim("Unit");
+}
+
+function decodeUnit(node: Micheline): undefined {
+  args(node, "Unit", 0);
+  return undefined;
+}
+
+function encodeBool(value: boolean): Micheline {
+  return prim(value ? "True" : "False");
+}
+
+function decodeBool(node: Micheline): boolean {
+  const value = isPrim(node, "True");
+  args(node, value ? "True" : "False", 0);
+  return value;
+}
+
+function encodeInt(value: bigint): Micheline {
+  return { int: value.toString() };
+}
+
+function decodeInt(node: Micheline): bigint {
+  return !Array.isArray(node) && "int" in node ? BigInt(node.int) : invalid("an integer", node);
+}
+
+function encodeString(value: string): Micheline {
+  return { string: value };
+}
+
+function decodeString(node: Micheline): string {
+  return !Array.isArray(node) && "string" in node ? node.string : invalid("a string", node);
+}
+
+function encodeBytes(value: string): Micheline {
+  return { bytes: value };
+}
+
+function decodeBytes(node: Micheline): string {
+  return !Array.isArray(node) && "bytes" in node ? node.bytes : invalid("bytes", node);
+}
+
+function encodeOption<T>(encode: Encoder<T>): Encoder<T | null> {
+  return (value) => (value === null ? prim("None") : prim("Some", encode(value)));
+}
+
+function decodeOption<T>(decode: Decoder<T>): Decoder<T | null> {
+  return (node) => {
+    if (isPrim(node, "None")) {
+      args(node, "None", 0);
+      return null;
+    }
+    return decode(args(node, "Some", 1)[0]);
+  };
+}
+
+function encodeList<T>(encode: Encoder<T>): Encoder<T[]> {
+  return (values) => values.map((value) => encode(value));
+}
+
+function decodeList<T>(decode: Decoder<T>): Decoder<T[]> {
+  return (node) => (Array.isArray(node) ? node.map((item) => decode(item)) : invalid("a sequence", node));
+}
+
+function encodeMap<K, V>(encodeKey: Encoder<K>, encodeValue: Encoder<V>): Encoder<Map<K, V>> {
+  return (map) => Array.from(map, ([key, value]) => prim("Elt", encodeKey(key), encodeValue(value)));
+}
+
+function decodeMap<K, V>(decodeKey: Decoder<K>, decodeValue: Decoder<V>): Decoder<Map<K, V>> {
+  return (node) => {
+    const entries = decodeList((elt) => {
+      const [key, value] = args(elt, "Elt", 2);
+      return [decodeKey(key), decodeValue(value)] as [K, V];
+    })(node);
+    return new Map(entries);
+  };
+}
+
+export type BigMap<K, V> = { id: bigint } | Map<K, V>;
+
+function encodeBigMap<K, V>(encodeKey: Encoder<K>, encodeValue: Encoder<V>): Encoder<BigMap<K, V>> {
+  const encode = encodeMap(encodeKey, encodeValue);
+  return (value) => (value instanceof Map ? encode(value) : encodeInt(value.id));
+}
+
+function decodeBigMap<K, V>(decodeKey: Decoder<K>, decodeValue: Decoder<V>): Decoder<BigMap<K, V>> {
+  const decode = decodeMap(decodeKey, decodeValue);
+  return (node) => (!Array.isArray(node) && "int" in node ? { id: decodeInt(node) } : decode(node));
+}
+"#;
+
+// Names used by the prelude, as types or in `encode<Name>` and
+// `decode<Name>`, and the globals it relies on.
+const RESERVED: &[&str] = &[
+    "Micheline", "Encoder", "Decoder", "Unit", "Bool", "Int", "String", "Bytes", "Option", "List", "Map", "BigMap", "Array",
+    "BigInt", "Error", "JSON",
+];
+
+/// A TypeScript type with the expressions of its encoder and decoder.
+struct Type {
+    ts: String,
+    encode: String,
+    decode: String,
+}
+
+impl Type {
+    fn named(name: &str, ts: &str) -> Type {
+        Type { ts: String::from(ts), encode: format!("encode{}", name), decode: format!("decode{}", name) }
+    }
+}
+
+struct Generator {
+    items: Vec<String>,
+    names: Names,
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    matches!(chars.next(), Some(first) if valid(first) && !first.is_ascii_digit()) && chars.all(valid)
+}
+
+/// `name` as a property name.
+fn property(name: &str) -> String {
+    if is_identifier(name) { String::from(name) } else { format!("{:?}", name) }
+}
+
+/// The property `name` of `value`.
+fn access(value: &str, name: &str) -> String {
+    if is_identifier(name) { format!("{}.{}", value, name) } else { format!("{}[{:?}]", value, name) }
+}
+
+impl Generator {
+    fn ty(&mut self, ty: &Node<Primitive>, hint: &str, path: &Path) -> Result<Type, CodegenError> {
+        let (prim, args) = match ty {
+            Node::Prim(prim, args, _) => (*prim, args),
+            _ => return error(path, CodegenErrorKind::InvalidType),
+        };
+        let arg = |generator: &mut Generator, index: usize| match args.get(index) {
+            Some(arg) => generator.ty(arg, hint, &path.child(index)),
+            None => error(path, CodegenErrorKind::InvalidType),
+        };
+        Ok(match prim {
+            T_unit => Type::named("Unit", "undefined"),
+            T_bool => Type::named("Bool", "boolean"),
+            T_int | T_nat | T_mutez => Type::named("Int", "bigint"),
+            T_string | T_address | T_key | T_key_hash | T_signature | T_chain_id | T_timestamp | T_contract
+            | T_tx_rollup_l2_address => Type::named("String", "string"),
+            T_bytes | T_bls12_381_g1 | T_bls12_381_g2 | T_bls12_381_fr | T_chest | T_chest_key => {
+                Type::named("Bytes", "string")
+            }
+            T_option => {
+                // `null` would stand for both `None` and `Some None`.
+                if let Some(Node::Prim(T_option, _, _)) = args.first() {
+                    return error(&path.child(0), CodegenErrorKind::UnsupportedType(T_option));
+                }
+                let inner = arg(self, 0)?;
+                Type {
+                    ts: format!("{} | null", inner.ts),
+                    encode: format!("encodeOption({})", inner.encode),
+                    decode: format!("decodeOption({})", inner.decode),
+                }
+            }
+            T_list | T_set => {
+                let inner = arg(self, 0)?;
+                Type {
+                    ts: format!("Array<{}>", inner.ts),
+                    encode: format!("encodeList({})", inner.encode),
+                    decode: format!("decodeList({})", inner.decode),
+                }
+            }
+            T_map | T_big_map => {
+                let key = arg(self, 0)?;
+                let value = arg(self, 1)?;
+                let name = if prim == T_map { "Map" } else { "BigMap" };
+                Type {
+                    ts: format!("{}<{}, {}>", name, key.ts, value.ts),
+                    encode: format!("encode{}({}, {})", name, key.encode, value.encode),
+                    decode: format!("decode{}({}, {})", name, key.decode, value.decode),
+                }
+            }
+            T_pair | T_or => {
+                let hint = field_annotation(ty).unwrap_or(hint);
+                let name = self.definition(&Definition { name: String::from(hint), ty: ty.clone(), path: path.clone() })?;
+                Type::named(&name, &name)
+            }
+            prim => return error(path, CodegenErrorKind::UnsupportedType(prim)),
+        })
+    }
+
+    /// The declaration of the type `name` of the pair `definition`, and the
+    /// bodies of its encoder and decoder.
+    fn pair(&mut self, name: &str, definition: &Definition) -> Result<(String, String, String), CodegenError> {
+        let mut names = Vec::new();
+        let mut paths = Vec::new();
+        let mut types = Vec::new();
+        for (index, (ty, path)) in pair_fields(&definition.ty, &definition.path)?.into_iter().enumerate() {
+            let annotation = field_annotation(ty).filter(|name| !name.is_empty());
+            let hint = annotation.map(String::from).unwrap_or_else(|| format!("{}_{}", definition.name, index));
+            names.push(annotation.map(String::from));
+            types.push(self.ty(ty, &hint, &path)?);
+            paths.push(path);
+        }
+        let count = types.len();
+        let (mut declaration, mut encode, mut decode) = (String::new(), String::new(), String::new());
+        if names.iter().any(Option::is_some) {
+            let names: Vec<String> =
+                names.into_iter().enumerate().map(|(index, name)| name.unwrap_or_else(|| format!("field{}", index))).collect();
+            distinct(&names, &paths)?;
+            declaration.push_str(&format!("export interface {} {{\n", name));
+            encode.push_str("return pair(\n");
+            decode.push_str(&format!("const fields = unpair(node, {});\n  return {{\n", count));
+            for (index, (field, ty)) in names.iter().zip(&types).enumerate() {
+                declaration.push_str(&format!("  {}: {};\n", property(field), ty.ts));
+                encode.push_str(&format!("    {}({}),\n", ty.encode, access("value", field)));
+                decode.push_str(&format!("    {}: {}(fields[{}]),\n", property(field), ty.decode, index));
+            }
+            declaration.push('}');
+            encode.push_str("  );");
+            decode.push_str("  };");
+        } else {
+            let elements: Vec<&str> = types.iter().map(|ty| ty.ts.as_str()).collect();
+            declaration.push_str(&format!("export type {} = [{}];", name, elements.join(", ")));
+            encode.push_str("return pair(\n");
+            decode.push_str(&format!("const fields = unpair(node, {});\n  return [\n", count));
+            for (index, ty) in types.iter().enumerate() {
+                encode.push_str(&format!("    {}(value[{}]),\n", ty.encode, index));
+                decode.push_str(&format!("    {}(fields[{}]),\n", ty.decode, index));
+            }
+            encode.push_str("  );");
+            decode.push_str("  ];");
+        }
+        Ok((declaration, encode, decode))
+    }
+
+    /// The declaration of the union `name` of the `or` of `definition`, and
+    /// the bodies of its encoder and decoder.
+    fn or(&mut self, name: &str, definition: &Definition) -> Result<(String, String, String), CodegenError> {
+        let variants = or_variants(&definition.ty, &definition.path)?;
+        let count = variants.len();
+        let mut declaration = format!("export type {} =", name);
+        let mut encode = String::from("switch (value.kind) {\n");
+        let mut decode = format!("const [index, value] = unor(node, {});\n  switch (index) {{\n", count);
+        let mut tags = Vec::new();
+        let mut paths = Vec::new();
+        for (index, (ty, path)) in variants.into_iter().enumerate() {
+            let annotation = field_annotation(ty).filter(|name| !name.is_empty());
+            let tag = annotation.map(String::from).unwrap_or_else(|| format!("variant{}", index));
+            tags.push(tag.clone());
+            paths.push(path.clone());
+            let case = if index + 1 < count { format!("case {}:", index) } else { String::from("default:") };
+            if let Node::Prim(T_unit, _, _) = ty {
+                declaration.push_str(&format!("\n  | {{ kind: {:?} }}", tag));
+                encode.push_str(&format!("    case {:?}:\n      return or({}, {}, encodeUnit(undefined));\n", tag, index, count));
+                decode.push_str(&format!("    {}\n      decodeUnit(value);\n      return {{ kind: {:?} }};\n", case, tag));
+                continue;
+            }
+            let hint = annotation.map(String::from).unwrap_or_else(|| format!("{}_{}", definition.name, index));
+            let ty = self.ty(&without_annotations(ty), &hint, &path)?;
+            declaration.push_str(&format!("\n  | {{ kind: {:?}; value: {} }}", tag, ty.ts));
+            encode.push_str(&format!("    case {:?}:\n      return or({}, {}, {}(value.value));\n", tag, index, count, ty.encode));
+            decode.push_str(&format!("    {}\n      return {{ kind: {:?}, value: {}(value) }};\n", case, tag, ty.decode));
+        }
+        // Branches with the same `kind` could not be told apart.
+        distinct(&tags, &paths)?;
+        declaration.push(';');
+        encode.push_str("  }");
+        decode.push_str("  }");
+        Ok((declaration, encode, decode))
+    }
+
+    /// Defines the type of `definition`, with its encoder and decoder, and
+    /// returns its name.
+    fn definition(&mut self, definition: &Definition) -> Result<String, CodegenError> {
+        if let Some(name) = self.names.find(&definition.name, &definition.ty) {
+            return Ok(name);
+        }
+        let name = self.names.name(&definition.name, &definition.ty);
+        // Reserved so that the type comes before the nested ones it uses.
+        let slot = self.items.len();
+        self.items.push(String::new());
+        let (declaration, encode, decode) = match &definition.ty {
+            Node::Prim(T_pair, _, _) => self.pair(&name, definition)?,
+            Node::Prim(T_or, _, _) => self.or(&name, definition)?,
+            ty => {
+                let ty = self.ty(&without_annotations(ty), &definition.name, &definition.path)?;
+                (
+                    format!("export type {} = {};", name, ty.ts),
+                    format!("return {}(value);", ty.encode),
+                    format!("return {}(node);", ty.decode),
+                )
+            }
+        };
+        self.items[slot] = format!(
+            "{}\n\nexport function encode{name}(value: {name}): Micheline {{\n  {}\n}}\n\n\
+             export function decode{name}(node: Micheline): {name} {{\n  {}\n}}\n",
+            declaration,
+            encode,
+            decode,
+            name = name,
+        );
+        Ok(name)
+    }
+}
+
+/// TypeScript source defining the types of `definitions`, in order, along
+/// with the types of their pairs and `or` named by annotations, and
+/// functions `encode<Type>` and `decode<Type>` converting their values.
+pub fn generate(definitions: &[Definition]) -> Result<String, CodegenError> {
+    let mut generator = Generator { items: Vec::new(), names: Names::reserving(RESERVED) };
+    for definition in definitions {
+        generator.definition(definition)?;
+    }
+    let mut output = String::from("// Generated by `micheline::codegen::typescript`.\n\n");
+    output.push_str(PRELUDE);
+    for item in &generator.items {
+        output.push('\n');
+        output.push_str(item);
+    }
+    Ok(output)
+}
+
+/// TypeScript source for the storage and entrypoints of a contract.
+pub fn generate_interface(interface: &Interface) -> Result<String, CodegenError> {
+    let mut definitions = vec![interface.storage.clone()];
+    definitions.extend(interface.entrypoints.iter().cloned());
+    generate(&definitions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::typescript::*;
+    use crate::syntax;
+
+    fn definition(name: &str, source: &str) -> Definition {
+        let ty = syntax::to_node(&syntax::parse(source).unwrap()).unwrap();
+        Definition { name: String::from(name), ty, path: Path(vec![]) }
+    }
+
+    /// The items generated for `definitions`, without the prelude.
+    fn items(definitions: &[Definition]) -> String {
+        let code = generate(definitions).unwrap();
+        String::from(code.split_once(PRELUDE).unwrap().1)
+    }
+
+    #[test]
+    fn declarations() {
+        let code = items(&[definition("storage", "pair (nat %token.id) (or %kind (unit %a) (pair %b int bytes)) (list (option int))")]);
+        assert!(code.contains("export interface Storage {\n  \"token.id\": bigint;\n  kind: Kind;\n  field2: Array<bigint | null>;\n}"));
+        assert!(code.contains("    encodeInt(value[\"token.id\"]),\n"));
+        assert!(code.contains("    field2: decodeList(decodeOption(decodeInt))(fields[2]),\n"));
+        assert!(code.contains("export type Kind =\n  | { kind: \"a\" }\n  | { kind: \"b\"; value: B };"));
+        assert!(code.contains("export type B = [bigint, string];"));
+        assert!(code.contains("      return or(1, 2, encodeB(value.value));\n"));
+        assert!(code.contains("    default:\n      return { kind: \"b\", value: decodeB(value) };\n"));
+
+        // Types are generated once for each name.
+        let code = items(&[definition("a", "pair (int %x) (int %y)"), definition("b", "pair (int %n) (pair %a (int %x) (int %y))")]);
+        assert_eq!(code.matches("export interface A ").count(), 1);
+        assert!(code.contains("export interface B {\n  n: bigint;\n  a: A;\n}"));
+
+        // Types are not named like those of the prelude.
+        let code = items(&[definition("storage", "pair (pair %int nat nat) (or %option (unit %a) (unit %b))")]);
+        assert!(code.contains("export interface Storage {\n  int: Int2;\n  option: Option2;\n}"));
+        assert!(code.contains("export function encodeInt2(value: Int2): Micheline {"));
+        assert!(code.contains("export function decodeOption2(node: Micheline): Option2 {"));
+        assert!(!code.contains("function encodeInt("));
+
+        // Big maps are their id or their bindings.
+        let code = items(&[definition("storage", "big_map address (map nat int)")]);
+        assert!(code.contains("export type Storage = BigMap<string, Map<bigint, bigint>>;"));
+        assert!(code.contains("return decodeBigMap(decodeString, decodeMap(decodeInt, decodeInt))(node);"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            generate(&[definition("storage", "pair nat (option (option int))")]),
+            Err(CodegenError { path: Path(vec![1, 0]), kind: CodegenErrorKind::UnsupportedType(T_option) })
+        );
+        assert_eq!(
+            generate(&[definition("storage", "or int (ticket nat)")]),
+            Err(CodegenError { path: Path(vec![1]), kind: CodegenErrorKind::UnsupportedType(T_ticket) })
+        );
+        let duplicate = |name: &str| Err(CodegenError { path: Path(vec![1]), kind: CodegenErrorKind::DuplicateName(String::from(name)) });
+        assert_eq!(generate(&[definition("storage", "pair (int %a) (nat %a)")]), duplicate("a"));
+        assert_eq!(generate(&[definition("storage", "or (int %a) (nat %a)")]), duplicate("a"));
+        assert_eq!(generate(&[definition("storage", "pair (int %field1) int")]), duplicate("field1"));
+    }
+}
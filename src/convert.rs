@@ -0,0 +1,678 @@
+// Conversions between Rust values and Michelson values, and the Michelson
+// types of Rust types.
+//
+// Structs are right-comb pairs of their fields and enums nested `or` of
+// their variants, with field annotations naming fields and variants. The
+// helpers below implement these encodings for `#[derive(MichelsonType,
+// IntoMicheline, FromMicheline)]`, under the `derive` feature, which derives
+// `ToMicheline` and so `IntoMicheline`.
+//
+// Native types convert to the matching data constructors: integers to `int`
+// or `nat`, `Vec<u8>` to `bytes`, tuples to pairs and `Result` to `or`. `BigMap`
+// is a `big_map` either as the id storage holds or as its bindings.
+// Integers outside of the 32 bits of `Node::Int` are rejected both ways.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::Node;
+use crate::michelson_v1_primitives::Primitive;
+use crate::michelson_v1_primitives::Primitive::*;
+use crate::path::Path;
+
+#[cfg(feature = "derive")]
+pub use micheline_macros::{FromMicheline, IntoMicheline, MichelsonType};
+
+#[derive(Debug, PartialEq)]
+pub enum ConversionErrorKind {
+    /// The value does not have the shape of the type, which is given.
+    UnexpectedValue(Node<Primitive>),
+    /// An integer does not fit in the Rust type, or in `Node::Int`.
+    OutOfRange,
+    /// Duplicate element in a set, or duplicate key in a map.
+    Duplicate,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ConversionError {
+    pub path: Path,
+    pub kind: ConversionErrorKind,
+}
+
+impl ConversionError {
+    /// The error, for a value found at `path` in the converted one.
+    pub fn within(mut self, path: &[usize]) -> Self {
+        self.path.0.splice(0..0, path.iter().copied());
+        self
+    }
+}
+
+/// Rust types with a Michelson counterpart.
+pub trait MichelsonType {
+    fn michelson_type() -> Node<Primitive>;
+
+    /// The type of lists of the type, `list` but for `bytes` as lists of
+    /// `u8`.
+    fn list_type() -> Node<Primitive> {
+        prim(T_list, vec![Self::michelson_type()])
+    }
+}
+
+pub trait ToMicheline {
+    fn to_micheline(&self) -> Result<Node<Primitive>, ConversionError>;
+
+    /// The value of lists of the type, as typed by `list_type`.
+    fn list_to_micheline(items: &[Self]) -> Result<Node<Primitive>, ConversionError> where Self: Sized {
+        let items = items.iter().enumerate().map(|(index, item)| to_micheline_at(&[index], item));
+        Ok(Node::Seq(items.collect::<Result<_, _>>()?))
+    }
+}
+
+/// Consuming conversion, provided for all `ToMicheline` types.
+pub trait IntoMicheline {
+    fn into_micheline(self) -> Result<Node<Primitive>, ConversionError>;
+}
+
+impl<T: ToMicheline> IntoMicheline for T {
+    fn into_micheline(self) -> Result<Node<Primitive>, ConversionError> {
+        self.to_micheline()
+    }
+}
+
+pub trait FromMicheline: MichelsonType + Sized {
+    fn from_micheline(node: &Node<Primitive>) -> Result<Self, ConversionError>;
+
+    /// Lists of the type, as typed by `list_type`.
+    fn list_from_micheline(node: &Node<Primitive>) -> Result<Vec<Self>, ConversionError> {
+        let items = items::<Vec<Self>>(node)?;
+        items.iter().enumerate().map(|(index, item)| convert_at(&[index], item)).collect()
+    }
+}
+
+fn prim(primitive: Primitive, args: Vec<Node<Primitive>>) -> Node<Primitive> {
+    Node::Prim(primitive, args, vec![])
+}
+
+fn mismatch<T: MichelsonType, R>() -> Result<R, ConversionError> {
+    Err(ConversionError { path: Path::root(), kind: ConversionErrorKind::UnexpectedValue(T::michelson_type()) })
+}
+
+/// A value within a converted one, with its path.
+pub type Field<'a> = (Path, &'a Node<Primitive>);
+
+/// Converts a node found at `path` in the converted one.
+pub fn convert_at<T: FromMicheline>(path: &[usize], node: &Node<Primitive>) -> Result<T, ConversionError> {
+    T::from_micheline(node).map_err(|error| error.within(path))
+}
+
+/// Converts a value to the node at `path` in the converted one.
+pub fn to_micheline_at<T: ToMicheline>(path: &[usize], value: &T) -> Result<Node<Primitive>, ConversionError> {
+    value.to_micheline().map_err(|error| error.within(path))
+}
+
+/// Adds an annotation, such as `%field`, to a type.
+pub fn annotate(mut ty: Node<Primitive>, annotation: &str) -> Node<Primitive> {
+    if let Node::Prim(_, _, annots) = &mut ty {
+        annots.push(String::from(annotation));
+    }
+    ty
+}
+
+fn right_comb(
+    mut items: Vec<Node<Primitive>>,
+    empty: Node<Primitive>,
+    combine: impl Fn(Node<Primitive>, Node<Primitive>) -> Node<Primitive>,
+) -> Node<Primitive> {
+    let mut node = match items.pop() {
+        Some(last) => last,
+        None => return empty,
+    };
+    while let Some(item) = items.pop() {
+        node = combine(item, node);
+    }
+    node
+}
+
+/// The type of a right-comb pair of fields: `unit` without fields, the type
+/// of the field if there is only one.
+pub fn pair_type(fields: Vec<Node<Primitive>>) -> Node<Primitive> {
+    right_comb(fields, prim(T_unit, vec![]), |left, right| prim(T_pair, vec![left, right]))
+}
+
+/// The right-comb pair of converted values, as typed by `pair_type`, or the
+/// error of the first value that could not be converted.
+pub fn pair(values: Vec<Result<Node<Primitive>, ConversionError>>) -> Result<Node<Primitive>, ConversionError> {
+    let count = values.len();
+    let mut nodes = Vec::with_capacity(count);
+    for (index, value) in values.into_iter().enumerate() {
+        // Fields are the left of nested pairs, but for the last one.
+        let mut path = vec![1; index];
+        if index + 1 < count {
+            path.push(0);
+        }
+        nodes.push(value.map_err(|error| error.within(&path))?);
+    }
+    Ok(right_comb(nodes, prim(D_Unit, vec![]), |left, right| prim(D_Pair, vec![left, right])))
+}
+
+/// The `count` values of a right-comb pair of type `T`, with their paths.
+/// Combs may be written flat, as in `Pair 1 2 3`.
+pub fn unpair<T: MichelsonType>(node: &Node<Primitive>, count: usize) -> Result<Vec<Field<'_>>, ConversionError> {
+    match (count, node) {
+        (0, Node::Prim(D_Unit, args, _)) if args.is_empty() => return Ok(vec![]),
+        (0, _) => return mismatch::<T, _>(),
+        (1, _) => return Ok(vec![(Path::root(), node)]),
+        _ => {}
+    }
+    let mut fields = Vec::with_capacity(count);
+    let (mut path, mut node) = (Path::root(), node);
+    while fields.len() + 1 < count {
+        let args = match node {
+            Node::Prim(D_Pair, args, _) if args.len() >= 2 && args.len() <= count - fields.len() => args,
+            _ => return mismatch::<T, _>(),
+        };
+        let (last, init) = args.split_last().expect("pair arguments");
+        for (index, arg) in init.iter().enumerate() {
+            fields.push((path.child(index), arg));
+        }
+        path.0.push(init.len());
+        node = last;
+    }
+    fields.push((path, node));
+    Ok(fields)
+}
+
+/// The type of a right comb of `or` over variants: `never` without
+/// variants, the type of the variant if there is only one.
+pub fn or_type(variants: Vec<Node<Primitive>>) -> Node<Primitive> {
+    right_comb(variants, prim(T_never, vec![]), |left, right| prim(T_or, vec![left, right]))
+}
+
+/// The value of variant `index` out of `count`, as typed by `or_type`, from
+/// its converted value.
+pub fn or(
+    index: usize,
+    count: usize,
+    value: Result<Node<Primitive>, ConversionError>,
+) -> Result<Node<Primitive>, ConversionError> {
+    let depth = if index + 1 < count { index + 1 } else { index };
+    let value = value.map_err(|error| error.within(&vec![0; depth]))?;
+    let mut node = if index + 1 < count { prim(D_Left, vec![value]) } else { value };
+    for _ in 0..index {
+        node = prim(D_Right, vec![node]);
+    }
+    Ok(node)
+}
+
+/// The variant index and value of a right comb of `or` of type `T` over
+/// `count` variants, with the path of the value.
+pub fn unor<T: MichelsonType>(node: &Node<Primitive>, count: usize) -> Result<(usize, Path, &Node<Primitive>), ConversionError> {
+    let (mut path, mut node) = (Path::root(), node);
+    for index in 0..count {
+        if index + 1 == count {
+            return Ok((index, path, node));
+        }
+        match node {
+            Node::Prim(D_Left, args, _) if args.len() == 1 => {
+                path.0.push(0);
+                return Ok((index, path, &args[0]));
+            }
+            Node::Prim(D_Right, args, _) if args.len() == 1 => {
+                path.0.push(0);
+                node = &args[0];
+            }
+            _ => break,
+        }
+    }
+    mismatch::<T, _>()
+}
+
+/// Implements the conversion traits for a type without arguments.
+macro_rules! simple {
+    ($ty:ty, $michelson:expr, |$value:ident| $to:expr, |$node:ident| $from:expr) => {
+        impl MichelsonType for $ty {
+            fn michelson_type() -> Node<Primitive> {
+                prim($michelson, vec![])
+            }
+        }
+
+        impl ToMicheline for $ty {
+            fn to_micheline(&self) -> Result<Node<Primitive>, ConversionError> {
+                let $value = self;
+                $to
+            }
+        }
+
+        impl FromMicheline for $ty {
+            fn from_micheline($node: &Node<Primitive>) -> Result<Self, ConversionError> {
+                $from
+            }
+        }
+    };
+}
+
+simple!((), T_unit, |_value| Ok(prim(D_Unit, vec![])), |node| match node {
+    Node::Prim(D_Unit, args, _) if args.is_empty() => Ok(()),
+    _ => mismatch::<Self, _>(),
+});
+
+simple!(bool, T_bool, |value| Ok(prim(if *value { D_True } else { D_False }, vec![])), |node| match node {
+    Node::Prim(D_True, args, _) if args.is_empty() => Ok(true),
+    Node::Prim(D_False, args, _) if args.is_empty() => Ok(false),
+    _ => mismatch::<Self, _>(),
+});
+
+simple!(String, T_string, |value| Ok(Node::String(value.clone())), |node| match node {
+    Node::String(value) => Ok(value.clone()),
+    _ => mismatch::<Self, _>(),
+});
+
+fn int<T: MichelsonType + TryFrom<i32>>(node: &Node<Primitive>) -> Result<T, ConversionError> {
+    match node {
+        Node::Int(value) => T::try_from(*value).map_err(|_| ConversionError { path: Path::root(), kind: ConversionErrorKind::OutOfRange }),
+        _ => mismatch::<T, _>(),
+    }
+}
+
+fn to_int<T: Copy>(value: &T) -> Result<Node<Primitive>, ConversionError> where i32: TryFrom<T> {
+    match i32::try_from(*value) {
+        Ok(value) => Ok(Node::Int(value)),
+        Err(_) => Err(ConversionError { path: Path::root(), kind: ConversionErrorKind::OutOfRange }),
+    }
+}
+
+simple!(i8, T_int, |value| to_int(value), |node| int(node));
+simple!(i16, T_int, |value| to_int(value), |node| int(node));
+simple!(i32, T_int, |value| to_int(value), |node| int(node));
+simple!(i64, T_int, |value| to_int(value), |node| int(node));
+simple!(u16, T_nat, |value| to_int(value), |node| int(node));
+simple!(u32, T_nat, |value| to_int(value), |node| int(node));
+simple!(u64, T_nat, |value| to_int(value), |node| int(node));
+
+// `u8` by hand, for lists of bytes.
+impl MichelsonType for u8 {
+    fn michelson_type() -> Node<Primitive> {
+        prim(T_nat, vec![])
+    }
+
+    fn list_type() -> Node<Primitive> {
+        prim(T_bytes, vec![])
+    }
+}
+
+impl ToMicheline for u8 {
+    fn to_micheline(&self) -> Result<Node<Primitive>, ConversionError> {
+        to_int(self)
+    }
+
+    fn list_to_micheline(items: &[Self]) -> Result<Node<Primitive>, ConversionError> {
+        Ok(Node::Bytes(items.to_vec()))
+    }
+}
+
+impl FromMicheline for u8 {
+    fn from_micheline(node: &Node<Primitive>) -> Result<Self, ConversionError> {
+        int(node)
+    }
+
+    fn list_from_micheline(node: &Node<Primitive>) -> Result<Vec<Self>, ConversionError> {
+        match node {
+            Node::Bytes(bytes) => Ok(bytes.clone()),
+            _ => mismatch::<Vec<u8>, _>(),
+        }
+    }
+}
+
+impl<T: MichelsonType> MichelsonType for Option<T> {
+    fn michelson_type() -> Node<Primitive> {
+        prim(T_option, vec![T::michelson_type()])
+    }
+}
+
+impl<T: ToMicheline> ToMicheline for Option<T> {
+    fn to_micheline(&self) -> Result<Node<Primitive>, ConversionError> {
+        match self {
+            Some(value) => Ok(prim(D_Some, vec![to_micheline_at(&[0], value)?])),
+            None => Ok(prim(D_None, vec![])),
+        }
+    }
+}
+
+impl<T: FromMicheline> FromMicheline for Option<T> {
+    fn from_micheline(node: &Node<Primitive>) -> Result<Self, ConversionError> {
+        match node {
+            Node::Prim(D_Some, args, _) if args.len() == 1 => Ok(Some(convert_at(&[0], &args[0])?)),
+            Node::Prim(D_None, args, _) if args.is_empty() => Ok(None),
+            _ => mismatch::<Self, _>(),
+        }
+    }
+}
+
+impl<T: MichelsonType, E: MichelsonType> MichelsonType for Result<T, E> {
+    fn michelson_type() -> Node<Primitive> {
+        prim(T_or, vec![T::michelson_type(), E::michelson_type()])
+    }
+}
+
+impl<T: ToMicheline, E: ToMicheline> ToMicheline for Result<T, E> {
+    fn to_micheline(&self) -> Result<Node<Primitive>, ConversionError> {
+        match self {
+            Ok(value) => Ok(prim(D_Left, vec![to_micheline_at(&[0], value)?])),
+            Err(error) => Ok(prim(D_Right, vec![to_micheline_at(&[0], error)?])),
+        }
+    }
+}
+
+impl<T: FromMicheline, E: FromMicheline> FromMicheline for Result<T, E> {
+    fn from_micheline(node: &Node<Primitive>) -> Result<Self, ConversionError> {
+        match node {
+            Node::Prim(D_Left, args, _) if args.len() == 1 => Ok(Ok(convert_at(&[0], &args[0])?)),
+            Node::Prim(D_Right, args, _) if args.len() == 1 => Ok(Err(convert_at(&[0], &args[0])?)),
+            _ => mismatch::<Self, _>(),
+        }
+    }
+}
+
+/// Implements the conversion traits for tuples, as right-comb pairs.
+macro_rules! tuple {
+    ($count:literal: $($ty:ident $index:tt),+) => {
+        impl<$($ty: MichelsonType),+> MichelsonType for ($($ty,)+) {
+            fn michelson_type() -> Node<Primitive> {
+                pair_type(vec![$($ty::michelson_type()),+])
+            }
+        }
+
+        impl<$($ty: ToMicheline),+> ToMicheline for ($($ty,)+) {
+            fn to_micheline(&self) -> Result<Node<Primitive>, ConversionError> {
+                pair(vec![$(self.$index.to_micheline()),+])
+            }
+        }
+
+        impl<$($ty: FromMicheline),+> FromMicheline for ($($ty,)+) {
+            fn from_micheline(node: &Node<Primitive>) -> Result<Self, ConversionError> {
+                let fields = unpair::<Self>(node, $count)?;
+                Ok(($(convert_at(&fields[$index].0, fields[$index].1)?,)+))
+            }
+        }
+    };
+}
+
+tuple!(2: A 0, B 1);
+tuple!(3: A 0, B 1, C 2);
+tuple!(4: A 0, B 1, C 2, D 3);
+tuple!(5: A 0, B 1, C 2, D 3, E 4);
+tuple!(6: A 0, B 1, C 2, D 3, E 4, F 5);
+
+fn items<T: MichelsonType>(node: &Node<Primitive>) -> Result<&[Node<Primitive>], ConversionError> {
+    match node {
+        Node::Seq(items) => Ok(items),
+        _ => mismatch::<T, _>(),
+    }
+}
+
+impl<T: MichelsonType> MichelsonType for Vec<T> {
+    fn michelson_type() -> Node<Primitive> {
+        T::list_type()
+    }
+}
+
+impl<T: ToMicheline> ToMicheline for Vec<T> {
+    fn to_micheline(&self) -> Result<Node<Primitive>, ConversionError> {
+        T::list_to_micheline(self)
+    }
+}
+
+impl<T: FromMicheline> FromMicheline for Vec<T> {
+    fn from_micheline(node: &Node<Primitive>) -> Result<Self, ConversionError> {
+        T::list_from_micheline(node)
+    }
+}
+
+impl<T: MichelsonType> MichelsonType for BTreeSet<T> {
+    fn michelson_type() -> Node<Primitive> {
+        prim(T_set, vec![T::michelson_type()])
+    }
+}
+
+impl<T: ToMicheline> ToMicheline for BTreeSet<T> {
+    fn to_micheline(&self) -> Result<Node<Primitive>, ConversionError> {
+        let items = self.iter().enumerate().map(|(index, item)| to_micheline_at(&[index], item));
+        Ok(Node::Seq(items.collect::<Result<_, _>>()?))
+    }
+}
+
+impl<T: FromMicheline + Ord> FromMicheline for BTreeSet<T> {
+    fn from_micheline(node: &Node<Primitive>) -> Result<Self, ConversionError> {
+        let mut set = BTreeSet::new();
+        for (index, item) in items::<Self>(node)?.iter().enumerate() {
+            if !set.insert(convert_at(&[index], item)?) {
+                return Err(ConversionError { path: Path(vec![index]), kind: ConversionErrorKind::Duplicate });
+            }
+        }
+        Ok(set)
+    }
+}
+
+impl<K: MichelsonType, V: MichelsonType> MichelsonType for BTreeMap<K, V> {
+    fn michelson_type() -> Node<Primitive> {
+        prim(T_map, vec![K::michelson_type(), V::michelson_type()])
+    }
+}
+
+impl<K: ToMicheline, V: ToMicheline> ToMicheline for BTreeMap<K, V> {
+    fn to_micheline(&self) -> Result<Node<Primitive>, ConversionError> {
+        let elts = self.iter().enumerate().map(|(index, (key, value))| {
+            Ok(prim(D_Elt, vec![to_micheline_at(&[index, 0], key)?, to_micheline_at(&[index, 1], value)?]))
+        });
+        Ok(Node::Seq(elts.collect::<Result<_, _>>()?))
+    }
+}
+
+/// The map of a sequence of `Elt`, reporting mismatches against `T`.
+fn elts<T: MichelsonType, K: FromMicheline + Ord, V: FromMicheline>(
+    node: &Node<Primitive>,
+) -> Result<BTreeMap<K, V>, ConversionError> {
+    let mut map = BTreeMap::new();
+    for (index, item) in items::<T>(node)?.iter().enumerate() {
+        let (key, value) = match item {
+            Node::Prim(D_Elt, args, _) if args.len() == 2 => (&args[0], &args[1]),
+            _ => return mismatch::<T, _>().map_err(|error| error.within(&[index])),
+        };
+        let key = convert_at(&[index, 0], key)?;
+        if map.contains_key(&key) {
+            return Err(ConversionError { path: Path(vec![index]), kind: ConversionErrorKind::Duplicate });
+        }
+        map.insert(key, convert_at(&[index, 1], value)?);
+    }
+    Ok(map)
+}
+
+impl<K: FromMicheline + Ord, V: FromMicheline> FromMicheline for BTreeMap<K, V> {
+    fn from_micheline(node: &Node<Primitive>) -> Result<Self, ConversionError> {
+        elts::<Self, K, V>(node)
+    }
+}
+
+/// A `big_map`, which storage holds as the `Id` of the map on chain, and
+/// parameters and literals as the `Map` of its bindings.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BigMap<K, V> {
+    Id(i32),
+    Map(BTreeMap<K, V>),
+}
+
+impl<K: MichelsonType, V: MichelsonType> MichelsonType for BigMap<K, V> {
+    fn michelson_type() -> Node<Primitive> {
+        prim(T_big_map, vec![K::michelson_type(), V::michelson_type()])
+    }
+}
+
+impl<K: ToMicheline, V: ToMicheline> ToMicheline for BigMap<K, V> {
+    fn to_micheline(&self) -> Result<Node<Primitive>, ConversionError> {
+        match self {
+            BigMap::Id(id) => Ok(Node::Int(*id)),
+            BigMap::Map(map) => map.to_micheline(),
+        }
+    }
+}
+
+impl<K: FromMicheline + Ord, V: FromMicheline> FromMicheline for BigMap<K, V> {
+    fn from_micheline(node: &Node<Primitive>) -> Result<Self, ConversionError> {
+        match node {
+            Node::Int(id) => Ok(BigMap::Id(*id)),
+            _ => Ok(BigMap::Map(elts::<Self, K, V>(node)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::convert::*;
+    use crate::syntax;
+
+    fn node(source: &str) -> Node<Primitive> {
+        syntax::to_node(&syntax::parse(source).unwrap()).unwrap()
+    }
+
+    fn unexpected(path: Vec<usize>, ty: &str) -> ConversionError {
+        ConversionError { path: Path(path), kind: ConversionErrorKind::UnexpectedValue(node(ty)) }
+    }
+
+    #[test]
+    fn combs() {
+        let fields = vec![node("nat %a"), node("string %b"), node("bool %c")];
+        assert_eq!(pair_type(fields), node("pair (nat %a) (pair (string %b) (bool %c))"));
+        assert_eq!(pair_type(vec![node("nat")]), node("nat"));
+        assert_eq!(pair_type(vec![]), node("unit"));
+        assert_eq!(pair(vec![Ok(Node::Int(1)), Ok(Node::Int(2)), Ok(Node::Int(3))]), Ok(node("Pair 1 (Pair 2 3)")));
+        let out_of_range = || Err(ConversionError { path: Path::root(), kind: ConversionErrorKind::OutOfRange });
+        assert_eq!(
+            pair(vec![Ok(Node::Int(1)), out_of_range(), Ok(Node::Int(3))]),
+            Err(ConversionError { path: Path(vec![1, 0]), kind: ConversionErrorKind::OutOfRange })
+        );
+        assert_eq!(
+            pair(vec![Ok(Node::Int(1)), Ok(Node::Int(2)), out_of_range()]),
+            Err(ConversionError { path: Path(vec![1, 1]), kind: ConversionErrorKind::OutOfRange })
+        );
+
+        let nested = node("Pair 1 (Pair 2 3)");
+        let flat = node("Pair 1 2 3");
+        let mixed = node("Pair 1 (Pair 2 3 4)");
+        assert_eq!(
+            unpair::<bool>(&nested, 3),
+            Ok(vec![(Path(vec![0]), &Node::Int(1)), (Path(vec![1, 0]), &Node::Int(2)), (Path(vec![1, 1]), &Node::Int(3))])
+        );
+        assert_eq!(
+            unpair::<bool>(&flat, 3),
+            Ok(vec![(Path(vec![0]), &Node::Int(1)), (Path(vec![1]), &Node::Int(2)), (Path(vec![2]), &Node::Int(3))])
+        );
+        assert_eq!(unpair::<bool>(&mixed, 4).map(|fields| fields.len()), Ok(4));
+        assert_eq!(unpair::<bool>(&flat, 2), Err(unexpected(vec![], "bool")));
+        assert_eq!(unpair::<bool>(&node("Unit"), 0), Ok(vec![]));
+    }
+
+    #[test]
+    fn ors() {
+        let variants = vec![node("unit %a"), node("nat %b"), node("string %c")];
+        assert_eq!(or_type(variants), node("or (unit %a) (or (nat %b) (string %c))"));
+        assert_eq!(or(0, 3, Ok(Node::Int(1))), Ok(node("Left 1")));
+        assert_eq!(or(1, 3, Ok(Node::Int(1))), Ok(node("Right (Left 1)")));
+        assert_eq!(or(2, 3, Ok(Node::Int(1))), Ok(node("Right (Right 1)")));
+        assert_eq!(or(0, 1, Ok(Node::Int(1))), Ok(Node::Int(1)));
+        let out_of_range = ConversionError { path: Path::root(), kind: ConversionErrorKind::OutOfRange };
+        assert_eq!(or(1, 3, Err(out_of_range)).map_err(|error| error.path), Err(Path(vec![0, 0])));
+
+        let value = node("Right (Left 1)");
+        assert_eq!(unor::<bool>(&value, 3), Ok((1, Path(vec![0, 0]), &Node::Int(1))));
+        let value = node("Right (Right 1)");
+        assert_eq!(unor::<bool>(&value, 3), Ok((2, Path(vec![0, 0]), &Node::Int(1))));
+        assert_eq!(unor::<bool>(&node("Some 1"), 3), Err(unexpected(vec![], "bool")));
+    }
+
+    #[test]
+    fn containers() {
+        let map = BTreeMap::from([(String::from("a"), vec![Some(1), None]), (String::from("b"), vec![])]);
+        assert_eq!(BTreeMap::<String, Vec<Option<i32>>>::michelson_type(), node("map string (list (option int))"));
+        let value = map.clone().into_micheline().unwrap();
+        assert_eq!(value, node("{ Elt \"a\" { Some 1 ; None } ; Elt \"b\" {} }"));
+        assert_eq!(BTreeMap::from_micheline(&value), Ok(map));
+
+        let set = BTreeSet::from([true, false]);
+        assert_eq!(set.clone().into_micheline(), Ok(node("{ False ; True }")));
+        assert_eq!(BTreeSet::from_micheline(&node("{ False ; True }")), Ok(set));
+
+        assert_eq!(
+            BTreeMap::<String, Vec<Option<i32>>>::from_micheline(&node("{ Elt \"a\" { Some 1 ; Some \"x\" } }")),
+            Err(unexpected(vec![0, 1, 1, 0], "int"))
+        );
+        assert_eq!(
+            BTreeMap::<i32, bool>::from_micheline(&node("{ Elt 1 True ; Pair 2 False }")),
+            Err(unexpected(vec![1], "map int bool"))
+        );
+        assert_eq!(
+            BTreeMap::<i32, bool>::from_micheline(&node("{ Elt 1 True ; Elt 1 False }")),
+            Err(ConversionError { path: Path(vec![1]), kind: ConversionErrorKind::Duplicate })
+        );
+        assert_eq!(
+            BTreeSet::<i32>::from_micheline(&node("{ 1 ; 1 }")),
+            Err(ConversionError { path: Path(vec![1]), kind: ConversionErrorKind::Duplicate })
+        );
+
+        assert_eq!(BigMap::<String, i32>::michelson_type(), node("big_map string int"));
+        let big_map = BigMap::Map(BTreeMap::from([(String::from("a"), 1)]));
+        assert_eq!(big_map.to_micheline(), Ok(node("{ Elt \"a\" 1 }")));
+        assert_eq!(BigMap::from_micheline(&node("{ Elt \"a\" 1 }")), Ok(big_map));
+        assert_eq!(BigMap::<String, i32>::Id(7).to_micheline(), Ok(Node::Int(7)));
+        assert_eq!(BigMap::<String, i32>::from_micheline(&Node::Int(7)), Ok(BigMap::Id(7)));
+        assert_eq!(
+            BigMap::<String, i32>::from_micheline(&node("Unit")),
+            Err(unexpected(vec![], "big_map string int"))
+        );
+    }
+
+    fn round_trip<T: ToMicheline + FromMicheline + PartialEq + std::fmt::Debug>(value: T, ty: &str, expected: &str) {
+        assert_eq!(T::michelson_type(), node(ty));
+        assert_eq!(value.to_micheline(), Ok(node(expected)));
+        assert_eq!(T::from_micheline(&node(expected)), Ok(value));
+    }
+
+    #[test]
+    fn natives() {
+        round_trip((), "unit", "Unit");
+        round_trip(true, "bool", "True");
+        round_trip(-5i8, "int", "-5");
+        round_trip(300u16, "nat", "300");
+        round_trip(70000u32, "nat", "70000");
+        round_trip(-70000i64, "int", "-70000");
+        round_trip(70000u64, "nat", "70000");
+        round_trip(String::from("a"), "string", "\"a\"");
+        round_trip(vec![0xcau8, 0xfe], "bytes", "0xcafe");
+        round_trip(vec![vec![1u8]], "list bytes", "{ 0x01 }");
+        round_trip(BTreeSet::from([1u8, 2]), "set nat", "{ 1 ; 2 }");
+        round_trip((1i32, String::from("a"), false), "pair int (pair string bool)", "Pair 1 (Pair \"a\" False)");
+        round_trip(Ok::<i32, String>(1), "or int string", "Left 1");
+        round_trip(Err::<i32, String>(String::from("e")), "or int string", "Right \"e\"");
+        round_trip(vec![Some(((), 1i16))], "list (option (pair unit int))", "{ Some (Pair Unit 1) }");
+
+        assert_eq!(<(i32, i32, i32)>::from_micheline(&node("Pair 1 2 3")), Ok((1, 2, 3)));
+        assert_eq!(u8::from_micheline(&node("256")), Err(ConversionError { path: Path::root(), kind: ConversionErrorKind::OutOfRange }));
+        assert_eq!(u16::from_micheline(&node("-1")), Err(ConversionError { path: Path::root(), kind: ConversionErrorKind::OutOfRange }));
+        assert_eq!(u64::from_micheline(&node("-1")), Err(ConversionError { path: Path::root(), kind: ConversionErrorKind::OutOfRange }));
+        // Values outside of `Node::Int` are rejected rather than truncated.
+        assert_eq!(u32::MAX.to_micheline(), Err(ConversionError { path: Path::root(), kind: ConversionErrorKind::OutOfRange }));
+        assert_eq!(i64::from(i32::MIN).to_micheline(), Ok(Node::Int(i32::MIN)));
+        assert_eq!(
+            vec![(String::from("a"), 1u64), (String::from("b"), u64::MAX)].to_micheline(),
+            Err(ConversionError { path: Path(vec![1, 1]), kind: ConversionErrorKind::OutOfRange })
+        );
+        assert_eq!(
+            BTreeMap::from([(i64::MAX, ())]).to_micheline(),
+            Err(ConversionError { path: Path(vec![0, 0]), kind: ConversionErrorKind::OutOfRange })
+        );
+        assert_eq!(Vec::<u8>::from_micheline(&node("{ 1 }")), Err(unexpected(vec![], "bytes")));
+        assert_eq!(
+            <(i32, Result<bool, ()>)>::from_micheline(&node("Pair 1 (Right 2)")),
+            Err(unexpected(vec![1, 0], "unit"))
+        );
+        assert_eq!(<()>::from_micheline(&node("Unit 1")), Err(unexpected(vec![], "unit")));
+    }
+}
@@ -0,0 +1,226 @@
+// Structural differences between two trees.
+//
+// Trees are compared top-down: primitives with the same name, annotations
+// and number of arguments are compared argument by argument, and sequences
+// element by element. Any other difference replaces the whole subtree.
+
+use crate::{Encodable, Node};
+use crate::michelson_v1_primitives::Primitive;
+use crate::path::Path;
+use crate::syntax;
+
+/// How elements of sequences are paired up.
+pub enum Alignment<P: Encodable> {
+    /// By index; extra elements are inserted or deleted at the end.
+    Position,
+    /// By the key the function extracts from each element, keeping the
+    /// longest common subsequence of keys in order.
+    Key(fn(&Node<P>) -> &Node<P>),
+}
+
+/// Key of map elements: `Elt` by their key, and other elements, such as
+/// those of lists and sets, by their whole value.
+pub fn elt_key(node: &Node<Primitive>) -> &Node<Primitive> {
+    match node {
+        Node::Prim(Primitive::D_Elt, args, _) if args.len() == 2 => &args[0],
+        _ => node,
+    }
+}
+
+/// A difference, with the paths of the subtrees it involves in their trees.
+#[derive(Debug, PartialEq)]
+pub enum Change<'a, P: Encodable> {
+    Inserted { path: Path, node: &'a Node<P> },
+    Deleted { path: Path, node: &'a Node<P> },
+    Modified { old_path: Path, new_path: Path, old: &'a Node<P>, new: &'a Node<P> },
+}
+
+/// Pairs of indices of equal keys, in order, forming a longest common
+/// subsequence.
+fn align<P: Encodable + PartialEq>(old: &[Node<P>], new: &[Node<P>], key: fn(&Node<P>) -> &Node<P>) -> Vec<(usize, usize)> {
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if key(&old[i]) == key(&new[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut pairs = Vec::new();
+    while i < old.len() && j < new.len() {
+        if key(&old[i]) == key(&new[j]) {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+struct Differ<'a, P: Encodable> {
+    alignment: Alignment<P>,
+    // Changes, with the path of their subtree in the tree being turned into
+    // `new` when the previous changes have been applied.
+    changes: Vec<(Change<'a, P>, Path)>,
+}
+
+impl<'a, P: Encodable + PartialEq> Differ<'a, P> {
+    fn diff(&mut self, old: &'a Node<P>, new: &'a Node<P>, old_path: &Path, new_path: &Path) {
+        if old == new {
+            return;
+        }
+        match (old, new) {
+            (Node::Prim(old_prim, old_args, old_annots), Node::Prim(new_prim, new_args, new_annots))
+                if old_prim == new_prim && old_annots == new_annots && old_args.len() == new_args.len() =>
+            {
+                for (index, (old, new)) in old_args.iter().zip(new_args).enumerate() {
+                    self.diff(old, new, &old_path.child(index), &new_path.child(index));
+                }
+            }
+            (Node::Seq(old), Node::Seq(new)) => self.diff_seq(old, new, old_path, new_path),
+            _ => self.changes.push((
+                Change::Modified { old_path: old_path.clone(), new_path: new_path.clone(), old, new },
+                new_path.clone(),
+            )),
+        }
+    }
+
+    fn diff_seq(&mut self, old: &'a [Node<P>], new: &'a [Node<P>], old_path: &Path, new_path: &Path) {
+        let mut pairs = match self.alignment {
+            Alignment::Position => (0..old.len().min(new.len())).map(|index| (index, index)).collect(),
+            Alignment::Key(key) => align(old, new, key),
+        };
+        pairs.push((old.len(), new.len()));
+        let (mut i, mut j) = (0, 0);
+        for (next_i, next_j) in pairs {
+            for (index, node) in old.iter().enumerate().take(next_i).skip(i) {
+                self.changes.push((Change::Deleted { path: old_path.child(index), node }, new_path.child(j)));
+            }
+            for (index, node) in new.iter().enumerate().take(next_j).skip(j) {
+                self.changes.push((Change::Inserted { path: new_path.child(index), node }, new_path.child(index)));
+            }
+            if next_i < old.len() && next_j < new.len() {
+                self.diff(&old[next_i], &new[next_j], &old_path.child(next_i), &new_path.child(next_j));
+            }
+            (i, j) = (next_i + 1, next_j + 1);
+        }
+    }
+}
+
+/// The changes turning `old` into `new`, in prefix order.
+pub fn diff<'a, P: Encodable + PartialEq>(old: &'a Node<P>, new: &'a Node<P>, alignment: Alignment<P>) -> Vec<Change<'a, P>> {
+    diff_in_place(old, new, alignment).into_iter().map(|(change, _)| change).collect()
+}
+
+/// The changes turning `old` into `new`, each with the path of its subtree
+/// once the changes before it have been applied to `old`.
+pub(crate) fn diff_in_place<'a, P: Encodable + PartialEq>(
+    old: &'a Node<P>,
+    new: &'a Node<P>,
+    alignment: Alignment<P>,
+) -> Vec<(Change<'a, P>, Path)> {
+    let mut differ = Differ { alignment, changes: Vec::new() };
+    differ.diff(old, new, &Path::root(), &Path::root());
+    differ.changes
+}
+
+fn render_line(output: &mut String, sign: char, path: &Path, node: &Node<Primitive>) {
+    let prefix = format!("{} {} ", sign, path);
+    let printed = syntax::print(&syntax::from_node(node));
+    let indentation = format!("\n{}", " ".repeat(prefix.len()));
+    output.push_str(&prefix);
+    output.push_str(&printed.replace('\n', &indentation));
+    output.push('\n');
+}
+
+/// Renders changes in Michelson syntax, one `-` line per removed subtree and
+/// one `+` line per added subtree, with their paths.
+pub fn render(changes: &[Change<Primitive>]) -> String {
+    let mut output = String::new();
+    for change in changes {
+        match change {
+            Change::Inserted { path, node } => render_line(&mut output, '+', path, node),
+            Change::Deleted { path, node } => render_line(&mut output, '-', path, node),
+            Change::Modified { old_path, new_path, old, new } => {
+                render_line(&mut output, '-', old_path, old);
+                render_line(&mut output, '+', new_path, new);
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::*;
+
+    fn node(source: &str) -> Node<Primitive> {
+        syntax::to_node(&syntax::parse(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn positions() {
+        let old = node("Pair 1 { 1 ; 2 ; 3 } (Some \"a\")");
+        let new = node("Pair 2 { 1 ; 3 } None");
+        let changes = diff(&old, &new, Alignment::Position);
+        assert_eq!(changes, vec![
+            Change::Modified { old_path: Path(vec![0]), new_path: Path(vec![0]), old: &Node::Int(1), new: &Node::Int(2) },
+            Change::Modified { old_path: Path(vec![1, 1]), new_path: Path(vec![1, 1]), old: &Node::Int(2), new: &Node::Int(3) },
+            Change::Deleted { path: Path(vec![1, 2]), node: &Node::Int(3) },
+            Change::Modified { old_path: Path(vec![2]), new_path: Path(vec![2]), old: &old.get(&[2]).unwrap().clone(), new: &node("None") },
+        ]);
+        assert_eq!(diff(&old, &old, Alignment::Position), vec![]);
+    }
+
+    #[test]
+    fn keys() {
+        let old = node("{ Elt \"a\" 1 ; Elt \"b\" 2 ; Elt \"d\" 4 }");
+        let new = node("{ Elt \"b\" 20 ; Elt \"c\" 3 ; Elt \"d\" 4 }");
+        let changes = diff(&old, &new, Alignment::Key(elt_key));
+        assert_eq!(changes, vec![
+            Change::Deleted { path: Path(vec![0]), node: &node("Elt \"a\" 1") },
+            Change::Modified { old_path: Path(vec![1, 1]), new_path: Path(vec![0, 1]), old: &Node::Int(2), new: &Node::Int(20) },
+            Change::Inserted { path: Path(vec![1]), node: &node("Elt \"c\" 3") },
+        ]);
+        assert_eq!(
+            render(&changes),
+            "- /0 Elt \"a\" 1\n- /1/1 2\n+ /0/1 20\n+ /1 Elt \"c\" 3\n"
+        );
+
+        // Lists are aligned on their values.
+        let (old, new) = (node("{ 1 ; 2 ; 3 }"), node("{ 0 ; 1 ; 3 }"));
+        let changes = diff(&old, &new, Alignment::Key(elt_key));
+        assert_eq!(changes, vec![
+            Change::Inserted { path: Path(vec![0]), node: &Node::Int(0) },
+            Change::Deleted { path: Path(vec![1]), node: &Node::Int(2) },
+        ]);
+    }
+
+    #[test]
+    fn rendering() {
+        let long = format!("{{ {} }}", ["PUSH string \"a long string to force a line break\""; 3].join(" ; "));
+        let (empty, unit, long) = (node("{}"), node("Unit"), node(&long));
+        let changes = diff(&empty, &long, Alignment::Position);
+        assert_eq!(render(&changes), [
+            "+ /0 PUSH string \"a long string to force a line break\"",
+            "+ /1 PUSH string \"a long string to force a line break\"",
+            "+ /2 PUSH string \"a long string to force a line break\"",
+            "",
+        ].join("\n"));
+        let changes = diff(&unit, &long, Alignment::Position);
+        assert_eq!(render(&changes), [
+            "- / Unit",
+            "+ / { PUSH string \"a long string to force a line break\" ;",
+            "      PUSH string \"a long string to force a line break\" ;",
+            "      PUSH string \"a long string to force a line break\" }",
+            "",
+        ].join("\n"));
+    }
+}
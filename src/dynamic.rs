@@ -0,0 +1,149 @@
+// Primitives from unknown dialects.
+//
+// A `DynPrimitive` is a bare wire tag: decoding never rejects it, so any
+// canonically encoded expression survives a decoding and re-encoding round
+// trip byte for byte.
+// Names are looked up at runtime in a `Registry`.
+
+use std::collections::HashMap;
+
+use crate::Encodable;
+use crate::michelson_v1_primitives::Primitive;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct DynPrimitive(pub u8);
+
+impl DynPrimitive {
+    /// The Michelson primitive with the same tag, if any.
+    pub fn primitive(&self) -> Option<Primitive> {
+        Primitive::from_int_enum(self.0)
+    }
+}
+
+impl From<Primitive> for DynPrimitive {
+    fn from(primitive: Primitive) -> Self {
+        DynPrimitive(primitive.to_int_enum())
+    }
+}
+
+impl Encodable for DynPrimitive {
+    fn encode_to_buffer(&self, buffer: &mut Vec<u8>) -> usize {
+        buffer.push(self.0);
+        1
+    }
+
+    fn decode_from_buffer(buffer: &[u8]) -> Option<(Self, usize)> where Self: Sized {
+        buffer.first().map(|tag| (DynPrimitive(*tag), 1))
+    }
+}
+
+/// Names of the primitives of a dialect, by tag.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Registry {
+    names: HashMap<u8, String>,
+    tags: HashMap<String, u8>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// A registry of the Michelson primitives.
+    pub fn michelson() -> Self {
+        let mut registry = Registry::new();
+        for tag in 0..=u8::MAX {
+            if let Some(primitive) = Primitive::from_int_enum(tag) {
+                registry.register(tag, primitive.name());
+            }
+        }
+        registry
+    }
+
+    /// Names the primitive tagged `tag`, replacing any previous name.
+    pub fn register(&mut self, tag: u8, name: &str) {
+        if let Some(previous) = self.names.insert(tag, String::from(name)) {
+            self.tags.remove(&previous);
+        }
+        if let Some(previous) = self.tags.insert(String::from(name), tag) {
+            if previous != tag {
+                self.names.remove(&previous);
+            }
+        }
+    }
+
+    pub fn name(&self, primitive: &DynPrimitive) -> Option<&str> {
+        self.names.get(&primitive.0).map(String::as_str)
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<DynPrimitive> {
+        self.tags.get(name).map(|tag| DynPrimitive(*tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Node;
+    use crate::dynamic::*;
+
+    #[test]
+    fn unknown_tags() {
+        // `Pair 1 (<0xf0> "a")`, where 0xf0 is not a Michelson primitive.
+        let blob = b"\x07\x07\x00\x01\x05\xf0\x01\x00\x00\x00\x01a";
+        assert!(Node::<Primitive>::from(blob).is_err());
+
+        let node = Node::<DynPrimitive>::from(blob).unwrap();
+        assert_eq!(
+            node,
+            Node::Prim(DynPrimitive(7), vec![
+                Node::Int(1),
+                Node::Prim(DynPrimitive(0xf0), vec![Node::String(String::from("a"))], vec![]),
+            ], vec![])
+        );
+        assert_eq!(node.encode(), blob);
+    }
+
+    #[test]
+    fn malformed_blobs() {
+        use crate::Error;
+
+        let blobs: [(&[u8], Error); 9] = [
+            (b"", Error::OutOfBounds),
+            // Truncated integers.
+            (b"\x00", Error::OutOfBounds),
+            (b"\x00\x80", Error::InvalidInteger),
+            // Integers of more than 32 bits.
+            (b"\x00\xff\xff\xff\xff\xff\xff\x01", Error::InvalidInteger),
+            (b"\x00\xbf\xff\xff\xff\x1f", Error::InvalidInteger),
+            // Negative and truncated lengths.
+            (b"\x01\xff\xff\xff\xff", Error::InvalidString),
+            (b"\x02\x80\x00\x00\x00", Error::InvalidList),
+            (b"\x0a\x00\x00\x00\x02\x00", Error::InvalidString),
+            // A primitive without its argument.
+            (b"\x05\x07", Error::OutOfBounds),
+        ];
+        for (blob, error) in blobs {
+            assert_eq!(Node::<DynPrimitive>::from(blob), Err(error), "{:02x?}", blob);
+        }
+        assert_eq!(Node::<Primitive>::from(b"\x03"), Err(Error::InvalidPrimitive));
+        assert_eq!(Node::<DynPrimitive>::from(b"\x00\xff\xff\xff\xff\x0f"), Ok(Node::Int(-i32::MAX)));
+    }
+
+    #[test]
+    fn registry() {
+        let mut registry = Registry::michelson();
+        assert_eq!(registry.name(&DynPrimitive::from(Primitive::D_Pair)), Some("Pair"));
+        assert_eq!(registry.lookup("LAMBDA_REC"), Some(DynPrimitive(153)));
+        assert_eq!(registry.name(&DynPrimitive(0xf0)), None);
+        assert_eq!(DynPrimitive(0xf0).primitive(), None);
+        assert_eq!(DynPrimitive(7).primitive(), Some(Primitive::D_Pair));
+
+        registry.register(0xf0, "FUTURE");
+        assert_eq!(registry.name(&DynPrimitive(0xf0)), Some("FUTURE"));
+        registry.register(0xf0, "RENAMED");
+        assert_eq!(registry.lookup("FUTURE"), None);
+        assert_eq!(registry.lookup("RENAMED"), Some(DynPrimitive(0xf0)));
+        registry.register(0xf1, "RENAMED");
+        assert_eq!(registry.name(&DynPrimitive(0xf0)), None);
+    }
+}
@@ -0,0 +1,1725 @@
+// Interpreter for Michelson scripts, operating directly on `Node<Primitive>`.
+//
+// Scripts and their inputs are typechecked first, then values are converted
+// to an internal canonical form in which comparison and packing do not
+// depend on types: addresses, keys, signatures and chain ids are bytes,
+// timestamps are integers, pairs are binary and lambdas carry their type as
+// `LAMBDA arg ret code`. Results are converted back to readable values.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::Node;
+use crate::base58;
+use crate::hash;
+use crate::michelson_v1_primitives::Primitive;
+use crate::michelson_v1_primitives::Primitive::*;
+use crate::path::Path;
+use crate::typecheck::{
+    self, address_bytes, chain_id_bytes, find_entrypoint, key_bytes, key_hash_bytes, normalize_type, operand_types,
+    pair_components, script_section, signature_bytes, timestamp_seconds, typecheck_data, TypeError,
+};
+
+mod gas;
+pub use gas::{CostTable, GasMeter};
+
+/// Verifies a signature given the binary key, signature and message.
+pub type SignatureChecker = fn(&[u8], &[u8], &[u8]) -> bool;
+
+/// Blockchain state visible to the executed script.
+pub struct Context {
+    pub amount: i32,
+    pub balance: i32,
+    pub sender: String,
+    pub source: String,
+    pub self_address: String,
+    /// Seconds since the Unix epoch.
+    pub now: i32,
+    pub level: i32,
+    pub chain_id: String,
+    pub min_block_time: i32,
+    /// Voting power of bakers, by key hash.
+    pub voting_powers: BTreeMap<String, i32>,
+    /// Parameter types of other contracts, by address, for `CONTRACT`.
+    pub contracts: BTreeMap<String, Node<Primitive>>,
+    /// Signature verification for `CHECK_SIGNATURE`.
+    pub check_signature: Option<SignatureChecker>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            amount: 0,
+            balance: 0,
+            sender: String::from("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx"),
+            source: String::from("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx"),
+            self_address: String::from("KT1BEqzn5Wx8uJrZNvuS9DVHmLvG9td3fDLi"),
+            now: 0,
+            level: 0,
+            chain_id: String::from("NetXdQprcVkpaWU"),
+            min_block_time: 1,
+            voting_powers: BTreeMap::new(),
+            contracts: BTreeMap::new(),
+            check_signature: None,
+        }
+    }
+}
+
+/// Result of a successful execution.
+///
+/// Operations are represented by the instruction that emitted them:
+/// `TRANSFER_TOKENS parameter amount destination`, `SET_DELEGATE delegate`,
+/// `CREATE_CONTRACT delegate amount storage script address` and
+/// `EMIT value [type]` with the event tag as annotation.
+#[derive(Debug, PartialEq)]
+pub struct Execution {
+    pub operations: Vec<Node<Primitive>>,
+    pub storage: Node<Primitive>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RuntimeErrorKind {
+    IllTyped(TypeError),
+    /// A context field is not a valid address, chain id or key hash.
+    InvalidContext,
+    /// The script failed with the given value.
+    FailWith(Node<Primitive>),
+    Overflow,
+    /// The instruction is not supported by this interpreter.
+    Unsupported(Primitive),
+    /// Big maps given by identifier cannot be accessed.
+    UnknownBigMap(i32),
+    /// The execution consumed more gas than allowed by its meter.
+    GasExhausted,
+    /// A value does not have the shape required by an instruction. This
+    /// cannot happen for typechecked scripts.
+    InvalidValue,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError {
+    pub path: Path,
+    pub kind: RuntimeErrorKind,
+}
+
+fn error<T>(path: &[usize], kind: RuntimeErrorKind) -> Result<T, RuntimeError> {
+    Err(RuntimeError { path: Path::from(path), kind })
+}
+
+fn ill_typed(error: TypeError) -> RuntimeError {
+    RuntimeError { path: Path::root(), kind: RuntimeErrorKind::IllTyped(error) }
+}
+
+fn prim(prim: Primitive, args: Vec<Node<Primitive>>) -> Node<Primitive> {
+    Node::Prim(prim, args, vec![])
+}
+
+fn boolean(value: bool) -> Node<Primitive> {
+    prim(if value { D_True } else { D_False }, vec![])
+}
+
+fn some(value: Node<Primitive>) -> Node<Primitive> {
+    prim(D_Some, vec![value])
+}
+
+fn none() -> Node<Primitive> {
+    prim(D_None, vec![])
+}
+
+fn pair(left: Node<Primitive>, right: Node<Primitive>) -> Node<Primitive> {
+    prim(D_Pair, vec![left, right])
+}
+
+/// Converts a typechecked value of a normalized type to canonical form.
+pub(crate) fn canonicalize(ty: &Node<Primitive>, value: &Node<Primitive>) -> Result<Node<Primitive>, RuntimeErrorKind> {
+    let (type_prim, args) = match ty {
+        Node::Prim(prim, args, _) => (prim, &args[..]),
+        _ => return Err(RuntimeErrorKind::InvalidValue),
+    };
+    let bytes = |decode: fn(&Node<Primitive>) -> Option<Vec<u8>>| {
+        decode(value).map(Node::Bytes).ok_or(RuntimeErrorKind::InvalidValue)
+    };
+
+    match (type_prim, args, value) {
+        (T_address | T_contract, _, _) => bytes(address_bytes),
+        (T_key, _, _) => bytes(key_bytes),
+        (T_key_hash, _, _) => bytes(key_hash_bytes),
+        (T_signature, _, _) => bytes(signature_bytes),
+        (T_chain_id, _, _) => bytes(chain_id_bytes),
+        (T_tx_rollup_l2_address, _, Node::String(s)) => {
+            base58::decode_check(s, base58::TZ4, 20).map(Node::Bytes).ok_or(RuntimeErrorKind::InvalidValue)
+        }
+        (T_timestamp, _, _) => {
+            let seconds = timestamp_seconds(value).ok_or(RuntimeErrorKind::InvalidValue)?;
+            i32::try_from(seconds).map(Node::Int).map_err(|_| RuntimeErrorKind::Overflow)
+        }
+        (T_option, [inner], Node::Prim(D_Some, v, _)) => Ok(some(canonicalize(inner, &v[0])?)),
+        (T_or, [left, _], Node::Prim(D_Left, v, _)) => Ok(prim(D_Left, vec![canonicalize(left, &v[0])?])),
+        (T_or, [_, right], Node::Prim(D_Right, v, _)) => Ok(prim(D_Right, vec![canonicalize(right, &v[0])?])),
+        (T_pair, [left, right], _) => {
+            let components = pair_components(value).ok_or(RuntimeErrorKind::InvalidValue)?;
+            let rest = match components {
+                [_, rest] => rest.clone(),
+                _ => prim(D_Pair, components[1..].to_vec()),
+            };
+            Ok(pair(canonicalize(left, &components[0])?, canonicalize(right, &rest)?))
+        }
+        (T_ticket, [content], _) => {
+            let ty = prim(T_pair, vec![prim(T_address, vec![]), prim(T_pair, vec![content.clone(), prim(T_nat, vec![])])]);
+            match value {
+                Node::Prim(D_Ticket, v, _) => canonicalize(&ty, &prim(D_Pair, vec![v[0].clone(), v[2].clone(), v[3].clone()])),
+                _ => canonicalize(&ty, value),
+            }
+        }
+        (T_list | T_set, [element], Node::Seq(items)) => {
+            Ok(Node::Seq(items.iter().map(|item| canonicalize(element, item)).collect::<Result<_, _>>()?))
+        }
+        (T_map | T_big_map, [key, value_type], Node::Seq(items)) => {
+            let mut elements = Vec::new();
+            for item in items {
+                match item {
+                    Node::Prim(D_Elt, args, _) if args.len() == 2 => {
+                        elements.push(prim(D_Elt, vec![canonicalize(key, &args[0])?, canonicalize(value_type, &args[1])?]))
+                    }
+                    _ => return Err(RuntimeErrorKind::InvalidValue),
+                }
+            }
+            Ok(Node::Seq(elements))
+        }
+        (T_lambda, [argument, result], Node::Seq(_)) => Ok(prim(I_LAMBDA, vec![argument.clone(), result.clone(), value.clone()])),
+        (T_lambda, [argument, result], Node::Prim(D_Lambda_rec, code, _)) => {
+            Ok(prim(I_LAMBDA_REC, vec![argument.clone(), result.clone(), code[0].clone()]))
+        }
+        (_, _, Node::Prim(constructor, args, _)) => Ok(prim(*constructor, args.clone())),
+        _ => Ok(value.clone()),
+    }
+}
+
+/// Converts a canonical value back into a value accepted by the typechecker,
+/// by replacing lambdas with their code.
+fn packable(value: &Node<Primitive>) -> Node<Primitive> {
+    match value {
+        Node::Prim(I_LAMBDA, args, _) if args.len() == 3 => args[2].clone(),
+        Node::Prim(I_LAMBDA_REC, args, _) if args.len() == 3 => prim(D_Lambda_rec, vec![args[2].clone()]),
+        Node::Prim(prim, args, annot) => Node::Prim(*prim, args.iter().map(packable).collect(), annot.clone()),
+        Node::Seq(items) => Node::Seq(items.iter().map(packable).collect()),
+        other => other.clone(),
+    }
+}
+
+fn address_to_string(bytes: &[u8]) -> Option<String> {
+    if bytes.len() < 22 {
+        return None;
+    }
+    let contract = match (bytes[0], bytes[1]) {
+        (0, tag @ 0..=3) => {
+            let prefix = [base58::TZ1, base58::TZ2, base58::TZ3, base58::TZ4][tag as usize];
+            base58::encode_check(prefix, &bytes[2..22])
+        }
+        (tag @ 1..=3, _) => {
+            let prefix = [base58::KT1, base58::TXR1, base58::SR1][tag as usize - 1];
+            base58::encode_check(prefix, &bytes[1..21])
+        }
+        _ => return None,
+    };
+    match std::str::from_utf8(&bytes[22..]).ok()? {
+        "" => Some(contract),
+        entrypoint => Some(format!("{}%{}", contract, entrypoint)),
+    }
+}
+
+/// Converts a canonical value of a normalized type to its readable form.
+pub(crate) fn to_readable(ty: &Node<Primitive>, value: &Node<Primitive>) -> Node<Primitive> {
+    let (type_prim, args) = match ty {
+        Node::Prim(prim, args, _) => (prim, &args[..]),
+        _ => return value.clone(),
+    };
+    let readable = match (type_prim, args, value) {
+        (T_address | T_contract, _, Node::Bytes(b)) => address_to_string(b),
+        (T_key_hash, _, Node::Bytes(b)) if b.len() == 21 && b[0] <= 3 => {
+            let prefix = [base58::TZ1, base58::TZ2, base58::TZ3, base58::TZ4][b[0] as usize];
+            Some(base58::encode_check(prefix, &b[1..]))
+        }
+        (T_key, _, Node::Bytes(b)) => match b.first() {
+            Some(0) => Some(base58::encode_check(base58::EDPK, &b[1..])),
+            Some(1) => Some(base58::encode_check(base58::SPPK, &b[1..])),
+            Some(2) => Some(base58::encode_check(base58::P2PK, &b[1..])),
+            Some(3) => Some(base58::encode_check(base58::BLPK, &b[1..])),
+            _ => None,
+        },
+        (T_signature, _, Node::Bytes(b)) => {
+            Some(base58::encode_check(if b.len() == 96 { base58::BLSIG } else { base58::SIG }, b))
+        }
+        (T_chain_id, _, Node::Bytes(b)) => Some(base58::encode_check(base58::NET, b)),
+        (T_tx_rollup_l2_address, _, Node::Bytes(b)) => Some(base58::encode_check(base58::TZ4, b)),
+        (T_timestamp, _, Node::Int(seconds)) => Some(typecheck::format_timestamp(*seconds as i64)),
+        _ => None,
+    };
+    if let Some(readable) = readable {
+        return Node::String(readable);
+    }
+
+    match (type_prim, args, value) {
+        (T_option, [inner], Node::Prim(D_Some, v, _)) => some(to_readable(inner, &v[0])),
+        (T_or, [left, _], Node::Prim(D_Left, v, _)) => prim(D_Left, vec![to_readable(left, &v[0])]),
+        (T_or, [_, right], Node::Prim(D_Right, v, _)) => prim(D_Right, vec![to_readable(right, &v[0])]),
+        (T_pair, [left, right], Node::Prim(D_Pair, v, _)) => pair(to_readable(left, &v[0]), to_readable(right, &v[1])),
+        (T_ticket, [content], _) => {
+            let ty = prim(T_pair, vec![prim(T_address, vec![]), prim(T_pair, vec![content.clone(), prim(T_nat, vec![])])]);
+            to_readable(&ty, value)
+        }
+        (T_list | T_set, [element], Node::Seq(items)) => Node::Seq(items.iter().map(|item| to_readable(element, item)).collect()),
+        (T_map | T_big_map, [key, value_type], Node::Seq(items)) => Node::Seq(
+            items
+                .iter()
+                .map(|item| match item {
+                    Node::Prim(D_Elt, v, _) => prim(D_Elt, vec![to_readable(key, &v[0]), to_readable(value_type, &v[1])]),
+                    other => other.clone(),
+                })
+                .collect(),
+        ),
+        _ => packable(value),
+    }
+}
+
+fn constructor_rank(prim: &Primitive) -> u8 {
+    match prim {
+        D_True | D_Some | D_Right => 1,
+        _ => 0,
+    }
+}
+
+/// Compares two canonical values of the same comparable type.
+pub(crate) fn compare_values(a: &Node<Primitive>, b: &Node<Primitive>) -> Ordering {
+    match (a, b) {
+        (Node::Int(a), Node::Int(b)) => a.cmp(b),
+        (Node::String(a), Node::String(b)) => a.cmp(b),
+        (Node::Bytes(a), Node::Bytes(b)) => a.cmp(b),
+        (Node::Prim(a, a_args, _), Node::Prim(b, b_args, _)) => constructor_rank(a)
+            .cmp(&constructor_rank(b))
+            .then_with(|| {
+                a_args
+                    .iter()
+                    .zip(b_args.iter())
+                    .map(|(a, b)| compare_values(a, b))
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            }),
+        _ => Ordering::Equal,
+    }
+}
+
+fn elt_key(elt: &Node<Primitive>) -> &Node<Primitive> {
+    match elt {
+        Node::Prim(D_Elt, args, _) => &args[0],
+        other => other,
+    }
+}
+
+/// Position of `key` in a sorted set or map literal.
+fn search(items: &[Node<Primitive>], key: &Node<Primitive>) -> Result<usize, usize> {
+    items.binary_search_by(|item| compare_values(elt_key(item), key))
+}
+
+fn comb_get(comb: Node<Primitive>, n: usize) -> Option<Node<Primitive>> {
+    match (n, comb) {
+        (0, comb) => Some(comb),
+        (1, Node::Prim(D_Pair, mut args, _)) if args.len() == 2 => Some(args.swap_remove(0)),
+        (_, Node::Prim(D_Pair, mut args, _)) if args.len() == 2 => comb_get(args.pop()?, n - 2),
+        _ => None,
+    }
+}
+
+fn comb_update(comb: Node<Primitive>, n: usize, value: Node<Primitive>) -> Option<Node<Primitive>> {
+    match (n, comb) {
+        (0, _) => Some(value),
+        (1, Node::Prim(D_Pair, mut args, _)) if args.len() == 2 => {
+            args[0] = value;
+            Some(prim(D_Pair, args))
+        }
+        (_, Node::Prim(D_Pair, mut args, _)) if args.len() == 2 => {
+            let right = args.pop()?;
+            args.push(comb_update(right, n - 2, value)?);
+            Some(prim(D_Pair, args))
+        }
+        _ => None,
+    }
+}
+
+/// Bitwise operation on bytes aligned on their last byte. `AND` keeps the
+/// length of the shortest operand, `OR` and `XOR` that of the longest.
+fn bytes_bitwise(instruction: &Primitive, a: &[u8], b: &[u8]) -> Vec<u8> {
+    let length = if *instruction == I_AND { a.len().min(b.len()) } else { a.len().max(b.len()) };
+    let byte = |bytes: &[u8], index: usize| {
+        let offset = length - index;
+        if offset <= bytes.len() { bytes[bytes.len() - offset] } else { 0 }
+    };
+    (0..length)
+        .map(|index| match instruction {
+            I_AND => byte(a, index) & byte(b, index),
+            I_OR => byte(a, index) | byte(b, index),
+            _ => byte(a, index) ^ byte(b, index),
+        })
+        .collect()
+}
+
+/// Shifts bytes as a big-endian number, growing them by the bytes needed.
+fn bytes_shift_left(bytes: &[u8], shift: usize) -> Vec<u8> {
+    let (byte_shift, bit_shift) = (shift / 8, (shift % 8) as u32);
+    let mut extended = vec![0];
+    extended.extend_from_slice(bytes);
+    extended.extend(std::iter::repeat_n(0, byte_shift));
+    let mut result: Vec<u8> = (0..extended.len())
+        .map(|index| {
+            let carry = extended.get(index + 1).map(|next| ((*next as u16) << bit_shift >> 8) as u8).unwrap_or(0);
+            ((extended[index] as u16) << bit_shift) as u8 | carry
+        })
+        .collect();
+    if bit_shift == 0 {
+        result.remove(0);
+    }
+    result
+}
+
+/// Shifts bytes as a big-endian number, dropping the bytes shifted out.
+fn bytes_shift_right(bytes: &[u8], shift: usize) -> Vec<u8> {
+    let (byte_shift, bit_shift) = (shift / 8, (shift % 8) as u32);
+    if byte_shift >= bytes.len() {
+        return vec![];
+    }
+    let kept = &bytes[..bytes.len() - byte_shift];
+    (0..kept.len())
+        .map(|index| {
+            let carry = if index == 0 { 0 } else { ((kept[index - 1] as u16) << 8 >> bit_shift) as u8 };
+            (kept[index] >> bit_shift) | carry
+        })
+        .collect()
+}
+
+/// Reads bytes as a big-endian number, in two's complement if `signed`.
+fn bytes_to_int(bytes: &[u8], signed: bool) -> Option<i32> {
+    let negative = signed && bytes.first().is_some_and(|byte| byte & 0x80 != 0);
+    let padding = if negative { 0xff } else { 0 };
+    let significant = &bytes[bytes.iter().take_while(|byte| **byte == padding).count()..];
+    if significant.len() > 4 {
+        return None;
+    }
+    let value = significant.iter().fold(if negative { -1i64 } else { 0 }, |value, byte| (value << 8) | *byte as i64);
+    i32::try_from(value).ok()
+}
+
+/// The shortest big-endian encoding of `value`, in two's complement if
+/// `signed`, as `BYTES` does. Zero has no bytes.
+fn int_to_bytes(value: i32, signed: bool) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let padding = if value < 0 { 0xff } else { 0 };
+    let mut start = bytes.iter().take_while(|byte| **byte == padding).count();
+    // Keeps a byte whose sign bit is that of the value.
+    if signed && value != 0 && (start == 4 || (bytes[start] & 0x80 != 0) != (value < 0)) {
+        start -= 1;
+    }
+    bytes[start..].to_vec()
+}
+
+fn encoded_size(value: &Node<Primitive>) -> u64 {
+    value.encode_to_buffer(&mut Vec::new()) as u64
+}
+
+fn checked(value: Option<i32>, path: &[usize]) -> Result<Node<Primitive>, RuntimeError> {
+    match value {
+        Some(value) => Ok(Node::Int(value)),
+        None => error(path, RuntimeErrorKind::Overflow),
+    }
+}
+
+struct Interpreter<'a> {
+    context: &'a Context,
+    /// The executed script, for `SELF`, `VIEW` and `CONTRACT` on self.
+    script: &'a Node<Primitive>,
+    parameter_type: &'a Node<Primitive>,
+    /// Storage at the beginning of the execution, as seen by views.
+    storage: Node<Primitive>,
+    self_address: Vec<u8>,
+    origination_nonce: u32,
+    gas: &'a mut GasMeter,
+    /// The script code, views and lambdas being run, innermost last.
+    frames: Vec<Frame>,
+}
+
+/// Code being run on a stack of type `input` (top first), from the path
+/// `base`, to type its instructions when values are not enough to.
+struct Frame {
+    code: Node<Primitive>,
+    input: Vec<Node<Primitive>>,
+    base: usize,
+    /// Operand types of the code, computed when first needed.
+    operands: Option<Vec<(Vec<usize>, Node<Primitive>)>>,
+}
+
+type Stack = Vec<Node<Primitive>>;
+
+fn pop(stack: &mut Stack, path: &[usize]) -> Result<Node<Primitive>, RuntimeError> {
+    match stack.pop() {
+        Some(value) => Ok(value),
+        None => error(path, RuntimeErrorKind::InvalidValue),
+    }
+}
+
+fn invalid<T>(path: &[usize]) -> Result<T, RuntimeError> {
+    error(path, RuntimeErrorKind::InvalidValue)
+}
+
+fn natural_argument(args: &[Node<Primitive>], index: usize, default: usize) -> usize {
+    match args.get(index) {
+        Some(Node::Int(n)) => *n as usize,
+        _ => default,
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    fn consume(&mut self, milligas: u64, path: &[usize]) -> Result<(), RuntimeError> {
+        if self.gas.consume(milligas) {
+            Ok(())
+        } else {
+            error(path, RuntimeErrorKind::GasExhausted)
+        }
+    }
+
+    fn run(&mut self, code: &Node<Primitive>, stack: &mut Stack, path: &mut Vec<usize>) -> Result<(), RuntimeError> {
+        match code {
+            Node::Seq(instructions) => {
+                for (index, instruction) in instructions.iter().enumerate() {
+                    path.push(index);
+                    self.run(instruction, stack, path)?;
+                    path.pop();
+                }
+                Ok(())
+            }
+            Node::Prim(prim, args, annot) => {
+                self.consume(self.gas.costs.step + self.gas.costs.instruction(prim), path)?;
+                self.step(prim, args, annot, stack, path)
+            }
+            _ => invalid(path),
+        }
+    }
+
+    fn run_block(&mut self, args: &[Node<Primitive>], index: usize, stack: &mut Stack, path: &mut Vec<usize>) -> Result<(), RuntimeError> {
+        path.push(index);
+        self.run(&args[index], stack, path)?;
+        path.pop();
+        Ok(())
+    }
+
+    fn run_frame(&mut self, code: &Node<Primitive>, input: Vec<Node<Primitive>>, stack: &mut Stack, path: &mut Vec<usize>) -> Result<(), RuntimeError> {
+        self.frames.push(Frame { code: code.clone(), input, base: path.len(), operands: None });
+        let result = self.run(code, stack, path);
+        self.frames.pop();
+        result
+    }
+
+    /// The type of the operand of the `CONCAT` or `BYTES` instruction at
+    /// `path`, or of its elements for `CONCAT` on a list.
+    fn operand_type(&mut self, path: &[usize]) -> Option<Primitive> {
+        let parameter = self.parameter_type;
+        let frame = self.frames.last_mut()?;
+        // The code was typechecked, allowing `SELF` in lambdas changes nothing.
+        let operands = frame.operands.get_or_insert_with(|| operand_types(&frame.code, frame.input.clone(), Some(parameter)));
+        operands.iter().find(|(operand, _)| operand[..] == path[frame.base..]).and_then(|(_, ty)| match ty {
+            Node::Prim(prim, _, _) => Some(*prim),
+            _ => None,
+        })
+    }
+
+    fn exec(&mut self, lambda: Node<Primitive>, argument: Node<Primitive>, path: &mut Vec<usize>) -> Result<Node<Primitive>, RuntimeError> {
+        match lambda {
+            Node::Prim(I_LAMBDA, args, _) if args.len() == 3 => {
+                let mut stack = vec![argument];
+                self.run_frame(&args[2], vec![args[0].clone()], &mut stack, path)?;
+                pop(&mut stack, path)
+            }
+            Node::Prim(I_LAMBDA_REC, ref args, _) if args.len() == 3 => {
+                let input = vec![args[0].clone(), prim(T_lambda, vec![args[0].clone(), args[1].clone()])];
+                let mut stack = vec![lambda.clone(), argument];
+                self.run_frame(&args[2], input, &mut stack, path)?;
+                pop(&mut stack, path)
+            }
+            _ => invalid(path),
+        }
+    }
+
+    fn contract(&self, address: &[u8], entrypoint: Option<&str>, ty: &Node<Primitive>) -> Option<Node<Primitive>> {
+        let (contract, address_entrypoint) = address.split_at(22);
+        let entrypoint = match (std::str::from_utf8(address_entrypoint).ok()?, entrypoint) {
+            ("", None) => "default",
+            ("", Some(entrypoint)) | (entrypoint, None) => entrypoint,
+            (_, Some(_)) => return None,
+        };
+
+        let parameter = if contract == &self.self_address[..22] {
+            find_entrypoint(self.parameter_type, entrypoint)?.clone()
+        } else if contract[0] == 0 {
+            if entrypoint != "default" {
+                return None;
+            }
+            prim(T_unit, vec![])
+        } else {
+            let parameter = self.context.contracts.get(&address_to_string(contract)?)?;
+            find_entrypoint(parameter, entrypoint)?.clone()
+        };
+
+        if normalize_type(&parameter) != normalize_type(ty) {
+            return None;
+        }
+
+        let mut bytes = contract.to_vec();
+        if entrypoint != "default" {
+            bytes.extend(entrypoint.as_bytes());
+        }
+        Some(Node::Bytes(bytes))
+    }
+
+    fn view(&mut self, name: &str, input: Node<Primitive>, address: &[u8], output: &Node<Primitive>, path: &mut Vec<usize>) -> Result<Node<Primitive>, RuntimeError> {
+        if address != &self.self_address[..22] {
+            return Ok(none());
+        }
+        let sections = match self.script {
+            Node::Seq(sections) => sections,
+            _ => return invalid(path),
+        };
+        let view = sections.iter().find_map(|section| match section {
+            Node::Prim(K_view, args, _) => match &args[..] {
+                [Node::String(view), input_type, output_type, code] if view == name => Some((input_type, output_type, code)),
+                _ => None,
+            },
+            _ => None,
+        });
+        let (input_type, output_type, code) = match view {
+            Some(view) => view,
+            None => return Ok(none()),
+        };
+        if normalize_type(output_type) != normalize_type(output) || typecheck_data(input_type, &packable(&input)).is_err() {
+            return Ok(none());
+        }
+
+        let storage_type = script_section(sections, K_storage).map(|(_, ty)| normalize_type(ty)).expect("typechecked script");
+        let mut stack = vec![pair(input, self.storage.clone())];
+        self.run_frame(code, vec![prim(T_pair, vec![normalize_type(input_type), storage_type])], &mut stack, path)?;
+        Ok(some(pop(&mut stack, path)?))
+    }
+
+    fn step(
+        &mut self,
+        instruction: &Primitive,
+        args: &[Node<Primitive>],
+        annot: &[String],
+        stack: &mut Stack,
+        path: &mut Vec<usize>,
+    ) -> Result<(), RuntimeError> {
+        match instruction {
+            // Stack manipulation
+            I_DROP => {
+                let n = natural_argument(args, 0, 1);
+                if n > stack.len() {
+                    return invalid(path);
+                }
+                stack.truncate(stack.len() - n);
+            }
+            I_DUP => {
+                let n = natural_argument(args, 0, 1);
+                if n == 0 || n > stack.len() {
+                    return invalid(path);
+                }
+                stack.push(stack[stack.len() - n].clone());
+            }
+            I_SWAP => {
+                let (a, b) = (pop(stack, path)?, pop(stack, path)?);
+                stack.push(a);
+                stack.push(b);
+            }
+            I_DIG => {
+                let n = natural_argument(args, 0, 0);
+                if n >= stack.len() {
+                    return invalid(path);
+                }
+                let item = stack.remove(stack.len() - 1 - n);
+                stack.push(item);
+            }
+            I_DUG => {
+                let n = natural_argument(args, 0, 0);
+                if n >= stack.len() {
+                    return invalid(path);
+                }
+                let item = pop(stack, path)?;
+                stack.insert(stack.len() - n, item);
+            }
+            I_PUSH => {
+                let value = canonicalize(&normalize_type(&args[0]), &args[1]).map_err(|kind| RuntimeError { path: Path(path.clone()), kind })?;
+                stack.push(value);
+            }
+            I_DIP => {
+                let n = if args.len() == 2 { natural_argument(args, 0, 1) } else { 1 };
+                if n > stack.len() {
+                    return invalid(path);
+                }
+                let protected = stack.split_off(stack.len() - n);
+                self.run_block(args, args.len() - 1, stack, path)?;
+                stack.extend(protected);
+            }
+
+            // Options, unions and pairs
+            I_SOME => {
+                let value = pop(stack, path)?;
+                stack.push(some(value));
+            }
+            I_NONE => stack.push(none()),
+            I_UNIT => stack.push(prim(D_Unit, vec![])),
+            I_IF_NONE => match pop(stack, path)? {
+                Node::Prim(D_None, _, _) => self.run_block(args, 0, stack, path)?,
+                Node::Prim(D_Some, mut v, _) => {
+                    stack.push(pop(&mut v, path)?);
+                    self.run_block(args, 1, stack, path)?;
+                }
+                _ => return invalid(path),
+            },
+            I_LEFT | I_RIGHT => {
+                let value = pop(stack, path)?;
+                stack.push(prim(if *instruction == I_LEFT { D_Left } else { D_Right }, vec![value]));
+            }
+            I_IF_LEFT => match pop(stack, path)? {
+                Node::Prim(side @ (D_Left | D_Right), mut v, _) => {
+                    stack.push(pop(&mut v, path)?);
+                    self.run_block(args, if side == D_Left { 0 } else { 1 }, stack, path)?;
+                }
+                _ => return invalid(path),
+            },
+            I_PAIR => {
+                let n = natural_argument(args, 0, 2);
+                if n < 2 || n > stack.len() {
+                    return invalid(path);
+                }
+                let mut items = stack.split_off(stack.len() - n);
+                items.reverse();
+                let mut comb = pop(&mut items, path)?;
+                while let Some(item) = items.pop() {
+                    comb = pair(item, comb);
+                }
+                stack.push(comb);
+            }
+            I_UNPAIR => {
+                let n = natural_argument(args, 0, 2);
+                let mut current = pop(stack, path)?;
+                let mut components = Vec::new();
+                for _ in 1..n {
+                    match current {
+                        Node::Prim(D_Pair, mut v, _) if v.len() == 2 => {
+                            current = pop(&mut v, path)?;
+                            components.push(pop(&mut v, path)?);
+                        }
+                        _ => return invalid(path),
+                    }
+                }
+                components.push(current);
+                stack.extend(components.into_iter().rev());
+            }
+            I_CAR | I_CDR => match pop(stack, path)? {
+                Node::Prim(D_Pair, mut v, _) if v.len() == 2 => {
+                    let value = if *instruction == I_CAR { v.swap_remove(0) } else { pop(&mut v, path)? };
+                    stack.push(value);
+                }
+                _ => return invalid(path),
+            },
+            I_GET if args.len() == 1 => {
+                let comb = pop(stack, path)?;
+                match comb_get(comb, natural_argument(args, 0, 0)) {
+                    Some(value) => stack.push(value),
+                    None => return invalid(path),
+                }
+            }
+            I_UPDATE if args.len() == 1 => {
+                let (value, comb) = (pop(stack, path)?, pop(stack, path)?);
+                match comb_update(comb, natural_argument(args, 0, 0), value) {
+                    Some(comb) => stack.push(comb),
+                    None => return invalid(path),
+                }
+            }
+
+            // Collections
+            I_NIL | I_EMPTY_SET | I_EMPTY_MAP | I_EMPTY_BIG_MAP => stack.push(Node::Seq(vec![])),
+            I_CONS => {
+                let (element, list) = (pop(stack, path)?, pop(stack, path)?);
+                match list {
+                    Node::Seq(mut items) => {
+                        items.insert(0, element);
+                        stack.push(Node::Seq(items));
+                    }
+                    _ => return invalid(path),
+                }
+            }
+            I_IF_CONS => match pop(stack, path)? {
+                Node::Seq(items) if items.is_empty() => self.run_block(args, 1, stack, path)?,
+                Node::Seq(mut items) => {
+                    let head = items.remove(0);
+                    stack.push(Node::Seq(items));
+                    stack.push(head);
+                    self.run_block(args, 0, stack, path)?;
+                }
+                _ => return invalid(path),
+            },
+            I_SIZE => {
+                let size = match pop(stack, path)? {
+                    Node::Seq(items) => items.len(),
+                    Node::String(s) => s.len(),
+                    Node::Bytes(b) => b.len(),
+                    _ => return invalid(path),
+                };
+                stack.push(checked(i32::try_from(size).ok(), path)?);
+            }
+            I_MEM | I_GET => {
+                let (key, collection) = (pop(stack, path)?, pop(stack, path)?);
+                let items = match collection {
+                    Node::Seq(items) => items,
+                    Node::Int(id) => return error(path, RuntimeErrorKind::UnknownBigMap(id)),
+                    _ => return invalid(path),
+                };
+                let found = search(&items, &key).ok();
+                if *instruction == I_MEM {
+                    stack.push(boolean(found.is_some()));
+                } else {
+                    match found.map(|index| &items[index]) {
+                        Some(Node::Prim(D_Elt, v, _)) => stack.push(some(v[1].clone())),
+                        _ => stack.push(none()),
+                    }
+                }
+            }
+            I_UPDATE | I_GET_AND_UPDATE => {
+                let (key, value, collection) = (pop(stack, path)?, pop(stack, path)?, pop(stack, path)?);
+                let mut items = match collection {
+                    Node::Seq(items) => items,
+                    Node::Int(id) => return error(path, RuntimeErrorKind::UnknownBigMap(id)),
+                    _ => return invalid(path),
+                };
+                let position = search(&items, &key);
+                let previous = match value {
+                    Node::Prim(D_True, _, _) => {
+                        if let Err(index) = position {
+                            items.insert(index, key);
+                        }
+                        None
+                    }
+                    Node::Prim(D_False, _, _) => {
+                        if let Ok(index) = position {
+                            items.remove(index);
+                        }
+                        None
+                    }
+                    Node::Prim(D_Some, mut v, _) => {
+                        let elt = prim(D_Elt, vec![key, pop(&mut v, path)?]);
+                        match position {
+                            Ok(index) => Some(std::mem::replace(&mut items[index], elt)),
+                            Err(index) => {
+                                items.insert(index, elt);
+                                None
+                            }
+                        }
+                    }
+                    Node::Prim(D_None, _, _) => position.ok().map(|index| items.remove(index)),
+                    _ => return invalid(path),
+                };
+                stack.push(Node::Seq(items));
+                if *instruction == I_GET_AND_UPDATE {
+                    match previous {
+                        Some(Node::Prim(D_Elt, mut v, _)) => stack.push(some(pop(&mut v, path)?)),
+                        _ => stack.push(none()),
+                    }
+                }
+            }
+            I_MAP => match pop(stack, path)? {
+                Node::Prim(D_None, _, _) => stack.push(none()),
+                Node::Prim(D_Some, mut v, _) => {
+                    stack.push(pop(&mut v, path)?);
+                    self.run_block(args, 0, stack, path)?;
+                    let result = pop(stack, path)?;
+                    stack.push(some(result));
+                }
+                Node::Seq(items) => {
+                    let mut mapped = Vec::with_capacity(items.len());
+                    for item in items {
+                        match item {
+                            Node::Prim(D_Elt, mut v, _) => {
+                                let (value, key) = (pop(&mut v, path)?, pop(&mut v, path)?);
+                                stack.push(pair(key.clone(), value));
+                                self.run_block(args, 0, stack, path)?;
+                                mapped.push(prim(D_Elt, vec![key, pop(stack, path)?]));
+                            }
+                            item => {
+                                stack.push(item);
+                                self.run_block(args, 0, stack, path)?;
+                                mapped.push(pop(stack, path)?);
+                            }
+                        }
+                    }
+                    stack.push(Node::Seq(mapped));
+                }
+                _ => return invalid(path),
+            },
+            I_ITER => match pop(stack, path)? {
+                Node::Seq(items) => {
+                    for item in items {
+                        match item {
+                            Node::Prim(D_Elt, mut v, _) => {
+                                let (value, key) = (pop(&mut v, path)?, pop(&mut v, path)?);
+                                stack.push(pair(key, value));
+                            }
+                            item => stack.push(item),
+                        }
+                        self.run_block(args, 0, stack, path)?;
+                    }
+                }
+                _ => return invalid(path),
+            },
+
+            // Control structures
+            I_IF => match pop(stack, path)? {
+                Node::Prim(D_True, _, _) => self.run_block(args, 0, stack, path)?,
+                Node::Prim(D_False, _, _) => self.run_block(args, 1, stack, path)?,
+                _ => return invalid(path),
+            },
+            I_LOOP => loop {
+                match pop(stack, path)? {
+                    Node::Prim(D_True, _, _) => self.run_block(args, 0, stack, path)?,
+                    Node::Prim(D_False, _, _) => break,
+                    _ => return invalid(path),
+                }
+            },
+            I_LOOP_LEFT => loop {
+                match pop(stack, path)? {
+                    Node::Prim(D_Left, mut v, _) => {
+                        stack.push(pop(&mut v, path)?);
+                        self.run_block(args, 0, stack, path)?;
+                    }
+                    Node::Prim(D_Right, mut v, _) => {
+                        stack.push(pop(&mut v, path)?);
+                        break;
+                    }
+                    _ => return invalid(path),
+                }
+            },
+            I_LAMBDA | I_LAMBDA_REC => {
+                stack.push(prim(*instruction, vec![normalize_type(&args[0]), normalize_type(&args[1]), args[2].clone()]))
+            }
+            I_EXEC => {
+                let (argument, lambda) = (pop(stack, path)?, pop(stack, path)?);
+                let result = self.exec(lambda, argument, path)?;
+                stack.push(result);
+            }
+            I_APPLY => {
+                let (captured, lambda) = (pop(stack, path)?, pop(stack, path)?);
+                match lambda {
+                    Node::Prim(I_LAMBDA, mut v, _) if v.len() == 3 => {
+                        let (code, result) = (pop(&mut v, path)?, pop(&mut v, path)?);
+                        let (captured_type, argument) = match pop(&mut v, path)? {
+                            Node::Prim(T_pair, mut v, _) if v.len() == 2 => {
+                                let argument = pop(&mut v, path)?;
+                                (pop(&mut v, path)?, argument)
+                            }
+                            _ => return invalid(path),
+                        };
+                        let code = Node::Seq(vec![prim(I_PUSH, vec![captured_type, packable(&captured)]), prim(I_PAIR, vec![]), code]);
+                        stack.push(prim(I_LAMBDA, vec![argument, result, code]));
+                    }
+                    // The partial application of a recursive lambda calls it
+                    // with the captured value.
+                    Node::Prim(I_LAMBDA_REC, v, _) if v.len() == 3 => {
+                        let (captured_type, argument) = match &v[0] {
+                            Node::Prim(T_pair, types, _) if types.len() == 2 => (types[0].clone(), types[1].clone()),
+                            _ => return invalid(path),
+                        };
+                        let result = v[1].clone();
+                        let code = Node::Seq(vec![
+                            prim(I_PUSH, vec![captured_type, packable(&captured)]),
+                            prim(I_PAIR, vec![]),
+                            prim(I_LAMBDA_REC, v),
+                            prim(I_SWAP, vec![]),
+                            prim(I_EXEC, vec![]),
+                        ]);
+                        stack.push(prim(I_LAMBDA, vec![argument, result, code]));
+                    }
+                    _ => return invalid(path),
+                }
+            }
+            I_FAILWITH => {
+                let value = pop(stack, path)?;
+                return error(path, RuntimeErrorKind::FailWith(packable(&value)));
+            }
+            I_CAST | I_RENAME => {}
+            I_NEVER => return invalid(path),
+
+            // Arithmetic and logic
+            I_ADD | I_SUB | I_MUL | I_EDIV | I_SUB_MUTEZ | I_LSL | I_LSR | I_OR | I_AND | I_XOR => {
+                let (a, b) = (pop(stack, path)?, pop(stack, path)?);
+                let result = match (instruction, &a, &b) {
+                    (I_ADD, Node::Int(a), Node::Int(b)) => checked(a.checked_add(*b), path)?,
+                    (I_SUB, Node::Int(a), Node::Int(b)) => checked(a.checked_sub(*b), path)?,
+                    (I_MUL, Node::Int(a), Node::Int(b)) => checked(a.checked_mul(*b), path)?,
+                    (I_SUB_MUTEZ, Node::Int(a), Node::Int(b)) => match a.checked_sub(*b) {
+                        Some(difference) if difference >= 0 => some(Node::Int(difference)),
+                        _ => none(),
+                    },
+                    (I_EDIV, Node::Int(_), Node::Int(0)) => none(),
+                    (I_EDIV, Node::Int(a), Node::Int(b)) => {
+                        let quotient = checked(a.checked_div_euclid(*b), path)?;
+                        let remainder = checked(a.checked_rem_euclid(*b), path)?;
+                        some(pair(quotient, remainder))
+                    }
+                    (I_LSL, Node::Int(a), Node::Int(b)) => {
+                        if *b > 256 {
+                            return error(path, RuntimeErrorKind::Overflow);
+                        }
+                        let shifted = (*a as i64).checked_shl(*b as u32).filter(|shifted| shifted >> *b == *a as i64);
+                        checked(shifted.and_then(|shifted| i32::try_from(shifted).ok()), path)?
+                    }
+                    (I_LSR, Node::Int(a), Node::Int(b)) => {
+                        if *b > 256 {
+                            return error(path, RuntimeErrorKind::Overflow);
+                        }
+                        Node::Int(a.checked_shr(*b as u32).unwrap_or(0))
+                    }
+                    (I_OR, Node::Int(a), Node::Int(b)) => Node::Int(a | b),
+                    (I_AND, Node::Int(a), Node::Int(b)) => Node::Int(a & b),
+                    (I_XOR, Node::Int(a), Node::Int(b)) => Node::Int(a ^ b),
+                    (I_OR | I_AND | I_XOR, Node::Prim(a, _, _), Node::Prim(b, _, _)) => {
+                        let (a, b) = (*a == D_True, *b == D_True);
+                        boolean(match instruction {
+                            I_OR => a || b,
+                            I_AND => a && b,
+                            _ => a ^ b,
+                        })
+                    }
+                    (I_LSL | I_LSR, Node::Bytes(bytes), Node::Int(shift)) => {
+                        if *shift > 64000 {
+                            return error(path, RuntimeErrorKind::Overflow);
+                        }
+                        if *instruction == I_LSL {
+                            Node::Bytes(bytes_shift_left(bytes, *shift as usize))
+                        } else {
+                            Node::Bytes(bytes_shift_right(bytes, *shift as usize))
+                        }
+                    }
+                    (I_OR | I_AND | I_XOR, Node::Bytes(a), Node::Bytes(b)) => Node::Bytes(bytes_bitwise(instruction, a, b)),
+                    _ => return invalid(path),
+                };
+                stack.push(result);
+            }
+            I_NOT | I_NEG | I_ABS | I_ISNAT | I_INT | I_NAT | I_EQ | I_NEQ | I_LT | I_GT | I_LE | I_GE => {
+                let value = pop(stack, path)?;
+                let result = match (instruction, &value) {
+                    (I_NOT, Node::Prim(b, _, _)) => boolean(*b != D_True),
+                    (I_NOT, Node::Int(v)) => Node::Int(!v),
+                    (I_NEG, Node::Int(v)) => checked(v.checked_neg(), path)?,
+                    (I_ABS, Node::Int(v)) => checked(v.checked_abs(), path)?,
+                    (I_ISNAT, Node::Int(v)) => if *v >= 0 { some(value.clone()) } else { none() },
+                    (I_INT, Node::Int(_)) => value.clone(),
+                    (I_EQ, Node::Int(v)) => boolean(*v == 0),
+                    (I_NEQ, Node::Int(v)) => boolean(*v != 0),
+                    (I_LT, Node::Int(v)) => boolean(*v < 0),
+                    (I_GT, Node::Int(v)) => boolean(*v > 0),
+                    (I_LE, Node::Int(v)) => boolean(*v <= 0),
+                    (I_GE, Node::Int(v)) => boolean(*v >= 0),
+                    (I_NOT, Node::Bytes(bytes)) => Node::Bytes(bytes.iter().map(|byte| !byte).collect()),
+                    (I_INT, Node::Bytes(bytes)) => checked(bytes_to_int(bytes, true), path)?,
+                    (I_NAT, Node::Bytes(bytes)) => checked(bytes_to_int(bytes, false), path)?,
+                    _ => return invalid(path),
+                };
+                stack.push(result);
+            }
+            I_BYTES => match pop(stack, path)? {
+                Node::Int(value) => {
+                    let signed = self.operand_type(path) != Some(T_nat);
+                    stack.push(Node::Bytes(int_to_bytes(value, signed)));
+                }
+                _ => return invalid(path),
+            },
+            I_COMPARE => {
+                let (a, b) = (pop(stack, path)?, pop(stack, path)?);
+                stack.push(Node::Int(compare_values(&a, &b) as i32));
+            }
+
+            // Strings and bytes
+            I_CONCAT => match pop(stack, path)? {
+                Node::String(a) => match pop(stack, path)? {
+                    Node::String(b) => stack.push(Node::String(a + &b)),
+                    _ => return invalid(path),
+                },
+                Node::Bytes(mut a) => match pop(stack, path)? {
+                    Node::Bytes(b) => {
+                        a.extend(b);
+                        stack.push(Node::Bytes(a));
+                    }
+                    _ => return invalid(path),
+                },
+                Node::Seq(items) => {
+                    let mut is_bytes = items.is_empty() && self.operand_type(path) == Some(T_bytes);
+                    let (mut string, mut bytes) = (String::new(), Vec::new());
+                    for item in items {
+                        match item {
+                            Node::String(s) => string.push_str(&s),
+                            Node::Bytes(b) => {
+                                bytes.extend(b);
+                                is_bytes = true;
+                            }
+                            _ => return invalid(path),
+                        }
+                    }
+                    stack.push(if is_bytes { Node::Bytes(bytes) } else { Node::String(string) });
+                }
+                _ => return invalid(path),
+            },
+            I_SLICE => {
+                let (offset, length, sliced) = (pop(stack, path)?, pop(stack, path)?, pop(stack, path)?);
+                let (offset, length) = match (offset, length) {
+                    (Node::Int(offset), Node::Int(length)) => (offset as usize, length as usize),
+                    _ => return invalid(path),
+                };
+                let result = match sliced {
+                    Node::String(s) => s.get(offset..offset + length).map(|s| Node::String(s.to_string())),
+                    Node::Bytes(b) => b.get(offset..offset + length).map(|b| Node::Bytes(b.to_vec())),
+                    _ => return invalid(path),
+                };
+                stack.push(result.map(some).unwrap_or_else(none));
+            }
+            I_PACK => {
+                let value = pop(stack, path)?;
+                let mut bytes = vec![0x05];
+                bytes.extend(packable(&value).encode());
+                self.consume(self.gas.costs.encoding_per_byte * bytes.len() as u64, path)?;
+                stack.push(Node::Bytes(bytes));
+            }
+            I_UNPACK => {
+                let bytes = match pop(stack, path)? {
+                    Node::Bytes(bytes) => bytes,
+                    _ => return invalid(path),
+                };
+                self.consume(self.gas.costs.decoding_per_byte * bytes.len() as u64, path)?;
+                let ty = &args[0];
+                let unpacked = match bytes.split_first() {
+                    Some((0x05, data)) => match Node::<Primitive>::from_offset(data, 0) {
+                        Ok((value, size)) if size == data.len() && typecheck_data(ty, &value).is_ok() => {
+                            canonicalize(&normalize_type(ty), &value).ok()
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                stack.push(unpacked.map(some).unwrap_or_else(none));
+            }
+
+            // Cryptography
+            I_BLAKE2B | I_SHA256 | I_SHA512 | I_KECCAK | I_SHA3 => {
+                let bytes = match pop(stack, path)? {
+                    Node::Bytes(bytes) => bytes,
+                    _ => return invalid(path),
+                };
+                self.consume(self.gas.costs.hashing_per_byte * bytes.len() as u64, path)?;
+                let digest = match instruction {
+                    I_BLAKE2B => hash::blake2b(&bytes, 32),
+                    I_SHA256 => hash::sha256(&bytes).to_vec(),
+                    I_SHA512 => hash::sha512(&bytes).to_vec(),
+                    I_KECCAK => hash::keccak256(&bytes).to_vec(),
+                    _ => hash::sha3_256(&bytes).to_vec(),
+                };
+                stack.push(Node::Bytes(digest));
+            }
+            I_HASH_KEY => match pop(stack, path)? {
+                Node::Bytes(key) if !key.is_empty() => {
+                    let mut key_hash = vec![key[0]];
+                    key_hash.extend(hash::blake2b(&key[1..], 20));
+                    stack.push(Node::Bytes(key_hash));
+                }
+                _ => return invalid(path),
+            },
+            I_CHECK_SIGNATURE => {
+                let (key, signature, message) = (pop(stack, path)?, pop(stack, path)?, pop(stack, path)?);
+                let verify = match self.context.check_signature {
+                    Some(verify) => verify,
+                    None => return error(path, RuntimeErrorKind::Unsupported(I_CHECK_SIGNATURE)),
+                };
+                match (key, signature, message) {
+                    (Node::Bytes(key), Node::Bytes(signature), Node::Bytes(message)) => {
+                        stack.push(boolean(verify(&key, &signature, &message)))
+                    }
+                    _ => return invalid(path),
+                }
+            }
+
+            // Blockchain operations
+            I_AMOUNT => stack.push(Node::Int(self.context.amount)),
+            I_BALANCE => stack.push(Node::Int(self.context.balance)),
+            I_NOW => stack.push(Node::Int(self.context.now)),
+            I_LEVEL => stack.push(Node::Int(self.context.level)),
+            I_MIN_BLOCK_TIME => stack.push(Node::Int(self.context.min_block_time)),
+            I_CHAIN_ID | I_SOURCE | I_SENDER => {
+                let value = match instruction {
+                    I_CHAIN_ID => chain_id_bytes(&Node::String(self.context.chain_id.clone())),
+                    I_SOURCE => address_bytes(&Node::String(self.context.source.clone())),
+                    _ => address_bytes(&Node::String(self.context.sender.clone())),
+                };
+                match value {
+                    Some(bytes) => stack.push(Node::Bytes(bytes)),
+                    None => return error(path, RuntimeErrorKind::InvalidContext),
+                }
+            }
+            I_SELF_ADDRESS => stack.push(Node::Bytes(self.self_address.clone())),
+            I_SELF => {
+                let mut address = self.self_address.clone();
+                match annot.iter().find_map(|annot| annot.strip_prefix('%')) {
+                    Some("default") | None => {}
+                    Some(entrypoint) => address.extend(entrypoint.as_bytes()),
+                }
+                stack.push(Node::Bytes(address));
+            }
+            I_VOTING_POWER => {
+                let key_hash = pop(stack, path)?;
+                let power = match &key_hash {
+                    Node::Bytes(b) if b.len() == 21 => to_readable(&prim(T_key_hash, vec![]), &key_hash),
+                    _ => return invalid(path),
+                };
+                let power = match power {
+                    Node::String(key_hash) => self.context.voting_powers.get(&key_hash).copied().unwrap_or(0),
+                    _ => 0,
+                };
+                stack.push(Node::Int(power));
+            }
+            I_TOTAL_VOTING_POWER => {
+                let total = self.context.voting_powers.values().try_fold(0i32, |total, power| total.checked_add(*power));
+                stack.push(checked(total, path)?);
+            }
+            I_CONTRACT => {
+                let address = match pop(stack, path)? {
+                    Node::Bytes(address) => address,
+                    _ => return invalid(path),
+                };
+                let entrypoint = annot.iter().find_map(|annot| annot.strip_prefix('%'));
+                let contract = self.contract(&address, entrypoint, &args[0]);
+                stack.push(contract.map(some).unwrap_or_else(none));
+            }
+            I_IS_IMPLICIT_ACCOUNT => match pop(stack, path)? {
+                Node::Bytes(address) if address.len() >= 22 && address[0] == 0 => stack.push(some(Node::Bytes(address[1..22].to_vec()))),
+                Node::Bytes(_) => stack.push(none()),
+                _ => return invalid(path),
+            },
+            I_IMPLICIT_ACCOUNT => match pop(stack, path)? {
+                Node::Bytes(key_hash) => {
+                    let mut address = vec![0];
+                    address.extend(key_hash);
+                    stack.push(Node::Bytes(address));
+                }
+                _ => return invalid(path),
+            },
+            I_ADDRESS => {}
+            I_TRANSFER_TOKENS => {
+                let (parameter, amount, contract) = (pop(stack, path)?, pop(stack, path)?, pop(stack, path)?);
+                stack.push(prim(I_TRANSFER_TOKENS, vec![packable(&parameter), amount, contract]));
+            }
+            I_SET_DELEGATE => {
+                let delegate = pop(stack, path)?;
+                stack.push(prim(I_SET_DELEGATE, vec![delegate]));
+            }
+            I_CREATE_CONTRACT => {
+                let (delegate, amount, storage) = (pop(stack, path)?, pop(stack, path)?, pop(stack, path)?);
+                // Originated addresses depend on the operation hash, which is
+                // not known here; derive one from the origination nonce.
+                let mut seed = self.self_address.clone();
+                seed.extend(self.origination_nonce.to_be_bytes());
+                self.origination_nonce += 1;
+                let mut address = vec![1];
+                address.extend(hash::blake2b(&seed, 20));
+                address.push(0);
+
+                let operation = prim(
+                    I_CREATE_CONTRACT,
+                    vec![delegate, amount, packable(&storage), args[0].clone(), Node::Bytes(address.clone())],
+                );
+                stack.push(Node::Bytes(address));
+                stack.push(operation);
+            }
+            I_EMIT => {
+                let value = pop(stack, path)?;
+                let mut operation_args = vec![packable(&value)];
+                operation_args.extend(args.iter().cloned());
+                stack.push(Node::Prim(I_EMIT, operation_args, annot.to_vec()));
+            }
+            I_VIEW => {
+                let (input, address) = (pop(stack, path)?, pop(stack, path)?);
+                let (name, output) = match args {
+                    [Node::String(name), output] => (name, output),
+                    _ => return invalid(path),
+                };
+                let address = match address {
+                    Node::Bytes(address) if address.len() >= 22 => address,
+                    _ => return invalid(path),
+                };
+                let result = self.view(name, input, &address[..22], output, path)?;
+                stack.push(result);
+            }
+
+            // Tickets
+            I_TICKET | I_TICKET_DEPRECATED => {
+                let (content, amount) = (pop(stack, path)?, pop(stack, path)?);
+                let zero = amount == Node::Int(0);
+                let ticket = pair(Node::Bytes(self.self_address.clone()), pair(content, amount));
+                stack.push(match instruction {
+                    I_TICKET_DEPRECATED => ticket,
+                    _ if zero => none(),
+                    _ => some(ticket),
+                });
+            }
+            I_READ_TICKET => {
+                let ticket = pop(stack, path)?;
+                stack.push(ticket.clone());
+                stack.push(ticket);
+            }
+            I_SPLIT_TICKET => {
+                let (ticket, amounts) = (pop(stack, path)?, pop(stack, path)?);
+                let result = match (ticket, amounts) {
+                    (Node::Prim(D_Pair, mut ticket, _), Node::Prim(D_Pair, amounts, _)) => {
+                        let (content, ticketer) = (pop(&mut ticket, path)?, pop(&mut ticket, path)?);
+                        match (content, &amounts[..]) {
+                            (Node::Prim(D_Pair, content, _), [Node::Int(a), Node::Int(b)]) => match &content[..] {
+                                [content, Node::Int(amount)] if *a > 0 && *b > 0 && a.checked_add(*b) == Some(*amount) => {
+                                    let split = |amount: i32| pair(ticketer.clone(), pair(content.clone(), Node::Int(amount)));
+                                    some(pair(split(*a), split(*b)))
+                                }
+                                _ => none(),
+                            },
+                            _ => return invalid(path),
+                        }
+                    }
+                    _ => return invalid(path),
+                };
+                stack.push(result);
+            }
+            I_JOIN_TICKETS => {
+                let tickets = match pop(stack, path)? {
+                    Node::Prim(D_Pair, tickets, _) => tickets,
+                    _ => return invalid(path),
+                };
+                let parts = tickets
+                    .iter()
+                    .map(|ticket| match ticket {
+                        Node::Prim(D_Pair, v, _) => match &v[..] {
+                            [ticketer, Node::Prim(D_Pair, inner, _)] => match &inner[..] {
+                                [content, Node::Int(amount)] => Some((ticketer, content, *amount)),
+                                _ => None,
+                            },
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                let result = match parts.as_deref() {
+                    Some([(ticketer, content, a), (other_ticketer, other_content, b)])
+                        if ticketer == other_ticketer && content == other_content => {
+                        let amount = checked(a.checked_add(*b), path)?;
+                        some(pair((*ticketer).clone(), pair((*content).clone(), amount)))
+                    }
+                    Some(_) => none(),
+                    None => return invalid(path),
+                };
+                stack.push(result);
+            }
+
+            I_SAPLING_EMPTY_STATE => stack.push(Node::Seq(vec![])),
+            _ => return error(path, RuntimeErrorKind::Unsupported(*instruction)),
+        }
+        Ok(())
+    }
+}
+
+/// Executes the code of a script on a parameter and storage, and returns
+/// the emitted operations and the new storage.
+pub fn run_script(
+    script: &Node<Primitive>,
+    parameter: &Node<Primitive>,
+    storage: &Node<Primitive>,
+    context: &Context,
+) -> Result<Execution, RuntimeError> {
+    run_script_with_gas(script, parameter, storage, context, &mut GasMeter::unlimited(CostTable::default()))
+}
+
+/// Like `run_script`, but charges the execution to `gas`, and fails with
+/// `GasExhausted` when its limit is reached.
+pub fn run_script_with_gas(
+    script: &Node<Primitive>,
+    parameter: &Node<Primitive>,
+    storage: &Node<Primitive>,
+    context: &Context,
+    gas: &mut GasMeter,
+) -> Result<Execution, RuntimeError> {
+    let decoded = encoded_size(script) + encoded_size(parameter) + encoded_size(storage);
+    if !gas.consume(gas.costs.decoding_per_byte * decoded) {
+        return error(&[], RuntimeErrorKind::GasExhausted);
+    }
+    typecheck::typecheck_script(script).map_err(ill_typed)?;
+
+    let sections = match script {
+        Node::Seq(sections) => sections,
+        _ => return error(&[], RuntimeErrorKind::InvalidValue),
+    };
+    let section = |keyword| script_section(sections, keyword).map(|(_, node)| node).expect("typechecked script");
+    let (parameter_type, storage_type, code) = (section(K_parameter), section(K_storage), section(K_code));
+
+    typecheck_data(parameter_type, parameter).map_err(ill_typed)?;
+    typecheck_data(storage_type, storage).map_err(ill_typed)?;
+
+    let normalized_storage_type = normalize_type(storage_type);
+    let canonical = |ty: &Node<Primitive>, value| canonicalize(&normalize_type(ty), value).map_err(|kind| RuntimeError { path: Path::root(), kind });
+    let parameter = canonical(parameter_type, parameter)?;
+    let storage = canonical(storage_type, storage)?;
+    let self_address = address_bytes(&Node::String(context.self_address.clone()))
+        .ok_or(RuntimeError { path: Path::root(), kind: RuntimeErrorKind::InvalidContext })?;
+
+    let mut interpreter = Interpreter {
+        context,
+        script,
+        parameter_type,
+        storage: storage.clone(),
+        self_address,
+        origination_nonce: 0,
+        gas,
+        frames: Vec::new(),
+    };
+
+    let code_index = script_section(sections, K_code).map(|(index, _)| index).expect("typechecked script");
+    let mut stack = vec![pair(parameter, storage)];
+    let mut path = vec![code_index, 0];
+    let input = vec![prim(T_pair, vec![normalize_type(parameter_type), normalized_storage_type.clone()])];
+    interpreter.run_frame(code, input, &mut stack, &mut path)?;
+
+    match stack.pop() {
+        Some(Node::Prim(D_Pair, mut result, _)) if result.len() == 2 && stack.is_empty() => {
+            let storage = result.pop().expect("pair");
+            let operations = match result.pop() {
+                Some(Node::Seq(operations)) => operations,
+                _ => return error(&[], RuntimeErrorKind::InvalidValue),
+            };
+            let storage = to_readable(&normalized_storage_type, &storage);
+            if !interpreter.gas.consume(interpreter.gas.costs.encoding_per_byte * encoded_size(&storage)) {
+                return error(&[], RuntimeErrorKind::GasExhausted);
+            }
+            Ok(Execution { operations, storage })
+        }
+        _ => error(&[], RuntimeErrorKind::InvalidValue),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::*;
+
+    fn t(primitive: Primitive) -> Node<Primitive> {
+        prim(primitive, vec![])
+    }
+
+    fn script(parameter: Node<Primitive>, storage: Node<Primitive>, code: Vec<Node<Primitive>>) -> Node<Primitive> {
+        Node::Seq(vec![
+            prim(K_parameter, vec![parameter]),
+            prim(K_storage, vec![storage]),
+            prim(K_code, vec![Node::Seq(code)]),
+        ])
+    }
+
+    #[test]
+    fn counter() {
+        let script = script(t(T_int), t(T_int), vec![
+            t(I_UNPAIR),
+            t(I_ADD),
+            prim(I_NIL, vec![t(T_operation)]),
+            t(I_PAIR),
+        ]);
+        let context = Context::default();
+        assert_eq!(
+            run_script(&script, &Node::Int(5), &Node::Int(37), &context),
+            Ok(Execution { operations: vec![], storage: Node::Int(42) })
+        );
+        assert_eq!(
+            run_script(&script, &Node::String(String::from("5")), &Node::Int(37), &context).map_err(|error| error.kind),
+            Err(RuntimeErrorKind::IllTyped(TypeError { path: Path(vec![]), kind: typecheck::TypeErrorKind::UnexpectedValue(t(T_int)) }))
+        );
+        assert_eq!(
+            run_script(&script, &Node::Int(0), &Node::Int(i32::MIN), &context),
+            Ok(Execution { operations: vec![], storage: Node::Int(i32::MIN) })
+        );
+    }
+
+    #[test]
+    fn maps_and_loops() {
+        // Sums the values of a map into the storage, then records the sum
+        // under key "total".
+        let map = prim(T_map, vec![t(T_string), t(T_nat)]);
+        let script = script(t(T_unit), map.clone(), vec![
+            t(I_CDR),
+            prim(I_PUSH, vec![t(T_nat), Node::Int(0)]),
+            prim(I_DUP, vec![Node::Int(2)]),
+            prim(I_ITER, vec![Node::Seq(vec![t(I_CDR), t(I_ADD)])]),
+            t(I_SOME),
+            prim(I_PUSH, vec![t(T_string), Node::String(String::from("total"))]),
+            t(I_UPDATE),
+            prim(I_NIL, vec![t(T_operation)]),
+            t(I_PAIR),
+        ]);
+        let elt = |key: &str, value: i32| prim(D_Elt, vec![Node::String(String::from(key)), Node::Int(value)]);
+        assert_eq!(
+            run_script(&script, &t(D_Unit), &Node::Seq(vec![elt("a", 1), elt("z", 2)]), &Context::default()),
+            Ok(Execution { operations: vec![], storage: Node::Seq(vec![elt("a", 1), elt("total", 3), elt("z", 2)]) })
+        );
+    }
+
+    #[test]
+    fn failures() {
+        let script = script(t(T_nat), t(T_nat), vec![
+            t(I_CAR),
+            prim(I_PUSH, vec![t(T_nat), Node::Int(10)]),
+            prim(I_DUP, vec![Node::Int(2)]),
+            t(I_COMPARE),
+            t(I_GT),
+            prim(I_IF, vec![
+                Node::Seq(vec![prim(I_PUSH, vec![t(T_string), Node::String(String::from("too large"))]), t(I_FAILWITH)]),
+                Node::Seq(vec![]),
+            ]),
+            prim(I_NIL, vec![t(T_operation)]),
+            t(I_PAIR),
+        ]);
+        assert_eq!(
+            run_script(&script, &Node::Int(3), &Node::Int(0), &Context::default()).map(|execution| execution.storage),
+            Ok(Node::Int(3))
+        );
+        assert_eq!(
+            run_script(&script, &Node::Int(11), &Node::Int(0), &Context::default()),
+            Err(RuntimeError { path: Path(vec![2, 0, 5, 0, 1]), kind: RuntimeErrorKind::FailWith(Node::String(String::from("too large"))) })
+        );
+
+        let overflow = self::script(t(T_int), t(T_int), vec![
+            t(I_UNPAIR),
+            t(I_MUL),
+            prim(I_NIL, vec![t(T_operation)]),
+            t(I_PAIR),
+        ]);
+        assert_eq!(
+            run_script(&overflow, &Node::Int(i32::MAX), &Node::Int(2), &Context::default()).map_err(|error| error.kind),
+            Err(RuntimeErrorKind::Overflow)
+        );
+    }
+
+    #[test]
+    fn operations_and_context() {
+        let script = script(t(T_unit), prim(T_pair, vec![t(T_address), t(T_timestamp)]), vec![
+            t(I_DROP),
+            t(I_NOW),
+            t(I_SENDER),
+            t(I_PAIR),
+            t(I_SENDER),
+            prim(I_CONTRACT, vec![t(T_unit)]),
+            prim(I_IF_NONE, vec![Node::Seq(vec![t(I_UNIT), t(I_FAILWITH)]), Node::Seq(vec![])]),
+            t(I_AMOUNT),
+            t(I_UNIT),
+            t(I_TRANSFER_TOKENS),
+            prim(I_NIL, vec![t(T_operation)]),
+            t(I_SWAP),
+            t(I_CONS),
+            t(I_PAIR),
+        ]);
+        let context = Context { amount: 100, now: 1569495591, ..Context::default() };
+        let storage = prim(D_Pair, vec![Node::String(context.self_address.clone()), Node::Int(0)]);
+        let sender = address_bytes(&Node::String(context.sender.clone())).unwrap();
+        assert!(chain_id_bytes(&Node::String(context.chain_id.clone())).is_some());
+        assert_eq!(
+            run_script(&script, &t(D_Unit), &storage, &context),
+            Ok(Execution {
+                operations: vec![prim(I_TRANSFER_TOKENS, vec![t(D_Unit), Node::Int(100), Node::Bytes(sender)])],
+                storage: prim(D_Pair, vec![
+                    Node::String(context.sender.clone()),
+                    Node::String(String::from("2019-09-26T10:59:51Z")),
+                ]),
+            })
+        );
+    }
+
+    #[test]
+    fn gas() {
+        // Counts down from the parameter to zero.
+        let script = script(t(T_nat), t(T_nat), vec![
+            t(I_CAR),
+            t(I_DUP),
+            t(I_INT),
+            t(I_GT),
+            prim(I_LOOP, vec![Node::Seq(vec![
+                prim(I_PUSH, vec![t(T_int), Node::Int(-1)]),
+                t(I_ADD),
+                t(I_ISNAT),
+                prim(I_IF_NONE, vec![Node::Seq(vec![t(I_UNIT), t(I_FAILWITH)]), Node::Seq(vec![])]),
+                t(I_DUP),
+                t(I_INT),
+                t(I_GT),
+            ])]),
+            prim(I_NIL, vec![t(T_operation)]),
+            t(I_PAIR),
+        ]);
+        let context = Context::default();
+        let consumed = |n| {
+            let mut gas = GasMeter::unlimited(CostTable::default());
+            run_script_with_gas(&script, &Node::Int(n), &Node::Int(0), &context, &mut gas).unwrap();
+            gas.consumed_milligas()
+        };
+        let (small, large) = (consumed(10), consumed(20));
+        let per_iteration = (large - small) / 10;
+        assert!(per_iteration > 0 && small > 10 * per_iteration);
+
+        let mut gas = GasMeter::new(small / 1000, CostTable::default());
+        assert_eq!(
+            run_script_with_gas(&script, &Node::Int(10), &Node::Int(0), &context, &mut gas).map_err(|error| error.kind),
+            Err(RuntimeErrorKind::GasExhausted)
+        );
+        let mut gas = GasMeter::new(small / 1000 + 1, CostTable::default());
+        assert!(run_script_with_gas(&script, &Node::Int(10), &Node::Int(0), &context, &mut gas).is_ok());
+        assert_eq!(gas.consumed(), small / 1000 + 1);
+    }
+
+    #[test]
+    fn lambdas_and_packing() {
+        let lambda = prim(T_lambda, vec![prim(T_pair, vec![t(T_nat), t(T_nat)]), t(T_nat)]);
+        let packing = script(t(T_nat), t(T_bool), vec![
+            t(I_CAR),
+            prim(I_LAMBDA, vec![prim(T_pair, vec![t(T_nat), t(T_nat)]), t(T_nat), Node::Seq(vec![t(I_UNPAIR), t(I_MUL)])]),
+            prim(I_PUSH, vec![t(T_nat), Node::Int(3)]),
+            t(I_APPLY),
+            t(I_SWAP),
+            t(I_EXEC),
+            prim(I_PUSH, vec![lambda, Node::Seq(vec![t(I_UNPAIR), t(I_ADD)])]),
+            t(I_DROP),
+            t(I_PACK),
+            prim(I_UNPACK, vec![t(T_nat)]),
+            prim(I_IF_NONE, vec![Node::Seq(vec![t(I_UNIT), t(I_FAILWITH)]), Node::Seq(vec![])]),
+            prim(I_PUSH, vec![t(T_nat), Node::Int(21)]),
+            t(I_COMPARE),
+            t(I_EQ),
+            prim(I_NIL, vec![t(T_operation)]),
+            t(I_PAIR),
+        ]);
+        assert_eq!(
+            run_script(&packing, &Node::Int(7), &t(D_False), &Context::default()).map(|execution| execution.storage),
+            Ok(t(D_True))
+        );
+
+        // A packed value whose encoding is truncated.
+        let unpack = script(t(T_bytes), prim(T_option, vec![t(T_int)]), vec![
+            t(I_CAR),
+            prim(I_UNPACK, vec![t(T_int)]),
+            prim(I_NIL, vec![t(T_operation)]),
+            t(I_PAIR),
+        ]);
+        assert_eq!(
+            run_script(&unpack, &Node::Bytes(vec![0x05, 0x00]), &t(D_None), &Context::default()).map(|execution| execution.storage),
+            Ok(t(D_None))
+        );
+    }
+
+    #[test]
+    fn recursive_lambdas_and_tickets() {
+        // Factorial of the parameter.
+        let factorial = script(t(T_nat), t(T_nat), vec![
+            t(I_CAR),
+            prim(I_LAMBDA_REC, vec![t(T_nat), t(T_nat), Node::Seq(vec![
+                t(I_DUP),
+                prim(I_PUSH, vec![t(T_nat), Node::Int(0)]),
+                t(I_COMPARE),
+                t(I_EQ),
+                prim(I_IF, vec![
+                    Node::Seq(vec![t(I_DROP), t(I_DROP), prim(I_PUSH, vec![t(T_nat), Node::Int(1)])]),
+                    Node::Seq(vec![
+                        t(I_DUP),
+                        prim(I_PUSH, vec![t(T_nat), Node::Int(1)]),
+                        t(I_SWAP),
+                        t(I_SUB),
+                        t(I_ABS),
+                        prim(I_DIG, vec![Node::Int(2)]),
+                        t(I_SWAP),
+                        t(I_EXEC),
+                        t(I_MUL),
+                    ]),
+                ]),
+            ])]),
+            t(I_SWAP),
+            t(I_EXEC),
+            prim(I_NIL, vec![t(T_operation)]),
+            t(I_PAIR),
+        ]);
+        assert_eq!(
+            run_script(&factorial, &Node::Int(5), &Node::Int(0), &Context::default()).map(|execution| execution.storage),
+            Ok(Node::Int(120))
+        );
+
+        // `TICKET` returns `None` for a zero amount.
+        let script = script(t(T_nat), t(T_bool), vec![
+            t(I_CAR),
+            t(I_UNIT),
+            t(I_TICKET),
+            prim(I_IF_NONE, vec![
+                Node::Seq(vec![prim(I_PUSH, vec![t(T_bool), t(D_False)])]),
+                Node::Seq(vec![t(I_DROP), prim(I_PUSH, vec![t(T_bool), t(D_True)])]),
+            ]),
+            prim(I_NIL, vec![t(T_operation)]),
+            t(I_PAIR),
+        ]);
+        let context = Context::default();
+        assert_eq!(run_script(&script, &Node::Int(0), &t(D_True), &context).map(|execution| execution.storage), Ok(t(D_False)));
+        assert_eq!(run_script(&script, &Node::Int(3), &t(D_False), &context).map(|execution| execution.storage), Ok(t(D_True)));
+    }
+
+    #[test]
+    fn bytes() {
+        let run = |code: Vec<Node<Primitive>>, storage: Node<Primitive>| {
+            let mut instructions = vec![t(I_DROP)];
+            instructions.extend(code);
+            instructions.extend([prim(I_NIL, vec![t(T_operation)]), t(I_PAIR)]);
+            let ty = match storage {
+                Node::Bytes(_) => t(T_bytes),
+                _ => t(T_int),
+            };
+            run_script(&script(t(T_unit), ty, instructions), &t(D_Unit), &storage, &Context::default())
+                .map(|execution| execution.storage)
+                .map_err(|error| error.kind)
+        };
+        let bytes = |value: &[u8]| Node::Bytes(value.to_vec());
+        let push = |value: &[u8]| prim(I_PUSH, vec![t(T_bytes), bytes(value)]);
+        let push_nat = |value: i32| prim(I_PUSH, vec![t(T_nat), Node::Int(value)]);
+
+        assert_eq!(run(vec![push_nat(1), push(&[0x06]), t(I_LSL)], bytes(&[])), Ok(bytes(&[0x00, 0x0c])));
+        assert_eq!(run(vec![push_nat(8), push(&[0x06]), t(I_LSL)], bytes(&[])), Ok(bytes(&[0x06, 0x00])));
+        assert_eq!(run(vec![push_nat(1), push(&[0x01, 0x00]), t(I_LSR)], bytes(&[])), Ok(bytes(&[0x00, 0x80])));
+        assert_eq!(run(vec![push_nat(9), push(&[0x01, 0x00]), t(I_LSR)], bytes(&[])), Ok(bytes(&[0x00])));
+        assert_eq!(run(vec![push_nat(16), push(&[0x01, 0x00]), t(I_LSR)], bytes(&[])), Ok(bytes(&[])));
+        assert_eq!(run(vec![push(&[0x0f, 0x0f]), push(&[0x05]), t(I_AND)], bytes(&[])), Ok(bytes(&[0x05])));
+        assert_eq!(run(vec![push(&[0x0f, 0x0f]), push(&[0xf0]), t(I_OR)], bytes(&[])), Ok(bytes(&[0x0f, 0xff])));
+        assert_eq!(run(vec![push(&[0x0f, 0x0f]), push(&[0xff]), t(I_XOR)], bytes(&[])), Ok(bytes(&[0x0f, 0xf0])));
+        assert_eq!(run(vec![push(&[0x0f, 0xf0]), t(I_NOT)], bytes(&[])), Ok(bytes(&[0xf0, 0x0f])));
+        assert_eq!(run(vec![push(&[0xff, 0x7f]), t(I_INT)], Node::Int(0)), Ok(Node::Int(-129)));
+        assert_eq!(run(vec![push(&[0x00, 0x00, 0x01, 0x00]), t(I_INT)], Node::Int(0)), Ok(Node::Int(256)));
+        assert_eq!(run(vec![push(&[0xff]), t(I_NAT), t(I_INT)], Node::Int(0)), Ok(Node::Int(255)));
+        assert_eq!(run(vec![push(&[0x01, 0, 0, 0, 0]), t(I_NAT), t(I_INT)], Node::Int(0)), Err(RuntimeErrorKind::Overflow));
+
+        let push_int = |value: i32| prim(I_PUSH, vec![t(T_int), Node::Int(value)]);
+        assert_eq!(run(vec![push_int(0), t(I_BYTES)], bytes(&[0x01])), Ok(bytes(&[])));
+        assert_eq!(run(vec![push_int(-1), t(I_BYTES)], bytes(&[])), Ok(bytes(&[0xff])));
+        assert_eq!(run(vec![push_int(-129), t(I_BYTES)], bytes(&[])), Ok(bytes(&[0xff, 0x7f])));
+        assert_eq!(run(vec![push_int(128), t(I_BYTES)], bytes(&[])), Ok(bytes(&[0x00, 0x80])));
+        assert_eq!(run(vec![push_int(i32::MIN + 1), t(I_BYTES)], bytes(&[])), Ok(bytes(&[0x80, 0, 0, 1])));
+        assert_eq!(run(vec![push_int(i32::MIN), t(I_BYTES)], bytes(&[])), Ok(bytes(&[0x80, 0, 0, 0])));
+        assert_eq!(run(vec![push_nat(128), t(I_BYTES)], bytes(&[])), Ok(bytes(&[0x80])));
+        assert_eq!(run(vec![push_nat(256), t(I_BYTES), t(I_NAT), t(I_INT)], Node::Int(0)), Ok(Node::Int(256)));
+
+        let concat = vec![prim(I_NIL, vec![t(T_bytes)]), t(I_CONCAT)];
+        assert_eq!(run(concat.clone(), bytes(&[0x01])), Ok(bytes(&[])));
+        let lambda = prim(I_LAMBDA, vec![t(T_unit), t(T_bytes), Node::Seq([vec![t(I_DROP)], concat].concat())]);
+        assert_eq!(run(vec![lambda, t(I_UNIT), t(I_EXEC)], bytes(&[0x01])), Ok(bytes(&[])));
+    }
+}
@@ -0,0 +1,134 @@
+// Gas accounting for the interpreter.
+//
+// Costs are expressed in milligas, as in the protocol, and approximate its
+// cost model: a fixed cost per instruction, an interpretation overhead per
+// step, and costs proportional to the size of encoded values for
+// serialization and hashing.
+
+use crate::michelson_v1_primitives::Primitive;
+use crate::michelson_v1_primitives::Primitive::*;
+
+/// Costs, in milligas, of the operations performed by the interpreter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostTable {
+    /// Cost of each instruction, indexed by its tag.
+    pub instructions: Vec<u64>,
+    /// Overhead of interpreting any instruction.
+    pub step: u64,
+    /// Cost per byte of deserializing scripts and values, including the
+    /// script, parameter and storage and `UNPACK`.
+    pub decoding_per_byte: u64,
+    /// Cost per byte of serializing values, including the resulting
+    /// storage and `PACK`.
+    pub encoding_per_byte: u64,
+    /// Cost per byte hashed by hashing instructions.
+    pub hashing_per_byte: u64,
+}
+
+impl CostTable {
+    pub fn instruction(&self, instruction: &Primitive) -> u64 {
+        self.instructions.get(instruction.to_int_enum() as usize).copied().unwrap_or(0)
+    }
+
+    pub fn set_instruction(&mut self, instruction: &Primitive, cost: u64) {
+        let index = instruction.to_int_enum() as usize;
+        if index >= self.instructions.len() {
+            self.instructions.resize(index + 1, 0);
+        }
+        self.instructions[index] = cost;
+    }
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        let mut table = CostTable {
+            instructions: vec![10; 256],
+            step: 10,
+            decoding_per_byte: 60,
+            encoding_per_byte: 20,
+            hashing_per_byte: 2,
+        };
+        let costs = [
+            (I_ADD, 35), (I_SUB, 35), (I_SUB_MUTEZ, 20), (I_MUL, 200), (I_EDIV, 300), (I_NEG, 25),
+            (I_ABS, 25), (I_LSL, 40), (I_LSR, 40), (I_COMPARE, 50), (I_CONCAT, 50), (I_SLICE, 30),
+            (I_EMPTY_SET, 300), (I_EMPTY_MAP, 300), (I_EMPTY_BIG_MAP, 300), (I_MEM, 200), (I_GET, 200),
+            (I_UPDATE, 250), (I_GET_AND_UPDATE, 300), (I_MAP, 20), (I_ITER, 20), (I_EXEC, 20),
+            (I_APPLY, 150), (I_PACK, 100), (I_UNPACK, 100), (I_BLAKE2B, 450), (I_SHA256, 600),
+            (I_SHA512, 700), (I_KECCAK, 1500), (I_SHA3, 1500), (I_HASH_KEY, 650),
+            (I_CHECK_SIGNATURE, 65000), (I_CONTRACT, 30000), (I_VIEW, 1500), (I_TRANSFER_TOKENS, 60),
+            (I_SET_DELEGATE, 60), (I_CREATE_CONTRACT, 60), (I_EMIT, 30), (I_SPLIT_TICKET, 40),
+            (I_JOIN_TICKETS, 80), (I_VOTING_POWER, 640), (I_TOTAL_VOTING_POWER, 450),
+        ];
+        for (instruction, cost) in costs.iter() {
+            table.set_instruction(instruction, *cost);
+        }
+        table
+    }
+}
+
+/// Tracks the gas consumed by an execution against a limit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GasMeter {
+    pub costs: CostTable,
+    /// Limit in milligas, if any.
+    limit: Option<u64>,
+    consumed: u64,
+}
+
+impl GasMeter {
+    /// Creates a meter allowing `limit` units of gas.
+    pub fn new(limit: u64, costs: CostTable) -> Self {
+        GasMeter { costs, limit: Some(limit.saturating_mul(1000)), consumed: 0 }
+    }
+
+    pub fn unlimited(costs: CostTable) -> Self {
+        GasMeter { costs, limit: None, consumed: 0 }
+    }
+
+    /// Consumes `milligas`, and returns false if this exceeds the limit.
+    pub fn consume(&mut self, milligas: u64) -> bool {
+        self.consumed = self.consumed.saturating_add(milligas);
+        self.limit.is_none_or(|limit| self.consumed <= limit)
+    }
+
+    pub fn consumed_milligas(&self) -> u64 {
+        self.consumed
+    }
+
+    /// Consumed gas, rounded up to the next unit.
+    pub fn consumed(&self) -> u64 {
+        self.consumed.div_ceil(1000)
+    }
+
+    /// Remaining milligas, or `None` if the meter is unlimited.
+    pub fn remaining_milligas(&self) -> Option<u64> {
+        self.limit.map(|limit| limit.saturating_sub(self.consumed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::gas::*;
+
+    #[test]
+    fn meter() {
+        let mut costs = CostTable::default();
+        assert_eq!(costs.instruction(&I_MUL), 200);
+        assert_eq!(costs.instruction(&I_DROP), 10);
+        costs.set_instruction(&I_DROP, 500);
+        assert_eq!(costs.instruction(&I_DROP), 500);
+
+        let mut meter = GasMeter::new(1, costs);
+        assert!(meter.consume(999));
+        assert_eq!(meter.consumed(), 1);
+        assert_eq!(meter.remaining_milligas(), Some(1));
+        assert!(meter.consume(1));
+        assert!(!meter.consume(1));
+        assert_eq!(meter.consumed_milligas(), 1001);
+        assert_eq!(meter.remaining_milligas(), Some(0));
+
+        let mut meter = GasMeter::unlimited(CostTable::default());
+        assert!(meter.consume(u64::MAX));
+        assert_eq!(meter.remaining_milligas(), None);
+    }
+}
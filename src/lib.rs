@@ -6,17 +6,27 @@ pub enum Error {
     InvalidInteger,
     InvalidString,
     InvalidPrimitive,
-    InvalidList
+    InvalidList,
+    InvalidArity
 }
 
 pub type Annot = Vec<String>;
 
+#[cfg(feature = "derive")]
+pub use micheline_macros::{micheline, Encodable};
+
 pub trait Encodable {
     fn encode_to_buffer(&self, buffer: &mut Vec<u8>) -> usize;
     fn decode_from_buffer(buffer: &[u8]) -> Option<(Self, usize)> where Self: Sized;
+
+    /// Whether the primitive can be applied to `count` arguments. Decoding
+    /// rejects primitives applied to other numbers of arguments.
+    fn accepts_arity(&self, _count: usize) -> bool {
+        true
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Node<P: Encodable> {
     Int(i32),
     String(std::string::String),
@@ -41,11 +51,11 @@ fn read_int_be(buffer: &[u8]) -> Result<i32, Error>  {
         ((buffer[0] as i32) << 24) |
         ((buffer[1] as i32) << 16) |
         ((buffer[2] as i32) <<  8) |
-        ((buffer[3] as i32)     )
+        (buffer[3] as i32)
     )
 }
 
-fn write_int_be_into_offset(buffer: &mut Vec<u8>, value: i32, offset: usize) -> Result<(), Error> {
+fn write_int_be_into_offset(buffer: &mut [u8], value: i32, offset: usize) -> Result<(), Error> {
     if buffer.len() < offset + 4 {
         return Err(Error::OutOfBounds)
     }
@@ -88,31 +98,31 @@ fn write_zarith(buffer: &mut Vec<u8>, value: i32) -> usize {
 
     let sign = value < 0;
     let mut size = 0;
-    let mut value = value.abs();
+    let mut value = value.unsigned_abs();
 
     let mut first = if value > 0x3f { value & 0x3f | 0x80 } else { value & 0x3f } as u8;
 
     if sign {
-        first = first | 0x40;
+        first |= 0x40;
     }
 
     buffer.push(first);
     size += 1;
-    value = value >> 6;
+    value >>= 6;
 
     while value != 0 {
         let byte = if value > 0x7f { value & 0x7f | 0x80 } else { value & 0x7f } as u8;
         buffer.push(byte);
 
         size += 1;
-        value = value >> 7;
+        value >>= 7;
     }
 
     size
 }
 
 fn read_zarith(buffer: &[u8]) -> Result<(i32, usize), Error> {
-    let mut byte = buffer[0] as i32;
+    let mut byte = *buffer.first().ok_or(Error::OutOfBounds)? as i64;
     let mut value = byte & 0x3f;
     let mut shift = 6;
     let mut index = 1;
@@ -120,23 +130,34 @@ fn read_zarith(buffer: &[u8]) -> Result<(i32, usize), Error> {
     let sign = byte & 0x40 == 0x40;
 
     while (byte & 0x80) == 0x80 {
-        if index >= buffer.len() {
+        if index >= buffer.len() || shift >= 32 {
             return Err(Error::InvalidInteger)
         }
 
-        byte = buffer[index] as i32;
-        value = value | ((byte & 0x7f) << shift);
+        byte = buffer[index] as i64;
+        value |= (byte & 0x7f) << shift;
 
         index += 1;
         shift += 7;
     }
 
-    if sign { Ok((-value, index as usize)) }
-    else { Ok((value, index)) }
+    let value = i32::try_from(if sign { -value } else { value }).map_err(|_| Error::InvalidInteger)?;
+    Ok((value, index))
+}
+
+/// Reads the size prefixing a list or array, rejecting negative sizes.
+fn read_size(buffer: &[u8], error: Error) -> Result<usize, Error> {
+    usize::try_from(read_int_be(buffer)?).map_err(|_| error)
+}
+
+/// The part of `buffer` from `offset`, which sizes read from the buffer may
+/// put past its end.
+fn rest(buffer: &[u8], offset: usize) -> Result<&[u8], Error> {
+    buffer.get(offset..).ok_or(Error::OutOfBounds)
 }
 
 fn read_list<P: Encodable + Debug>(buffer: &[u8]) -> Result<(Vec<Node<P>>, usize), Error> {
-    let size = read_int_be(buffer)? as usize;
+    let size = read_size(buffer, Error::InvalidList)?;
     let mut items = Vec::new();
     let mut offset = 4;
 
@@ -154,12 +175,12 @@ fn read_list<P: Encodable + Debug>(buffer: &[u8]) -> Result<(Vec<Node<P>>, usize
 }
 
 fn read_vec(buffer: &[u8]) -> Result<(Vec<u8>, usize), Error> {
-    let size = read_int_be(buffer)? as usize;
+    let size = read_size(buffer, Error::InvalidString)?;
     if size + 4 > buffer.len() {
         return Err(Error::InvalidString);
     }
 
-    let value = (&buffer[4..size + 4]).to_vec();
+    let value = buffer[4..size + 4].to_vec();
 
     Ok((value, size + 4))
 }
@@ -168,65 +189,67 @@ fn read_annotation(buffer: &[u8]) -> Result<(Vec<String>, usize), Error> {
     let (vec, size) = read_vec(buffer)?;
     let annot = String::from_utf8(vec).map_err(|_| Error::InvalidString)?;
 
-    Ok((annot.split(" ").map(String::from).collect(), size))
+    if annot.is_empty() {
+        return Ok((vec![], size));
+    }
+
+    Ok((annot.split(' ').map(String::from).collect(), size))
 }
 
-fn encode_annotation(buffer: &mut Vec<u8>, annot: &Vec<String>) -> usize {
+fn encode_annotation(buffer: &mut Vec<u8>, annot: &[String]) -> usize {
     // TODO: Different semantics
     let annot = annot.join(" ");
-    write_array(buffer, &annot.as_bytes())
+    write_array(buffer, annot.as_bytes())
 }
 
-fn encode_primitive<P: Encodable + Debug>(buffer: &mut Vec<u8>, prim: &P, args: &Vec<Node<P>>, annot: &Vec<String>) -> usize {
-    let size =
-        match (&args[..], &annot[..]) {
-            ([], []) => {
-                buffer.push(3);
-                prim.encode_to_buffer(buffer) + 1
-            },
-            ([], _) => {
-                buffer.push(4);
-                prim.encode_to_buffer(buffer)
-                + encode_annotation(buffer, annot)
-                + 1
-            },
-            ([arg1], []) => {
-                buffer.push(5);
-                prim.encode_to_buffer(buffer)
-                + arg1.encode_to_buffer(buffer)
-                + 1
-            },
-            ([arg1], _) => {
-                buffer.push(6);
-                prim.encode_to_buffer(buffer)
-                + arg1.encode_to_buffer(buffer)
-                + encode_annotation(buffer, annot)
-                + 1
-            },
-            ([arg1, arg2], []) => {
-                buffer.push(7);
-                prim.encode_to_buffer(buffer)
-                + arg1.encode_to_buffer(buffer)
-                + arg2.encode_to_buffer(buffer)
-                + 1
-            },
-            ([arg1, arg2], _) => {
-                buffer.push(8);
-                prim.encode_to_buffer(buffer)
-                + arg1.encode_to_buffer(buffer)
-                + arg2.encode_to_buffer(buffer)
-                + encode_annotation(buffer, annot)
-                + 1
-            }
-            (_, _) => {
-                buffer.push(9);
-                prim.encode_to_buffer(buffer)
-                + write_list(buffer, args)
-                + encode_annotation(buffer, annot)
-                + 1
-            }
-        };
-    size
+fn encode_primitive<P: Encodable + Debug>(buffer: &mut Vec<u8>, prim: &P, args: &Vec<Node<P>>, annot: &[String]) -> usize {
+    match (&args[..], annot) {
+        ([], []) => {
+            buffer.push(3);
+            prim.encode_to_buffer(buffer) + 1
+        },
+        ([], _) => {
+            buffer.push(4);
+            prim.encode_to_buffer(buffer)
+            + encode_annotation(buffer, annot)
+            + 1
+        },
+        ([arg1], []) => {
+            buffer.push(5);
+            prim.encode_to_buffer(buffer)
+            + arg1.encode_to_buffer(buffer)
+            + 1
+        },
+        ([arg1], _) => {
+            buffer.push(6);
+            prim.encode_to_buffer(buffer)
+            + arg1.encode_to_buffer(buffer)
+            + encode_annotation(buffer, annot)
+            + 1
+        },
+        ([arg1, arg2], []) => {
+            buffer.push(7);
+            prim.encode_to_buffer(buffer)
+            + arg1.encode_to_buffer(buffer)
+            + arg2.encode_to_buffer(buffer)
+            + 1
+        },
+        ([arg1, arg2], _) => {
+            buffer.push(8);
+            prim.encode_to_buffer(buffer)
+            + arg1.encode_to_buffer(buffer)
+            + arg2.encode_to_buffer(buffer)
+            + encode_annotation(buffer, annot)
+            + 1
+        }
+        (_, _) => {
+            buffer.push(9);
+            prim.encode_to_buffer(buffer)
+            + write_list(buffer, args)
+            + encode_annotation(buffer, annot)
+            + 1
+        }
+    }
 }
 
 impl<P: Encodable + Debug> Node<P> {
@@ -238,11 +261,11 @@ impl<P: Encodable + Debug> Node<P> {
             },
             Node::String(v) => {
                 buffer.push(1);
-                write_array(buffer, v.as_bytes())
+                write_array(buffer, v.as_bytes()) + 1
             },
             Node::Bytes(v) => {
                 buffer.push(10);
-                write_array(buffer, v)
+                write_array(buffer, v) + 1
             },
             Node::Seq(v) => {
                 buffer.push(2);
@@ -260,6 +283,14 @@ impl<P: Encodable + Debug> Node<P> {
         buffer
     }
 
+    fn prim(prim: P, args: Vec<Node<P>>, annot: Annot) -> Result<Node<P>, Error> {
+        if !prim.accepts_arity(args.len()) {
+            return Err(Error::InvalidArity)
+        }
+
+        Ok(Node::Prim(prim, args, annot))
+    }
+
     fn from_offset(buffer: &[u8], offset: usize) -> Result<(Node<P>, usize), Error> {
         if offset >= buffer.len() {
             return Err(Error::OutOfBounds)
@@ -267,59 +298,59 @@ impl<P: Encodable + Debug> Node<P> {
 
         match buffer[offset] {
             0 => {
-                let (value, size) = read_zarith(&buffer[offset + 1..])?;
+                let (value, size) = read_zarith(rest(buffer, offset + 1)?)?;
                 Ok((Node::Int(value), size + 1))
             },
             1 => {
-                let (value, size) = read_vec(&buffer[offset + 1..])?;
+                let (value, size) = read_vec(rest(buffer, offset + 1)?)?;
                 let string = String::from_utf8(value).map_err(|_| Error::InvalidString)?;
                 Ok((Node::String(string), size + 1))
             },
             2 => {
-                let (items, size) = read_list(&buffer[offset + 1..])?;
+                let (items, size) = read_list(rest(buffer, offset + 1)?)?;
                 Ok((Node::Seq(items), size + 1))
             },
             3 => {
-                let (prim, size) = P::decode_from_buffer(&buffer[offset + 1..]).ok_or(Error::InvalidPrimitive)?;
-                Ok((Node::Prim(prim, vec![], vec![]), size + 1))
+                let (prim, size) = P::decode_from_buffer(rest(buffer, offset + 1)?).ok_or(Error::InvalidPrimitive)?;
+                Ok((Node::prim(prim, vec![], vec![])?, size + 1))
             },
             4 => {
-                let (prim, prim_size) = P::decode_from_buffer(&buffer[offset + 1..]).ok_or(Error::InvalidPrimitive)?;
-                let (annot, annot_size) = read_annotation(&buffer[offset + prim_size + 1..])?;
-                Ok((Node::Prim(prim, vec![], annot), prim_size + annot_size + 1))
+                let (prim, prim_size) = P::decode_from_buffer(rest(buffer, offset + 1)?).ok_or(Error::InvalidPrimitive)?;
+                let (annot, annot_size) = read_annotation(rest(buffer, offset + prim_size + 1)?)?;
+                Ok((Node::prim(prim, vec![], annot)?, prim_size + annot_size + 1))
             },
             5 => {
-                let (prim, prim_size) = P::decode_from_buffer(&buffer[offset + 1..]).ok_or(Error::InvalidPrimitive)?;
+                let (prim, prim_size) = P::decode_from_buffer(rest(buffer, offset + 1)?).ok_or(Error::InvalidPrimitive)?;
                 let (arg, arg_size) = Node::from_offset(buffer, offset + prim_size + 1)?;
-                Ok((Node::Prim(prim, vec![arg], vec![]), prim_size + arg_size + 1))
+                Ok((Node::prim(prim, vec![arg], vec![])?, prim_size + arg_size + 1))
             },
             6 => {
-                let (prim, prim_size) = P::decode_from_buffer(&buffer[offset + 1..]).ok_or(Error::InvalidPrimitive)?;
+                let (prim, prim_size) = P::decode_from_buffer(rest(buffer, offset + 1)?).ok_or(Error::InvalidPrimitive)?;
                 let (arg, arg_size) = Node::from_offset(buffer, offset + prim_size + 1)?;
-                let (annot, annot_size) = read_annotation(&buffer[offset + prim_size + arg_size + 1..])?;
-                Ok((Node::Prim(prim, vec![arg], annot), prim_size + arg_size + annot_size + 1))
+                let (annot, annot_size) = read_annotation(rest(buffer, offset + prim_size + arg_size + 1)?)?;
+                Ok((Node::prim(prim, vec![arg], annot)?, prim_size + arg_size + annot_size + 1))
             },
             7 => {
-                let (prim, prim_size) = P::decode_from_buffer(&buffer[offset + 1..]).ok_or(Error::InvalidPrimitive)?;
+                let (prim, prim_size) = P::decode_from_buffer(rest(buffer, offset + 1)?).ok_or(Error::InvalidPrimitive)?;
                 let (arg1, arg1_size) = Node::from_offset(buffer, offset + prim_size + 1)?;
                 let (arg2, arg2_size) = Node::from_offset(buffer, offset + prim_size + arg1_size + 1)?;
-                Ok((Node::Prim(prim, vec![arg1, arg2], vec![]), prim_size + arg1_size + arg2_size + 1))
+                Ok((Node::prim(prim, vec![arg1, arg2], vec![])?, prim_size + arg1_size + arg2_size + 1))
             },
             8 => {
-                let (prim, prim_size) = P::decode_from_buffer(&buffer[offset + 1..]).ok_or(Error::InvalidPrimitive)?;
+                let (prim, prim_size) = P::decode_from_buffer(rest(buffer, offset + 1)?).ok_or(Error::InvalidPrimitive)?;
                 let (arg1, arg1_size) = Node::from_offset(buffer, offset + prim_size + 1)?;
                 let (arg2, arg2_size) = Node::from_offset(buffer, offset + prim_size + arg1_size + 1)?;
-                let (annot, annot_size) = read_annotation(&buffer[offset + prim_size + arg1_size + arg2_size + 1..])?;
-                Ok((Node::Prim(prim, vec![arg1, arg2], annot), prim_size + arg1_size + arg2_size + annot_size + 1))
+                let (annot, annot_size) = read_annotation(rest(buffer, offset + prim_size + arg1_size + arg2_size + 1)?)?;
+                Ok((Node::prim(prim, vec![arg1, arg2], annot)?, prim_size + arg1_size + arg2_size + annot_size + 1))
             },
             9 => {
-                let (prim, prim_size) = P::decode_from_buffer(&buffer[offset + 1..]).ok_or(Error::InvalidPrimitive)?;
-                let (args, args_size) = read_list(&buffer[offset + prim_size + 1..])?;
-                let (annot, annot_size) = read_annotation(&buffer[offset + prim_size + args_size + 1..])?;
-                Ok((Node::Prim(prim, args, annot), prim_size + args_size + annot_size))
+                let (prim, prim_size) = P::decode_from_buffer(rest(buffer, offset + 1)?).ok_or(Error::InvalidPrimitive)?;
+                let (args, args_size) = read_list(rest(buffer, offset + prim_size + 1)?)?;
+                let (annot, annot_size) = read_annotation(rest(buffer, offset + prim_size + args_size + 1)?)?;
+                Ok((Node::prim(prim, args, annot)?, prim_size + args_size + annot_size + 1))
             },
             10 => {
-                let (value, size) = read_vec(&buffer[offset + 1..])?;
+                let (value, size) = read_vec(rest(buffer, offset + 1)?)?;
                 Ok((Node::Bytes(value), size + 1))
             }
             _ => Err(Error::InvalidPrimitive)
@@ -333,8 +364,81 @@ impl<P: Encodable + Debug> Node<P> {
 
 }
 
+/// A primitive rejected by the mapping given to `Node::try_map`.
+#[derive(Debug, PartialEq)]
+pub struct MapError<E> {
+    pub path: Path,
+    pub error: E,
+}
+
+fn try_map_children<P: Encodable, Q: Encodable, E>(
+    children: &[Node<P>],
+    f: &mut impl FnMut(&P) -> Result<Q, E>,
+    path: &mut Vec<usize>,
+) -> Result<Vec<Node<Q>>, MapError<E>> {
+    let mut mapped = Vec::with_capacity(children.len());
+    for (index, child) in children.iter().enumerate() {
+        path.push(index);
+        mapped.push(try_map_at(child, f, path)?);
+        path.pop();
+    }
+    Ok(mapped)
+}
+
+fn try_map_at<P: Encodable, Q: Encodable, E>(
+    node: &Node<P>,
+    f: &mut impl FnMut(&P) -> Result<Q, E>,
+    path: &mut Vec<usize>,
+) -> Result<Node<Q>, MapError<E>> {
+    match node {
+        Node::Int(value) => Ok(Node::Int(*value)),
+        Node::String(value) => Ok(Node::String(value.clone())),
+        Node::Bytes(value) => Ok(Node::Bytes(value.clone())),
+        Node::Seq(items) => Ok(Node::Seq(try_map_children(items, f, path)?)),
+        Node::Prim(prim, args, annot) => {
+            let prim = f(prim).map_err(|error| MapError { path: Path::from(path.as_slice()), error })?;
+            Ok(Node::Prim(prim, try_map_children(args, f, path)?, annot.clone()))
+        }
+    }
+}
+
+impl<P: Encodable> Node<P> {
+    /// Converts to another primitive set, keeping the structure and
+    /// annotations. Fails on the first primitive `f` rejects, in prefix
+    /// order.
+    pub fn try_map<Q: Encodable, E>(&self, mut f: impl FnMut(&P) -> Result<Q, E>) -> Result<Node<Q>, MapError<E>> {
+        try_map_at(self, &mut f, &mut Vec::new())
+    }
+
+    /// Converts to another primitive set, keeping the structure and
+    /// annotations.
+    pub fn map<Q: Encodable>(&self, mut f: impl FnMut(&P) -> Q) -> Node<Q> {
+        match self.try_map(|prim| Ok::<Q, std::convert::Infallible>(f(prim))) {
+            Ok(node) => node,
+            Err(error) => match error.error {},
+        }
+    }
+}
+
 pub mod michelson_v1_primitives;
+pub mod base58;
+pub mod hash;
+pub mod typecheck;
+pub mod interpreter;
+pub mod syntax;
+pub mod macros;
+pub mod protocol;
+pub mod dynamic;
+pub mod visit;
+pub mod rewrite;
+pub mod path;
+pub mod pattern;
+pub mod diff;
+pub mod patch;
+pub mod convert;
+pub mod codegen;
 use michelson_v1_primitives::{*};
+use path::Path;
 
 impl Encodable for Primitive {
     fn encode_to_buffer(&self, buffer: &mut Vec<u8>) -> usize {
@@ -343,9 +447,13 @@ impl Encodable for Primitive {
     }
 
     fn decode_from_buffer(buffer: &[u8]) -> Option<(Self, usize)> where Self: Sized {
-        Primitive::from_int_enum(buffer[0])
+        Primitive::from_int_enum(*buffer.first()?)
             .map(|value| (value, 1))
     }
+
+    fn accepts_arity(&self, count: usize) -> bool {
+        self.arity().contains(&count)
+    }
 }
 
 #[cfg(test)]
@@ -361,7 +469,7 @@ mod tests {
         }
 
         fn decode_from_buffer(buffer: &[u8]) -> Option<(Self, usize)> where Self: Sized {
-            if buffer[0] != 0 {
+            if buffer.first() != Some(&0) {
                 return None;
             }
 
@@ -388,6 +496,12 @@ mod tests {
         assert_eq!(Node::<DummyPrimitive>::from(b"\x00\xe3\x89\x8b\x06").unwrap(), Node::Int(-0x616263));
 
         assert_eq!(Node::<DummyPrimitive>::from(b"\x00\xe3\x89\x8b\x86"), Err(Error::InvalidInteger));
+
+        assert_eq!(Node::Int::<DummyPrimitive>(i32::MAX).encode(), b"\x00\xbf\xff\xff\xff\x0f");
+        assert_eq!(Node::Int::<DummyPrimitive>(i32::MIN).encode(), b"\x00\xc0\x80\x80\x80\x10");
+        assert_eq!(Node::<DummyPrimitive>::from(b"\x00\xbf\xff\xff\xff\x0f").unwrap(), Node::Int(i32::MAX));
+        assert_eq!(Node::<DummyPrimitive>::from(b"\x00\xc0\x80\x80\x80\x10").unwrap(), Node::Int(i32::MIN));
+        assert_eq!(Node::<DummyPrimitive>::from(b"\x00\x80\x80\x80\x80\x10"), Err(Error::InvalidInteger));
     }
 
     #[test]
@@ -453,6 +567,19 @@ mod tests {
             Node::<DummyPrimitive>::from(b"\x02\x00\x00\x00\x03\x00\x01\x00\x02"),
             Err(Error::InvalidList)
         );
+
+        assert_eq!(
+            Node::Seq::<DummyPrimitive>(
+                vec![Node::String(String::from("a")), Node::Bytes(b"b".to_vec())]
+            ).encode(),
+            b"\x02\x00\x00\x00\x0c\x01\x00\x00\x00\x01a\x0a\x00\x00\x00\x01b"
+        );
+        assert_eq!(
+            Node::from(b"\x02\x00\x00\x00\x0c\x01\x00\x00\x00\x01a\x0a\x00\x00\x00\x01b").unwrap(),
+            Node::Seq::<DummyPrimitive>(
+                vec![Node::String(String::from("a")), Node::Bytes(b"b".to_vec())]
+            )
+        );
     }
 
     #[test]
@@ -669,14 +796,14 @@ mod tests {
             b"\x09\x00\x00\x00\x00\x06\x00\x2a\x00\x2b\x00\x2c\x00\x00\x00\x0f%annot1 %annot2"
         );
 
-        // assert_eq!(
-        //     Node::from(b"\x09\x00\x00\x00\x00\x06\x00\x2a\x00\x2b\x00\x2c\x00\x00\x00\x00").unwrap(),
-        //     Node::Prim(
-        //         DummyPrimitive,
-        //         vec![Node::Int(42), Node::Int(43), Node::Int(44)],
-        //         vec![]
-        //     )
-        // );
+        assert_eq!(
+            Node::from(b"\x09\x00\x00\x00\x00\x06\x00\x2a\x00\x2b\x00\x2c\x00\x00\x00\x00").unwrap(),
+            Node::Prim(
+                DummyPrimitive,
+                vec![Node::Int(42), Node::Int(43), Node::Int(44)],
+                vec![]
+            )
+        );
         assert_eq!(
             Node::from(b"\x09\x00\x00\x00\x00\x06\x00\x2a\x00\x2b\x00\x2c\x00\x00\x00\x0f%annot1 %annot2").unwrap(),
             Node::Prim(
@@ -686,12 +813,140 @@ mod tests {
             )
         );
 
+        // The size of a generic application covers its tag, so that what
+        // follows it in a sequence is read at the right offset.
+        assert_eq!(
+            Node::from(b"\x02\x00\x00\x00\x0e\x09\x00\x00\x00\x00\x02\x00\x2a\x00\x00\x00\x00\x00\x2b").unwrap(),
+            Node::Seq(vec![
+                Node::Prim(DummyPrimitive, vec![Node::Int(42)], vec![]),
+                Node::Int(43)
+            ])
+        );
+
         assert_eq!(
             Node::<DummyPrimitive>::from(b""),
             Err(Error::OutOfBounds)
         )
     }
 
+    #[test]
+    fn primitive_tags() {
+        use michelson_v1_primitives::Primitive::*;
+
+        let tags = [
+            (K_parameter, 0x00), (K_storage, 0x01), (K_code, 0x02), (D_False, 0x03), (D_Elt, 0x04),
+            (D_Left, 0x05), (D_None, 0x06), (D_Pair, 0x07), (D_Right, 0x08), (D_Some, 0x09), (D_True, 0x0a),
+            (D_Unit, 0x0b), (I_PACK, 0x0c), (I_UNPACK, 0x0d), (I_BLAKE2B, 0x0e), (I_SHA256, 0x0f),
+            (I_SHA512, 0x10), (I_ABS, 0x11), (I_ADD, 0x12), (I_AMOUNT, 0x13), (I_AND, 0x14),
+            (I_BALANCE, 0x15), (I_CAR, 0x16), (I_CDR, 0x17), (I_CHECK_SIGNATURE, 0x18), (I_COMPARE, 0x19),
+            (I_CONCAT, 0x1a), (I_CONS, 0x1b), (I_CREATE_ACCOUNT, 0x1c), (I_CREATE_CONTRACT, 0x1d),
+            (I_IMPLICIT_ACCOUNT, 0x1e), (I_DIP, 0x1f), (I_DROP, 0x20), (I_DUP, 0x21), (I_EDIV, 0x22),
+            (I_EMPTY_MAP, 0x23), (I_EMPTY_SET, 0x24), (I_EQ, 0x25), (I_EXEC, 0x26), (I_FAILWITH, 0x27),
+            (I_GE, 0x28), (I_GET, 0x29), (I_GT, 0x2a), (I_HASH_KEY, 0x2b), (I_IF, 0x2c), (I_IF_CONS, 0x2d),
+            (I_IF_LEFT, 0x2e), (I_IF_NONE, 0x2f), (I_INT, 0x30), (I_LAMBDA, 0x31), (I_LE, 0x32),
+            (I_LEFT, 0x33), (I_LOOP, 0x34), (I_LSL, 0x35), (I_LSR, 0x36), (I_LT, 0x37), (I_MAP, 0x38),
+            (I_MEM, 0x39), (I_MUL, 0x3a), (I_NEG, 0x3b), (I_NEQ, 0x3c), (I_NIL, 0x3d), (I_NONE, 0x3e),
+            (I_NOT, 0x3f), (I_NOW, 0x40), (I_OR, 0x41), (I_PAIR, 0x42), (I_PUSH, 0x43), (I_RIGHT, 0x44),
+            (I_SIZE, 0x45), (I_SOME, 0x46), (I_SOURCE, 0x47), (I_SENDER, 0x48), (I_SELF, 0x49),
+            (I_STEPS_TO_QUOTA, 0x4a), (I_SUB, 0x4b), (I_SWAP, 0x4c), (I_TRANSFER_TOKENS, 0x4d),
+            (I_SET_DELEGATE, 0x4e), (I_UNIT, 0x4f), (I_UPDATE, 0x50), (I_XOR, 0x51), (I_ITER, 0x52),
+            (I_LOOP_LEFT, 0x53), (I_ADDRESS, 0x54), (I_CONTRACT, 0x55), (I_ISNAT, 0x56), (I_CAST, 0x57),
+            (I_RENAME, 0x58), (T_bool, 0x59), (T_contract, 0x5a), (T_int, 0x5b), (T_key, 0x5c),
+            (T_key_hash, 0x5d), (T_lambda, 0x5e), (T_list, 0x5f), (T_map, 0x60), (T_big_map, 0x61),
+            (T_nat, 0x62), (T_option, 0x63), (T_or, 0x64), (T_pair, 0x65), (T_set, 0x66),
+            (T_signature, 0x67), (T_string, 0x68), (T_bytes, 0x69), (T_mutez, 0x6a), (T_timestamp, 0x6b),
+            (T_unit, 0x6c), (T_operation, 0x6d), (T_address, 0x6e), (I_SLICE, 0x6f), (I_DIG, 0x70),
+            (I_DUG, 0x71), (I_EMPTY_BIG_MAP, 0x72), (I_APPLY, 0x73), (T_chain_id, 0x74), (I_CHAIN_ID, 0x75),
+            (I_LEVEL, 0x76), (I_SELF_ADDRESS, 0x77), (T_never, 0x78), (I_NEVER, 0x79), (I_UNPAIR, 0x7a),
+            (I_VOTING_POWER, 0x7b), (I_TOTAL_VOTING_POWER, 0x7c), (I_KECCAK, 0x7d), (I_SHA3, 0x7e),
+            (I_PAIRING_CHECK, 0x7f), (T_bls12_381_g1, 0x80), (T_bls12_381_g2, 0x81), (T_bls12_381_fr, 0x82),
+            (T_sapling_state, 0x83), (T_sapling_transaction_deprecated, 0x84), (I_SAPLING_EMPTY_STATE, 0x85),
+            (I_SAPLING_VERIFY_UPDATE, 0x86), (T_ticket, 0x87), (I_TICKET_DEPRECATED, 0x88),
+            (I_READ_TICKET, 0x89), (I_SPLIT_TICKET, 0x8a), (I_JOIN_TICKETS, 0x8b), (I_GET_AND_UPDATE, 0x8c),
+            (T_chest, 0x8d), (T_chest_key, 0x8e), (I_OPEN_CHEST, 0x8f), (I_VIEW, 0x90), (K_view, 0x91),
+            (H_constant, 0x92), (I_SUB_MUTEZ, 0x93), (T_tx_rollup_l2_address, 0x94),
+            (I_MIN_BLOCK_TIME, 0x95), (T_sapling_transaction, 0x96), (I_EMIT, 0x97), (D_Lambda_rec, 0x98),
+            (I_LAMBDA_REC, 0x99), (I_TICKET, 0x9a), (I_BYTES, 0x9b), (I_NAT, 0x9c), (D_Ticket, 0x9d),
+            (I_IS_IMPLICIT_ACCOUNT, 0x9e), (I_INDEX_ADDRESS, 0x9f), (I_GET_ADDRESS_INDEX, 0xa0),
+        ];
+        for (prim, tag) in tags.iter() {
+            assert_eq!(prim.to_int_enum(), *tag, "{:?}", prim);
+            assert_eq!(michelson_v1_primitives::Primitive::from_int_enum(*tag).as_ref(), Some(prim));
+        }
+        assert_eq!(michelson_v1_primitives::Primitive::from_int_enum(tags.len() as u8), None);
+
+        assert_eq!(Node::Prim(I_LAMBDA_REC, vec![], vec![]).encode(), b"\x03\x99");
+        assert_eq!(Node::<michelson_v1_primitives::Primitive>::from(b"\x03\x9a").unwrap(), Node::Prim(I_TICKET, vec![], vec![]));
+        assert_eq!(Node::<michelson_v1_primitives::Primitive>::from(b"\x03\xa1"), Err(Error::InvalidPrimitive));
+    }
+
+    #[test]
+    fn primitive_metadata() {
+        use michelson_v1_primitives::{AnnotationKind, Namespace};
+        use michelson_v1_primitives::Primitive::*;
+
+        for tag in 0..=u8::MAX {
+            if let Some(prim) = Primitive::from_int_enum(tag) {
+                assert_eq!(prim.to_string().parse(), Ok(prim));
+            }
+        }
+        assert_eq!(I_LAMBDA_REC.to_string(), "LAMBDA_REC");
+        assert_eq!("Lambda_rec".parse(), Ok(D_Lambda_rec));
+        assert_eq!("view".parse(), Ok(K_view));
+        assert_eq!("VIEW".parse(), Ok(I_VIEW));
+        assert_eq!("DUUP".parse::<Primitive>(), Err(Error::InvalidPrimitive));
+
+        assert_eq!(K_view.namespace(), Namespace::Keyword);
+        assert_eq!(D_Ticket.namespace(), Namespace::Constructor);
+        assert_eq!(I_TICKET.namespace(), Namespace::Instruction);
+        assert_eq!(T_ticket.namespace(), Namespace::Type);
+        assert_eq!(H_constant.namespace(), Namespace::Constant);
+
+        assert!(D_Pair.arity().contains(&5));
+        assert!(!D_Pair.arity().contains(&1));
+        assert_eq!(I_DIP.arity(), 1..=2);
+        assert_eq!(I_ADD.arity(), 0..=0);
+        assert_eq!(I_CAR.annotations(), &[AnnotationKind::Variable, AnnotationKind::Field]);
+        assert_eq!(D_Some.annotations(), &[]);
+
+        // `ADD 1` and `Pair 1`
+        assert_eq!(Node::<Primitive>::from(b"\x05\x12\x00\x01"), Err(Error::InvalidArity));
+        assert_eq!(Node::<Primitive>::from(b"\x05\x07\x00\x01"), Err(Error::InvalidArity));
+        assert_eq!(Node::<Primitive>::from(b"\x05\x09\x00\x01"), Ok(Node::Prim(D_Some, vec![Node::Int(1)], vec![])));
+    }
+
+    #[test]
+    fn mapping() {
+        use dynamic::DynPrimitive;
+        use michelson_v1_primitives::Primitive::{D_Pair, D_Some, I_DROP};
+
+        let node = Node::Seq(vec![
+            Node::Prim(DynPrimitive(32), vec![], vec![String::from("@x")]),
+            Node::Prim(DynPrimitive(7), vec![
+                Node::Int(1),
+                Node::Prim(DynPrimitive(9), vec![Node::Prim(DynPrimitive(0xf0), vec![], vec![])], vec![]),
+            ], vec![]),
+        ]);
+        let to_primitive = |prim: &DynPrimitive| prim.primitive().ok_or(prim.0);
+        assert_eq!(node.try_map(to_primitive), Err(MapError { path: Path(vec![1, 1, 0]), error: 0xf0 }));
+
+        let known = Node::Prim(DynPrimitive(7), vec![Node::Prim(DynPrimitive(32), vec![], vec![String::from("@x")]), Node::Int(1)], vec![]);
+        let mapped = known.try_map(to_primitive);
+        assert_eq!(
+            mapped,
+            Ok(Node::Prim(D_Pair, vec![Node::Prim(I_DROP, vec![], vec![String::from("@x")]), Node::Int(1)], vec![]))
+        );
+        assert_eq!(mapped.unwrap().map(|prim| DynPrimitive::from(*prim)), known);
+
+        // Primitives are mapped before their arguments.
+        let mut visited = Vec::new();
+        Node::Prim(D_Pair, vec![Node::Prim(D_Some, vec![Node::Int(1)], vec![])], vec![]).map(|prim| {
+            visited.push(*prim);
+            *prim
+        });
+        assert_eq!(visited, vec![D_Pair, D_Some]);
+    }
+
     #[test]
     fn michelson_v1_primitives() {
         use michelson_v1_primitives::Primitive::{D_Pair, I_PUSH, I_ADD, T_nat};
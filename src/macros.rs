@@ -0,0 +1,1014 @@
+// Expansion of Michelson macros, following the Tezos client.
+//
+// Macros are expanded on concrete syntax, recursively: a macro is replaced
+// by a sequence of instructions, in which macros are expanded in turn.
+
+use std::collections::BTreeMap;
+
+use crate::syntax::Expr;
+use crate::path::Path;
+
+#[derive(Debug, PartialEq)]
+pub enum MacroErrorKind {
+    InvalidArity { name: String, given: usize, expected: usize },
+    /// The index a `CAR n` or `CDR n` stands for does not fit an integer.
+    Overflow(String),
+    SequenceExpected(String),
+    UnexpectedAnnotation(String),
+}
+
+/// Error in a macro application. The path is that of the macro in the
+/// expression being expanded.
+#[derive(Debug, PartialEq)]
+pub struct MacroError {
+    pub path: Path,
+    pub kind: MacroErrorKind,
+}
+
+type Expansion = Result<Option<Expr>, MacroErrorKind>;
+
+fn prim(name: &str, args: Vec<Expr>, annots: Vec<String>) -> Expr {
+    Expr::Prim(name.to_string(), args, annots)
+}
+
+fn instr(name: &str) -> Expr {
+    prim(name, vec![], vec![])
+}
+
+fn annotated(name: &str, annots: &[&str]) -> Expr {
+    prim(name, vec![], annots.iter().map(|annot| annot.to_string()).collect())
+}
+
+fn arity(name: &str, args: &[Expr], expected: usize) -> Result<(), MacroErrorKind> {
+    if args.len() == expected {
+        Ok(())
+    } else {
+        Err(MacroErrorKind::InvalidArity { name: name.to_string(), given: args.len(), expected })
+    }
+}
+
+fn no_annotation(name: &str, annots: &[String]) -> Result<(), MacroErrorKind> {
+    if annots.is_empty() {
+        Ok(())
+    } else {
+        Err(MacroErrorKind::UnexpectedAnnotation(name.to_string()))
+    }
+}
+
+fn letters(name: &str, start: usize, end: usize, allowed: &[u8]) -> bool {
+    start <= end && name.as_bytes()[start..=end].iter().all(|c| allowed.contains(c))
+}
+
+/// Splits field annotations from the others.
+fn field_annotations(annots: &[String]) -> (Vec<String>, Vec<String>) {
+    annots.iter().cloned().partition(|annot| annot.starts_with('%'))
+}
+
+fn single_field_annotation(name: &str, annots: &[String]) -> Result<(Option<String>, Vec<String>), MacroErrorKind> {
+    let (mut fields, annots) = field_annotations(annots);
+    match fields.len() {
+        0 | 1 => Ok((fields.pop(), annots)),
+        _ => Err(MacroErrorKind::UnexpectedAnnotation(name.to_string())),
+    }
+}
+
+fn expand_carn(expr: &Expr) -> Expansion {
+    match expr {
+        Expr::Prim(name, args, annots) if (name == "CAR" || name == "CDR") && args.len() == 1 => match args[0] {
+            Expr::Int(n) => {
+                let index = n.checked_mul(2).and_then(|index| if name == "CAR" { index.checked_add(1) } else { Some(index) });
+                let index = index.ok_or_else(|| MacroErrorKind::Overflow(name.clone()))?;
+                Ok(Some(Expr::Seq(vec![prim("GET", vec![Expr::Int(index)], annots.clone())])))
+            }
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// `CA(A|D)*R` and `CD(A|D)*R`.
+fn expand_caddadr(expr: &Expr) -> Expansion {
+    let (name, args, annots) = match expr {
+        Expr::Prim(name, args, annots) => (name, args, annots),
+        _ => return Ok(None),
+    };
+    let len = name.len();
+    if !(len > 3 && name.starts_with('C') && name.ends_with('R') && letters(name, 1, len - 2, b"AD")) {
+        return Ok(None);
+    }
+    arity(name, args, 0)?;
+
+    let path_annots: Vec<String> = annots.iter().filter(|annot| *annot == "@%" || *annot == "@%%").cloned().collect();
+    let items = name.as_bytes()[1..len - 1]
+        .iter()
+        .enumerate()
+        .map(|(index, letter)| {
+            let annots = if index == len - 3 { annots.clone() } else { path_annots.clone() };
+            prim(if *letter == b'A' { "CAR" } else { "CDR" }, vec![], annots)
+        })
+        .collect();
+    Ok(Some(Expr::Seq(items)))
+}
+
+/// Wraps the update of a nested field into the updates of the pairs along
+/// its path, for `SET_C[AD]+R` and `MAP_C[AD]+R`.
+fn rebuild_path(name: &str, annots: Vec<String>, init: Expr) -> Expr {
+    let bytes = name.as_bytes();
+    let mut acc = init;
+    for i in (5..bytes.len() - 2).rev() {
+        let mut pair_annots = vec![String::from("%@"), String::from("%@")];
+        if i == 5 {
+            pair_annots.extend(annots.iter().cloned());
+        }
+        acc = if bytes[i] == b'A' {
+            Expr::Seq(vec![
+                instr("DUP"),
+                prim("DIP", vec![Expr::Seq(vec![annotated("CAR", &["@%%"]), acc])], vec![]),
+                annotated("CDR", &["@%%"]),
+                instr("SWAP"),
+                prim("PAIR", vec![], pair_annots),
+            ])
+        } else {
+            Expr::Seq(vec![
+                instr("DUP"),
+                prim("DIP", vec![Expr::Seq(vec![annotated("CDR", &["@%%"]), acc])], vec![]),
+                annotated("CAR", &["@%%"]),
+                prim("PAIR", vec![], pair_annots),
+            ])
+        };
+    }
+    acc
+}
+
+fn field_path_macro(name: &str, prefix: &str) -> bool {
+    let len = name.len();
+    len >= 7 && name.starts_with(prefix) && name.ends_with('R') && letters(name, 5, len - 2, b"AD")
+}
+
+fn expand_set_caddadr(expr: &Expr) -> Expansion {
+    let (name, args, annots) = match expr {
+        Expr::Prim(name, args, annots) if field_path_macro(name, "SET_C") => (name, args, annots),
+        _ => return Ok(None),
+    };
+    arity(name, args, 0)?;
+    let (field, annots) = single_field_annotation(name, annots)?;
+
+    let is_car = name.as_bytes()[name.len() - 2] == b'A';
+    let mut items = Vec::new();
+    if let Some(field) = &field {
+        items.push(instr("DUP"));
+        items.push(annotated(if is_car { "CAR" } else { "CDR" }, &[field]));
+        items.push(instr("DROP"));
+    }
+    let field = field.unwrap_or_else(|| String::from("%"));
+    if is_car {
+        items.push(annotated("CDR", &["@%%"]));
+        items.push(instr("SWAP"));
+        items.push(annotated("PAIR", &[&field, "%@"]));
+    } else {
+        items.push(annotated("CAR", &["@%%"]));
+        items.push(annotated("PAIR", &["%@", &field]));
+    }
+    Ok(Some(rebuild_path(name, annots, Expr::Seq(items))))
+}
+
+fn expand_map_caddadr(expr: &Expr) -> Expansion {
+    let (name, args, annots) = match expr {
+        Expr::Prim(name, args, annots) if field_path_macro(name, "MAP_C") => (name, args, annots),
+        _ => return Ok(None),
+    };
+    let code = match &args[..] {
+        [code @ Expr::Seq(_)] => code.clone(),
+        [_] => return Err(MacroErrorKind::SequenceExpected(name.clone())),
+        _ => return Err(MacroErrorKind::InvalidArity { name: name.clone(), given: args.len(), expected: 1 }),
+    };
+    let (field, annots) = single_field_annotation(name, annots)?;
+
+    let variable: Vec<String> = field.iter().map(|field| format!("@{}", &field[1..])).collect();
+    let field = field.unwrap_or_else(|| String::from("%"));
+    let init = if name.as_bytes()[name.len() - 2] == b'A' {
+        Expr::Seq(vec![
+            instr("DUP"),
+            annotated("CDR", &["@%%"]),
+            prim("DIP", vec![Expr::Seq(vec![prim("CAR", vec![], variable), code])], vec![]),
+            instr("SWAP"),
+            annotated("PAIR", &[&field, "%@"]),
+        ])
+    } else {
+        Expr::Seq(vec![
+            instr("DUP"),
+            prim("CDR", vec![], variable),
+            code,
+            instr("SWAP"),
+            annotated("CAR", &["@%%"]),
+            annotated("PAIR", &["%@", &field]),
+        ])
+    };
+    Ok(Some(rebuild_path(name, annots, init)))
+}
+
+/// `DI+P`, superseded by `DIP n`.
+fn expand_deprecated_dxiiivp(expr: &Expr) -> Expansion {
+    let (name, args, annots) = match expr {
+        Expr::Prim(name, args, annots) => (name, args, annots),
+        _ => return Ok(None),
+    };
+    let len = name.len();
+    if !(len > 3 && name.starts_with('D') && name.ends_with('P') && letters(name, 1, len - 2, b"I")) {
+        return Ok(None);
+    }
+    let code = match &args[..] {
+        [code @ Expr::Seq(_)] => code.clone(),
+        [_] => return Err(MacroErrorKind::SequenceExpected(name.clone())),
+        _ => return Err(MacroErrorKind::InvalidArity { name: name.clone(), given: args.len(), expected: 1 }),
+    };
+    Ok(Some(dip(len - 2, code, annots.clone())))
+}
+
+/// Binary tree described by the letters of `PAPPAIIR`-like macros, with
+/// the position of each `P` in the name.
+enum PairItem {
+    Left,
+    Right,
+    Pair(usize, Box<PairItem>, Box<PairItem>),
+}
+
+fn parse_pair_item(name: &[u8], i: usize, left: bool) -> Option<(usize, PairItem)> {
+    if i + 1 >= name.len() {
+        return None;
+    }
+    match name[i] {
+        b'P' => {
+            let (next, l) = parse_pair_item(name, i + 1, true)?;
+            let (next, r) = parse_pair_item(name, next, false)?;
+            Some((next, PairItem::Pair(i, Box::new(l), Box::new(r))))
+        }
+        b'A' if left => Some((i + 1, PairItem::Left)),
+        b'I' if !left => Some((i + 1, PairItem::Right)),
+        _ => None,
+    }
+}
+
+/// Parses the letters from `start` to the final `R`.
+fn parse_pair(name: &str, start: usize) -> Option<PairItem> {
+    match parse_pair_item(name.as_bytes(), start, false)? {
+        (last, item) if last == name.len() - 1 => Some(item),
+        _ => None,
+    }
+}
+
+/// Assigns field annotations, in order, to the leaves of the tree, and
+/// returns the annotations of the left and right leaves of each pair.
+fn pair_field_annotations(
+    item: &PairItem,
+    position: usize,
+    fields: &mut std::vec::IntoIter<String>,
+    positions: &mut BTreeMap<usize, (Vec<String>, Vec<String>)>,
+) {
+    match item {
+        PairItem::Pair(i, left, right) => {
+            pair_field_annotations(left, *i, fields, positions);
+            pair_field_annotations(right, *i, fields, positions);
+        }
+        PairItem::Left | PairItem::Right => {
+            if let Some(field) = fields.next() {
+                let entry = positions.entry(position).or_default();
+                if let PairItem::Left = item {
+                    entry.0 = vec![field];
+                } else {
+                    entry.1 = vec![field];
+                }
+            }
+        }
+    }
+}
+
+/// Visits the pairs of the tree in prefix order, with the number of leaves
+/// to their left.
+fn visit_pairs(item: &PairItem, depth: usize, f: &mut impl FnMut(usize, usize)) -> usize {
+    match item {
+        PairItem::Pair(i, left, right) => {
+            f(*i, depth);
+            let depth = visit_pairs(left, depth, f);
+            visit_pairs(right, depth, f)
+        }
+        PairItem::Left | PairItem::Right => depth + 1,
+    }
+}
+
+/// `DIP code`, or `DIP depth code` below the top of the stack.
+fn dip(depth: usize, code: Expr, annots: Vec<String>) -> Expr {
+    if depth == 1 {
+        prim("DIP", vec![code], annots)
+    } else {
+        prim("DIP", vec![Expr::Int(depth as i32), code], annots)
+    }
+}
+
+fn at_depth(depth: usize, instruction: Expr) -> Expr {
+    if depth == 0 {
+        instruction
+    } else {
+        dip(depth, Expr::Seq(vec![instruction]), vec![])
+    }
+}
+
+/// `P(A|I|P...)+R`, building nested pairs.
+fn expand_pappaiiir(expr: &Expr) -> Expansion {
+    let (name, args, annots) = match expr {
+        Expr::Prim(name, args, annots) => (name, args, annots),
+        _ => return Ok(None),
+    };
+    let len = name.len();
+    if !(len > 4 && name.starts_with('P') && name.ends_with('R') && letters(name, 1, len - 2, b"PAI")) {
+        return Ok(None);
+    }
+    let tree = match parse_pair(name, 0) {
+        Some(tree) => tree,
+        None => return Ok(None),
+    };
+    arity(name, args, 0)?;
+
+    let (fields, annots) = field_annotations(annots);
+    let mut positions = BTreeMap::new();
+    pair_field_annotations(&tree, 0, &mut fields.into_iter(), &mut positions);
+
+    let mut items = Vec::new();
+    visit_pairs(&tree, 0, &mut |i, depth| {
+        let mut pair_annots = match positions.get(&i) {
+            None => vec![],
+            Some((car, cdr)) if car.is_empty() => [vec![String::from("%")], cdr.clone()].concat(),
+            Some((car, cdr)) => [car.clone(), cdr.clone()].concat(),
+        };
+        if i == 0 {
+            pair_annots.extend(annots.iter().cloned());
+        }
+        items.push(at_depth(depth, prim("PAIR", vec![], pair_annots)));
+    });
+    items.reverse();
+    Ok(Some(Expr::Seq(items)))
+}
+
+/// `UNP(A|I|P...)+R`, destructuring nested pairs.
+fn expand_unpappaiiir(expr: &Expr) -> Expansion {
+    let (name, args) = match expr {
+        Expr::Prim(name, args, _) => (name, args),
+        _ => return Ok(None),
+    };
+    let len = name.len();
+    // `UNPAIR` itself is an instruction.
+    if !(len > 6 && name.starts_with("UNP") && name.ends_with('R') && letters(name, 3, len - 2, b"PAI")) {
+        return Ok(None);
+    }
+    let tree = match parse_pair(name, 2) {
+        Some(tree) => tree,
+        None => return Ok(None),
+    };
+    arity(name, args, 0)?;
+
+    let mut items = Vec::new();
+    visit_pairs(&tree, 0, &mut |_, depth| items.push(at_depth(depth, instr("UNPAIR"))));
+    Ok(Some(Expr::Seq(items)))
+}
+
+/// `DU+P`, superseded by `DUP n`.
+fn expand_deprecated_duuuuup(expr: &Expr) -> Expansion {
+    let (name, args, annots) = match expr {
+        Expr::Prim(name, args, annots) => (name, args, annots),
+        _ => return Ok(None),
+    };
+    let len = name.len();
+    if !(len > 3 && name.starts_with('D') && name.ends_with('P') && letters(name, 1, len - 2, b"U")) {
+        return Ok(None);
+    }
+    arity(name, args, 0)?;
+    Ok(Some(prim("DUP", vec![Expr::Int(len as i32 - 2)], annots.clone())))
+}
+
+const COMPARISONS: [&str; 6] = ["EQ", "NEQ", "LT", "GT", "LE", "GE"];
+
+/// `CMP*`, `IFCMP*` and `IF*` for each comparison.
+fn expand_compare(expr: &Expr) -> Expansion {
+    let (name, args, annots) = match expr {
+        Expr::Prim(name, args, annots) => (name, args, annots),
+        _ => return Ok(None),
+    };
+    if let Some(comparison) = name.strip_prefix("CMP").filter(|c| COMPARISONS.contains(c)) {
+        arity(name, args, 0)?;
+        return Ok(Some(Expr::Seq(vec![instr("COMPARE"), prim(comparison, vec![], annots.clone())])));
+    }
+
+    let (comparison, compare) = match name.strip_prefix("IFCMP") {
+        Some(comparison) => (comparison, true),
+        None => match name.strip_prefix("IF") {
+            Some(comparison) => (comparison, false),
+            None => return Ok(None),
+        },
+    };
+    if !COMPARISONS.contains(&comparison) {
+        return Ok(None);
+    }
+    arity(name, args, 2)?;
+    let mut items = Vec::new();
+    if compare {
+        items.push(instr("COMPARE"));
+    }
+    items.push(instr(comparison));
+    items.push(prim("IF", args.clone(), annots.clone()));
+    Ok(Some(Expr::Seq(items)))
+}
+
+fn fail() -> Expr {
+    Expr::Seq(vec![instr("FAIL")])
+}
+
+fn rename(annots: &[String]) -> Expr {
+    if annots.is_empty() {
+        Expr::Seq(vec![])
+    } else {
+        Expr::Seq(vec![prim("RENAME", vec![], annots.to_vec())])
+    }
+}
+
+fn expand_asserts(expr: &Expr) -> Expansion {
+    let (name, args, annots) = match expr {
+        Expr::Prim(name, args, annots) if name.starts_with("ASSERT") => (name, args, annots),
+        _ => return Ok(None),
+    };
+    let branch = |instruction: &str, branches: Vec<Expr>| Expr::Seq(vec![prim(instruction, branches, vec![])]);
+
+    let expansion = match name.as_str() {
+        "ASSERT" => branch("IF", vec![rename(&[]), fail()]),
+        "ASSERT_NONE" => branch("IF_NONE", vec![rename(&[]), fail()]),
+        "ASSERT_SOME" => branch("IF_NONE", vec![fail(), rename(annots)]),
+        "ASSERT_LEFT" => branch("IF_LEFT", vec![rename(annots), fail()]),
+        "ASSERT_RIGHT" => branch("IF_LEFT", vec![fail(), rename(annots)]),
+        _ => {
+            let comparison = match name.strip_prefix("ASSERT_") {
+                Some(comparison) if !comparison.is_empty() => comparison,
+                _ => return Ok(None),
+            };
+            no_annotation(name, annots)?;
+            arity(name, args, 0)?;
+            let test = if COMPARISONS.contains(&comparison) {
+                instr(comparison)
+            } else {
+                match expand_compare(&instr(comparison))? {
+                    Some(test) => test,
+                    None => return Ok(None),
+                }
+            };
+            return Ok(Some(Expr::Seq(vec![test, prim("IF", vec![rename(&[]), fail()], vec![])])));
+        }
+    };
+    if !matches!(name.as_str(), "ASSERT_SOME" | "ASSERT_LEFT" | "ASSERT_RIGHT") {
+        no_annotation(name, annots)?;
+    }
+    arity(name, args, 0)?;
+    Ok(Some(expansion))
+}
+
+fn expand_if_some(expr: &Expr) -> Expansion {
+    match expr {
+        Expr::Prim(name, args, annots) if name == "IF_SOME" || name == "IF_RIGHT" => {
+            arity(name, args, 2)?;
+            let instruction = if name == "IF_SOME" { "IF_NONE" } else { "IF_LEFT" };
+            Ok(Some(Expr::Seq(vec![prim(instruction, vec![args[1].clone(), args[0].clone()], annots.clone())])))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn expand_fail(expr: &Expr) -> Expansion {
+    match expr {
+        Expr::Prim(name, args, annots) if name == "FAIL" && args.is_empty() && annots.is_empty() => {
+            Ok(Some(Expr::Seq(vec![instr("UNIT"), instr("FAILWITH")])))
+        }
+        _ => Ok(None),
+    }
+}
+
+const EXPANDERS: [fn(&Expr) -> Expansion; 12] = [
+    expand_carn,
+    expand_caddadr,
+    expand_set_caddadr,
+    expand_map_caddadr,
+    expand_deprecated_dxiiivp,
+    expand_pappaiiir,
+    expand_unpappaiiir,
+    expand_deprecated_duuuuup,
+    expand_compare,
+    expand_asserts,
+    expand_if_some,
+    expand_fail,
+];
+
+fn expand_at(expr: &Expr, path: &mut Vec<usize>, in_expansion: bool) -> Result<Expr, MacroError> {
+    let mut expanded = None;
+    for expander in EXPANDERS.iter() {
+        match expander(expr) {
+            Ok(None) => continue,
+            Ok(Some(expansion)) => {
+                expanded = Some(expansion);
+                break;
+            }
+            Err(kind) => return Err(MacroError { path: Path(path.clone()), kind }),
+        }
+    }
+    // Errors inside expansions are reported at the macro.
+    let in_expansion = in_expansion || expanded.is_some();
+    let expr = expanded.as_ref().unwrap_or(expr);
+
+    let mut expand_all = |items: &[Expr]| -> Result<Vec<Expr>, MacroError> {
+        let mut expanded = Vec::with_capacity(items.len());
+        for (index, item) in items.iter().enumerate() {
+            if !in_expansion {
+                path.push(index);
+            }
+            expanded.push(expand_at(item, path, in_expansion)?);
+            if !in_expansion {
+                path.pop();
+            }
+        }
+        Ok(expanded)
+    };
+    match expr {
+        Expr::Seq(items) => Ok(Expr::Seq(expand_all(items)?)),
+        Expr::Prim(name, args, annots) => Ok(Expr::Prim(name.clone(), expand_all(args)?, annots.clone())),
+        atom => Ok(atom.clone()),
+    }
+}
+
+/// Expands all the macros of an expression into core instructions.
+pub fn expand(expr: &Expr) -> Result<Expr, MacroError> {
+    expand_at(expr, &mut vec![], false)
+}
+
+fn is_instr<'a>(expr: &'a Expr, expected: &str) -> Option<&'a [String]> {
+    match expr {
+        Expr::Prim(name, args, annots) if name == expected && args.is_empty() => Some(annots),
+        _ => None,
+    }
+}
+
+/// `CA(A|D)*R` from `{ CAR ; CDR ; ... }`.
+fn fold_caddadr(items: &[Expr]) -> Vec<Expr> {
+    let mut name = String::from("C");
+    for item in items {
+        match item {
+            Expr::Prim(instruction, args, _) if args.is_empty() && (instruction == "CAR" || instruction == "CDR") => {
+                name.push_str(&instruction[1..2])
+            }
+            _ => return vec![],
+        }
+    }
+    match items.last() {
+        Some(Expr::Prim(_, _, annots)) if items.len() > 1 => {
+            name.push('R');
+            vec![prim(&name, vec![], annots.clone())]
+        }
+        _ => vec![],
+    }
+}
+
+fn fold_carn(items: &[Expr]) -> Vec<Expr> {
+    match items {
+        [Expr::Prim(name, args, annots)] if name == "GET" => match args[..] {
+            [Expr::Int(n)] if n % 2 == 1 => vec![prim("CAR", vec![Expr::Int(n / 2)], annots.clone())],
+            [Expr::Int(n)] => vec![prim("CDR", vec![Expr::Int(n / 2)], annots.clone())],
+            _ => vec![],
+        },
+        _ => vec![],
+    }
+}
+
+/// `SET_C[AD]+R` and `MAP_C[AD]+R`, by following the updates of the pairs
+/// along the path down to the updated field.
+fn fold_set_map_caddadr(items: &[Expr]) -> Vec<Expr> {
+    let mut letters = String::new();
+    let mut annots = vec![];
+    let mut current = items;
+    loop {
+        let (letter, inner, pair) = match current {
+            [dup, Expr::Prim(dip, dip_args, _), access, swap, pair] if is_instr(swap, "SWAP").is_some() => {
+                match (is_instr(dup, "DUP"), &dip[..], &dip_args[..], is_instr(access, "CDR")) {
+                    (Some(_), "DIP", [Expr::Seq(inner)], Some(_)) => ('A', inner, pair),
+                    _ => break,
+                }
+            }
+            [dup, Expr::Prim(dip, dip_args, _), access, pair] => {
+                match (is_instr(dup, "DUP"), &dip[..], &dip_args[..], is_instr(access, "CAR")) {
+                    (Some(_), "DIP", [Expr::Seq(inner)], Some(_)) => ('D', inner, pair),
+                    _ => break,
+                }
+            }
+            _ => break,
+        };
+        match (&inner[..], pair) {
+            ([_, Expr::Seq(next)], Expr::Prim(_, _, pair_annots)) => {
+                if letters.is_empty() {
+                    annots = pair_annots.iter().skip(2).cloned().collect();
+                }
+                letters.push(letter);
+                current = next;
+            }
+            _ => break,
+        }
+    }
+
+    // The last letter and the field annotation are given by the final
+    // `PAIR`, and the code of `MAP` by the instructions before it.
+    let (last, field) = match current.last() {
+        Some(Expr::Prim(name, args, pair_annots)) if name == "PAIR" && args.is_empty() => match &pair_annots[..] {
+            [field, second] if second == "%@" && field != "%@" => ('A', field),
+            [first, field] if first == "%@" => ('D', field),
+            _ => return vec![],
+        },
+        _ => return vec![],
+    };
+    if field != "%" {
+        annots.push(field.clone());
+    }
+    let name = |prefix: &str| format!("{}_C{}{}R", prefix, letters, last);
+
+    let mut candidates = vec![prim(&name("SET"), vec![], annots.clone())];
+    let code = match (last, current) {
+        ('A', [_, _, Expr::Prim(dip, args, _), _, _]) if dip == "DIP" => match &args[..] {
+            [Expr::Seq(inner)] if inner.len() == 2 => Some(inner[1].clone()),
+            _ => None,
+        },
+        ('D', [_, _, code, _, _, _]) => Some(code.clone()),
+        _ => None,
+    };
+    if let Some(code) = code {
+        candidates.push(prim(&name("MAP"), vec![code], annots));
+    }
+    candidates
+}
+
+/// Pairs instructions, possibly under `DIP` or `DIP n`, with the depth at which they
+/// apply.
+fn pair_operations<'a>(items: &'a [Expr], instruction: &str) -> Option<Vec<(usize, &'a [String])>> {
+    let under = |inner: &'a [Expr], depth: usize| match inner {
+        [pair] => is_instr(pair, instruction).map(|annots| (depth, annots)),
+        _ => None,
+    };
+    items
+        .iter()
+        .map(|item| match item {
+            Expr::Prim(name, args, _) if name == "DIP" => match &args[..] {
+                [Expr::Seq(inner)] | [Expr::Int(1), Expr::Seq(inner)] => under(inner, 1),
+                [Expr::Int(depth), Expr::Seq(inner)] if *depth > 1 => under(inner, *depth as usize),
+                _ => None,
+            },
+            item => is_instr(item, instruction).map(|annots| (0, annots)),
+        })
+        .collect()
+}
+
+/// Name of the letters of a pair tree, where leaves are `A` on the left and
+/// `I` on the right.
+fn pair_letters(item: &PairItem, name: &mut String) {
+    match item {
+        PairItem::Pair(_, left, right) => {
+            name.push('P');
+            pair_letters(left, name);
+            pair_letters(right, name);
+        }
+        PairItem::Left => name.push('A'),
+        PairItem::Right => name.push('I'),
+    }
+}
+
+fn with_sides(item: PairItem, left: bool) -> PairItem {
+    match item {
+        PairItem::Pair(i, l, r) => PairItem::Pair(i, Box::new(with_sides(*l, true)), Box::new(with_sides(*r, false))),
+        _ if left => PairItem::Left,
+        _ => PairItem::Right,
+    }
+}
+
+/// `P(A|I|P...)+R` from `PAIR`s at increasing depths.
+fn fold_pappaiiir(items: &[Expr]) -> Vec<Expr> {
+    let operations = match pair_operations(items, "PAIR") {
+        Some(operations) if operations.len() > 1 => operations,
+        _ => return vec![],
+    };
+    // Replays the pairs on a stack of trees.
+    let mut stack: Vec<PairItem> = (0..=operations.len()).map(|_| PairItem::Right).collect();
+    for (depth, _) in operations.iter() {
+        if depth + 1 >= stack.len() {
+            return vec![];
+        }
+        let right = stack.remove(depth + 1);
+        let left = stack.remove(*depth);
+        stack.insert(*depth, PairItem::Pair(0, Box::new(left), Box::new(right)));
+    }
+    let mut name = String::new();
+    pair_letters(&with_sides(stack.remove(0), false), &mut name);
+    name.push('R');
+
+    // Pairs are built in reverse prefix order, in which field annotations
+    // mostly follow the leaves. Other annotations are those of the outer
+    // pair.
+    let annots: Vec<String> = operations.iter().rev().flat_map(|(_, annots)| annots.iter()).filter(|annot| *annot != "%").cloned().collect();
+    let (fields, others) = field_annotations(&annots);
+    vec![prim(&name, vec![], [others, fields].concat()), prim(&name, vec![], vec![])]
+}
+
+/// `UNP(A|I|P...)+R` from `UNPAIR`s at increasing depths.
+fn fold_unpappaiiir(items: &[Expr]) -> Vec<Expr> {
+    let operations = match pair_operations(items, "UNPAIR") {
+        Some(operations) if operations.len() > 1 => operations,
+        _ => return vec![],
+    };
+    // Replays the unpairs, keeping the path of each stack element in the
+    // tree as a sequence of sides.
+    let mut stack: Vec<Vec<bool>> = vec![vec![]];
+    let mut pairs: Vec<Vec<bool>> = vec![];
+    for (depth, _) in operations.iter() {
+        if *depth >= stack.len() {
+            return vec![];
+        }
+        let path = stack.remove(*depth);
+        stack.insert(*depth, [path.clone(), vec![false]].concat());
+        stack.insert(*depth, [path.clone(), vec![true]].concat());
+        pairs.push(path);
+    }
+    fn build(path: Vec<bool>, pairs: &[Vec<bool>], left: bool) -> PairItem {
+        if pairs.contains(&path) {
+            let l = build([path.clone(), vec![true]].concat(), pairs, true);
+            let r = build([path, vec![false]].concat(), pairs, false);
+            PairItem::Pair(0, Box::new(l), Box::new(r))
+        } else if left {
+            PairItem::Left
+        } else {
+            PairItem::Right
+        }
+    }
+    let mut name = String::from("UN");
+    pair_letters(&build(vec![], &pairs, false), &mut name);
+    name.push('R');
+    vec![prim(&name, vec![], vec![])]
+}
+
+fn fold_compare(items: &[Expr]) -> Vec<Expr> {
+    let comparison = |expr: &Expr| match expr {
+        Expr::Prim(name, args, annots) if args.is_empty() && COMPARISONS.contains(&name.as_str()) => Some((name.clone(), annots.clone())),
+        _ => None,
+    };
+    let branch = |expr: &Expr| match expr {
+        Expr::Prim(name, args, annots) if name == "IF" && args.len() == 2 => Some((args.clone(), annots.clone())),
+        _ => None,
+    };
+    let compare = |expr: &Expr| is_instr(expr, "COMPARE").is_some_and(|annots| annots.is_empty());
+    match items {
+        [c, test] if compare(c) => match comparison(test) {
+            Some((name, annots)) => vec![prim(&format!("CMP{}", name), vec![], annots)],
+            None => vec![],
+        },
+        [c, test, branches] if compare(c) => match (comparison(test), branch(branches)) {
+            (Some((name, _)), Some((args, annots))) => vec![prim(&format!("IFCMP{}", name), args, annots)],
+            _ => vec![],
+        },
+        [test, branches] => match (comparison(test), branch(branches)) {
+            (Some((name, _)), Some((args, annots))) => vec![prim(&format!("IF{}", name), args, annots)],
+            _ => vec![],
+        },
+        _ => vec![],
+    }
+}
+
+fn fold_asserts(items: &[Expr]) -> Vec<Expr> {
+    let renamed = |branch: &Expr| match branch {
+        Expr::Seq(items) => match &items[..] {
+            [] => Some(vec![]),
+            [rename] => is_instr(rename, "RENAME").map(|annots| annots.to_vec()),
+            _ => None,
+        },
+        _ => None,
+    };
+    match items {
+        [Expr::Prim(name, args, annots)] if annots.is_empty() && args.len() == 2 => {
+            let (macro_name, annots) = match (name.as_str(), renamed(&args[0]), renamed(&args[1])) {
+                ("IF", _, _) => ("ASSERT", vec![]),
+                ("IF_NONE", Some(_), _) => ("ASSERT_NONE", vec![]),
+                ("IF_NONE", None, Some(annots)) => ("ASSERT_SOME", annots),
+                ("IF_LEFT", Some(annots), _) => ("ASSERT_LEFT", annots),
+                ("IF_LEFT", None, Some(annots)) => ("ASSERT_RIGHT", annots),
+                _ => return vec![],
+            };
+            vec![prim(macro_name, vec![], annots)]
+        }
+        [test, Expr::Prim(name, _, _)] if name == "IF" => match test {
+            Expr::Prim(comparison, _, _) => vec![instr(&format!("ASSERT_{}", comparison))],
+            Expr::Seq(compare) => match fold_compare(compare).pop() {
+                Some(Expr::Prim(comparison, _, _)) => vec![instr(&format!("ASSERT_{}", comparison))],
+                _ => vec![],
+            },
+            _ => vec![],
+        },
+        _ => vec![],
+    }
+}
+
+fn fold_if_some(items: &[Expr]) -> Vec<Expr> {
+    match items {
+        [Expr::Prim(name, args, annots)] if args.len() == 2 => {
+            let macro_name = match name.as_str() {
+                "IF_NONE" => "IF_SOME",
+                "IF_LEFT" => "IF_RIGHT",
+                _ => return vec![],
+            };
+            vec![prim(macro_name, vec![args[1].clone(), args[0].clone()], annots.clone())]
+        }
+        _ => vec![],
+    }
+}
+
+fn fold_fail(items: &[Expr]) -> Vec<Expr> {
+    match items {
+        [unit, failwith] if is_instr(unit, "UNIT") == Some(&[]) && is_instr(failwith, "FAILWITH") == Some(&[]) => vec![instr("FAIL")],
+        _ => vec![],
+    }
+}
+
+type Folder = fn(&[Expr]) -> Vec<Expr>;
+
+const FOLDERS: [Folder; 9] = [
+    fold_fail,
+    fold_asserts,
+    fold_caddadr,
+    fold_carn,
+    fold_set_map_caddadr,
+    fold_pappaiiir,
+    fold_unpappaiiir,
+    fold_compare,
+    fold_if_some,
+];
+
+/// Folds a sequence into the macro it is the expansion of, if any. Each
+/// candidate is checked by expanding it back.
+fn fold_sequence(items: &[Expr]) -> Option<Expr> {
+    FOLDERS
+        .iter()
+        .flat_map(|folder| folder(items))
+        .find(|candidate| matches!(expand(candidate), Ok(Expr::Seq(expansion)) if expansion == items))
+}
+
+fn fold_at(expr: &Expr, instruction: bool) -> Expr {
+    let folded = match expr {
+        Expr::Seq(items) if instruction => fold_sequence(items),
+        _ => None,
+    };
+    match folded.as_ref().unwrap_or(expr) {
+        Expr::Seq(items) => Expr::Seq(items.iter().map(|item| fold_at(item, true)).collect()),
+        Expr::Prim(name, args, annots) => Expr::Prim(name.clone(), args.iter().map(|arg| fold_at(arg, false)).collect(), annots.clone()),
+        atom => atom.clone(),
+    }
+}
+
+/// Replaces the expansions of macros by the macros, inverting `expand` for
+/// printing. Only sequences nested in sequences are folded, since code
+/// arguments of instructions must remain sequences.
+pub fn fold(expr: &Expr) -> Expr {
+    fold_at(expr, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::macros::*;
+    use crate::syntax::{parse, print};
+
+    fn expanded(source: &str) -> Result<Expr, MacroError> {
+        expand(&parse(source).unwrap())
+    }
+
+    fn assert_expands(source: &str, expansion: &str) {
+        assert_eq!(expanded(source), Ok(parse(expansion).unwrap()), "{}", source);
+    }
+
+    #[test]
+    fn accessors() {
+        assert_expands("CAR", "CAR");
+        assert_expands("CADDR @x", "{ CAR ; CDR ; CDR @x }");
+        assert_expands("CDAR @%%", "{ CDR @%% ; CAR @%% }");
+        assert_expands("CAR 2", "{ GET 5 }");
+        assert_expands("CDR 2", "{ GET 4 }");
+        assert_expands("SET_CAR", "{ CDR @%% ; SWAP ; PAIR % %@ }");
+        assert_expands("SET_CDR %b", "{ DUP ; CDR %b ; DROP ; CAR @%% ; PAIR %@ %b }");
+        assert_expands(
+            "SET_CADR",
+            "{ DUP ; DIP { CAR @%% ; { CAR @%% ; PAIR %@ % } } ; CDR @%% ; SWAP ; PAIR %@ %@ }",
+        );
+        assert_expands("MAP_CDR { INT }", "{ DUP ; CDR ; { INT } ; SWAP ; CAR @%% ; PAIR %@ % }");
+        assert_expands(
+            "MAP_CAR %a { ABS }",
+            "{ DUP ; CDR @%% ; DIP { CAR @a ; { ABS } } ; SWAP ; PAIR %a %@ }",
+        );
+    }
+
+    #[test]
+    fn pairs_and_stack() {
+        assert_expands("PAIR", "PAIR");
+        assert_expands("PAPAIR", "{ DIP { PAIR } ; PAIR }");
+        assert_expands("PAPPAIIR", "{ DIP { PAIR } ; DIP { PAIR } ; PAIR }");
+        assert_expands("PPAIPAIR", "{ DIP 2 { PAIR } ; PAIR ; PAIR }");
+        assert_expands("PAPAIR @p %a %b %c", "{ DIP { PAIR %b %c } ; PAIR %a @p }");
+        assert_expands("UNPAIR", "UNPAIR");
+        assert_expands("UNPAPAIR", "{ UNPAIR ; DIP { UNPAIR } }");
+        assert_expands("UNPPAIPAIR", "{ UNPAIR ; UNPAIR ; DIP 2 { UNPAIR } }");
+        assert_expands("PAPIR", "PAPIR");
+        assert_expands("DIIP { DROP }", "DIP 2 { DROP }");
+        assert_expands("DIIIP @d { DROP }", "DIP @d 3 { DROP }");
+        assert_expands("DUUP @x", "DUP @x 2");
+        assert_expands("DUUUP", "DUP 3");
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_expands("CMPLT @b", "{ COMPARE ; LT @b }");
+        assert_expands("IFCMPEQ { FAIL } {}", "{ COMPARE ; EQ ; IF { { UNIT ; FAILWITH } } {} }");
+        assert_expands("IFGT { DROP } { CMPLE }", "{ GT ; IF { DROP } { { COMPARE ; LE } } }");
+        assert_expands("IF_SOME { DROP } { UNIT }", "{ IF_NONE { UNIT } { DROP } }");
+        assert_expands("IF_RIGHT { A } { B }", "{ IF_LEFT { B } { A } }");
+        assert_expands("FAIL", "{ UNIT ; FAILWITH }");
+        assert_expands("ASSERT", "{ IF {} { { UNIT ; FAILWITH } } }");
+        assert_expands("ASSERT_SOME @x", "{ IF_NONE { { UNIT ; FAILWITH } } { RENAME @x } }");
+        assert_expands("ASSERT_LEFT", "{ IF_LEFT {} { { UNIT ; FAILWITH } } }");
+        assert_expands("ASSERT_EQ", "{ EQ ; IF {} { { UNIT ; FAILWITH } } }");
+        assert_expands("ASSERT_CMPGE", "{ { COMPARE ; GE } ; IF {} { { UNIT ; FAILWITH } } }");
+        assert_expands(
+            "{ DUP ; CADR ; IF_SOME { DIIP { FAIL } } {} }",
+            "{ DUP ; { CAR ; CDR } ; { IF_NONE {} { DIP 2 { { UNIT ; FAILWITH } } } } }",
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            expanded("{ DUP ; IF_SOME {} }"),
+            Err(MacroError {
+                path: Path(vec![1]),
+                kind: MacroErrorKind::InvalidArity { name: String::from("IF_SOME"), given: 1, expected: 2 },
+            })
+        );
+        assert_eq!(
+            expanded("{ { CADR 1 } }"),
+            Err(MacroError {
+                path: Path(vec![0, 0]),
+                kind: MacroErrorKind::InvalidArity { name: String::from("CADR"), given: 1, expected: 0 },
+            })
+        );
+        assert_eq!(
+            expanded("MAP_CAR DROP"),
+            Err(MacroError { path: Path(vec![]), kind: MacroErrorKind::SequenceExpected(String::from("MAP_CAR")) })
+        );
+        assert_eq!(
+            expanded("ASSERT @x"),
+            Err(MacroError { path: Path(vec![]), kind: MacroErrorKind::UnexpectedAnnotation(String::from("ASSERT")) })
+        );
+        assert_eq!(
+            expanded("CAR 1073741824"),
+            Err(MacroError { path: Path(vec![]), kind: MacroErrorKind::Overflow(String::from("CAR")) })
+        );
+        assert_eq!(expanded("CDR 1073741823"), Ok(parse("{ GET 2147483646 }").unwrap()));
+        assert_eq!(
+            expanded("IF_SOME { CDAR 1 } {}"),
+            Err(MacroError {
+                path: Path(vec![]),
+                kind: MacroErrorKind::InvalidArity { name: String::from("CDAR"), given: 1, expected: 0 },
+            })
+        );
+    }
+
+    #[test]
+    fn folding() {
+        let macros = [
+            "CADDR @x", "CDAR @%%", "CAR 2", "CDR 3", "SET_CAR", "SET_CDR %b", "SET_CADR", "SET_CDDAR @s %f",
+            "MAP_CDR { INT }", "MAP_CAR %a { ABS }", "MAP_CADR { DROP ; UNIT }", "PAPAIR", "PAPPAIIR", "PPAIPAIR",
+            "PAPAIR @p %a %b %c", "UNPAPAIR", "UNPPAIPAIR", "CMPLT @b",
+            "IFCMPEQ { FAIL } {}", "IFGT { DROP } { CMPLE }", "IF_SOME { DROP } { UNIT }", "IF_RIGHT { A } { B }",
+            "FAIL", "ASSERT", "ASSERT_NONE", "ASSERT_SOME @x", "ASSERT_LEFT", "ASSERT_RIGHT", "ASSERT_EQ",
+            "ASSERT_CMPGE",
+        ];
+        for source in macros {
+            let code = parse(&format!("{{ {} }}", source)).unwrap();
+            let expanded = expand(&code).unwrap();
+            assert_eq!(fold(&expanded), code, "{}", source);
+        }
+
+        let code = parse("{ { CAR ; CDR ; CAR } ; { COMPARE ; EQ ; IF {} { { UNIT ; FAILWITH } } } ; PAIR }").unwrap();
+        assert_eq!(print(&fold(&code)), "{ CADAR ; IFCMPEQ {} { FAIL } ; PAIR }");
+
+        // As compiled by the Tezos client.
+        let compiled = parse("{ { DIP { PAIR } ; PAIR } ; { UNPAIR ; DIP { UNPAIR } } ; DIP 2 { DROP } ; DUP 2 }").unwrap();
+        assert_eq!(print(&fold(&compiled)), "{ PAPAIR ; UNPAPAIR ; DIP 2 { DROP } ; DUP 2 }");
+
+        // Instruction arguments are kept as sequences.
+        let lambda = parse("LAMBDA (pair int int) int { CAR ; CDR }").unwrap();
+        assert_eq!(fold(&lambda), lambda);
+        let not_expansion = parse("{ { CAR ; DUP } ; { UNIT ; FAILWITH @x } }").unwrap();
+        assert_eq!(fold(&not_expansion), not_expansion);
+    }
+}
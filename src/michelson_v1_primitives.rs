@@ -1,162 +1,178 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Clone)]
+use crate::Error;
+
+/// Michelson primitives, whose discriminants are their binary tags.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 #[allow(non_camel_case_types)]
+#[repr(u8)]
 pub enum Primitive {
-  K_parameter,
-  K_storage,
-  K_code,
-  K_view,
-  D_False,
-  D_Elt,
-  D_Left,
-  D_None,
-  D_Pair,
-  D_Right,
-  D_Some,
-  D_True,
-  D_Unit,
-  I_PACK,
-  I_UNPACK,
-  I_BLAKE2B,
-  I_SHA256,
-  I_SHA512,
-  I_ABS,
-  I_ADD,
-  I_AMOUNT,
-  I_AND,
-  I_BALANCE,
-  I_CAR,
-  I_CDR,
-  I_CHAIN_ID,
-  I_CHECK_SIGNATURE,
-  I_COMPARE,
-  I_CONCAT,
-  I_CONS,
-  I_CREATE_ACCOUNT,
-  I_CREATE_CONTRACT,
-  I_IMPLICIT_ACCOUNT,
-  I_DIP,
-  I_DROP,
-  I_DUP,
-  I_VIEW,
-  I_EDIV,
-  I_EMPTY_BIG_MAP,
-  I_EMPTY_MAP,
-  I_EMPTY_SET,
-  I_EQ,
-  I_EXEC,
-  I_APPLY,
-  I_FAILWITH,
-  I_GE,
-  I_GET,
-  I_GET_AND_UPDATE,
-  I_GT,
-  I_HASH_KEY,
-  I_IF,
-  I_IF_CONS,
-  I_IF_LEFT,
-  I_IF_NONE,
-  I_INT,
-  I_LAMBDA,
-  I_LE,
-  I_LEFT,
-  I_LEVEL,
-  I_LOOP,
-  I_LSL,
-  I_LSR,
-  I_LT,
-  I_MAP,
-  I_MEM,
-  I_MUL,
-  I_NEG,
-  I_NEQ,
-  I_NIL,
-  I_NONE,
-  I_NOT,
-  I_NOW,
-  I_MIN_BLOCK_TIME,
-  I_OR,
-  I_PAIR,
-  I_UNPAIR,
-  I_PUSH,
-  I_RIGHT,
-  I_SIZE,
-  I_SOME,
-  I_SOURCE,
-  I_SENDER,
-  I_SELF,
-  I_SELF_ADDRESS,
-  I_SLICE,
-  I_STEPS_TO_QUOTA,
-  I_SUB,
-  I_SUB_MUTEZ,
-  I_SWAP,
-  I_TRANSFER_TOKENS,
-  I_SET_DELEGATE,
-  I_UNIT,
-  I_UPDATE,
-  I_XOR,
-  I_ITER,
-  I_LOOP_LEFT,
-  I_ADDRESS,
-  I_CONTRACT,
-  I_ISNAT,
-  I_CAST,
-  I_RENAME,
-  I_SAPLING_EMPTY_STATE,
-  I_SAPLING_VERIFY_UPDATE,
-  I_DIG,
-  I_DUG,
-  I_NEVER,
-  I_VOTING_POWER,
-  I_TOTAL_VOTING_POWER,
-  I_KECCAK,
-  I_SHA3,
-  I_PAIRING_CHECK,
-  I_TICKET,
-  I_READ_TICKET,
-  I_SPLIT_TICKET,
-  I_JOIN_TICKETS,
-  I_OPEN_CHEST,
-  I_EMIT,
-  T_bool,
-  T_contract,
-  T_int,
-  T_key,
-  T_key_hash,
-  T_lambda,
-  T_list,
-  T_map,
-  T_big_map,
-  T_nat,
-  T_option,
-  T_or,
-  T_pair,
-  T_set,
-  T_signature,
-  T_string,
-  T_bytes,
-  T_mutez,
-  T_timestamp,
-  T_unit,
-  T_operation,
-  T_address,
-  T_tx_rollup_l2_address,
-  T_sapling_transaction,
-  T_sapling_transaction_deprecated,
-  T_sapling_state,
-  T_chain_id,
-  T_never,
-  T_bls12_381_g1,
-  T_bls12_381_g2,
-  T_bls12_381_fr,
-  T_ticket,
-  T_chest_key,
-  T_chest,
-  H_constant
+  K_parameter = 0,
+  K_storage = 1,
+  K_code = 2,
+  K_view = 145,
+  D_False = 3,
+  D_Elt = 4,
+  D_Left = 5,
+  D_None = 6,
+  D_Pair = 7,
+  D_Right = 8,
+  D_Some = 9,
+  D_True = 10,
+  D_Unit = 11,
+  D_Lambda_rec = 152,
+  D_Ticket = 157,
+  I_PACK = 12,
+  I_UNPACK = 13,
+  I_BLAKE2B = 14,
+  I_SHA256 = 15,
+  I_SHA512 = 16,
+  I_ABS = 17,
+  I_ADD = 18,
+  I_AMOUNT = 19,
+  I_AND = 20,
+  I_BALANCE = 21,
+  I_CAR = 22,
+  I_CDR = 23,
+  I_CHAIN_ID = 117,
+  I_CHECK_SIGNATURE = 24,
+  I_COMPARE = 25,
+  I_CONCAT = 26,
+  I_CONS = 27,
+  I_CREATE_ACCOUNT = 28,
+  I_CREATE_CONTRACT = 29,
+  I_IMPLICIT_ACCOUNT = 30,
+  I_IS_IMPLICIT_ACCOUNT = 158,
+  I_INDEX_ADDRESS = 159,
+  I_GET_ADDRESS_INDEX = 160,
+  I_DIP = 31,
+  I_DROP = 32,
+  I_DUP = 33,
+  I_VIEW = 144,
+  I_EDIV = 34,
+  I_EMPTY_BIG_MAP = 114,
+  I_EMPTY_MAP = 35,
+  I_EMPTY_SET = 36,
+  I_EQ = 37,
+  I_EXEC = 38,
+  I_APPLY = 115,
+  I_FAILWITH = 39,
+  I_GE = 40,
+  I_GET = 41,
+  I_GET_AND_UPDATE = 140,
+  I_GT = 42,
+  I_HASH_KEY = 43,
+  I_IF = 44,
+  I_IF_CONS = 45,
+  I_IF_LEFT = 46,
+  I_IF_NONE = 47,
+  I_INT = 48,
+  I_NAT = 156,
+  I_BYTES = 155,
+  I_LAMBDA = 49,
+  I_LAMBDA_REC = 153,
+  I_LE = 50,
+  I_LEFT = 51,
+  I_LEVEL = 118,
+  I_LOOP = 52,
+  I_LSL = 53,
+  I_LSR = 54,
+  I_LT = 55,
+  I_MAP = 56,
+  I_MEM = 57,
+  I_MUL = 58,
+  I_NEG = 59,
+  I_NEQ = 60,
+  I_NIL = 61,
+  I_NONE = 62,
+  I_NOT = 63,
+  I_NOW = 64,
+  I_MIN_BLOCK_TIME = 149,
+  I_OR = 65,
+  I_PAIR = 66,
+  I_UNPAIR = 122,
+  I_PUSH = 67,
+  I_RIGHT = 68,
+  I_SIZE = 69,
+  I_SOME = 70,
+  I_SOURCE = 71,
+  I_SENDER = 72,
+  I_SELF = 73,
+  I_SELF_ADDRESS = 119,
+  I_SLICE = 111,
+  I_STEPS_TO_QUOTA = 74,
+  I_SUB = 75,
+  I_SUB_MUTEZ = 147,
+  I_SWAP = 76,
+  I_TRANSFER_TOKENS = 77,
+  I_SET_DELEGATE = 78,
+  I_UNIT = 79,
+  I_UPDATE = 80,
+  I_XOR = 81,
+  I_ITER = 82,
+  I_LOOP_LEFT = 83,
+  I_ADDRESS = 84,
+  I_CONTRACT = 85,
+  I_ISNAT = 86,
+  I_CAST = 87,
+  I_RENAME = 88,
+  I_SAPLING_EMPTY_STATE = 133,
+  I_SAPLING_VERIFY_UPDATE = 134,
+  I_DIG = 112,
+  I_DUG = 113,
+  I_NEVER = 121,
+  I_VOTING_POWER = 123,
+  I_TOTAL_VOTING_POWER = 124,
+  I_KECCAK = 125,
+  I_SHA3 = 126,
+  I_PAIRING_CHECK = 127,
+  I_TICKET = 154,
+  I_TICKET_DEPRECATED = 136,
+  I_READ_TICKET = 137,
+  I_SPLIT_TICKET = 138,
+  I_JOIN_TICKETS = 139,
+  I_OPEN_CHEST = 143,
+  I_EMIT = 151,
+  T_bool = 89,
+  T_contract = 90,
+  T_int = 91,
+  T_key = 92,
+  T_key_hash = 93,
+  T_lambda = 94,
+  T_list = 95,
+  T_map = 96,
+  T_big_map = 97,
+  T_nat = 98,
+  T_option = 99,
+  T_or = 100,
+  T_pair = 101,
+  T_set = 102,
+  T_signature = 103,
+  T_string = 104,
+  T_bytes = 105,
+  T_mutez = 106,
+  T_timestamp = 107,
+  T_unit = 108,
+  T_operation = 109,
+  T_address = 110,
+  T_tx_rollup_l2_address = 148,
+  T_sapling_transaction = 150,
+  T_sapling_transaction_deprecated = 132,
+  T_sapling_state = 131,
+  T_chain_id = 116,
+  T_never = 120,
+  T_bls12_381_g1 = 128,
+  T_bls12_381_g2 = 129,
+  T_bls12_381_fr = 130,
+  T_ticket = 135,
+  T_chest_key = 142,
+  T_chest = 141,
+  H_constant = 146
 }
 
-const PRIMS: [Primitive; 152] = [
+const PRIMS: [Primitive; 161] = [
     Primitive::K_parameter,
     Primitive::K_storage,
     Primitive::K_code,
@@ -293,7 +309,7 @@ const PRIMS: [Primitive; 152] = [
     Primitive::I_SAPLING_EMPTY_STATE,
     Primitive::I_SAPLING_VERIFY_UPDATE,
     Primitive::T_ticket,
-    Primitive::I_TICKET,
+    Primitive::I_TICKET_DEPRECATED,
     Primitive::I_READ_TICKET,
     Primitive::I_SPLIT_TICKET,
     Primitive::I_JOIN_TICKETS,
@@ -308,27 +324,325 @@ const PRIMS: [Primitive; 152] = [
     Primitive::T_tx_rollup_l2_address,
     Primitive::I_MIN_BLOCK_TIME,
     Primitive::T_sapling_transaction,
-    Primitive::I_EMIT
+    Primitive::I_EMIT,
+    Primitive::D_Lambda_rec,
+    Primitive::I_LAMBDA_REC,
+    Primitive::I_TICKET,
+    Primitive::I_BYTES,
+    Primitive::I_NAT,
+    Primitive::D_Ticket,
+    Primitive::I_IS_IMPLICIT_ACCOUNT,
+    Primitive::I_INDEX_ADDRESS,
+    Primitive::I_GET_ADDRESS_INDEX
 ];
 
+/// The syntactic class of a primitive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+    /// Script sections: `parameter`, `storage`, `code` and `view`.
+    Keyword,
+    /// Data constructors, such as `Pair` or `Some`.
+    Constructor,
+    Instruction,
+    Type,
+    /// Global constant references, `constant`.
+    Constant,
+}
+
+/// The kinds of annotations, by their prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationKind {
+    /// `:type`
+    Type,
+    /// `@variable`
+    Variable,
+    /// `%field`
+    Field,
+}
+
+const UNBOUNDED: usize = usize::MAX;
+
 impl Primitive {
     pub fn to_int_enum(&self) -> u8 {
-        // TODO: we can do a lot better
-        for (index, prim) in PRIMS.iter().enumerate() {
-            if prim == self {
-                return index as u8;
+        *self as u8
+    }
+
+    pub fn from_int_enum(val: u8) -> Option<Primitive> {
+        PRIMS.get(val as usize).copied()
+    }
+
+    /// The primitive named `name` in Michelson source. Usable in constants.
+    pub const fn from_name(name: &str) -> Option<Primitive> {
+        let name = name.as_bytes();
+        let mut index = 0;
+        while index < PRIMS.len() {
+            let candidate = PRIMS[index].name().as_bytes();
+            if candidate.len() == name.len() {
+                let mut position = 0;
+                while position < name.len() && candidate[position] == name[position] {
+                    position += 1;
+                }
+                if position == name.len() {
+                    return Some(PRIMS[index]);
+                }
             }
+            index += 1;
         }
+        None
+    }
 
-        panic!("Should not be reachable");
+    /// The name of the primitive in Michelson source.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Primitive::K_parameter => "parameter",
+            Primitive::K_storage => "storage",
+            Primitive::K_code => "code",
+            Primitive::K_view => "view",
+            Primitive::D_False => "False",
+            Primitive::D_Elt => "Elt",
+            Primitive::D_Left => "Left",
+            Primitive::D_None => "None",
+            Primitive::D_Pair => "Pair",
+            Primitive::D_Right => "Right",
+            Primitive::D_Some => "Some",
+            Primitive::D_True => "True",
+            Primitive::D_Unit => "Unit",
+            Primitive::D_Lambda_rec => "Lambda_rec",
+            Primitive::D_Ticket => "Ticket",
+            Primitive::I_PACK => "PACK",
+            Primitive::I_UNPACK => "UNPACK",
+            Primitive::I_BLAKE2B => "BLAKE2B",
+            Primitive::I_SHA256 => "SHA256",
+            Primitive::I_SHA512 => "SHA512",
+            Primitive::I_ABS => "ABS",
+            Primitive::I_ADD => "ADD",
+            Primitive::I_AMOUNT => "AMOUNT",
+            Primitive::I_AND => "AND",
+            Primitive::I_BALANCE => "BALANCE",
+            Primitive::I_CAR => "CAR",
+            Primitive::I_CDR => "CDR",
+            Primitive::I_CHAIN_ID => "CHAIN_ID",
+            Primitive::I_CHECK_SIGNATURE => "CHECK_SIGNATURE",
+            Primitive::I_COMPARE => "COMPARE",
+            Primitive::I_CONCAT => "CONCAT",
+            Primitive::I_CONS => "CONS",
+            Primitive::I_CREATE_ACCOUNT => "CREATE_ACCOUNT",
+            Primitive::I_CREATE_CONTRACT => "CREATE_CONTRACT",
+            Primitive::I_IMPLICIT_ACCOUNT => "IMPLICIT_ACCOUNT",
+            Primitive::I_IS_IMPLICIT_ACCOUNT => "IS_IMPLICIT_ACCOUNT",
+            Primitive::I_INDEX_ADDRESS => "INDEX_ADDRESS",
+            Primitive::I_GET_ADDRESS_INDEX => "GET_ADDRESS_INDEX",
+            Primitive::I_DIP => "DIP",
+            Primitive::I_DROP => "DROP",
+            Primitive::I_DUP => "DUP",
+            Primitive::I_VIEW => "VIEW",
+            Primitive::I_EDIV => "EDIV",
+            Primitive::I_EMPTY_BIG_MAP => "EMPTY_BIG_MAP",
+            Primitive::I_EMPTY_MAP => "EMPTY_MAP",
+            Primitive::I_EMPTY_SET => "EMPTY_SET",
+            Primitive::I_EQ => "EQ",
+            Primitive::I_EXEC => "EXEC",
+            Primitive::I_APPLY => "APPLY",
+            Primitive::I_FAILWITH => "FAILWITH",
+            Primitive::I_GE => "GE",
+            Primitive::I_GET => "GET",
+            Primitive::I_GET_AND_UPDATE => "GET_AND_UPDATE",
+            Primitive::I_GT => "GT",
+            Primitive::I_HASH_KEY => "HASH_KEY",
+            Primitive::I_IF => "IF",
+            Primitive::I_IF_CONS => "IF_CONS",
+            Primitive::I_IF_LEFT => "IF_LEFT",
+            Primitive::I_IF_NONE => "IF_NONE",
+            Primitive::I_INT => "INT",
+            Primitive::I_NAT => "NAT",
+            Primitive::I_BYTES => "BYTES",
+            Primitive::I_LAMBDA => "LAMBDA",
+            Primitive::I_LAMBDA_REC => "LAMBDA_REC",
+            Primitive::I_LE => "LE",
+            Primitive::I_LEFT => "LEFT",
+            Primitive::I_LEVEL => "LEVEL",
+            Primitive::I_LOOP => "LOOP",
+            Primitive::I_LSL => "LSL",
+            Primitive::I_LSR => "LSR",
+            Primitive::I_LT => "LT",
+            Primitive::I_MAP => "MAP",
+            Primitive::I_MEM => "MEM",
+            Primitive::I_MUL => "MUL",
+            Primitive::I_NEG => "NEG",
+            Primitive::I_NEQ => "NEQ",
+            Primitive::I_NIL => "NIL",
+            Primitive::I_NONE => "NONE",
+            Primitive::I_NOT => "NOT",
+            Primitive::I_NOW => "NOW",
+            Primitive::I_MIN_BLOCK_TIME => "MIN_BLOCK_TIME",
+            Primitive::I_OR => "OR",
+            Primitive::I_PAIR => "PAIR",
+            Primitive::I_UNPAIR => "UNPAIR",
+            Primitive::I_PUSH => "PUSH",
+            Primitive::I_RIGHT => "RIGHT",
+            Primitive::I_SIZE => "SIZE",
+            Primitive::I_SOME => "SOME",
+            Primitive::I_SOURCE => "SOURCE",
+            Primitive::I_SENDER => "SENDER",
+            Primitive::I_SELF => "SELF",
+            Primitive::I_SELF_ADDRESS => "SELF_ADDRESS",
+            Primitive::I_SLICE => "SLICE",
+            Primitive::I_STEPS_TO_QUOTA => "STEPS_TO_QUOTA",
+            Primitive::I_SUB => "SUB",
+            Primitive::I_SUB_MUTEZ => "SUB_MUTEZ",
+            Primitive::I_SWAP => "SWAP",
+            Primitive::I_TRANSFER_TOKENS => "TRANSFER_TOKENS",
+            Primitive::I_SET_DELEGATE => "SET_DELEGATE",
+            Primitive::I_UNIT => "UNIT",
+            Primitive::I_UPDATE => "UPDATE",
+            Primitive::I_XOR => "XOR",
+            Primitive::I_ITER => "ITER",
+            Primitive::I_LOOP_LEFT => "LOOP_LEFT",
+            Primitive::I_ADDRESS => "ADDRESS",
+            Primitive::I_CONTRACT => "CONTRACT",
+            Primitive::I_ISNAT => "ISNAT",
+            Primitive::I_CAST => "CAST",
+            Primitive::I_RENAME => "RENAME",
+            Primitive::I_SAPLING_EMPTY_STATE => "SAPLING_EMPTY_STATE",
+            Primitive::I_SAPLING_VERIFY_UPDATE => "SAPLING_VERIFY_UPDATE",
+            Primitive::I_DIG => "DIG",
+            Primitive::I_DUG => "DUG",
+            Primitive::I_NEVER => "NEVER",
+            Primitive::I_VOTING_POWER => "VOTING_POWER",
+            Primitive::I_TOTAL_VOTING_POWER => "TOTAL_VOTING_POWER",
+            Primitive::I_KECCAK => "KECCAK",
+            Primitive::I_SHA3 => "SHA3",
+            Primitive::I_PAIRING_CHECK => "PAIRING_CHECK",
+            Primitive::I_TICKET => "TICKET",
+            Primitive::I_TICKET_DEPRECATED => "TICKET_DEPRECATED",
+            Primitive::I_READ_TICKET => "READ_TICKET",
+            Primitive::I_SPLIT_TICKET => "SPLIT_TICKET",
+            Primitive::I_JOIN_TICKETS => "JOIN_TICKETS",
+            Primitive::I_OPEN_CHEST => "OPEN_CHEST",
+            Primitive::I_EMIT => "EMIT",
+            Primitive::T_bool => "bool",
+            Primitive::T_contract => "contract",
+            Primitive::T_int => "int",
+            Primitive::T_key => "key",
+            Primitive::T_key_hash => "key_hash",
+            Primitive::T_lambda => "lambda",
+            Primitive::T_list => "list",
+            Primitive::T_map => "map",
+            Primitive::T_big_map => "big_map",
+            Primitive::T_nat => "nat",
+            Primitive::T_option => "option",
+            Primitive::T_or => "or",
+            Primitive::T_pair => "pair",
+            Primitive::T_set => "set",
+            Primitive::T_signature => "signature",
+            Primitive::T_string => "string",
+            Primitive::T_bytes => "bytes",
+            Primitive::T_mutez => "mutez",
+            Primitive::T_timestamp => "timestamp",
+            Primitive::T_unit => "unit",
+            Primitive::T_operation => "operation",
+            Primitive::T_address => "address",
+            Primitive::T_tx_rollup_l2_address => "tx_rollup_l2_address",
+            Primitive::T_sapling_transaction => "sapling_transaction",
+            Primitive::T_sapling_transaction_deprecated => "sapling_transaction_deprecated",
+            Primitive::T_sapling_state => "sapling_state",
+            Primitive::T_chain_id => "chain_id",
+            Primitive::T_never => "never",
+            Primitive::T_bls12_381_g1 => "bls12_381_g1",
+            Primitive::T_bls12_381_g2 => "bls12_381_g2",
+            Primitive::T_bls12_381_fr => "bls12_381_fr",
+            Primitive::T_ticket => "ticket",
+            Primitive::T_chest_key => "chest_key",
+            Primitive::T_chest => "chest",
+            Primitive::H_constant => "constant",
+        }
     }
 
-    pub fn from_int_enum(val: u8) -> Option<Primitive> {
-        let index = val as usize;
-        if index < PRIMS.len() {
-            Some(PRIMS[index].clone())
-        } else {
-            None
+    pub fn namespace(&self) -> Namespace {
+        use Primitive::*;
+        match self {
+            K_parameter | K_storage | K_code | K_view => Namespace::Keyword,
+            D_False | D_Elt | D_Left | D_None | D_Pair | D_Right | D_Some | D_True | D_Unit | D_Lambda_rec | D_Ticket => Namespace::Constructor,
+            T_bool | T_contract | T_int | T_key | T_key_hash | T_lambda | T_list | T_map | T_big_map | T_nat | T_option | T_or | T_pair | T_set | T_signature | T_string | T_bytes | T_mutez | T_timestamp | T_unit | T_operation | T_address | T_tx_rollup_l2_address | T_sapling_transaction | T_sapling_transaction_deprecated | T_sapling_state | T_chain_id | T_never | T_bls12_381_g1 | T_bls12_381_g2 | T_bls12_381_fr | T_ticket | T_chest_key | T_chest => Namespace::Type,
+            H_constant => Namespace::Constant,
+            _ => Namespace::Instruction,
+        }
+    }
+
+    /// The numbers of arguments the primitive can be applied to.
+    pub fn arity(&self) -> RangeInclusive<usize> {
+        use Primitive::*;
+        match self {
+            K_parameter | K_storage | K_code => 1..=1,
+            K_view => 4..=4,
+            D_Left | D_Right | D_Some | D_Lambda_rec => 1..=1,
+            D_Elt => 2..=2,
+            D_Pair => 2..=UNBOUNDED,
+            D_Ticket => 4..=4,
+            I_DROP | I_DUP | I_PAIR | I_UNPAIR | I_GET | I_UPDATE | I_EMIT => 0..=1,
+            I_DIP => 1..=2,
+            I_DIG | I_DUG | I_NIL | I_NONE | I_LEFT | I_RIGHT | I_EMPTY_SET | I_MAP | I_ITER | I_LOOP
+            | I_LOOP_LEFT | I_CAST | I_UNPACK | I_CONTRACT | I_CREATE_CONTRACT | I_SAPLING_EMPTY_STATE => 1..=1,
+            I_PUSH | I_EMPTY_MAP | I_EMPTY_BIG_MAP | I_VIEW | I_IF | I_IF_CONS | I_IF_LEFT | I_IF_NONE => 2..=2,
+            I_LAMBDA | I_LAMBDA_REC => 3..=3,
+            T_option | T_list | T_set | T_contract | T_ticket | T_sapling_state | T_sapling_transaction
+            | T_sapling_transaction_deprecated => 1..=1,
+            T_lambda | T_map | T_big_map | T_or => 2..=2,
+            T_pair => 2..=UNBOUNDED,
+            H_constant => 1..=1,
+            _ => 0..=0,
+        }
+    }
+
+    /// The kinds of annotations the primitive accepts.
+    pub fn annotations(&self) -> &'static [AnnotationKind] {
+        use Primitive::*;
+        const NONE: &[AnnotationKind] = &[];
+        const VARIABLE: &[AnnotationKind] = &[AnnotationKind::Variable];
+        const VARIABLE_FIELD: &[AnnotationKind] = &[AnnotationKind::Variable, AnnotationKind::Field];
+        const ALL: &[AnnotationKind] = &[AnnotationKind::Type, AnnotationKind::Variable, AnnotationKind::Field];
+        const TYPE_FIELD: &[AnnotationKind] = &[AnnotationKind::Type, AnnotationKind::Field];
+        match self.namespace() {
+            Namespace::Keyword | Namespace::Constructor | Namespace::Constant => NONE,
+            Namespace::Type => TYPE_FIELD,
+            Namespace::Instruction => match self {
+                I_DROP | I_SWAP | I_DIP | I_DIG | I_DUG | I_IF | I_IF_CONS | I_IF_LEFT | I_IF_NONE | I_LOOP
+                | I_LOOP_LEFT | I_ITER | I_FAILWITH | I_NEVER => NONE,
+                I_CAR | I_CDR | I_UNPAIR | I_SELF | I_CONTRACT | I_CREATE_CONTRACT => VARIABLE_FIELD,
+                I_PAIR | I_LEFT | I_RIGHT | I_SOME | I_NONE | I_UNIT | I_NIL | I_EMPTY_SET | I_EMPTY_MAP
+                | I_EMPTY_BIG_MAP | I_CAST => ALL,
+                _ => VARIABLE,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Primitive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Primitive {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Primitive::from_name(name).ok_or(Error::InvalidPrimitive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::michelson_v1_primitives::*;
+
+    #[test]
+    fn names_round_trip() {
+        for prim in PRIMS {
+            assert_eq!(Primitive::from_name(prim.name()), Some(prim));
         }
+        const PUSH: Option<Primitive> = Primitive::from_name("PUSH");
+        assert_eq!(PUSH, Some(Primitive::I_PUSH));
+        assert_eq!(Primitive::from_name("push"), None);
+        assert_eq!(Primitive::from_name("PUSHX"), None);
     }
 }
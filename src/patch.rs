@@ -0,0 +1,230 @@
+// Patches: operations on paths of a tree, applied in order.
+//
+// Paths are those of the tree as modified by the previous operations. A
+// patch is serialized as a Micheline sequence with one element per
+// operation, `{ 0 ; path ; node }` to replace, `{ 1 ; path ; node }` to
+// insert and `{ 2 ; path }` to remove, where `path` is the sequence of its
+// indices, so patches share the binary encoding of the trees they apply to.
+
+use std::fmt::Debug;
+
+use crate::{Encodable, Error, Node};
+use crate::diff::{diff_in_place, Alignment, Change};
+use crate::path::{Path, PathError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation<P: Encodable> {
+    /// Replaces the node at `path`.
+    Replace { path: Path, node: Node<P> },
+    /// Inserts a node at `path`, shifting the following siblings.
+    Insert { path: Path, node: Node<P> },
+    /// Removes the node at `path`, shifting the following siblings.
+    Remove { path: Path },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch<P: Encodable> {
+    pub operations: Vec<Operation<P>>,
+}
+
+/// The operation at index `operation` of a patch does not apply.
+#[derive(Debug, PartialEq)]
+pub struct PatchError {
+    pub operation: usize,
+    pub error: PathError,
+}
+
+impl<P: Encodable + Clone + PartialEq> Patch<P> {
+    pub fn new() -> Self {
+        Patch { operations: Vec::new() }
+    }
+
+    /// The patch turning `old` into `new`, from their differences.
+    pub fn between(old: &Node<P>, new: &Node<P>, alignment: Alignment<P>) -> Self {
+        let operations = diff_in_place(old, new, alignment)
+            .into_iter()
+            .map(|(change, path)| match change {
+                Change::Inserted { node, .. } => Operation::Insert { path, node: node.clone() },
+                Change::Deleted { .. } => Operation::Remove { path },
+                Change::Modified { new, .. } => Operation::Replace { path, node: new.clone() },
+            })
+            .collect();
+        Patch { operations }
+    }
+
+    /// The patch applying `self`, then `next`.
+    pub fn then(mut self, next: Patch<P>) -> Self {
+        self.operations.extend(next.operations);
+        self
+    }
+
+    /// Applies the operations in order. On error, `node` holds the result
+    /// of the operations before the failing one.
+    pub fn apply(&self, node: &mut Node<P>) -> Result<(), PatchError> {
+        for (index, operation) in self.operations.iter().enumerate() {
+            let result = match operation {
+                Operation::Replace { path, node: value } => node.replace(path, value.clone()).map(|_| ()),
+                Operation::Insert { path, node: value } => node.insert(path, value.clone()),
+                Operation::Remove { path } => node.remove(path).map(|_| ()),
+            };
+            result.map_err(|error| PatchError { operation: index, error })?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: Encodable + Clone + PartialEq> Default for Patch<P> {
+    fn default() -> Self {
+        Patch::new()
+    }
+}
+
+fn path_node<P: Encodable>(path: &[usize]) -> Result<Node<P>, Error> {
+    path.iter()
+        .map(|index| i32::try_from(*index).map(Node::Int).or(Err(Error::InvalidInteger)))
+        .collect::<Result<_, _>>()
+        .map(Node::Seq)
+}
+
+fn read_path<P: Encodable>(node: &Node<P>) -> Result<Path, Error> {
+    match node {
+        Node::Seq(items) => items
+            .iter()
+            .map(|item| match item {
+                Node::Int(index) if *index >= 0 => Ok(*index as usize),
+                _ => Err(Error::InvalidList),
+            })
+            .collect::<Result<_, _>>()
+            .map(Path),
+        _ => Err(Error::InvalidList),
+    }
+}
+
+impl<P: Encodable + Debug> Patch<P> {
+    /// The patch as a Micheline node. Fails with `Error::InvalidInteger` if
+    /// an index of a path does not fit a Micheline integer.
+    pub fn to_node(self) -> Result<Node<P>, Error> {
+        let operations = self.operations.into_iter().map(|operation| match operation {
+            Operation::Replace { path, node } => Ok(Node::Seq(vec![Node::Int(0), path_node(&path)?, node])),
+            Operation::Insert { path, node } => Ok(Node::Seq(vec![Node::Int(1), path_node(&path)?, node])),
+            Operation::Remove { path } => Ok(Node::Seq(vec![Node::Int(2), path_node(&path)?])),
+        });
+        operations.collect::<Result<_, _>>().map(Node::Seq)
+    }
+
+    pub fn from_node(node: Node<P>) -> Result<Self, Error> {
+        let Node::Seq(items) = node else { return Err(Error::InvalidList) };
+        let mut operations = Vec::with_capacity(items.len());
+        for item in items {
+            let Node::Seq(mut fields) = item else { return Err(Error::InvalidList) };
+            let node = if fields.len() == 3 { fields.pop() } else { None };
+            let operation = match (&fields[..], node) {
+                ([Node::Int(0), path], Some(node)) => Operation::Replace { path: read_path(path)?, node },
+                ([Node::Int(1), path], Some(node)) => Operation::Insert { path: read_path(path)?, node },
+                ([Node::Int(2), path], None) => Operation::Remove { path: read_path(path)? },
+                _ => return Err(Error::InvalidList),
+            };
+            operations.push(operation);
+        }
+        Ok(Patch { operations })
+    }
+
+    pub fn encode(self) -> Result<Vec<u8>, Error> {
+        Ok(self.to_node()?.encode())
+    }
+
+    pub fn from(buffer: &[u8]) -> Result<Self, Error> {
+        Patch::from_node(Node::from(buffer)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::elt_key;
+    use crate::michelson_v1_primitives::Primitive;
+    use crate::path::PathErrorKind;
+    use crate::patch::*;
+    use crate::syntax;
+
+    fn node(source: &str) -> Node<Primitive> {
+        syntax::to_node(&syntax::parse(source).unwrap()).unwrap()
+    }
+
+    fn check(old: &str, new: &str, alignment: fn() -> Alignment<Primitive>) -> Patch<Primitive> {
+        let (old, new) = (node(old), node(new));
+        let patch = Patch::between(&old, &new, alignment());
+        let mut patched = old.clone();
+        assert_eq!(patch.apply(&mut patched), Ok(()));
+        assert_eq!(patched, new);
+        patch
+    }
+
+    #[test]
+    fn reproduces_target() {
+        let patch = check("Pair 1 { 1 ; 2 ; 3 } (Some \"a\")", "Pair 2 { 1 ; 3 } None", || Alignment::Position);
+        assert_eq!(patch.operations, vec![
+            Operation::Replace { path: Path(vec![0]), node: Node::Int(2) },
+            Operation::Replace { path: Path(vec![1, 1]), node: Node::Int(3) },
+            Operation::Remove { path: Path(vec![1, 2]) },
+            Operation::Replace { path: Path(vec![2]), node: node("None") },
+        ]);
+
+        let patch = check(
+            "{ Elt \"a\" 1 ; Elt \"b\" 2 ; Elt \"d\" 4 }",
+            "{ Elt \"b\" 20 ; Elt \"c\" 3 ; Elt \"d\" 4 ; Elt \"e\" 5 }",
+            || Alignment::Key(elt_key),
+        );
+        assert_eq!(patch.operations, vec![
+            Operation::Remove { path: Path(vec![0]) },
+            Operation::Replace { path: Path(vec![0, 1]), node: Node::Int(20) },
+            Operation::Insert { path: Path(vec![1]), node: node("Elt \"c\" 3") },
+            Operation::Insert { path: Path(vec![3]), node: node("Elt \"e\" 5") },
+        ]);
+
+        check("{ 1 ; 2 ; 3 ; 4 }", "{ 0 ; 2 ; 5 ; 6 ; 4 }", || Alignment::Key(elt_key));
+        check("{ { 1 ; 2 } ; 3 }", "{ 0 ; { 2 } ; 3 }", || Alignment::Key(elt_key));
+        check("Unit", "{ Unit }", || Alignment::Position);
+        assert_eq!(check("Unit", "Unit", || Alignment::Position), Patch::new());
+    }
+
+    #[test]
+    fn composition() {
+        let (first, second, third) = (node("{ 1 ; 2 }"), node("{ 2 ; 3 }"), node("{ 3 ; Unit }"));
+        let patch = Patch::between(&first, &second, Alignment::Key(elt_key))
+            .then(Patch::between(&second, &third, Alignment::Key(elt_key)));
+        let mut patched = first.clone();
+        assert_eq!(patch.apply(&mut patched), Ok(()));
+        assert_eq!(patched, third);
+
+        let mut patched = node("Unit");
+        assert_eq!(patch.apply(&mut patched), Err(PatchError {
+            operation: 0,
+            error: PathError { path: Path(vec![0]), kind: PathErrorKind::OutOfRange { length: 0 } },
+        }));
+    }
+
+    #[test]
+    fn serialization() {
+        let patch = Patch {
+            operations: vec![
+                Operation::Replace { path: Path(vec![]), node: node("Pair 1 \"a\"") },
+                Operation::Insert { path: Path(vec![0, 3]), node: Node::Bytes(vec![0xca, 0xfe]) },
+                Operation::Remove { path: Path(vec![1]) },
+            ],
+        };
+        assert_eq!(
+            syntax::print(&syntax::from_node(&patch.clone().to_node().unwrap())),
+            "{ { 0 ; {} ; Pair 1 \"a\" } ; { 1 ; { 0 ; 3 } ; 0xcafe } ; { 2 ; { 1 } } }"
+        );
+        assert_eq!(Patch::from(&patch.clone().encode().unwrap()), Ok(patch));
+
+        let far = Patch::<Primitive> { operations: vec![Operation::Remove { path: Path(vec![0, i32::MAX as usize + 1]) }] };
+        assert_eq!(far.clone().to_node(), Err(Error::InvalidInteger));
+        assert_eq!(far.encode(), Err(Error::InvalidInteger));
+
+        assert_eq!(Patch::<Primitive>::from_node(node("{ { 2 ; { -1 } } }")), Err(Error::InvalidList));
+        assert_eq!(Patch::<Primitive>::from_node(node("{ { 2 ; { 1 } ; Unit } }")), Err(Error::InvalidList));
+        assert_eq!(Patch::<Primitive>::from_node(node("{ { 3 ; {} } }")), Err(Error::InvalidList));
+        assert_eq!(Patch::<Primitive>::from_node(node("Unit")), Err(Error::InvalidList));
+    }
+}
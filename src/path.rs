@@ -0,0 +1,253 @@
+// Addressing of nodes within a tree.
+//
+// Paths are written `/1/0/2`, the root being `/`.
+
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use crate::{Encodable, Node};
+use crate::visit::{children, children_mut};
+
+/// The location of a node in a tree: the list of child indices (in `Prim`
+/// arguments or `Seq` elements) leading to it from the root. Errors, changes
+/// and matches found in trees report where they are with a path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Path(pub Vec<usize>);
+
+impl Path {
+    pub fn root() -> Self {
+        Path(Vec::new())
+    }
+
+    pub fn child(&self, index: usize) -> Self {
+        let mut path = self.clone();
+        path.0.push(index);
+        path
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        self.0.split_last().map(|(_, parent)| Path(parent.to_vec()))
+    }
+}
+
+impl Deref for Path {
+    type Target = [usize];
+
+    fn deref(&self) -> &[usize] {
+        &self.0
+    }
+}
+
+impl From<Vec<usize>> for Path {
+    fn from(indices: Vec<usize>) -> Self {
+        Path(indices)
+    }
+}
+
+impl From<&[usize]> for Path {
+    fn from(indices: &[usize]) -> Self {
+        Path(indices.to_vec())
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("/");
+        }
+        for index in &self.0 {
+            write!(f, "/{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+/// A path string is malformed at byte `position`.
+#[derive(Debug, PartialEq)]
+pub struct ParsePathError {
+    pub position: usize,
+}
+
+impl FromStr for Path {
+    type Err = ParsePathError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "/" => return Ok(Path::root()),
+            "" => return Err(ParsePathError { position: 0 }),
+            _ => {}
+        }
+        let mut indices = Vec::new();
+        let mut position = 0;
+        for segment in value.split('/') {
+            let valid = if position == 0 {
+                segment.is_empty()
+            } else {
+                !segment.is_empty() && segment.bytes().all(|byte| byte.is_ascii_digit())
+            };
+            if !valid {
+                return Err(ParsePathError { position });
+            }
+            if position > 0 {
+                indices.push(segment.parse().map_err(|_| ParsePathError { position })?);
+            }
+            position += segment.len() + 1;
+        }
+        Ok(Path(indices))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PathErrorKind {
+    /// The node is an `Int`, `String` or `Bytes` and has no children.
+    NoChildren,
+    /// The node has only `length` children.
+    OutOfRange { length: usize },
+    /// The root cannot be inserted or removed.
+    Root,
+}
+
+/// A path does not exist. `path` is the prefix of the path up to the first
+/// index that could not be followed.
+#[derive(Debug, PartialEq)]
+pub struct PathError {
+    pub path: Path,
+    pub kind: PathErrorKind,
+}
+
+fn error<T>(path: &[usize], kind: PathErrorKind) -> Result<T, PathError> {
+    Err(PathError { path: Path::from(path), kind })
+}
+
+impl<P: Encodable> Node<P> {
+    pub fn get(&self, path: &[usize]) -> Result<&Node<P>, PathError> {
+        let mut node = self;
+        for (depth, index) in path.iter().enumerate() {
+            node = match node {
+                Node::Prim(..) | Node::Seq(_) => {
+                    let nodes = children(node);
+                    match nodes.get(*index) {
+                        Some(child) => child,
+                        None => return error(&path[..=depth], PathErrorKind::OutOfRange { length: nodes.len() }),
+                    }
+                }
+                _ => return error(&path[..=depth], PathErrorKind::NoChildren),
+            };
+        }
+        Ok(node)
+    }
+
+    pub fn get_mut(&mut self, path: &[usize]) -> Result<&mut Node<P>, PathError> {
+        let mut node = self;
+        for (depth, index) in path.iter().enumerate() {
+            let nodes = match children_mut(node) {
+                Some(nodes) => nodes,
+                None => return error(&path[..=depth], PathErrorKind::NoChildren),
+            };
+            let length = nodes.len();
+            node = match nodes.get_mut(*index) {
+                Some(child) => child,
+                None => return error(&path[..=depth], PathErrorKind::OutOfRange { length }),
+            };
+        }
+        Ok(node)
+    }
+
+    /// Replaces the node at `path`, and returns the previous one.
+    pub fn replace(&mut self, path: &[usize], value: Node<P>) -> Result<Node<P>, PathError> {
+        Ok(std::mem::replace(self.get_mut(path)?, value))
+    }
+
+    fn siblings_mut(&mut self, path: &[usize]) -> Result<(&mut Vec<Node<P>>, usize), PathError> {
+        let (index, parent) = match path.split_last() {
+            Some(split) => split,
+            None => return error(path, PathErrorKind::Root),
+        };
+        match children_mut(self.get_mut(parent)?) {
+            Some(nodes) => Ok((nodes, *index)),
+            None => error(path, PathErrorKind::NoChildren),
+        }
+    }
+
+    /// Inserts `value` so that it ends up at `path`, shifting the following
+    /// siblings. The last index may be the number of children, to append.
+    pub fn insert(&mut self, path: &[usize], value: Node<P>) -> Result<(), PathError> {
+        let (nodes, index) = self.siblings_mut(path)?;
+        if index > nodes.len() {
+            return error(path, PathErrorKind::OutOfRange { length: nodes.len() });
+        }
+        nodes.insert(index, value);
+        Ok(())
+    }
+
+    /// Removes the node at `path`, shifting the following siblings.
+    pub fn remove(&mut self, path: &[usize]) -> Result<Node<P>, PathError> {
+        let (nodes, index) = self.siblings_mut(path)?;
+        if index >= nodes.len() {
+            return error(path, PathErrorKind::OutOfRange { length: nodes.len() });
+        }
+        Ok(nodes.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::michelson_v1_primitives::Primitive;
+    use crate::michelson_v1_primitives::Primitive::*;
+    use crate::path::*;
+
+    fn prim(primitive: Primitive, args: Vec<Node<Primitive>>) -> Node<Primitive> {
+        Node::Prim(primitive, args, vec![])
+    }
+
+    #[test]
+    fn syntax() {
+        assert_eq!("/1/0/2".parse(), Ok(Path(vec![1, 0, 2])));
+        assert_eq!("/".parse(), Ok(Path::root()));
+        assert_eq!(Path(vec![1, 0, 2]).to_string(), "/1/0/2");
+        assert_eq!(Path::root().to_string(), "/");
+        assert_eq!(Path(vec![1]).child(3).parent(), Some(Path(vec![1])));
+        assert_eq!(Path::root().parent(), None);
+
+        assert_eq!("".parse::<Path>(), Err(ParsePathError { position: 0 }));
+        assert_eq!("1/2".parse::<Path>(), Err(ParsePathError { position: 0 }));
+        assert_eq!("/1//2".parse::<Path>(), Err(ParsePathError { position: 3 }));
+        assert_eq!("/1/x".parse::<Path>(), Err(ParsePathError { position: 3 }));
+        assert_eq!("/1/".parse::<Path>(), Err(ParsePathError { position: 3 }));
+        assert_eq!("/99999999999999999999999".parse::<Path>(), Err(ParsePathError { position: 1 }));
+    }
+
+    #[test]
+    fn access() {
+        // `Pair 0 (Pair 1 2 3) { 4 }`
+        let mut node = prim(D_Pair, vec![
+            Node::Int(0),
+            prim(D_Pair, vec![Node::Int(1), Node::Int(2), Node::Int(3)]),
+            Node::Seq(vec![Node::Int(4)]),
+        ]);
+        let path: Path = "/1/2".parse().unwrap();
+        assert_eq!(node.get(&path), Ok(&Node::Int(3)));
+        assert_eq!(node.get(&[]), Ok(&node.clone()));
+        assert_eq!(node.get(&[1, 3]), Err(PathError { path: Path(vec![1, 3]), kind: PathErrorKind::OutOfRange { length: 3 } }));
+        assert_eq!(node.get(&[0, 0, 1]), Err(PathError { path: Path(vec![0, 0]), kind: PathErrorKind::NoChildren }));
+
+        *node.get_mut(&path).unwrap() = Node::Int(30);
+        assert_eq!(node.replace(&[1, 2], Node::Int(300)), Ok(Node::Int(30)));
+        assert_eq!(node.remove(&[1, 0]), Ok(Node::Int(1)));
+        assert_eq!(node.insert(&[2, 1], Node::Int(5)), Ok(()));
+        assert_eq!(node.insert(&[2, 0], Node::Int(3)), Ok(()));
+        assert_eq!(node, prim(D_Pair, vec![
+            Node::Int(0),
+            prim(D_Pair, vec![Node::Int(2), Node::Int(300)]),
+            Node::Seq(vec![Node::Int(3), Node::Int(4), Node::Int(5)]),
+        ]));
+
+        assert_eq!(node.insert(&[2, 4], Node::Int(6)), Err(PathError { path: Path(vec![2, 4]), kind: PathErrorKind::OutOfRange { length: 3 } }));
+        assert_eq!(node.remove(&[2, 3]), Err(PathError { path: Path(vec![2, 3]), kind: PathErrorKind::OutOfRange { length: 3 } }));
+        assert_eq!(node.remove(&[0, 0]), Err(PathError { path: Path(vec![0, 0]), kind: PathErrorKind::NoChildren }));
+        assert_eq!(node.remove(&[]), Err(PathError { path: Path(vec![]), kind: PathErrorKind::Root }));
+        assert_eq!(node.replace(&[], Node::Int(7)).map(|_| ()), Ok(()));
+        assert_eq!(node, Node::Int(7));
+    }
+}
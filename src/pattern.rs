@@ -0,0 +1,344 @@
+// Structural patterns over Michelson expressions.
+//
+// Patterns are written like Michelson expressions, with additions:
+//
+// - `_` matches any node, and `_` applied to arguments or annotations any
+//   primitive with them;
+// - `PUSH | DUP` matches either primitive;
+// - `...` matches any number of arguments or sequence elements;
+// - `$name` captures the node it matches, and `$name=pattern` the node
+//   matched by `pattern`. A name captured twice must match equal nodes;
+// - annotations are filters: `CAR %x` matches a `CAR` annotated at least
+//   with `%x`, and `%_` any field annotation.
+//
+// For instance `{ ... ; PUSH address $a ; CONTRACT unit ; ... }` finds
+// sequences pushing an address to build a `unit` contract, and
+// `big_map address _` big maps whose keys are addresses.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::Node;
+use crate::michelson_v1_primitives::Primitive;
+use crate::path::Path;
+use crate::syntax::{ParseError, ParseErrorKind, Parser, Token};
+use crate::visit::children;
+
+#[derive(Debug, PartialEq)]
+pub enum PatternErrorKind {
+    Syntax(ParseErrorKind),
+    UnknownPrimitive(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PatternError {
+    /// Offset in bytes of the error in the source.
+    pub position: usize,
+    pub kind: PatternErrorKind,
+}
+
+impl From<ParseError> for PatternError {
+    fn from(error: ParseError) -> Self {
+        PatternError { position: error.position, kind: PatternErrorKind::Syntax(error.kind) }
+    }
+}
+
+fn error<T>(position: usize, kind: ParseErrorKind) -> Result<T, PatternError> {
+    Err(PatternError { position, kind: PatternErrorKind::Syntax(kind) })
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Item {
+    Any,
+    /// `...`, only valid in argument lists and sequences.
+    Rest,
+    Int(i32),
+    String(String),
+    Bytes(Vec<u8>),
+    Capture(String, Box<Item>),
+    /// Any of `prims`, or any primitive if empty.
+    Prim { prims: Vec<Primitive>, annots: Vec<String>, args: Vec<Item> },
+    Seq(Vec<Item>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pattern {
+    root: Item,
+}
+
+/// A node matched by a pattern, with the nodes captured in it.
+#[derive(Debug, PartialEq)]
+pub struct Match<'a> {
+    pub path: Path,
+    pub captures: HashMap<String, &'a Node<Primitive>>,
+}
+
+/// Parses `name | name | ...` after its first name.
+fn alternatives(parser: &mut Parser, first: String, position: usize) -> Result<Vec<Primitive>, PatternError> {
+    let mut names = vec![(position, first)];
+    while parser.eat(&Token::Bar) {
+        let position = parser.position();
+        match parser.next()? {
+            Token::Ident(name) if name != "_" => names.push((position, name)),
+            _ => return error(position, ParseErrorKind::UnexpectedToken),
+        }
+    }
+    if names.len() == 1 && names[0].1 == "_" {
+        return Ok(vec![]);
+    }
+    names
+        .into_iter()
+        .map(|(position, name)| match name.parse::<Primitive>() {
+            Ok(prim) => Ok(prim),
+            Err(_) => Err(PatternError { position, kind: PatternErrorKind::UnknownPrimitive(name) }),
+        })
+        .collect()
+}
+
+fn prim(parser: &mut Parser, first: String, position: usize, with_args: bool) -> Result<Item, PatternError> {
+    let any = first == "_";
+    let prims = alternatives(parser, first, position)?;
+    let annots = parser.annotations();
+    let mut args = Vec::new();
+    if with_args {
+        while let Some(
+            Token::Int(_) | Token::String(_) | Token::Bytes(_) | Token::Ident(_) | Token::Capture(_) | Token::Rest | Token::Open(_),
+        ) = parser.peek()
+        {
+            args.push(argument(parser)?);
+        }
+    }
+    if any && prims.is_empty() && annots.is_empty() && args.is_empty() {
+        return Ok(Item::Any);
+    }
+    Ok(Item::Prim { prims, annots, args })
+}
+
+/// Parses a pattern in argument position, where primitives do not take
+/// arguments unless parenthesized.
+fn argument(parser: &mut Parser) -> Result<Item, PatternError> {
+    let position = parser.position();
+    match parser.next()? {
+        Token::Int(value) => Ok(Item::Int(value)),
+        Token::String(value) => Ok(Item::String(value)),
+        Token::Bytes(value) => Ok(Item::Bytes(value)),
+        Token::Rest => Ok(Item::Rest),
+        Token::Ident(name) => prim(parser, name, position, false),
+        Token::Capture(name) => {
+            if parser.eat(&Token::Equals) {
+                let position = parser.position();
+                match argument(parser)? {
+                    Item::Rest => error(position, ParseErrorKind::UnexpectedToken),
+                    item => Ok(Item::Capture(name, Box::new(item))),
+                }
+            } else {
+                Ok(Item::Capture(name, Box::new(Item::Any)))
+            }
+        }
+        Token::Open('{') => {
+            let items = parser.sequence(application)?;
+            parser.close('}')?;
+            Ok(Item::Seq(items))
+        }
+        Token::Open(_) => {
+            let position = parser.position();
+            let item = application(parser)?;
+            if item == Item::Rest {
+                return error(position, ParseErrorKind::UnexpectedToken);
+            }
+            parser.close(')')?;
+            Ok(item)
+        }
+        _ => error(position, ParseErrorKind::UnexpectedToken),
+    }
+}
+
+/// Parses a pattern in which a primitive takes all the arguments that
+/// follow it.
+fn application(parser: &mut Parser) -> Result<Item, PatternError> {
+    let position = parser.position();
+    match parser.peek() {
+        Some(Token::Ident(_)) => match parser.next()? {
+            Token::Ident(name) => prim(parser, name, position, true),
+            _ => unreachable!(),
+        },
+        _ => argument(parser),
+    }
+}
+
+/// Captures in the order they were bound, so that backtracking drops the
+/// ones bound by a failed attempt by truncating.
+type Captures<'p, 'a> = Vec<(&'p str, &'a Node<Primitive>)>;
+
+fn annotation_matches(filter: &str, annots: &[String]) -> bool {
+    match filter.strip_suffix('_') {
+        Some(prefix) if prefix.len() == 1 => annots.iter().any(|annot| annot.starts_with(prefix)),
+        _ => annots.iter().any(|annot| annot == filter),
+    }
+}
+
+fn match_item<'p, 'a>(item: &'p Item, node: &'a Node<Primitive>, captures: &mut Captures<'p, 'a>) -> bool {
+    match (item, node) {
+        (Item::Any, _) => true,
+        (Item::Int(expected), Node::Int(value)) => expected == value,
+        (Item::String(expected), Node::String(value)) => expected == value,
+        (Item::Bytes(expected), Node::Bytes(value)) => expected == value,
+        (Item::Capture(name, item), _) => {
+            if let Some((_, previous)) = captures.iter().find(|(bound, _)| *bound == name) {
+                return *previous == node && match_item(item, node, captures);
+            }
+            if !match_item(item, node, captures) {
+                return false;
+            }
+            captures.push((name, node));
+            true
+        }
+        (Item::Prim { prims, annots: filters, args: items }, Node::Prim(prim, args, annots)) => {
+            (prims.is_empty() || prims.contains(prim))
+                && filters.iter().all(|filter| annotation_matches(filter, annots))
+                && match_list(items, args, captures)
+        }
+        (Item::Seq(items), Node::Seq(nodes)) => match_list(items, nodes, captures),
+        _ => false,
+    }
+}
+
+fn match_list<'p, 'a>(items: &'p [Item], nodes: &'a [Node<Primitive>], captures: &mut Captures<'p, 'a>) -> bool {
+    let Some((item, rest)) = items.split_first() else {
+        return nodes.is_empty();
+    };
+    let attempt = |captures: &mut Captures<'p, 'a>, matched: &dyn Fn(&mut Captures<'p, 'a>) -> bool| {
+        let bound = captures.len();
+        matched(captures) || {
+            captures.truncate(bound);
+            false
+        }
+    };
+    match (item, nodes.split_first()) {
+        (Item::Rest, _) => (0..=nodes.len()).any(|skip| attempt(captures, &|captures| match_list(rest, &nodes[skip..], captures))),
+        (_, Some((node, tail))) => {
+            attempt(captures, &|captures| match_item(item, node, captures) && match_list(rest, tail, captures))
+        }
+        (_, None) => false,
+    }
+}
+
+impl Pattern {
+    /// Matches `node` itself, and returns the captures.
+    pub fn matches<'a>(&self, node: &'a Node<Primitive>) -> Option<HashMap<String, &'a Node<Primitive>>> {
+        let mut captures = Vec::new();
+        match_item(&self.root, node, &mut captures)
+            .then(|| captures.into_iter().map(|(name, node)| (name.to_string(), node)).collect())
+    }
+
+    /// Matches every node of the tree, and returns the matches in prefix
+    /// order.
+    pub fn find<'a>(&self, node: &'a Node<Primitive>) -> Vec<Match<'a>> {
+        let mut matches = Vec::new();
+        let mut stack = vec![(node, Path::root())];
+        while let Some((node, path)) = stack.pop() {
+            for (index, child) in children(node).iter().enumerate().rev() {
+                stack.push((child, path.child(index)));
+            }
+            if let Some(captures) = self.matches(node) {
+                matches.push(Match { path, captures });
+            }
+        }
+        matches
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(source, true)?;
+        let root = application(&mut parser)?;
+        if root == Item::Rest {
+            return error(0, ParseErrorKind::UnexpectedToken);
+        }
+        parser.finish()?;
+        Ok(Pattern { root })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::*;
+    use crate::syntax::node;
+
+    fn pattern(source: &str) -> Pattern {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn matching() {
+        assert!(pattern("_").matches(&Node::Int(1)).is_some());
+        assert!(pattern("Pair _ 2").matches(&node("Pair 1 2")).is_some());
+        assert!(pattern("Pair _ 2").matches(&node("Pair 1 2 3")).is_none());
+        assert!(pattern("Pair _ ...").matches(&node("Pair 1 2 3")).is_some());
+        assert!(pattern("_ 1 ...").matches(&node("Some 1")).is_some());
+        assert!(pattern("_ 1 ...").matches(&node("Pair 1 2")).is_some());
+        assert!(pattern("_ 1 ...").matches(&Node::Int(1)).is_none());
+        assert!(pattern("CAR|CDR").matches(&node("CDR")).is_some());
+        assert!(pattern("CAR|CDR").matches(&node("DUP")).is_none());
+        assert!(pattern("CAR %x").matches(&node("CAR @a %x")).is_some());
+        assert!(pattern("CAR %x").matches(&node("CAR %y")).is_none());
+        assert!(pattern("CAR %_").matches(&node("CAR %y")).is_some());
+        assert!(pattern("CAR %_").matches(&node("CAR @y")).is_none());
+        assert!(pattern("CAR").matches(&node("CAR @y")).is_some());
+        assert!(pattern("big_map address _").matches(&node("big_map address (pair nat nat)")).is_some());
+        assert!(pattern("big_map address _").matches(&node("big_map nat nat")).is_none());
+        assert!(pattern("{ 0x00 ; \"a\" ; -1 }").matches(&node("{ 0x00 ; \"a\" ; -1 }")).is_some());
+        assert!(pattern("PUSH string \"a\\tb\" /* tab */ # escaped").matches(&node("PUSH string \"a\\tb\"")).is_some());
+
+        let pair = node("Pair 1 (Some 2)");
+        let captures = pattern("Pair $a $b=(Some _)").matches(&pair).unwrap();
+        assert_eq!(captures.get("a"), Some(&&Node::Int(1)));
+        assert_eq!(captures.get("b"), Some(&&node("Some 2")));
+        assert!(pattern("Pair $a $a").matches(&node("Pair 1 1")).is_some());
+        assert!(pattern("Pair $a $a").matches(&node("Pair 1 2")).is_none());
+    }
+
+    #[test]
+    fn queries() {
+        let code = node(
+            "{ DROP ; \
+               PUSH address \"tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx\" ; \
+               CONTRACT unit ; \
+               IF_NONE { UNIT ; FAILWITH } { PUSH address \"tz1a\" ; CONTRACT unit ; DROP } ; \
+               PUSH address \"tz1b\" }",
+        );
+        let query = pattern("{ ... ; PUSH address $a ; CONTRACT unit ; ... }");
+        let matches = query.find(&code);
+        assert_eq!(matches.iter().map(|found| found.path.clone()).collect::<Vec<_>>(), vec![Path::root(), Path(vec![3, 1])]);
+        assert_eq!(matches[1].captures.get("a"), Some(&&Node::String(String::from("tz1a"))));
+
+        // Captures bound by a failed alternative are dropped.
+        let matches = pattern("{ ... ; PUSH _ $a ; ... ; PUSH _ $a ; ... }").find(&code);
+        assert_eq!(matches, vec![]);
+
+        assert_eq!(pattern("PUSH address _").find(&code).len(), 3);
+
+        // Backtracking over long sequences keeps only the captures of the
+        // attempt that matched.
+        let long = Node::Seq((0..40).chain([7]).map(Node::Int).collect());
+        let captures = pattern("{ ... ; $a ; ... ; $b ; ... ; $a }").matches(&long).unwrap();
+        assert_eq!(captures.get("a"), Some(&&Node::Int(7)));
+        assert_eq!(captures.len(), 2);
+        assert!(pattern("{ ... ; $a ; ... ; $b ; ... ; $c ; ... ; $a ; $a }").matches(&long).is_none());
+    }
+
+    #[test]
+    fn errors() {
+        let kind = |source: &str| source.parse::<Pattern>().map_err(|error| (error.position, error.kind));
+        assert_eq!(kind("PUSH | FOO"), Err((7, PatternErrorKind::UnknownPrimitive(String::from("FOO")))));
+        assert_eq!(kind("..."), Err((0, PatternErrorKind::Syntax(ParseErrorKind::UnexpectedToken))));
+        assert_eq!(kind("Some $x=..."), Err((8, PatternErrorKind::Syntax(ParseErrorKind::UnexpectedToken))));
+        assert_eq!(kind("{ CAR"), Err((5, PatternErrorKind::Syntax(ParseErrorKind::UnexpectedEnd))));
+        assert_eq!(kind("CAR | _"), Err((6, PatternErrorKind::Syntax(ParseErrorKind::UnexpectedToken))));
+        assert_eq!(kind("Pair 1 ?"), Err((7, PatternErrorKind::Syntax(ParseErrorKind::UnexpectedCharacter('?')))));
+        assert_eq!(kind("Pair $ 1"), Err((5, PatternErrorKind::Syntax(ParseErrorKind::UnexpectedCharacter('$')))));
+        assert_eq!(kind("\"a\\x\""), Err((2, PatternErrorKind::Syntax(ParseErrorKind::InvalidString))));
+    }
+}
@@ -0,0 +1,226 @@
+// Protocol-specific primitive sets.
+//
+// Each protocol accepts the primitives introduced up to it, and keeps
+// accepting deprecated ones so that existing contracts remain readable.
+// Decoding or validating against a protocol rejects primitives it does not
+// know and reports the deprecated ones it meets.
+
+use crate::{Error, Node};
+use crate::michelson_v1_primitives::Primitive;
+use crate::michelson_v1_primitives::Primitive::*;
+use crate::path::Path;
+
+/// Tezos protocols, in activation order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Protocol {
+    Athens,
+    Babylon,
+    Carthage,
+    Delphi,
+    Edo,
+    Florence,
+    Granada,
+    Hangzhou,
+    Ithaca,
+    Jakarta,
+    Kathmandu,
+    Lima,
+    Mumbai,
+    Nairobi,
+    Oxford,
+    Paris,
+    Quebec,
+    Rio,
+    Seoul,
+    Tallinn,
+}
+
+impl Protocol {
+    pub const LATEST: Protocol = Protocol::Tallinn;
+
+    /// Whether `primitive` can appear in expressions of this protocol,
+    /// deprecated or not.
+    pub fn supports(self, primitive: &Primitive) -> bool {
+        primitive.introduced() <= self
+    }
+
+    /// Whether `primitive` is deprecated in this protocol.
+    pub fn deprecates(self, primitive: &Primitive) -> bool {
+        primitive.deprecated().is_some_and(|since| since <= self)
+    }
+}
+
+impl Primitive {
+    /// The first protocol accepting this primitive.
+    pub fn introduced(&self) -> Protocol {
+        match self {
+            I_DIG | I_DUG | I_EMPTY_BIG_MAP | I_APPLY | T_chain_id | I_CHAIN_ID => Protocol::Babylon,
+            I_LEVEL | I_SELF_ADDRESS | T_never | I_NEVER | I_UNPAIR | I_VOTING_POWER | I_TOTAL_VOTING_POWER
+            | I_KECCAK | I_SHA3 | I_PAIRING_CHECK | T_bls12_381_g1 | T_bls12_381_g2 | T_bls12_381_fr
+            | T_sapling_state | T_sapling_transaction_deprecated | I_SAPLING_EMPTY_STATE | I_SAPLING_VERIFY_UPDATE
+            | T_ticket | I_TICKET_DEPRECATED | I_READ_TICKET | I_SPLIT_TICKET | I_JOIN_TICKETS | I_GET_AND_UPDATE => Protocol::Edo,
+            I_VIEW | K_view | H_constant | T_chest | T_chest_key | I_OPEN_CHEST => Protocol::Hangzhou,
+            I_SUB_MUTEZ => Protocol::Ithaca,
+            T_tx_rollup_l2_address | I_MIN_BLOCK_TIME | T_sapling_transaction => Protocol::Jakarta,
+            I_EMIT => Protocol::Kathmandu,
+            D_Lambda_rec | I_LAMBDA_REC | I_TICKET => Protocol::Lima,
+            I_BYTES | I_NAT => Protocol::Mumbai,
+            D_Ticket => Protocol::Oxford,
+            I_IS_IMPLICIT_ACCOUNT => Protocol::Paris,
+            I_INDEX_ADDRESS | I_GET_ADDRESS_INDEX => Protocol::Tallinn,
+            _ => Protocol::Athens,
+        }
+    }
+
+    /// The first protocol deprecating this primitive, if any.
+    pub fn deprecated(&self) -> Option<Protocol> {
+        match self {
+            I_CREATE_ACCOUNT | I_STEPS_TO_QUOTA => Some(Protocol::Babylon),
+            T_sapling_transaction_deprecated => Some(Protocol::Jakarta),
+            I_TICKET_DEPRECATED => Some(Protocol::Lima),
+            T_tx_rollup_l2_address => Some(Protocol::Mumbai),
+            _ => None,
+        }
+    }
+}
+
+/// A primitive unknown to the protocol an expression is validated against.
+#[derive(Debug, PartialEq)]
+pub struct ProtocolError {
+    pub path: Path,
+    pub primitive: Primitive,
+    pub protocol: Protocol,
+}
+
+/// A deprecated primitive found while validating an expression.
+#[derive(Debug, PartialEq)]
+pub struct Deprecation {
+    pub path: Path,
+    pub primitive: Primitive,
+    pub since: Protocol,
+}
+
+fn validate_at(
+    node: &Node<Primitive>,
+    protocol: Protocol,
+    path: &mut Vec<usize>,
+    deprecations: &mut Vec<Deprecation>,
+) -> Result<(), ProtocolError> {
+    let children = match node {
+        Node::Prim(primitive, args, _) => {
+            if !protocol.supports(primitive) {
+                return Err(ProtocolError { path: Path(path.clone()), primitive: *primitive, protocol });
+            }
+            if let Some(since) = primitive.deprecated().filter(|_| protocol.deprecates(primitive)) {
+                deprecations.push(Deprecation { path: Path(path.clone()), primitive: *primitive, since });
+            }
+            args
+        }
+        Node::Seq(items) => items,
+        _ => return Ok(()),
+    };
+    for (index, child) in children.iter().enumerate() {
+        path.push(index);
+        let result = validate_at(child, protocol, path, deprecations);
+        path.pop();
+        result?;
+    }
+    Ok(())
+}
+
+/// Error decoding a binary expression for a protocol.
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    /// The expression is malformed.
+    Decoding(Error),
+    /// The expression is well-formed but uses a primitive unknown to the
+    /// protocol.
+    Protocol(ProtocolError),
+}
+
+/// Checks that every primitive of `node` is accepted by `protocol`, and
+/// returns the deprecated ones, in prefix order.
+pub fn validate(node: &Node<Primitive>, protocol: Protocol) -> Result<Vec<Deprecation>, ProtocolError> {
+    let mut deprecations = Vec::new();
+    validate_at(node, protocol, &mut Vec::new(), &mut deprecations)?;
+    Ok(deprecations)
+}
+
+/// Decodes a binary expression, rejecting primitives unknown to `protocol`
+/// with their path in the expression.
+pub fn decode(buffer: &[u8], protocol: Protocol) -> Result<(Node<Primitive>, Vec<Deprecation>), DecodeError> {
+    let node = Node::from(buffer).map_err(DecodeError::Decoding)?;
+    let deprecations = validate(&node, protocol).map_err(DecodeError::Protocol)?;
+    Ok((node, deprecations))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::*;
+
+    fn t(primitive: Primitive) -> Node<Primitive> {
+        Node::Prim(primitive, vec![], vec![])
+    }
+
+    #[test]
+    fn primitive_sets() {
+        assert!(Protocol::Athens.supports(&I_CREATE_ACCOUNT));
+        assert!(!Protocol::Athens.deprecates(&I_CREATE_ACCOUNT));
+        assert!(Protocol::LATEST.deprecates(&I_CREATE_ACCOUNT));
+        assert!(!Protocol::Kathmandu.supports(&I_LAMBDA_REC));
+        assert!(Protocol::Lima.supports(&I_LAMBDA_REC));
+        assert!(Protocol::Lima.deprecates(&I_TICKET_DEPRECATED));
+        assert!(!Protocol::Kathmandu.deprecates(&I_TICKET_DEPRECATED));
+        assert!(Protocol::Edo.supports(&T_ticket));
+        assert!(!Protocol::Granada.supports(&T_chest));
+        assert!(!Protocol::Granada.supports(&I_OPEN_CHEST));
+        assert!(Protocol::Hangzhou.supports(&T_chest_key));
+
+        // Every primitive is accepted by the latest protocol, and none is
+        // deprecated before being introduced.
+        for tag in 0..=u8::MAX {
+            if let Some(primitive) = Primitive::from_int_enum(tag) {
+                assert!(Protocol::LATEST.supports(&primitive));
+                assert!(primitive.deprecated().is_none_or(|since| since >= primitive.introduced()));
+            }
+        }
+    }
+
+    #[test]
+    fn validation() {
+        let code = Node::Seq(vec![
+            t(I_DROP),
+            Node::Prim(I_PUSH, vec![t(T_tx_rollup_l2_address), Node::String(String::from("tz4"))], vec![]),
+            Node::Prim(I_LAMBDA_REC, vec![t(T_nat), t(T_nat), Node::Seq(vec![t(I_STEPS_TO_QUOTA)])], vec![]),
+        ]);
+        assert_eq!(
+            validate(&code, Protocol::Kathmandu),
+            Err(ProtocolError { path: Path(vec![2]), primitive: I_LAMBDA_REC, protocol: Protocol::Kathmandu })
+        );
+        assert_eq!(
+            validate(&code, Protocol::Lima),
+            Ok(vec![Deprecation { path: Path(vec![2, 2, 0]), primitive: I_STEPS_TO_QUOTA, since: Protocol::Babylon }])
+        );
+        assert_eq!(
+            validate(&code, Protocol::Mumbai).map(|deprecations| deprecations.into_iter().map(|d| d.path).collect::<Vec<_>>()),
+            Ok(vec![Path(vec![1, 0]), Path(vec![2, 2, 0])])
+        );
+
+        // `Lambda_rec` only decodes from Lima on.
+        let encoded = Node::Prim(D_Lambda_rec, vec![Node::Seq(vec![])], vec![]).encode();
+        assert_eq!(
+            decode(&encoded, Protocol::Kathmandu),
+            Err(DecodeError::Protocol(ProtocolError { path: Path(vec![]), primitive: D_Lambda_rec, protocol: Protocol::Kathmandu }))
+        );
+        let nested = Node::Seq(vec![t(I_UNIT), Node::Prim(I_LAMBDA_REC, vec![t(T_nat), t(T_nat), Node::Seq(vec![])], vec![])]).encode();
+        assert_eq!(
+            decode(&nested, Protocol::Kathmandu),
+            Err(DecodeError::Protocol(ProtocolError { path: Path(vec![1]), primitive: I_LAMBDA_REC, protocol: Protocol::Kathmandu }))
+        );
+        assert_eq!(decode(&[0x05], Protocol::Kathmandu), Err(DecodeError::Decoding(Error::InvalidPrimitive)));
+        assert_eq!(
+            decode(&encoded, Protocol::Lima),
+            Ok((Node::Prim(D_Lambda_rec, vec![Node::Seq(vec![])], vec![]), vec![]))
+        );
+    }
+}
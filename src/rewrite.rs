@@ -0,0 +1,338 @@
+// Rewriting of `Node` trees to a fixpoint.
+//
+// Rules are tried on every node of the tree, in registration order, and the
+// first one that applies rewrites the node in place. Passes over the tree
+// repeat until none applies, or until one would apply beyond the step limit
+// for rule sets that do not terminate, which rules tell without rewriting.
+// Rules are closures, or patterns with the replacement of the nodes they
+// match.
+
+use std::collections::HashMap;
+
+use crate::{Encodable, Node};
+use crate::michelson_v1_primitives::Primitive;
+use crate::path::Path;
+use crate::pattern::Pattern;
+use crate::visit::{visit_mut, Visit, VisitorMut};
+
+type Applies<P> = Box<dyn FnMut(&Node<P>) -> bool>;
+type Rewrite<P> = Box<dyn FnMut(&mut Node<P>)>;
+
+/// A rewrite rule: whether it applies to a node, and the in-place rewrite
+/// of the nodes it applies to.
+struct Rule<P: Encodable> {
+    name: String,
+    applies: Applies<P>,
+    rewrite: Rewrite<P>,
+}
+
+/// Where rules are tried first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Nodes are rewritten before their children, which are then those of
+    /// the rewritten node.
+    TopDown,
+    /// Nodes are rewritten after their children.
+    BottomUp,
+}
+
+/// A rule application, at the path of the node it rewrote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Firing {
+    pub rule: String,
+    pub path: Path,
+}
+
+/// A rule applies once the step limit is reached. The node holds the
+/// rewrites done so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepLimitExceeded {
+    pub firings: Vec<Firing>,
+}
+
+pub struct Rewriter<P: Encodable> {
+    pub strategy: Strategy,
+    /// Maximum number of rule applications.
+    pub step_limit: usize,
+    rules: Vec<Rule<P>>,
+}
+
+struct Pass<'a, P: Encodable> {
+    strategy: Strategy,
+    step_limit: usize,
+    rules: &'a mut [Rule<P>],
+    firings: &'a mut Vec<Firing>,
+    fired: bool,
+    exceeded: bool,
+}
+
+impl<P: Encodable> Pass<'_, P> {
+    fn apply(&mut self, node: &mut Node<P>, path: &[usize]) {
+        if self.exceeded {
+            return;
+        }
+        for rule in self.rules.iter_mut() {
+            if !(rule.applies)(node) {
+                continue;
+            }
+            // Only a fixpoint does not exceed the limit.
+            if self.firings.len() >= self.step_limit {
+                self.exceeded = true;
+                return;
+            }
+            (rule.rewrite)(node);
+            self.firings.push(Firing { rule: rule.name.clone(), path: Path::from(path) });
+            self.fired = true;
+            return;
+        }
+    }
+}
+
+impl<P: Encodable> VisitorMut<P> for Pass<'_, P> {
+    fn enter(&mut self, node: &mut Node<P>, path: &[usize]) -> Visit {
+        if self.strategy == Strategy::TopDown {
+            self.apply(node, path);
+        }
+        if self.exceeded { Visit::Skip } else { Visit::Continue }
+    }
+
+    fn leave(&mut self, node: &mut Node<P>, path: &[usize]) {
+        if self.strategy == Strategy::BottomUp {
+            self.apply(node, path);
+        }
+    }
+}
+
+impl<P: Encodable> Rewriter<P> {
+    pub fn new(strategy: Strategy, step_limit: usize) -> Self {
+        Rewriter { strategy, step_limit, rules: Vec::new() }
+    }
+
+    /// Adds a rule rewriting in place, with `rewrite`, the nodes for which
+    /// `applies` holds.
+    pub fn add_rule(
+        &mut self,
+        name: &str,
+        applies: impl FnMut(&Node<P>) -> bool + 'static,
+        rewrite: impl FnMut(&mut Node<P>) + 'static,
+    ) {
+        self.rules.push(Rule { name: String::from(name), applies: Box::new(applies), rewrite: Box::new(rewrite) });
+    }
+
+    /// Rewrites `node` until no rule applies, and returns the rules applied,
+    /// in order.
+    pub fn rewrite(&mut self, node: &mut Node<P>) -> Result<Vec<Firing>, StepLimitExceeded> {
+        let mut firings = Vec::new();
+        loop {
+            let mut pass = Pass {
+                strategy: self.strategy,
+                step_limit: self.step_limit,
+                rules: &mut self.rules,
+                firings: &mut firings,
+                fired: false,
+                exceeded: false,
+            };
+            visit_mut(node, &mut pass);
+            match (pass.exceeded, pass.fired) {
+                (true, _) => return Err(StepLimitExceeded { firings }),
+                (false, true) => continue,
+                (false, false) => return Ok(firings),
+            }
+        }
+    }
+}
+
+impl Rewriter<Primitive> {
+    /// Adds a rule replacing the nodes matched by `pattern` with the node
+    /// `replacement` builds from the captures. The replacement must not
+    /// match the pattern again, or rewriting never reaches a fixpoint.
+    pub fn add_pattern_rule(
+        &mut self,
+        name: &str,
+        pattern: Pattern,
+        replacement: impl Fn(&HashMap<String, &Node<Primitive>>) -> Node<Primitive> + 'static,
+    ) {
+        let matcher = pattern.clone();
+        self.add_rule(
+            name,
+            move |node| matcher.matches(node).is_some(),
+            move |node| {
+                if let Some(captures) = pattern.matches(node) {
+                    *node = replacement(&captures);
+                }
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::michelson_v1_primitives::Primitive;
+    use crate::michelson_v1_primitives::Primitive::*;
+    use crate::rewrite::*;
+
+    fn prim(primitive: Primitive, args: Vec<Node<Primitive>>) -> Node<Primitive> {
+        Node::Prim(primitive, args, vec![])
+    }
+
+    fn firing(rule: &str, path: Vec<usize>) -> Firing {
+        Firing { rule: String::from(rule), path: Path(path) }
+    }
+
+    fn nested_pair(node: &Node<Primitive>) -> bool {
+        match node {
+            Node::Prim(D_Pair, args, _) => matches!(args.last(), Some(Node::Prim(D_Pair, _, annot)) if annot.is_empty()),
+            _ => false,
+        }
+    }
+
+    // `Pair a (Pair b c)` into `Pair a b c`, moving the nested arguments.
+    fn flatten_pair(node: &mut Node<Primitive>) {
+        if let Node::Prim(D_Pair, args, _) = node {
+            if let Some(Node::Prim(D_Pair, nested, _)) = args.pop() {
+                args.extend(nested);
+            }
+        }
+    }
+
+    fn dup_drop_position(items: &[Node<Primitive>]) -> Option<usize> {
+        items.windows(2).position(|pair| match pair {
+            [Node::Prim(I_DUP, dup, _), Node::Prim(I_DROP, drop, _)] => dup.is_empty() && drop.is_empty(),
+            _ => false,
+        })
+    }
+
+    fn has_dup_drop(node: &Node<Primitive>) -> bool {
+        matches!(node, Node::Seq(items) if dup_drop_position(items).is_some())
+    }
+
+    // Removes `DUP ; DROP` from sequences.
+    fn dup_drop(node: &mut Node<Primitive>) {
+        if let Node::Seq(items) = node {
+            if let Some(index) = dup_drop_position(items) {
+                items.drain(index..index + 2);
+            }
+        }
+    }
+
+    #[test]
+    fn fixpoint() {
+        let mut node = Node::Seq(vec![
+            prim(I_PUSH, vec![
+                prim(T_pair, vec![prim(T_nat, vec![]), prim(T_nat, vec![]), prim(T_nat, vec![])]),
+                prim(D_Pair, vec![Node::Int(1), prim(D_Pair, vec![Node::Int(2), Node::Int(3)])]),
+            ]),
+            Node::Seq(vec![prim(I_DUP, vec![]), prim(I_DROP, vec![])]),
+            prim(I_DROP, vec![]),
+        ]);
+
+        let mut rewriter = Rewriter::new(Strategy::BottomUp, 100);
+        rewriter.add_rule("flatten_pair", nested_pair, flatten_pair);
+        rewriter.add_rule("dup_drop", has_dup_drop, dup_drop);
+        assert_eq!(
+            rewriter.rewrite(&mut node),
+            Ok(vec![firing("flatten_pair", vec![0, 1]), firing("dup_drop", vec![1])])
+        );
+        assert_eq!(node, Node::Seq(vec![
+            prim(I_PUSH, vec![
+                prim(T_pair, vec![prim(T_nat, vec![]), prim(T_nat, vec![]), prim(T_nat, vec![])]),
+                prim(D_Pair, vec![Node::Int(1), Node::Int(2), Node::Int(3)]),
+            ]),
+            Node::Seq(vec![]),
+            prim(I_DROP, vec![]),
+        ]));
+        assert_eq!(rewriter.rewrite(&mut node), Ok(vec![]));
+    }
+
+    #[test]
+    fn strategies() {
+        // Bottom-up, the innermost pair is flattened first and the outer
+        // one then needs a second application; top-down, the outer pair is
+        // flattened once per nested pair.
+        let nested = || prim(D_Pair, vec![Node::Int(1), prim(D_Pair, vec![Node::Int(2), prim(D_Pair, vec![Node::Int(3), Node::Int(4)])])]);
+        let flat = prim(D_Pair, vec![Node::Int(1), Node::Int(2), Node::Int(3), Node::Int(4)]);
+
+        let mut node = nested();
+        let mut rewriter = Rewriter::new(Strategy::BottomUp, 100);
+        rewriter.add_rule("flatten_pair", nested_pair, flatten_pair);
+        assert_eq!(
+            rewriter.rewrite(&mut node),
+            Ok(vec![firing("flatten_pair", vec![1]), firing("flatten_pair", vec![])])
+        );
+        assert_eq!(node, flat);
+
+        let mut node = nested();
+        let mut rewriter = Rewriter::new(Strategy::TopDown, 100);
+        rewriter.add_rule("flatten_pair", nested_pair, flatten_pair);
+        assert_eq!(
+            rewriter.rewrite(&mut node),
+            Ok(vec![firing("flatten_pair", vec![]), firing("flatten_pair", vec![])])
+        );
+        assert_eq!(node, flat);
+    }
+
+    #[test]
+    fn step_limit() {
+        let mut node = Node::Int(0);
+        let mut rewriter = Rewriter::new(Strategy::TopDown, 3);
+        let increment = |node: &mut Node<Primitive>| {
+            if let Node::Int(value) = node {
+                *value += 1;
+            }
+        };
+        rewriter.add_rule("increment", |node| matches!(node, Node::Int(_)), increment);
+        assert_eq!(
+            rewriter.rewrite(&mut node),
+            Err(StepLimitExceeded { firings: vec![firing("increment", vec![]); 3] })
+        );
+        assert_eq!(node, Node::Int(3));
+
+        // Reaching the limit at the fixpoint is not exceeding it.
+        let mut node = Node::Int(0);
+        let mut rewriter = Rewriter::new(Strategy::BottomUp, 3);
+        rewriter.add_rule("increment", |node| matches!(node, Node::Int(value) if *value < 3), increment);
+        assert_eq!(rewriter.rewrite(&mut node), Ok(vec![firing("increment", vec![]); 3]));
+        assert_eq!(node, Node::Int(3));
+
+        let mut rewriter = Rewriter::new(Strategy::TopDown, 0);
+        rewriter.add_rule("dup_drop", has_dup_drop, dup_drop);
+        assert_eq!(rewriter.rewrite(&mut Node::Seq(vec![prim(I_DROP, vec![])])), Ok(vec![]));
+        let mut node = Node::Seq(vec![prim(I_DUP, vec![]), prim(I_DROP, vec![])]);
+        assert_eq!(rewriter.rewrite(&mut node), Err(StepLimitExceeded { firings: vec![] }));
+        assert_eq!(node, Node::Seq(vec![prim(I_DUP, vec![]), prim(I_DROP, vec![])]));
+
+        // Past the limit, rules are only asked whether they apply.
+        let rewrites = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = rewrites.clone();
+        let mut rewriter = Rewriter::new(Strategy::BottomUp, 2);
+        rewriter.add_rule("increment", |node| matches!(node, Node::Int(_)), move |node| {
+            counter.set(counter.get() + 1);
+            increment(node);
+        });
+        assert!(rewriter.rewrite(&mut Node::Int(0)).is_err());
+        assert_eq!(rewrites.get(), 2);
+    }
+
+    #[test]
+    fn pattern_rules() {
+        let mut node = Node::Seq(vec![
+            prim(I_PUSH, vec![prim(T_nat, vec![]), Node::Int(1)]),
+            Node::Seq(vec![prim(I_SWAP, vec![]), prim(I_SWAP, vec![])]),
+            prim(I_DIP, vec![Node::Int(1), Node::Seq(vec![prim(I_DROP, vec![])])]),
+        ]);
+        let mut rewriter = Rewriter::new(Strategy::BottomUp, 100);
+        rewriter.add_pattern_rule("dip_1", "DIP 1 $code".parse().unwrap(), |captures| {
+            prim(I_DIP, vec![captures["code"].clone()])
+        });
+        rewriter.add_pattern_rule("swap_swap", "{ SWAP ; SWAP }".parse().unwrap(), |_| Node::Seq(vec![]));
+        assert_eq!(
+            rewriter.rewrite(&mut node),
+            Ok(vec![firing("swap_swap", vec![1]), firing("dip_1", vec![2])])
+        );
+        assert_eq!(node, Node::Seq(vec![
+            prim(I_PUSH, vec![prim(T_nat, vec![]), Node::Int(1)]),
+            Node::Seq(vec![]),
+            prim(I_DIP, vec![Node::Seq(vec![prim(I_DROP, vec![])])]),
+        ]));
+    }
+}
@@ -0,0 +1,641 @@
+// Concrete syntax of Michelson, as found in `.tz` files.
+//
+// Source is parsed into `Expr`, whose primitives are plain names so that
+// macros can be represented and expanded before conversion to
+// `Node<Primitive>`.
+
+use crate::Node;
+use crate::michelson_v1_primitives::Primitive;
+use crate::path::Path;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    Int(i32),
+    String(String),
+    Bytes(Vec<u8>),
+    Prim(String, Vec<Expr>, Vec<String>),
+    Seq(Vec<Expr>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseErrorKind {
+    UnexpectedCharacter(char),
+    UnexpectedEnd,
+    /// A token that cannot appear at this position, such as an annotation
+    /// outside of a primitive application.
+    UnexpectedToken,
+    UnterminatedComment,
+    InvalidInt,
+    InvalidString,
+    InvalidBytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    /// Offset in bytes of the error in the source.
+    pub position: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct UnknownPrimitive {
+    pub path: Path,
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Token {
+    Int(i32),
+    String(String),
+    Bytes(Vec<u8>),
+    Ident(String),
+    Annot(String),
+    /// `$name`, `|`, `=` and `...`, only lexed in patterns.
+    Capture(String),
+    Bar,
+    Equals,
+    Rest,
+    Open(char),
+    Close(char),
+    Semi,
+}
+
+fn error<T>(position: usize, kind: ParseErrorKind) -> Result<T, ParseError> {
+    Err(ParseError { position, kind })
+}
+
+fn is_annotation_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, b'_' | b'.' | b'%' | b'@')
+}
+
+/// Splits `source` into tokens with their offsets. With `pattern`, also
+/// lexes the additions of `pattern::Pattern` to the syntax.
+pub(crate) fn tokenize(source: &str, pattern: bool) -> Result<Vec<(usize, Token)>, ParseError> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        match c {
+            b' ' | b'\t' | b'\n' | b'\r' => i += 1,
+            b'#' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => match source[i + 2..].find("*/") {
+                Some(end) => i += end + 4,
+                None => return error(start, ParseErrorKind::UnterminatedComment),
+            },
+            b'{' | b'(' => {
+                tokens.push((start, Token::Open(c as char)));
+                i += 1;
+            }
+            b'}' | b')' => {
+                tokens.push((start, Token::Close(c as char)));
+                i += 1;
+            }
+            b';' => {
+                tokens.push((start, Token::Semi));
+                i += 1;
+            }
+            b'|' | b'=' if pattern => {
+                tokens.push((start, if c == b'|' { Token::Bar } else { Token::Equals }));
+                i += 1;
+            }
+            b'.' if pattern && source[i..].starts_with("...") => {
+                tokens.push((start, Token::Rest));
+                i += 3;
+            }
+            b'$' if pattern => {
+                i += 1;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'_' | b'.')) {
+                    i += 1;
+                }
+                if i == start + 1 {
+                    return error(start, ParseErrorKind::UnexpectedCharacter('$'));
+                }
+                tokens.push((start, Token::Capture(source[start + 1..i].to_string())));
+            }
+            b'"' => {
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match bytes.get(i) {
+                        None | Some(b'\n') => return error(start, ParseErrorKind::InvalidString),
+                        Some(b'"') => break,
+                        Some(b'\\') => {
+                            value.push(match bytes.get(i + 1) {
+                                Some(b'n') => '\n',
+                                Some(b'r') => '\r',
+                                Some(b't') => '\t',
+                                Some(b'b') => '\x08',
+                                Some(b'\\') => '\\',
+                                Some(b'"') => '"',
+                                _ => return error(i, ParseErrorKind::InvalidString),
+                            });
+                            i += 2;
+                        }
+                        Some(_) => {
+                            let character = source[i..].chars().next().expect("character boundary");
+                            value.push(character);
+                            i += character.len_utf8();
+                        }
+                    }
+                }
+                i += 1;
+                tokens.push((start, Token::String(value)));
+            }
+            b'0' if bytes.get(i + 1) == Some(&b'x') => {
+                i += 2;
+                while i < bytes.len() && bytes[i].is_ascii_alphanumeric() {
+                    i += 1;
+                }
+                let digits = &source[start + 2..i];
+                if !digits.len().is_multiple_of(2) {
+                    return error(start, ParseErrorKind::InvalidBytes);
+                }
+                let value = (0..digits.len())
+                    .step_by(2)
+                    .map(|offset| u8::from_str_radix(&digits[offset..offset + 2], 16))
+                    .collect::<Result<Vec<u8>, _>>()
+                    .or(error(start, ParseErrorKind::InvalidBytes))?;
+                tokens.push((start, Token::Bytes(value)));
+            }
+            b'-' | b'0'..=b'9' => {
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                match source[start..i].parse::<i32>() {
+                    Ok(value) => tokens.push((start, Token::Int(value))),
+                    Err(_) => return error(start, ParseErrorKind::InvalidInt),
+                }
+            }
+            b'@' | b':' | b'%' | b'$' => {
+                i += 1;
+                while i < bytes.len() && is_annotation_char(bytes[i]) {
+                    i += 1;
+                }
+                tokens.push((start, Token::Annot(source[start..i].to_string())));
+            }
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                tokens.push((start, Token::Ident(source[start..i].to_string())));
+            }
+            _ => {
+                let character = source[i..].chars().next().expect("character boundary");
+                return error(start, ParseErrorKind::UnexpectedCharacter(character));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parser over the tokens of an expression, shared with `pattern::Pattern`
+/// which builds its own items from the same token stream.
+pub(crate) struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    end: usize,
+}
+
+impl Parser {
+    pub(crate) fn new(source: &str, pattern: bool) -> Result<Parser, ParseError> {
+        Ok(Parser { tokens: tokenize(source, pattern)?, index: 0, end: source.len() })
+    }
+
+    pub(crate) fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.tokens.get(self.index).map(|(position, _)| *position).unwrap_or(self.end)
+    }
+
+    pub(crate) fn next(&mut self) -> Result<Token, ParseError> {
+        match self.tokens.get_mut(self.index) {
+            Some((_, token)) => {
+                self.index += 1;
+                Ok(std::mem::replace(token, Token::Semi))
+            }
+            None => error(self.end, ParseErrorKind::UnexpectedEnd),
+        }
+    }
+
+    /// Skips the next token if it is `expected`.
+    pub(crate) fn eat(&mut self, expected: &Token) -> bool {
+        let found = self.peek() == Some(expected);
+        if found {
+            self.index += 1;
+        }
+        found
+    }
+
+    pub(crate) fn annotations(&mut self) -> Vec<String> {
+        let mut annots = Vec::new();
+        while let Some(Token::Annot(_)) = self.peek() {
+            if let Ok(Token::Annot(annot)) = self.next() {
+                annots.push(annot);
+            }
+        }
+        annots
+    }
+
+    /// Parses an expression in argument position, where primitives do not
+    /// take arguments unless parenthesized.
+    fn argument(&mut self) -> Result<Expr, ParseError> {
+        let position = self.position();
+        match self.next()? {
+            Token::Int(value) => Ok(Expr::Int(value)),
+            Token::String(value) => Ok(Expr::String(value)),
+            Token::Bytes(value) => Ok(Expr::Bytes(value)),
+            Token::Ident(name) => Ok(Expr::Prim(name, vec![], self.annotations())),
+            Token::Open('{') => {
+                let items = self.sequence(Parser::application)?;
+                self.close('}')?;
+                Ok(Expr::Seq(items))
+            }
+            Token::Open(_) => {
+                let expr = self.application()?;
+                self.close(')')?;
+                Ok(expr)
+            }
+            _ => error(position, ParseErrorKind::UnexpectedToken),
+        }
+    }
+
+    /// Parses an expression in which a primitive takes all the arguments
+    /// that follow it.
+    fn application(&mut self) -> Result<Expr, ParseError> {
+        let name = match self.peek() {
+            Some(Token::Ident(_)) => match self.next()? {
+                Token::Ident(name) => name,
+                _ => unreachable!(),
+            },
+            _ => return self.argument(),
+        };
+        let annots = self.annotations();
+        let mut args = Vec::new();
+        while let Some(Token::Int(_) | Token::String(_) | Token::Bytes(_) | Token::Ident(_) | Token::Open(_)) = self.peek() {
+            args.push(self.argument()?);
+        }
+        Ok(Expr::Prim(name, args, annots))
+    }
+
+    /// Parses `;`-separated items up to a closing brace or the end.
+    pub(crate) fn sequence<T, E: From<ParseError>>(
+        &mut self,
+        mut item: impl FnMut(&mut Parser) -> Result<T, E>,
+    ) -> Result<Vec<T>, E> {
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Close('}')) => return Ok(items),
+                _ => items.push(item(self)?),
+            }
+            match self.peek() {
+                Some(Token::Semi) => {
+                    self.index += 1;
+                }
+                None | Some(Token::Close('}')) => return Ok(items),
+                _ => return Err(ParseError { position: self.position(), kind: ParseErrorKind::UnexpectedToken }.into()),
+            }
+        }
+    }
+
+    pub(crate) fn close(&mut self, delimiter: char) -> Result<(), ParseError> {
+        let position = self.position();
+        match self.next()? {
+            Token::Close(c) if c == delimiter => Ok(()),
+            _ => error(position, ParseErrorKind::UnexpectedToken),
+        }
+    }
+
+    pub(crate) fn finish(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => error(self.position(), ParseErrorKind::UnexpectedToken),
+        }
+    }
+}
+
+/// Parses a single expression, such as `Pair 1 "a"` or `{ DUP ; CAR }`.
+pub fn parse(source: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser::new(source, false)?;
+    let expr = parser.application()?;
+    parser.finish()?;
+    Ok(expr)
+}
+
+/// Parses the contents of a `.tz` file, a sequence whose braces may be
+/// omitted, such as `parameter unit; storage unit; code { ... }`.
+pub fn parse_script(source: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser::new(source, false)?;
+    let mut items = parser.sequence(Parser::application)?;
+    parser.finish()?;
+    match items.pop() {
+        Some(Expr::Seq(inner)) if items.is_empty() => Ok(Expr::Seq(inner)),
+        Some(last) => {
+            items.push(last);
+            Ok(Expr::Seq(items))
+        }
+        None => Ok(Expr::Seq(items)),
+    }
+}
+
+fn to_node_at(expr: &Expr, path: &mut Vec<usize>) -> Result<Node<Primitive>, UnknownPrimitive> {
+    let convert = |items: &[Expr], path: &mut Vec<usize>| {
+        let mut nodes = Vec::with_capacity(items.len());
+        for (index, item) in items.iter().enumerate() {
+            path.push(index);
+            nodes.push(to_node_at(item, path)?);
+            path.pop();
+        }
+        Ok(nodes)
+    };
+    match expr {
+        Expr::Int(value) => Ok(Node::Int(*value)),
+        Expr::String(value) => Ok(Node::String(value.clone())),
+        Expr::Bytes(value) => Ok(Node::Bytes(value.clone())),
+        Expr::Seq(items) => Ok(Node::Seq(convert(items, path)?)),
+        Expr::Prim(name, args, annots) => match name.parse::<Primitive>() {
+            Ok(prim) => Ok(Node::Prim(prim, convert(args, path)?, annots.clone())),
+            Err(_) => Err(UnknownPrimitive { path: Path(path.clone()), name: name.clone() }),
+        },
+    }
+}
+
+/// Converts an expression to a `Node`. Macros must have been expanded
+/// beforehand, see `macros::expand`.
+pub fn to_node(expr: &Expr) -> Result<Node<Primitive>, UnknownPrimitive> {
+    to_node_at(expr, &mut vec![])
+}
+
+/// The node written `source`, for tests.
+#[cfg(test)]
+pub(crate) fn node(source: &str) -> Node<Primitive> {
+    to_node(&parse(source).unwrap()).unwrap()
+}
+
+/// Converts a `Node` to an expression.
+pub fn from_node(node: &Node<Primitive>) -> Expr {
+    match node {
+        Node::Int(value) => Expr::Int(*value),
+        Node::String(value) => Expr::String(value.clone()),
+        Node::Bytes(value) => Expr::Bytes(value.clone()),
+        Node::Seq(items) => Expr::Seq(items.iter().map(from_node).collect()),
+        Node::Prim(prim, args, annots) => Expr::Prim(prim.to_string(), args.iter().map(from_node).collect(), annots.clone()),
+    }
+}
+
+const WIDTH: usize = 80;
+
+fn print_string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            '\x08' => result.push_str("\\b"),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn print_atom(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Int(value) => Some(value.to_string()),
+        Expr::String(value) => Some(print_string(value)),
+        Expr::Bytes(value) => {
+            let digits: String = value.iter().map(|byte| format!("{:02x}", byte)).collect();
+            Some(format!("0x{}", digits))
+        }
+        Expr::Seq(items) if items.is_empty() => Some(String::from("{}")),
+        _ => None,
+    }
+}
+
+/// Appends `expr` on a single line to `output`, unless it would take
+/// `output` past `limit` characters. The work is bounded by `limit`, so that
+/// trying to keep each subtree on one line stays linear overall.
+fn flat(expr: &Expr, argument: bool, output: &mut String, limit: usize) -> bool {
+    if let Some(atom) = print_atom(expr) {
+        output.push_str(&atom);
+        return output.len() <= limit;
+    }
+    match expr {
+        Expr::Seq(items) => {
+            output.push_str("{ ");
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    output.push_str(" ; ");
+                }
+                if !flat(item, false, output, limit) {
+                    return false;
+                }
+            }
+            output.push_str(" }");
+        }
+        Expr::Prim(name, args, annots) => {
+            let parenthesized = argument && (!args.is_empty() || !annots.is_empty());
+            if parenthesized {
+                output.push('(');
+            }
+            output.push_str(name);
+            for annot in annots {
+                output.push(' ');
+                output.push_str(annot);
+            }
+            for arg in args {
+                output.push(' ');
+                if !flat(arg, true, output, limit) {
+                    return false;
+                }
+            }
+            if parenthesized {
+                output.push(')');
+            }
+        }
+        _ => unreachable!("atoms are printed above"),
+    }
+    output.len() <= limit
+}
+
+/// `expr` on a single line, if it fits in the `WIDTH` from `column`.
+fn fitting(expr: &Expr, column: usize) -> Option<String> {
+    let mut output = String::new();
+    if flat(expr, false, &mut output, WIDTH.saturating_sub(column)) { Some(output) } else { None }
+}
+
+/// Lays out an expression starting at `column`. Primitives take their
+/// arguments unless they are in argument position, where they are
+/// parenthesized.
+fn layout(expr: &Expr, column: usize, argument: bool) -> String {
+    if let Some(atom) = print_atom(expr) {
+        return atom;
+    }
+    match expr {
+        Expr::Seq(items) => {
+            if let Some(flat) = fitting(expr, column) {
+                return flat;
+            }
+            let separator = format!(" ;\n{}", " ".repeat(column + 2));
+            let items: Vec<String> = items.iter().map(|item| layout(item, column + 2, false)).collect();
+            format!("{{ {} }}", items.join(&separator))
+        }
+        Expr::Prim(name, args, annots) => {
+            if argument && (!args.is_empty() || !annots.is_empty()) {
+                return format!("({})", layout(expr, column + 1, false));
+            }
+            let mut head = name.clone();
+            for annot in annots {
+                head.push(' ');
+                head.push_str(annot);
+            }
+            if args.is_empty() {
+                return head;
+            }
+
+            if let Some(flat) = fitting(expr, column) {
+                return flat;
+            }
+            let arguments_column = column + head.len() + 1;
+            let separator = format!("\n{}", " ".repeat(arguments_column));
+            let args: Vec<String> = args.iter().map(|arg| layout(arg, arguments_column, true)).collect();
+            format!("{} {}", head, args.join(&separator))
+        }
+        _ => unreachable!("atoms are printed above"),
+    }
+}
+
+/// Prints an expression in concrete syntax, breaking lines longer than 80
+/// characters.
+pub fn print(expr: &Expr) -> String {
+    layout(expr, 0, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::syntax::*;
+    use crate::michelson_v1_primitives::Primitive::*;
+
+    fn p(name: &str, args: Vec<Expr>) -> Expr {
+        Expr::Prim(name.to_string(), args, vec![])
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(parse("-42"), Ok(Expr::Int(-42)));
+        assert_eq!(parse("\"a\\\"b\\n\""), Ok(Expr::String(String::from("a\"b\n"))));
+        assert_eq!(parse("0x00ff"), Ok(Expr::Bytes(vec![0, 255])));
+        assert_eq!(
+            parse("Pair 1 (Some \"a\") { Elt 0x 2 }"),
+            Ok(p("Pair", vec![
+                Expr::Int(1),
+                p("Some", vec![Expr::String(String::from("a"))]),
+                Expr::Seq(vec![p("Elt", vec![Expr::Bytes(vec![]), Expr::Int(2)])]),
+            ]))
+        );
+        assert_eq!(
+            parse("(pair :point (int %x) nat)"),
+            Ok(Expr::Prim(String::from("pair"), vec![
+                Expr::Prim(String::from("int"), vec![], vec![String::from("%x")]),
+                p("nat", vec![]),
+            ], vec![String::from(":point")]))
+        );
+        assert_eq!(parse("{ DUP ; # comment\n /* block\n comment */ CAR ; }"), Ok(Expr::Seq(vec![p("DUP", vec![]), p("CAR", vec![])])));
+
+        assert_eq!(parse("{ DUP"), Err(ParseError { position: 5, kind: ParseErrorKind::UnexpectedEnd }));
+        assert_eq!(parse("{ DUP CAR ; ]"), Err(ParseError { position: 12, kind: ParseErrorKind::UnexpectedCharacter(']') }));
+        assert_eq!(parse("0x0"), Err(ParseError { position: 0, kind: ParseErrorKind::InvalidBytes }));
+        assert_eq!(parse("4294967296"), Err(ParseError { position: 0, kind: ParseErrorKind::InvalidInt }));
+        assert_eq!(parse("DUP ; DROP"), Err(ParseError { position: 4, kind: ParseErrorKind::UnexpectedToken }));
+        assert_eq!(parse("\"a"), Err(ParseError { position: 0, kind: ParseErrorKind::InvalidString }));
+    }
+
+    #[test]
+    fn scripts() {
+        let source = "parameter unit;\nstorage (option nat);\ncode { CDR ; NIL operation ; PAIR }\n";
+        let expected = Expr::Seq(vec![
+            p("parameter", vec![p("unit", vec![])]),
+            p("storage", vec![p("option", vec![p("nat", vec![])])]),
+            p("code", vec![Expr::Seq(vec![p("CDR", vec![]), p("NIL", vec![p("operation", vec![])]), p("PAIR", vec![])])]),
+        ]);
+        assert_eq!(parse_script(source), Ok(expected.clone()));
+        assert_eq!(parse_script(&format!("{{ {} }}", source)), Ok(expected.clone()));
+
+        let node = to_node(&expected).unwrap();
+        assert_eq!(
+            node,
+            Node::Seq(vec![
+                Node::Prim(K_parameter, vec![Node::Prim(T_unit, vec![], vec![])], vec![]),
+                Node::Prim(K_storage, vec![Node::Prim(T_option, vec![Node::Prim(T_nat, vec![], vec![])], vec![])], vec![]),
+                Node::Prim(K_code, vec![Node::Seq(vec![
+                    Node::Prim(I_CDR, vec![], vec![]),
+                    Node::Prim(I_NIL, vec![Node::Prim(T_operation, vec![], vec![])], vec![]),
+                    Node::Prim(I_PAIR, vec![], vec![]),
+                ])], vec![]),
+            ])
+        );
+        assert_eq!(from_node(&node), expected);
+        assert_eq!(
+            to_node(&parse("{ DUP ; CADR }").unwrap()),
+            Err(UnknownPrimitive { path: Path(vec![1]), name: String::from("CADR") })
+        );
+        assert_eq!(to_node(&p("constant", vec![Expr::String(String::from("expr"))])).map(|_| ()), Ok(()));
+    }
+
+    #[test]
+    fn printing() {
+        for source in [
+            "Pair 1 (Some \"a\\\"\\n\") { Elt 0x00ff -2 }",
+            "pair :point (int %x) (nat %y)",
+            "{ DUP ; CAR @x ; DIP 2 { DROP } }",
+            "{}",
+        ] {
+            assert_eq!(print(&parse(source).unwrap()), source);
+        }
+
+        let source = "parameter (or (pair %transfer address nat) (unit %default));\n\
+                      storage (big_map address nat);\n\
+                      code { UNPAIR ; IF_LEFT { UNPAIR ; DIG 2 ; SWAP ; SOME ; SWAP ; UPDATE } { DROP } ; NIL operation ; PAIR }";
+        let long = "IF_LEFT { UNPAIR ; DIG 2 ; SWAP ; SOME ; SWAP ; UPDATE ; NIL operation ; PAIR } { DROP }";
+        assert_eq!(
+            print(&parse(long).unwrap()),
+            "IF_LEFT { UNPAIR ; DIG 2 ; SWAP ; SOME ; SWAP ; UPDATE ; NIL operation ; PAIR }\n        { DROP }"
+        );
+        let script = parse_script(source).unwrap();
+        let printed = print(&script);
+        assert_eq!(
+            printed,
+            "{ parameter (or (pair %transfer address nat) (unit %default)) ;\n  \
+               storage (big_map address nat) ;\n  \
+               code { UNPAIR ;\n         \
+                      IF_LEFT { UNPAIR ; DIG 2 ; SWAP ; SOME ; SWAP ; UPDATE } { DROP } ;\n         \
+                      NIL operation ;\n         \
+                      PAIR } }"
+        );
+        assert_eq!(parse_script(&printed), Ok(script));
+
+        // Deeply nested code, as found in decompiled contracts, is laid out
+        // without trying every subtree on one line at every depth.
+        let mut code = p("UNIT", vec![]);
+        for _ in 0..64 {
+            code = p("IF_LEFT", vec![Expr::Seq(vec![p("DROP", vec![]), code]), Expr::Seq(vec![p("FAILWITH", vec![])])]);
+        }
+        let printed = print(&code);
+        assert!(printed.starts_with("IF_LEFT { DROP ;\n          IF_LEFT { DROP ;\n"));
+        assert_eq!(parse(&printed), Ok(code));
+    }
+}
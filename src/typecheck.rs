@@ -0,0 +1,844 @@
+// Local typechecking of Michelson values, mirroring the checks performed by
+// the protocol when parsing data against a type.
+
+use std::cmp::Ordering;
+
+use crate::Node;
+use crate::base58;
+use crate::michelson_v1_primitives::Primitive;
+use crate::michelson_v1_primitives::Primitive::*;
+use crate::path::Path;
+
+mod instructions;
+pub use instructions::{find_entrypoint, normalize_type, typecheck_code, typecheck_script, Stack};
+pub(crate) use instructions::{operand_types, script_section};
+
+#[derive(Debug, PartialEq)]
+pub enum TypeErrorKind {
+    /// The type expression itself is malformed; the path points into the type.
+    InvalidType,
+    /// A `set`, `map` or `big_map` key or `ticket` content type is not
+    /// comparable; the path points into the type.
+    NotComparable,
+    /// The value does not have the shape expected by the type.
+    UnexpectedValue(Node<Primitive>),
+    /// A data constructor or instruction is applied to the wrong number of
+    /// arguments.
+    InvalidArity(Primitive),
+    /// `Elt` used outside of a map or big map literal.
+    UnexpectedElt,
+    NegativeNat,
+    NegativeMutez,
+    InvalidString,
+    InvalidTimestamp,
+    InvalidAddress,
+    InvalidKey,
+    InvalidKeyHash,
+    InvalidSignature,
+    InvalidChainId,
+    InvalidBytesLength,
+    /// Elements of a set, or keys of a map, are not in increasing order.
+    Unsorted,
+    /// Duplicate element in a set, or duplicate key in a map.
+    Duplicate,
+    /// No value inhabits the type (`never`, `operation`).
+    Uninhabited,
+    /// A script is not a sequence of `parameter`, `storage`, `code` and
+    /// `view` sections, with each of the first three exactly once.
+    InvalidScript,
+    /// Not an instruction, or an instruction with malformed arguments.
+    InvalidInstruction,
+    /// The instruction cannot be applied to the given stack.
+    BadStack { instruction: Primitive, stack: Vec<Node<Primitive>> },
+    /// The branches of a conditional end with different stacks.
+    UnmatchedBranches { left: Vec<Node<Primitive>>, right: Vec<Node<Primitive>> },
+    /// A block, loop body or lambda ends with an unexpected stack.
+    StackMismatch { expected: Vec<Node<Primitive>>, actual: Vec<Node<Primitive>> },
+    /// Instructions following a `FAILWITH` or `NEVER`.
+    FailNotInTail,
+    NotPushable,
+    NotPackable,
+    NotPassable,
+    NotStorable,
+    NotDuplicable,
+    /// The instruction is not allowed in this context, such as `SELF` in a
+    /// lambda or `TRANSFER_TOKENS` in a view.
+    ForbiddenInstruction(Primitive),
+    /// The instruction is no longer supported by the protocol.
+    Deprecated(Primitive),
+    /// The instruction reads or extends the chain's table of address
+    /// indexes, which neither the typechecker nor the interpreter model:
+    /// `INDEX_ADDRESS` and `GET_ADDRESS_INDEX`.
+    Unsupported(Primitive),
+    UndefinedEntrypoint(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TypeError {
+    pub path: Path,
+    pub kind: TypeErrorKind,
+}
+
+fn error<T>(path: &[usize], kind: TypeErrorKind) -> Result<T, TypeError> {
+    Err(TypeError { path: Path::from(path), kind })
+}
+
+fn mismatch<T>(path: &[usize], ty: &Node<Primitive>) -> Result<T, TypeError> {
+    error(path, TypeErrorKind::UnexpectedValue(ty.clone()))
+}
+
+fn with_index<T>(path: &mut Vec<usize>, index: usize, f: impl FnOnce(&mut Vec<usize>) -> T) -> T {
+    path.push(index);
+    let result = f(path);
+    path.pop();
+    result
+}
+
+fn type_args(ty: &Node<Primitive>) -> Option<(&Primitive, &[Node<Primitive>])> {
+    match ty {
+        Node::Prim(prim, args, _) => Some((prim, args)),
+        _ => None,
+    }
+}
+
+pub fn is_comparable(ty: &Node<Primitive>) -> bool {
+    match type_args(ty) {
+        Some((T_unit | T_never | T_bool | T_int | T_nat | T_string | T_chain_id | T_bytes
+            | T_mutez | T_key_hash | T_key | T_signature | T_timestamp | T_address
+            | T_tx_rollup_l2_address, [])) => true,
+        Some((T_option, [t])) => is_comparable(t),
+        Some((T_or | T_pair, args)) => args.len() >= 2 && args.iter().all(is_comparable),
+        _ => false,
+    }
+}
+
+fn check_type_at(ty: &Node<Primitive>, path: &mut Vec<usize>) -> Result<(), TypeError> {
+    let (prim, args) = match type_args(ty) {
+        Some(prim_args) => prim_args,
+        None => return error(path, TypeErrorKind::InvalidType),
+    };
+
+    let arity_ok = match prim {
+        T_unit | T_never | T_bool | T_int | T_nat | T_string | T_chain_id | T_bytes | T_mutez
+        | T_key_hash | T_key | T_signature | T_timestamp | T_address | T_tx_rollup_l2_address
+        | T_operation | T_bls12_381_g1 | T_bls12_381_g2 | T_bls12_381_fr | T_chest
+        | T_chest_key => args.is_empty(),
+        T_option | T_list | T_set | T_contract | T_ticket => args.len() == 1,
+        T_or | T_lambda | T_map | T_big_map => args.len() == 2,
+        T_pair => args.len() >= 2,
+        T_sapling_state | T_sapling_transaction | T_sapling_transaction_deprecated => {
+            return match args {
+                [Node::Int(size)] if *size >= 0 => Ok(()),
+                _ => error(path, TypeErrorKind::InvalidType),
+            };
+        }
+        _ => false,
+    };
+    if !arity_ok {
+        return error(path, TypeErrorKind::InvalidType);
+    }
+
+    for (index, arg) in args.iter().enumerate() {
+        with_index(path, index, |path| check_type_at(arg, path))?;
+    }
+
+    if let (T_set | T_map | T_big_map | T_ticket, Some(key)) = (prim, args.first()) {
+        if !is_comparable(key) {
+            path.push(0);
+            return error(path, TypeErrorKind::NotComparable);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a type expression is well-formed.
+pub fn check_type(ty: &Node<Primitive>) -> Result<(), TypeError> {
+    check_type_at(ty, &mut Vec::new())
+}
+
+/// Checks that `value` is a valid inhabitant of `ty`, as the protocol would
+/// when parsing it (with readable or optimized representations accepted).
+pub fn typecheck_data(ty: &Node<Primitive>, value: &Node<Primitive>) -> Result<(), TypeError> {
+    check_type(ty)?;
+    check_data(ty, value, &mut Vec::new())
+}
+
+fn check_string(value: &str) -> bool {
+    value.bytes().all(|byte| byte == b'\n' || (0x20..=0x7e).contains(&byte))
+}
+
+fn check_entrypoint(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 31
+        && name != "default"
+        && name.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"_.%@".contains(&byte))
+}
+
+const KEY_PREFIXES: [(&[u8], usize); 4] = [
+    (base58::EDPK, 32),
+    (base58::SPPK, 33),
+    (base58::P2PK, 33),
+    (base58::BLPK, 48),
+];
+
+const KEY_HASH_PREFIXES: [&[u8]; 4] = [base58::TZ1, base58::TZ2, base58::TZ3, base58::TZ4];
+
+const SIGNATURE_PREFIXES: [(&[u8], usize); 5] = [
+    (base58::EDSIG, 64),
+    (base58::SPSIG, 64),
+    (base58::P2SIG, 64),
+    (base58::SIG, 64),
+    (base58::BLSIG, 96),
+];
+
+fn key_hash_from_string(value: &str) -> Option<Vec<u8>> {
+    KEY_HASH_PREFIXES.iter().enumerate().find_map(|(tag, prefix)| {
+        let hash = base58::decode_check(value, prefix, 20)?;
+        let mut bytes = vec![tag as u8];
+        bytes.extend(hash);
+        Some(bytes)
+    })
+}
+
+/// Returns the binary representation of a `key_hash` value.
+pub fn key_hash_bytes(value: &Node<Primitive>) -> Option<Vec<u8>> {
+    match value {
+        Node::String(s) => key_hash_from_string(s),
+        Node::Bytes(b) if b.len() == 21 && b[0] <= 3 => Some(b.clone()),
+        _ => None,
+    }
+}
+
+/// Returns the binary representation of a `key` value.
+pub fn key_bytes(value: &Node<Primitive>) -> Option<Vec<u8>> {
+    match value {
+        Node::String(s) => KEY_PREFIXES.iter().enumerate().find_map(|(tag, (prefix, length))| {
+            let key = base58::decode_check(s, prefix, *length)?;
+            let mut bytes = vec![tag as u8];
+            bytes.extend(key);
+            Some(bytes)
+        }),
+        Node::Bytes(b) => match b.first() {
+            Some(tag) if (*tag as usize) < KEY_PREFIXES.len()
+                && b.len() == KEY_PREFIXES[*tag as usize].1 + 1 => Some(b.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the binary representation of a `signature` value.
+pub fn signature_bytes(value: &Node<Primitive>) -> Option<Vec<u8>> {
+    match value {
+        Node::String(s) => SIGNATURE_PREFIXES
+            .iter()
+            .find_map(|(prefix, length)| base58::decode_check(s, prefix, *length)),
+        Node::Bytes(b) if b.len() == 64 || b.len() == 96 => Some(b.clone()),
+        _ => None,
+    }
+}
+
+/// Returns the binary representation of a `chain_id` value.
+pub fn chain_id_bytes(value: &Node<Primitive>) -> Option<Vec<u8>> {
+    match value {
+        Node::String(s) => base58::decode_check(s, base58::NET, 4),
+        Node::Bytes(b) if b.len() == 4 => Some(b.clone()),
+        _ => None,
+    }
+}
+
+/// Returns the binary representation of an `address` value: 22 bytes for
+/// the contract, followed by the entrypoint name if any.
+pub fn address_bytes(value: &Node<Primitive>) -> Option<Vec<u8>> {
+    match value {
+        Node::String(s) => {
+            let (contract, entrypoint) = match s.split_once('%') {
+                Some((contract, entrypoint)) if check_entrypoint(entrypoint) => (contract, entrypoint),
+                Some(_) => return None,
+                None => (s.as_str(), ""),
+            };
+
+            let mut bytes = if let Some(key_hash) = key_hash_from_string(contract) {
+                let mut bytes = vec![0];
+                bytes.extend(key_hash);
+                bytes
+            } else {
+                let (tag, hash) = [base58::KT1, base58::TXR1, base58::SR1]
+                    .iter()
+                    .enumerate()
+                    .find_map(|(tag, prefix)| Some((tag as u8 + 1, base58::decode_check(contract, prefix, 20)?)))?;
+                let mut bytes = vec![tag];
+                bytes.extend(hash);
+                bytes.push(0);
+                bytes
+            };
+            bytes.extend(entrypoint.as_bytes());
+            Some(bytes)
+        }
+        Node::Bytes(b) => {
+            if b.len() < 22 {
+                return None;
+            }
+            let valid_contract = match b[0] {
+                0 => b[1] <= 3,
+                1..=3 => b[21] == 0,
+                _ => false,
+            };
+            let entrypoint = std::str::from_utf8(&b[22..]).ok()?;
+            if valid_contract && (entrypoint.is_empty() || check_entrypoint(entrypoint)) {
+                Some(b.clone())
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn tx_rollup_l2_address_bytes(value: &Node<Primitive>) -> Option<Vec<u8>> {
+    match value {
+        Node::String(s) => base58::decode_check(s, base58::TZ4, 20),
+        Node::Bytes(b) if b.len() == 20 => Some(b.clone()),
+        _ => None,
+    }
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Parses an RFC 3339 timestamp into seconds since the Unix epoch.
+pub fn parse_timestamp(value: &str) -> Option<i64> {
+    fn number(value: &str, range: std::ops::Range<usize>) -> Option<i64> {
+        let digits = value.get(range)?;
+        if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    }
+
+    let bytes = value.as_bytes();
+    if bytes.len() < 20 || bytes[4] != b'-' || bytes[7] != b'-' || !b"Tt ".contains(&bytes[10])
+        || bytes[13] != b':' || bytes[16] != b':' {
+        return None;
+    }
+
+    let (year, month, day) = (number(value, 0..4)?, number(value, 5..7)?, number(value, 8..10)?);
+    let (hour, minute, second) = (number(value, 11..13)?, number(value, 14..16)?, number(value, 17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let mut rest = &value[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(|byte| byte.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        rest = &fraction[digits..];
+    }
+
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            sign * (number(rest, 1..3)? * 3600 + number(rest, 4..6)? * 60)
+        }
+        _ => return None,
+    };
+
+    Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// Formats seconds since the Unix epoch as an RFC 3339 timestamp in UTC.
+pub fn format_timestamp(seconds: i64) -> String {
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, time / 3600, time / 60 % 60, time % 60
+    )
+}
+
+/// Returns the number of seconds since the Unix epoch of a `timestamp`.
+pub fn timestamp_seconds(value: &Node<Primitive>) -> Option<i64> {
+    match value {
+        Node::Int(v) => Some(*v as i64),
+        Node::String(s) => parse_timestamp(s),
+        _ => None,
+    }
+}
+
+fn check_bytes(value: &Node<Primitive>, ty: &Node<Primitive>, length: usize, path: &[usize]) -> Result<(), TypeError> {
+    match value {
+        Node::Bytes(b) if b.len() == length => Ok(()),
+        Node::Bytes(_) => error(path, TypeErrorKind::InvalidBytesLength),
+        _ => mismatch(path, ty),
+    }
+}
+
+fn check_representation(
+    value: &Node<Primitive>,
+    ty: &Node<Primitive>,
+    decode: fn(&Node<Primitive>) -> Option<Vec<u8>>,
+    kind: TypeErrorKind,
+    path: &[usize],
+) -> Result<(), TypeError> {
+    match value {
+        Node::String(_) | Node::Bytes(_) => match decode(value) {
+            Some(_) => Ok(()),
+            None => error(path, kind),
+        },
+        _ => mismatch(path, ty),
+    }
+}
+
+/// Components of a pair value, in either the `Pair a b ...` or `{ a ; b ; ... }`
+/// comb notations.
+pub(crate) fn pair_components(value: &Node<Primitive>) -> Option<&[Node<Primitive>]> {
+    match value {
+        Node::Prim(D_Pair, args, _) if args.len() >= 2 => Some(args),
+        Node::Seq(items) if items.len() >= 2 => Some(items),
+        _ => None,
+    }
+}
+
+type Leaf<'a> = (&'a Node<Primitive>, &'a Node<Primitive>, Vec<usize>);
+
+/// Matches a right comb of types against a right comb of values, written
+/// with any mix of nested and n-ary pairs, and collects the component
+/// (type, value, path) triples in order.
+fn comb_leaves<'a>(
+    types: &'a [Node<Primitive>],
+    values: &'a [Node<Primitive>],
+    path: &mut Vec<usize>,
+    offset: usize,
+    leaves: &mut Vec<Leaf<'a>>,
+) -> Result<(), TypeError> {
+    match (types, values) {
+        ([ty], [value]) => {
+            path.push(offset);
+            leaves.push((ty, value, path.clone()));
+            path.pop();
+            Ok(())
+        }
+        ([ty], _) => match type_args(ty) {
+            Some((T_pair, args)) => comb_leaves(args, values, path, offset, leaves),
+            _ => with_index(path, offset, |path| mismatch(path, ty)),
+        },
+        (_, [value]) => with_index(path, offset, |path| match value {
+            Node::Prim(D_Pair, args, _) if args.len() < 2 => error(path, TypeErrorKind::InvalidArity(D_Pair)),
+            _ => match pair_components(value) {
+                Some(components) => comb_leaves(types, components, path, 0, leaves),
+                None => error(path, TypeErrorKind::UnexpectedValue(Node::Prim(T_pair, types.to_vec(), vec![]))),
+            },
+        }),
+        ([ty, rest_types @ ..], [value, rest_values @ ..]) => {
+            path.push(offset);
+            leaves.push((ty, value, path.clone()));
+            path.pop();
+            comb_leaves(rest_types, rest_values, path, offset + 1, leaves)
+        }
+        // Only reached through `compare_data`, which does not check types.
+        ([], _) => error(path, TypeErrorKind::InvalidArity(T_pair)),
+        (_, []) => error(path, TypeErrorKind::InvalidArity(D_Pair)),
+    }
+}
+
+fn check_pair(types: &[Node<Primitive>], value: &Node<Primitive>, path: &[usize]) -> Result<(), TypeError> {
+    if let Node::Prim(D_Pair, args, _) = value {
+        if args.len() < 2 {
+            return error(path, TypeErrorKind::InvalidArity(D_Pair));
+        }
+    }
+
+    let components = match pair_components(value) {
+        Some(components) => components,
+        None => return mismatch(path, &Node::Prim(T_pair, types.to_vec(), vec![])),
+    };
+
+    let mut leaves = Vec::new();
+    comb_leaves(types, components, &mut path.to_vec(), 0, &mut leaves)?;
+    for (ty, value, mut leaf_path) in leaves {
+        check_data(ty, value, &mut leaf_path)?;
+    }
+    Ok(())
+}
+
+fn check_sorted_seq<'a>(
+    key_type: &Node<Primitive>,
+    keys: impl Iterator<Item = (usize, &'a Node<Primitive>)>,
+    path: &mut Vec<usize>,
+) -> Result<(), TypeError> {
+    let mut previous: Option<&Node<Primitive>> = None;
+    for (index, key) in keys {
+        if let Some(previous) = previous {
+            match compare_data(key_type, previous, key) {
+                Some(Ordering::Less) => {}
+                Some(Ordering::Equal) => return with_index(path, index, |path| error(path, TypeErrorKind::Duplicate)),
+                _ => return with_index(path, index, |path| error(path, TypeErrorKind::Unsorted)),
+            }
+        }
+        previous = Some(key);
+    }
+    Ok(())
+}
+
+fn check_map(
+    ty: &Node<Primitive>,
+    key_type: &Node<Primitive>,
+    value_type: &Node<Primitive>,
+    items: &[Node<Primitive>],
+    path: &mut Vec<usize>,
+) -> Result<(), TypeError> {
+    for (index, item) in items.iter().enumerate() {
+        with_index(path, index, |path| match item {
+            Node::Prim(D_Elt, args, _) => match &args[..] {
+                [key, value] => {
+                    with_index(path, 0, |path| check_data(key_type, key, path))?;
+                    with_index(path, 1, |path| check_data(value_type, value, path))
+                }
+                _ => error(path, TypeErrorKind::InvalidArity(D_Elt)),
+            },
+            _ => mismatch(path, ty),
+        })?;
+    }
+
+    let keys = items.iter().enumerate().filter_map(|(index, item)| match item {
+        Node::Prim(_, args, _) => Some((index, &args[0])),
+        _ => None,
+    });
+    check_sorted_seq(key_type, keys, path)
+}
+
+fn check_data(ty: &Node<Primitive>, value: &Node<Primitive>, path: &mut Vec<usize>) -> Result<(), TypeError> {
+    if let Node::Prim(D_Elt, _, _) = value {
+        return error(path, TypeErrorKind::UnexpectedElt);
+    }
+
+    let (prim, args) = match type_args(ty) {
+        Some(prim_args) => prim_args,
+        None => return error(path, TypeErrorKind::InvalidType),
+    };
+
+    if let Node::Prim(constructor, constructor_args, _) = value {
+        let arity = match constructor {
+            D_Unit | D_True | D_False | D_None => Some(0),
+            D_Some | D_Left | D_Right | D_Lambda_rec => Some(1),
+            D_Ticket => Some(4),
+            _ => None,
+        };
+        if arity.is_some_and(|arity| constructor_args.len() != arity) {
+            return error(path, TypeErrorKind::InvalidArity(*constructor));
+        }
+    }
+
+    match (prim, args, value) {
+        (T_unit, _, Node::Prim(D_Unit, _, _)) => Ok(()),
+        (T_bool, _, Node::Prim(D_True | D_False, _, _)) => Ok(()),
+        (T_int, _, Node::Int(_)) => Ok(()),
+        (T_nat, _, Node::Int(v)) => if *v < 0 { error(path, TypeErrorKind::NegativeNat) } else { Ok(()) },
+        (T_mutez, _, Node::Int(v)) => if *v < 0 { error(path, TypeErrorKind::NegativeMutez) } else { Ok(()) },
+        (T_string, _, Node::String(s)) => if check_string(s) { Ok(()) } else { error(path, TypeErrorKind::InvalidString) },
+        (T_bytes, _, Node::Bytes(_)) => Ok(()),
+        (T_timestamp, _, Node::Int(_)) => Ok(()),
+        (T_timestamp, _, Node::String(s)) => match parse_timestamp(s) {
+            Some(_) => Ok(()),
+            None => error(path, TypeErrorKind::InvalidTimestamp),
+        },
+        (T_address | T_contract, _, _) => check_representation(value, ty, address_bytes, TypeErrorKind::InvalidAddress, path),
+        (T_key, _, _) => check_representation(value, ty, key_bytes, TypeErrorKind::InvalidKey, path),
+        (T_key_hash, _, _) => check_representation(value, ty, key_hash_bytes, TypeErrorKind::InvalidKeyHash, path),
+        (T_signature, _, _) => check_representation(value, ty, signature_bytes, TypeErrorKind::InvalidSignature, path),
+        (T_chain_id, _, _) => check_representation(value, ty, chain_id_bytes, TypeErrorKind::InvalidChainId, path),
+        (T_tx_rollup_l2_address, _, _) => check_representation(value, ty, tx_rollup_l2_address_bytes, TypeErrorKind::InvalidAddress, path),
+        (T_bls12_381_g1, _, _) => check_bytes(value, ty, 96, path),
+        (T_bls12_381_g2, _, _) => check_bytes(value, ty, 192, path),
+        (T_bls12_381_fr, _, Node::Int(_)) => Ok(()),
+        (T_bls12_381_fr, _, _) => check_bytes(value, ty, 32, path),
+        (T_chest | T_chest_key | T_sapling_transaction | T_sapling_transaction_deprecated, _, Node::Bytes(_)) => Ok(()),
+        (T_sapling_state, _, Node::Int(_)) => Ok(()),
+        (T_sapling_state, _, Node::Seq(items)) if items.is_empty() => Ok(()),
+        (T_never | T_operation, _, _) => error(path, TypeErrorKind::Uninhabited),
+        (T_option, _, Node::Prim(D_None, _, _)) => Ok(()),
+        (T_option, [inner], Node::Prim(D_Some, v, _)) => with_index(path, 0, |path| check_data(inner, &v[0], path)),
+        (T_or, [left, _], Node::Prim(D_Left, v, _)) => with_index(path, 0, |path| check_data(left, &v[0], path)),
+        (T_or, [_, right], Node::Prim(D_Right, v, _)) => with_index(path, 0, |path| check_data(right, &v[0], path)),
+        (T_pair, types, _) => check_pair(types, value, path),
+        (T_ticket, [content], Node::Prim(D_Ticket, v, _)) => {
+            with_index(path, 0, |path| check_data(&Node::Prim(T_address, vec![], vec![]), &v[0], path))?;
+            with_index(path, 1, |path| check_type_at(&v[1], path))?;
+            if instructions::normalize_type(&v[1]) != instructions::normalize_type(content) {
+                return with_index(path, 1, |path| mismatch(path, content));
+            }
+            with_index(path, 2, |path| check_data(content, &v[2], path))?;
+            with_index(path, 3, |path| check_data(&Node::Prim(T_nat, vec![], vec![]), &v[3], path))
+        }
+        (T_ticket, [content], _) => {
+            let types = [
+                Node::Prim(T_address, vec![], vec![]),
+                content.clone(),
+                Node::Prim(T_nat, vec![], vec![]),
+            ];
+            check_pair(&types, value, path)
+        }
+        (T_list, [element], Node::Seq(items)) => {
+            for (index, item) in items.iter().enumerate() {
+                with_index(path, index, |path| check_data(element, item, path))?;
+            }
+            Ok(())
+        }
+        (T_set, [element], Node::Seq(items)) => {
+            for (index, item) in items.iter().enumerate() {
+                with_index(path, index, |path| check_data(element, item, path))?;
+            }
+            check_sorted_seq(element, items.iter().enumerate(), path)
+        }
+        (T_map | T_big_map, [key, value_type], Node::Seq(items)) => check_map(ty, key, value_type, items, path),
+        (T_big_map, _, Node::Int(_)) => Ok(()),
+        (T_lambda, [argument, result], Node::Prim(D_Lambda_rec, v, _)) => {
+            with_index(path, 0, |path| instructions::check_lambda_rec(argument, result, &v[0], path))
+        }
+        (T_lambda, [argument, result], _) => instructions::check_lambda(argument, result, value, path),
+        _ => mismatch(path, ty),
+    }
+}
+
+fn compare_bytes(
+    decode: fn(&Node<Primitive>) -> Option<Vec<u8>>,
+    a: &Node<Primitive>,
+    b: &Node<Primitive>,
+) -> Option<Ordering> {
+    Some(decode(a)?.cmp(&decode(b)?))
+}
+
+/// Compares two values of a comparable type following the protocol's
+/// ordering, regardless of whether they use the readable or optimized
+/// representation. Returns `None` if the values are not of that type.
+pub fn compare_data(ty: &Node<Primitive>, a: &Node<Primitive>, b: &Node<Primitive>) -> Option<Ordering> {
+    let (prim, args) = type_args(ty)?;
+    match (prim, args, a, b) {
+        (T_unit, _, Node::Prim(D_Unit, _, _), Node::Prim(D_Unit, _, _)) => Some(Ordering::Equal),
+        (T_bool, _, Node::Prim(a @ (D_False | D_True), _, _), Node::Prim(b @ (D_False | D_True), _, _)) => {
+            Some((*a == D_True).cmp(&(*b == D_True)))
+        }
+        (T_int | T_nat | T_mutez, _, Node::Int(a), Node::Int(b)) => Some(a.cmp(b)),
+        (T_string, _, Node::String(a), Node::String(b)) => Some(a.cmp(b)),
+        (T_bytes, _, Node::Bytes(a), Node::Bytes(b)) => Some(a.cmp(b)),
+        (T_timestamp, _, _, _) => Some(timestamp_seconds(a)?.cmp(&timestamp_seconds(b)?)),
+        (T_address, _, _, _) => {
+            // Addresses are compared by contract first, then by entrypoint.
+            let (a, b) = (address_bytes(a)?, address_bytes(b)?);
+            Some(a[..22].cmp(&b[..22]).then_with(|| a[22..].cmp(&b[22..])))
+        }
+        (T_key, _, _, _) => compare_bytes(key_bytes, a, b),
+        (T_key_hash, _, _, _) => compare_bytes(key_hash_bytes, a, b),
+        (T_signature, _, _, _) => compare_bytes(signature_bytes, a, b),
+        (T_chain_id, _, _, _) => compare_bytes(chain_id_bytes, a, b),
+        (T_tx_rollup_l2_address, _, _, _) => compare_bytes(tx_rollup_l2_address_bytes, a, b),
+        (T_option, [inner], _, _) => match (a, b) {
+            (Node::Prim(D_None, _, _), Node::Prim(D_None, _, _)) => Some(Ordering::Equal),
+            (Node::Prim(D_None, _, _), Node::Prim(D_Some, _, _)) => Some(Ordering::Less),
+            (Node::Prim(D_Some, _, _), Node::Prim(D_None, _, _)) => Some(Ordering::Greater),
+            (Node::Prim(D_Some, a, _), Node::Prim(D_Some, b, _)) => compare_data(inner, a.first()?, b.first()?),
+            _ => None,
+        },
+        (T_or, [left, right], Node::Prim(a_side, a, _), Node::Prim(b_side, b, _)) => match (a_side, b_side) {
+            (D_Left, D_Left) => compare_data(left, a.first()?, b.first()?),
+            (D_Right, D_Right) => compare_data(right, a.first()?, b.first()?),
+            (D_Left, D_Right) => Some(Ordering::Less),
+            (D_Right, D_Left) => Some(Ordering::Greater),
+            _ => None,
+        },
+        (T_pair, types, _, _) => {
+            let (mut a_leaves, mut b_leaves) = (Vec::new(), Vec::new());
+            comb_leaves(types, pair_components(a)?, &mut Vec::new(), 0, &mut a_leaves).ok()?;
+            comb_leaves(types, pair_components(b)?, &mut Vec::new(), 0, &mut b_leaves).ok()?;
+            if a_leaves.len() != b_leaves.len() {
+                return None;
+            }
+            for ((ty, a, _), (_, b, _)) in a_leaves.iter().zip(b_leaves.iter()) {
+                match compare_data(ty, a, b)? {
+                    Ordering::Equal => continue,
+                    ordering => return Some(ordering),
+                }
+            }
+            Some(Ordering::Equal)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::typecheck::*;
+
+    fn prim(prim: Primitive, args: Vec<Node<Primitive>>) -> Node<Primitive> {
+        Node::Prim(prim, args, vec![])
+    }
+
+    fn err(path: Vec<usize>, kind: TypeErrorKind) -> Result<(), TypeError> {
+        Err(TypeError { path: Path(path), kind })
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(typecheck_data(&prim(T_nat, vec![]), &Node::Int(3)), Ok(()));
+        assert_eq!(typecheck_data(&prim(T_nat, vec![]), &Node::Int(-3)), err(vec![], TypeErrorKind::NegativeNat));
+        assert_eq!(typecheck_data(&prim(T_mutez, vec![]), &Node::Int(-1)), err(vec![], TypeErrorKind::NegativeMutez));
+        assert_eq!(
+            typecheck_data(&prim(T_int, vec![]), &Node::String(String::from("1"))),
+            err(vec![], TypeErrorKind::UnexpectedValue(prim(T_int, vec![])))
+        );
+        assert_eq!(typecheck_data(&prim(T_string, vec![]), &Node::String(String::from("\t"))), err(vec![], TypeErrorKind::InvalidString));
+        assert_eq!(typecheck_data(&prim(T_bool, vec![]), &prim(D_True, vec![])), Ok(()));
+        assert_eq!(
+            typecheck_data(&prim(T_bool, vec![]), &prim(D_True, vec![Node::Int(1)])),
+            err(vec![], TypeErrorKind::InvalidArity(D_True))
+        );
+        assert_eq!(typecheck_data(&prim(T_timestamp, vec![]), &Node::String(String::from("2019-09-26T10:59:51Z"))), Ok(()));
+        assert_eq!(typecheck_data(&prim(T_timestamp, vec![]), &Node::String(String::from("2019-09-26"))), err(vec![], TypeErrorKind::InvalidTimestamp));
+        assert_eq!(typecheck_data(&prim(T_operation, vec![]), &Node::Int(0)), err(vec![], TypeErrorKind::Uninhabited));
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_timestamp("2019-09-26T10:59:51Z"), Some(1569495591));
+        assert_eq!(parse_timestamp("2019-09-26T12:59:51.25+02:00"), Some(1569495591));
+        assert_eq!(parse_timestamp("2019-13-26T10:59:51Z"), None);
+        assert_eq!(format_timestamp(1569495591), "2019-09-26T10:59:51Z");
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951782400), "2000-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn addresses_and_keys() {
+        let address = prim(T_address, vec![]);
+        let valid = [
+            "KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc",
+            "KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc%transfer",
+            "tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx",
+            "tz2BFTyPeYRzxd5aiBchbXN3WCZhx7BqbMBq",
+            "tz3hFR7NZtjT2QtzgMQnWb4xMuD6yt2YzXUt",
+        ];
+        for value in valid {
+            assert_eq!(typecheck_data(&address, &Node::String(String::from(value))), Ok(()), "{}", value);
+        }
+        let invalid = [
+            "KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesd",
+            "KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc%default",
+            "KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc%",
+            "edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav",
+        ];
+        for value in invalid {
+            assert_eq!(typecheck_data(&address, &Node::String(String::from(value))), err(vec![], TypeErrorKind::InvalidAddress), "{}", value);
+        }
+
+        let mut bytes = vec![1];
+        bytes.extend([0x11; 20]);
+        bytes.push(0);
+        assert_eq!(typecheck_data(&address, &Node::Bytes(bytes.clone())), Ok(()));
+        bytes[21] = 1;
+        assert_eq!(typecheck_data(&address, &Node::Bytes(bytes)), err(vec![], TypeErrorKind::InvalidAddress));
+
+        let key = prim(T_key, vec![]);
+        assert_eq!(typecheck_data(&key, &Node::String(String::from("edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav"))), Ok(()));
+        assert_eq!(typecheck_data(&key, &Node::Bytes(vec![0; 33])), Ok(()));
+        assert_eq!(typecheck_data(&key, &Node::Bytes(vec![0; 32])), err(vec![], TypeErrorKind::InvalidKey));
+        assert_eq!(typecheck_data(&prim(T_chain_id, vec![]), &Node::String(String::from("NetXdQprcVkpaWU"))), Ok(()));
+    }
+
+    #[test]
+    fn pairs() {
+        let ty = prim(T_pair, vec![prim(T_nat, vec![]), prim(T_string, vec![]), prim(T_int, vec![])]);
+        let nested = prim(T_pair, vec![prim(T_nat, vec![]), prim(T_pair, vec![prim(T_string, vec![]), prim(T_int, vec![])])]);
+        let flat = prim(D_Pair, vec![Node::Int(1), Node::String(String::from("a")), Node::Int(-1)]);
+        let comb = prim(D_Pair, vec![Node::Int(1), prim(D_Pair, vec![Node::String(String::from("a")), Node::Int(-1)])]);
+        let seq = Node::Seq(vec![Node::Int(1), Node::String(String::from("a")), Node::Int(-1)]);
+
+        for ty in [&ty, &nested] {
+            for value in [&flat, &comb, &seq] {
+                assert_eq!(typecheck_data(ty, value), Ok(()));
+            }
+        }
+
+        assert_eq!(
+            typecheck_data(&ty, &prim(D_Pair, vec![Node::Int(1), prim(D_Pair, vec![Node::String(String::from("a")), Node::Int(-1)])])),
+            Ok(())
+        );
+        assert_eq!(
+            typecheck_data(&nested, &prim(D_Pair, vec![Node::Int(1), Node::String(String::from("a")), Node::Int(-1), Node::Int(2)])),
+            err(vec![2], TypeErrorKind::UnexpectedValue(prim(T_int, vec![])))
+        );
+        assert_eq!(
+            typecheck_data(&ty, &prim(D_Pair, vec![Node::Int(1), prim(D_Pair, vec![Node::Int(2), Node::Int(-1)])])),
+            err(vec![1, 0], TypeErrorKind::UnexpectedValue(prim(T_string, vec![])))
+        );
+        assert_eq!(
+            typecheck_data(&ty, &prim(D_Pair, vec![Node::Int(1)])),
+            err(vec![], TypeErrorKind::InvalidArity(D_Pair))
+        );
+    }
+
+    #[test]
+    fn collections() {
+        let set = prim(T_set, vec![prim(T_nat, vec![])]);
+        assert_eq!(typecheck_data(&set, &Node::Seq(vec![Node::Int(1), Node::Int(2)])), Ok(()));
+        assert_eq!(typecheck_data(&set, &Node::Seq(vec![Node::Int(2), Node::Int(1)])), err(vec![1], TypeErrorKind::Unsorted));
+        assert_eq!(typecheck_data(&set, &Node::Seq(vec![Node::Int(1), Node::Int(1)])), err(vec![1], TypeErrorKind::Duplicate));
+        assert_eq!(typecheck_data(&set, &Node::Seq(vec![Node::Int(1), Node::Int(-1)])), err(vec![1], TypeErrorKind::NegativeNat));
+
+        let map = prim(T_map, vec![prim(T_string, vec![]), prim(T_nat, vec![])]);
+        let elt = |key: &str, value: i32| prim(D_Elt, vec![Node::String(String::from(key)), Node::Int(value)]);
+        assert_eq!(typecheck_data(&map, &Node::Seq(vec![elt("a", 1), elt("b", 2)])), Ok(()));
+        assert_eq!(typecheck_data(&map, &Node::Seq(vec![elt("b", 1), elt("a", 2)])), err(vec![1], TypeErrorKind::Unsorted));
+        assert_eq!(typecheck_data(&map, &Node::Seq(vec![elt("a", 1), elt("b", -2)])), err(vec![1, 1], TypeErrorKind::NegativeNat));
+        assert_eq!(typecheck_data(&map, &Node::Seq(vec![Node::Int(1)])), err(vec![0], TypeErrorKind::UnexpectedValue(map.clone())));
+
+        let list = prim(T_list, vec![prim(T_nat, vec![])]);
+        assert_eq!(typecheck_data(&list, &Node::Seq(vec![Node::Int(1), elt("a", 1)])), err(vec![1], TypeErrorKind::UnexpectedElt));
+
+        assert_eq!(typecheck_data(&prim(T_set, vec![list.clone()]), &Node::Seq(vec![])), err(vec![0], TypeErrorKind::NotComparable));
+        assert_eq!(typecheck_data(&prim(T_option, vec![]), &prim(D_None, vec![])), err(vec![], TypeErrorKind::InvalidType));
+    }
+
+    #[test]
+    fn comparison() {
+        let ty = prim(T_pair, vec![prim(T_nat, vec![]), prim(T_option, vec![prim(T_string, vec![])])]);
+        let a = prim(D_Pair, vec![Node::Int(1), prim(D_None, vec![])]);
+        let b = Node::Seq(vec![Node::Int(1), prim(D_Some, vec![Node::String(String::from("a"))])]);
+        assert_eq!(compare_data(&ty, &a, &b), Some(Ordering::Less));
+        assert_eq!(compare_data(&ty, &b, &b), Some(Ordering::Equal));
+
+        let address = prim(T_address, vec![]);
+        let implicit = Node::String(String::from("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx"));
+        let originated = Node::String(String::from("KT1BuEZtb68c1Q4yjtckcNjGELqWt56Xyesc"));
+        assert_eq!(compare_data(&address, &implicit, &originated), Some(Ordering::Less));
+
+        // Malformed pair types and values are not comparable.
+        let pair = prim(D_Pair, vec![Node::Int(1), Node::Int(2)]);
+        assert_eq!(compare_data(&prim(T_pair, vec![]), &pair, &pair), None);
+        assert_eq!(compare_data(&prim(T_pair, vec![prim(T_nat, vec![])]), &pair, &pair), None);
+        assert_eq!(compare_data(&ty, &prim(D_Pair, vec![]), &prim(D_Pair, vec![])), None);
+    }
+}
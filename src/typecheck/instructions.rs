@@ -0,0 +1,1151 @@
+// Stack typing of Michelson instruction sequences.
+//
+// Stack types are lists of types with the top of the stack first. Types on
+// the stack are kept normalized (no annotations, pairs as binary combs) so
+// they can be compared structurally.
+
+use std::cell::RefCell;
+
+use crate::Node;
+use crate::michelson_v1_primitives::{Namespace, Primitive};
+use crate::michelson_v1_primitives::Primitive::*;
+use crate::path::Path;
+use crate::typecheck::{check_data, check_type_at, error, is_comparable, with_index, TypeError, TypeErrorKind};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Stack {
+    Typed(Vec<Node<Primitive>>),
+    /// The code always fails; this is the bottom of stack types and unifies
+    /// with any other stack.
+    Failed,
+}
+
+fn ty(prim: Primitive, args: Vec<Node<Primitive>>) -> Node<Primitive> {
+    Node::Prim(prim, args, vec![])
+}
+
+fn simple(ty: &Node<Primitive>) -> Option<&Primitive> {
+    match ty {
+        Node::Prim(prim, args, _) if args.is_empty() => Some(prim),
+        _ => None,
+    }
+}
+
+fn args_of<'a>(ty: &'a Node<Primitive>, expected: &Primitive) -> Option<&'a [Node<Primitive>]> {
+    match ty {
+        Node::Prim(prim, args, _) if prim == expected => Some(args),
+        _ => None,
+    }
+}
+
+/// Strips annotations and rewrites n-ary pairs as right combs, so that
+/// equivalent types compare equal.
+pub fn normalize_type(node: &Node<Primitive>) -> Node<Primitive> {
+    match node {
+        Node::Prim(T_pair, args, _) if args.len() > 2 => {
+            ty(T_pair, vec![normalize_type(&args[0]), normalize_type(&ty(T_pair, args[1..].to_vec()))])
+        }
+        Node::Prim(prim, args, _) => ty(*prim, args.iter().map(normalize_type).collect()),
+        other => other.clone(),
+    }
+}
+
+fn contains(ty: &Node<Primitive>, forbidden: &[Primitive]) -> bool {
+    match ty {
+        Node::Prim(T_lambda, _, _) => false,
+        Node::Prim(prim, args, _) => forbidden.contains(prim) || args.iter().any(|arg| contains(arg, forbidden)),
+        _ => false,
+    }
+}
+
+fn is_pushable(ty: &Node<Primitive>) -> bool {
+    !contains(ty, &[T_operation, T_big_map, T_sapling_state, T_ticket, T_contract])
+}
+
+fn is_packable(ty: &Node<Primitive>) -> bool {
+    !contains(ty, &[T_operation, T_big_map, T_sapling_state, T_ticket])
+}
+
+fn is_passable(ty: &Node<Primitive>) -> bool {
+    !contains(ty, &[T_operation])
+}
+
+fn is_storable(ty: &Node<Primitive>) -> bool {
+    !contains(ty, &[T_operation, T_contract])
+}
+
+fn is_duplicable(ty: &Node<Primitive>) -> bool {
+    !contains(ty, &[T_ticket])
+}
+
+fn field_annotation(annot: &[String]) -> Option<&str> {
+    annot.iter().find_map(|annot| annot.strip_prefix('%'))
+}
+
+/// Finds the type of an entrypoint in a parameter type, following field
+/// annotations on the `or` branches.
+pub fn find_entrypoint<'a>(parameter: &'a Node<Primitive>, name: &str) -> Option<&'a Node<Primitive>> {
+    fn find<'a>(ty: &'a Node<Primitive>, name: &str) -> Option<&'a Node<Primitive>> {
+        match ty {
+            Node::Prim(_, _, annot) if field_annotation(annot) == Some(name) => Some(ty),
+            Node::Prim(T_or, args, _) => args.iter().find_map(|arg| find(arg, name)),
+            _ => None,
+        }
+    }
+
+    match find(parameter, name) {
+        None if name == "default" => Some(parameter),
+        found => found,
+    }
+}
+
+type Operands = Vec<(Vec<usize>, Node<Primitive>)>;
+
+#[derive(Clone, Copy)]
+struct Checker<'a> {
+    /// Parameter type of the enclosing contract, if any, for `SELF`.
+    parameter: Option<&'a Node<Primitive>>,
+    in_lambda: bool,
+    in_view: bool,
+    /// Collects the paths and operand types of the instructions whose
+    /// semantics values do not tell: `CONCAT` on lists, which may be empty,
+    /// and `BYTES`, whose operand may be an int or a nat.
+    operands: Option<&'a RefCell<Operands>>,
+}
+
+fn bad_stack<T>(path: &[usize], instruction: &Primitive, stack: &[Node<Primitive>]) -> Result<T, TypeError> {
+    error(path, TypeErrorKind::BadStack { instruction: *instruction, stack: stack.to_vec() })
+}
+
+fn push(top: Vec<Node<Primitive>>, rest: &[Node<Primitive>]) -> Result<Stack, TypeError> {
+    let mut stack = top;
+    stack.extend_from_slice(rest);
+    Ok(Stack::Typed(stack))
+}
+
+fn merge(left: Stack, right: Stack, path: &[usize]) -> Result<Stack, TypeError> {
+    match (left, right) {
+        (Stack::Failed, other) | (other, Stack::Failed) => Ok(other),
+        (Stack::Typed(left), Stack::Typed(right)) if left == right => Ok(Stack::Typed(left)),
+        (Stack::Typed(left), Stack::Typed(right)) => error(path, TypeErrorKind::UnmatchedBranches { left, right }),
+    }
+}
+
+fn expect(expected: &[Node<Primitive>], actual: Stack, path: &[usize]) -> Result<(), TypeError> {
+    match actual {
+        Stack::Failed => Ok(()),
+        Stack::Typed(actual) if actual == expected => Ok(()),
+        Stack::Typed(actual) => error(path, TypeErrorKind::StackMismatch { expected: expected.to_vec(), actual }),
+    }
+}
+
+fn natural_argument(args: &[Node<Primitive>], index: usize, path: &mut Vec<usize>) -> Result<usize, TypeError> {
+    match args.get(index) {
+        Some(Node::Int(n)) if *n >= 0 => Ok(*n as usize),
+        _ => with_index(path, index, |path| error(path, TypeErrorKind::UnexpectedValue(ty(T_nat, vec![])))),
+    }
+}
+
+fn type_argument(args: &[Node<Primitive>], index: usize, path: &mut Vec<usize>) -> Result<Node<Primitive>, TypeError> {
+    with_index(path, index, |path| {
+        check_type_at(&args[index], path)?;
+        Ok(normalize_type(&args[index]))
+    })
+}
+
+fn arithmetic(instruction: &Primitive, a: &Primitive, b: &Primitive) -> Option<Node<Primitive>> {
+    let result = match (instruction, a, b) {
+        (I_ADD, T_nat, T_nat) => T_nat,
+        (I_ADD, T_nat | T_int, T_nat | T_int) => T_int,
+        (I_ADD, T_timestamp, T_int) | (I_ADD, T_int, T_timestamp) => T_timestamp,
+        (I_ADD, T_mutez, T_mutez) => T_mutez,
+        (I_ADD, T_bls12_381_g1, T_bls12_381_g1) => T_bls12_381_g1,
+        (I_ADD, T_bls12_381_g2, T_bls12_381_g2) => T_bls12_381_g2,
+        (I_ADD, T_bls12_381_fr, T_bls12_381_fr) => T_bls12_381_fr,
+        (I_SUB, T_nat | T_int, T_nat | T_int) => T_int,
+        (I_SUB, T_timestamp, T_int) => T_timestamp,
+        (I_SUB, T_timestamp, T_timestamp) => T_int,
+        (I_SUB_MUTEZ, T_mutez, T_mutez) => return Some(ty(T_option, vec![ty(T_mutez, vec![])])),
+        (I_MUL, T_nat, T_nat) => T_nat,
+        (I_MUL, T_nat | T_int, T_nat | T_int) => T_int,
+        (I_MUL, T_mutez, T_nat) | (I_MUL, T_nat, T_mutez) => T_mutez,
+        (I_MUL, T_bls12_381_g1, T_bls12_381_fr) => T_bls12_381_g1,
+        (I_MUL, T_bls12_381_g2, T_bls12_381_fr) => T_bls12_381_g2,
+        (I_MUL, T_bls12_381_fr | T_nat | T_int, T_bls12_381_fr) => T_bls12_381_fr,
+        (I_MUL, T_bls12_381_fr, T_nat | T_int) => T_bls12_381_fr,
+        (I_EDIV, _, _) => {
+            let (quotient, remainder) = match (a, b) {
+                (T_nat, T_nat) => (T_nat, T_nat),
+                (T_nat | T_int, T_nat | T_int) => (T_int, T_nat),
+                (T_mutez, T_nat) => (T_mutez, T_mutez),
+                (T_mutez, T_mutez) => (T_nat, T_mutez),
+                _ => return None,
+            };
+            let pair = ty(T_pair, vec![ty(quotient, vec![]), ty(remainder, vec![])]);
+            return Some(ty(T_option, vec![pair]));
+        }
+        (I_LSL | I_LSR, T_nat, T_nat) => T_nat,
+        (I_LSL | I_LSR, T_bytes, T_nat) => T_bytes,
+        (I_OR | I_XOR | I_AND, T_bool, T_bool) => T_bool,
+        (I_OR | I_XOR | I_AND, T_nat, T_nat) => T_nat,
+        (I_OR | I_XOR | I_AND, T_bytes, T_bytes) => T_bytes,
+        (I_AND, T_int, T_nat) => T_nat,
+        _ => return None,
+    };
+    Some(ty(result, vec![]))
+}
+
+fn unary(instruction: &Primitive, a: &Primitive) -> Option<Node<Primitive>> {
+    let result = match (instruction, a) {
+        (I_NOT, T_bool) => T_bool,
+        (I_NOT, T_nat | T_int) => T_int,
+        (I_NOT, T_bytes) => T_bytes,
+        (I_NEG, T_nat | T_int) => T_int,
+        (I_NEG, T_bls12_381_g1 | T_bls12_381_g2 | T_bls12_381_fr) => *a,
+        (I_ABS, T_int) => T_nat,
+        (I_ISNAT, T_int) => return Some(ty(T_option, vec![ty(T_nat, vec![])])),
+        (I_INT, T_nat | T_bls12_381_fr | T_bytes) => T_int,
+        (I_NAT, T_bytes) => T_nat,
+        (I_BYTES, T_int | T_nat) => T_bytes,
+        (I_IS_IMPLICIT_ACCOUNT, T_address) => return Some(ty(T_option, vec![ty(T_key_hash, vec![])])),
+        (I_EQ | I_NEQ | I_LT | I_GT | I_LE | I_GE, T_int) => T_bool,
+        (I_BLAKE2B | I_SHA256 | I_SHA512 | I_KECCAK | I_SHA3, T_bytes) => T_bytes,
+        (I_HASH_KEY, T_key) => T_key_hash,
+        (I_VOTING_POWER, T_key_hash) => T_nat,
+        _ => return None,
+    };
+    Some(ty(result, vec![]))
+}
+
+fn constant(instruction: &Primitive) -> Option<Node<Primitive>> {
+    let result = match instruction {
+        I_UNIT => T_unit,
+        I_AMOUNT | I_BALANCE => T_mutez,
+        I_NOW => T_timestamp,
+        I_SOURCE | I_SENDER | I_SELF_ADDRESS => T_address,
+        I_CHAIN_ID => T_chain_id,
+        I_LEVEL | I_TOTAL_VOTING_POWER | I_MIN_BLOCK_TIME => T_nat,
+        _ => return None,
+    };
+    Some(ty(result, vec![]))
+}
+
+/// Type of the `n`-th element of a right comb, as accessed by `GET n`.
+fn comb_get(comb: &Node<Primitive>, n: usize) -> Option<Node<Primitive>> {
+    match (n, args_of(comb, &T_pair)) {
+        (0, _) => Some(comb.clone()),
+        (1, Some([left, _])) => Some(left.clone()),
+        (2, Some([_, right])) => Some(right.clone()),
+        (_, Some([_, right])) => comb_get(right, n - 2),
+        _ => None,
+    }
+}
+
+/// Type of a right comb after its `n`-th element is replaced, as by `UPDATE n`.
+fn comb_update(comb: &Node<Primitive>, n: usize, value: &Node<Primitive>) -> Option<Node<Primitive>> {
+    match (n, args_of(comb, &T_pair)) {
+        (0, _) => Some(value.clone()),
+        (1, Some([_, right])) => Some(ty(T_pair, vec![value.clone(), right.clone()])),
+        (2, Some([left, _])) => Some(ty(T_pair, vec![left.clone(), value.clone()])),
+        (_, Some([left, right])) => Some(ty(T_pair, vec![left.clone(), comb_update(right, n - 2, value)?])),
+        _ => None,
+    }
+}
+
+impl<'a> Checker<'a> {
+    fn record_operand(&self, path: &[usize], operand: &Node<Primitive>) {
+        if let Some(operands) = self.operands {
+            operands.borrow_mut().push((path.to_vec(), operand.clone()));
+        }
+    }
+
+    fn check_seq(&self, code: &Node<Primitive>, stack: Vec<Node<Primitive>>, path: &mut Vec<usize>) -> Result<Stack, TypeError> {
+        let instructions = match code {
+            Node::Seq(instructions) => instructions,
+            _ => return error(path, TypeErrorKind::InvalidInstruction),
+        };
+
+        let mut stack = Stack::Typed(stack);
+        for (index, instruction) in instructions.iter().enumerate() {
+            stack = match stack {
+                Stack::Failed => return with_index(path, index, |path| error(path, TypeErrorKind::FailNotInTail)),
+                Stack::Typed(stack) => with_index(path, index, |path| self.check_instruction(instruction, stack, path))?,
+            };
+        }
+        Ok(stack)
+    }
+
+    fn check_block(&self, args: &[Node<Primitive>], index: usize, stack: Vec<Node<Primitive>>, path: &mut Vec<usize>) -> Result<Stack, TypeError> {
+        with_index(path, index, |path| self.check_seq(&args[index], stack, path))
+    }
+
+    fn check_instruction(&self, instruction: &Node<Primitive>, stack: Vec<Node<Primitive>>, path: &mut Vec<usize>) -> Result<Stack, TypeError> {
+        match instruction {
+            Node::Seq(_) => self.check_seq(instruction, stack, path),
+            Node::Prim(prim, args, annot) => self.check_prim(prim, args, annot, stack, path),
+            _ => error(path, TypeErrorKind::InvalidInstruction),
+        }
+    }
+
+    fn check_arity(&self, prim: &Primitive, args: &[Node<Primitive>], path: &[usize]) -> Result<(), TypeError> {
+        if prim.namespace() != Namespace::Instruction {
+            return error(path, TypeErrorKind::InvalidInstruction);
+        }
+        let valid = prim.arity().contains(&args.len());
+        if valid { Ok(()) } else { error(path, TypeErrorKind::InvalidArity(*prim)) }
+    }
+
+    fn check_prim(
+        &self,
+        prim: &Primitive,
+        args: &[Node<Primitive>],
+        annot: &[String],
+        stack: Vec<Node<Primitive>>,
+        path: &mut Vec<usize>,
+    ) -> Result<Stack, TypeError> {
+        self.check_arity(prim, args, path)?;
+
+        match (prim, &stack[..]) {
+            (I_CREATE_ACCOUNT | I_STEPS_TO_QUOTA, _) => error(path, TypeErrorKind::Deprecated(*prim)),
+            (I_SELF, _) if self.in_lambda => error(path, TypeErrorKind::ForbiddenInstruction(*prim)),
+            (I_CREATE_CONTRACT | I_SET_DELEGATE | I_TRANSFER_TOKENS, _) if self.in_view => {
+                error(path, TypeErrorKind::ForbiddenInstruction(*prim))
+            }
+
+            // Stack manipulation
+            (I_DROP, _) => {
+                let n = if args.is_empty() { 1 } else { natural_argument(args, 0, path)? };
+                if stack.len() < n {
+                    return bad_stack(path, prim, &stack);
+                }
+                push(vec![], &stack[n..])
+            }
+            (I_DUP, _) => {
+                let n = if args.is_empty() { 1 } else { natural_argument(args, 0, path)? };
+                match stack.get(n.wrapping_sub(1)) {
+                    Some(top) if n > 0 && is_duplicable(top) => push(vec![top.clone()], &stack),
+                    Some(_) if n > 0 => error(path, TypeErrorKind::NotDuplicable),
+                    _ => bad_stack(path, prim, &stack),
+                }
+            }
+            (I_SWAP, [a, b, rest @ ..]) => push(vec![b.clone(), a.clone()], rest),
+            (I_DIG, _) => {
+                let n = natural_argument(args, 0, path)?;
+                if n >= stack.len() {
+                    return bad_stack(path, prim, &stack);
+                }
+                let mut stack = stack;
+                let item = stack.remove(n);
+                push(vec![item], &stack)
+            }
+            (I_DUG, _) => {
+                let n = natural_argument(args, 0, path)?;
+                if n >= stack.len() {
+                    return bad_stack(path, prim, &stack);
+                }
+                let mut stack = stack;
+                let item = stack.remove(0);
+                stack.insert(n, item);
+                Ok(Stack::Typed(stack))
+            }
+            (I_PUSH, _) => {
+                let pushed = type_argument(args, 0, path)?;
+                if !is_pushable(&pushed) {
+                    return with_index(path, 0, |path| error(path, TypeErrorKind::NotPushable));
+                }
+                with_index(path, 1, |path| check_data(&args[0], &args[1], path))?;
+                push(vec![pushed], &stack)
+            }
+            (I_DIP, _) => {
+                let n = if args.len() == 2 { natural_argument(args, 0, path)? } else { 1 };
+                if stack.len() < n {
+                    return bad_stack(path, prim, &stack);
+                }
+                let (protected, rest) = stack.split_at(n);
+                match self.check_block(args, args.len() - 1, rest.to_vec(), path)? {
+                    Stack::Typed(rest) => push(protected.to_vec(), &rest),
+                    Stack::Failed => error(path, TypeErrorKind::FailNotInTail),
+                }
+            }
+
+            // Options, unions and pairs
+            (I_SOME, [a, rest @ ..]) => push(vec![ty(T_option, vec![a.clone()])], rest),
+            (I_NONE, _) => {
+                let inner = type_argument(args, 0, path)?;
+                push(vec![ty(T_option, vec![inner])], &stack)
+            }
+            (I_IF_NONE, [option, rest @ ..]) => match args_of(option, &T_option) {
+                Some([inner]) => {
+                    let none = self.check_block(args, 0, rest.to_vec(), path)?;
+                    let mut some_stack = vec![inner.clone()];
+                    some_stack.extend_from_slice(rest);
+                    let some = self.check_block(args, 1, some_stack, path)?;
+                    merge(none, some, path)
+                }
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_LEFT | I_RIGHT, [a, rest @ ..]) => {
+                let other = type_argument(args, 0, path)?;
+                let or = if *prim == I_LEFT { vec![a.clone(), other] } else { vec![other, a.clone()] };
+                push(vec![ty(T_or, or)], rest)
+            }
+            (I_IF_LEFT, [or, rest @ ..]) => match args_of(or, &T_or) {
+                Some([left, right]) => {
+                    let mut left_stack = vec![left.clone()];
+                    left_stack.extend_from_slice(rest);
+                    let mut right_stack = vec![right.clone()];
+                    right_stack.extend_from_slice(rest);
+                    let left = self.check_block(args, 0, left_stack, path)?;
+                    let right = self.check_block(args, 1, right_stack, path)?;
+                    merge(left, right, path)
+                }
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_PAIR, _) => {
+                let n = if args.is_empty() { 2 } else { natural_argument(args, 0, path)? };
+                if n < 2 || stack.len() < n {
+                    return bad_stack(path, prim, &stack);
+                }
+                let comb = stack[..n]
+                    .iter()
+                    .rev()
+                    .cloned()
+                    .reduce(|right, left| ty(T_pair, vec![left, right]))
+                    .expect("n >= 2");
+                push(vec![comb], &stack[n..])
+            }
+            (I_UNPAIR, [comb, rest @ ..]) => {
+                let n = if args.is_empty() { 2 } else { natural_argument(args, 0, path)? };
+                if n < 2 {
+                    return bad_stack(path, prim, &stack);
+                }
+                let mut components = Vec::new();
+                let mut current = comb.clone();
+                for _ in 1..n {
+                    match args_of(&current, &T_pair) {
+                        Some([left, right]) => {
+                            components.push(left.clone());
+                            current = right.clone();
+                        }
+                        _ => return bad_stack(path, prim, &stack),
+                    }
+                }
+                components.push(current);
+                push(components, rest)
+            }
+            (I_CAR | I_CDR, [pair, rest @ ..]) => match args_of(pair, &T_pair) {
+                Some([left, right]) => push(vec![if *prim == I_CAR { left.clone() } else { right.clone() }], rest),
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_GET, [comb, rest @ ..]) if args.len() == 1 => {
+                let n = natural_argument(args, 0, path)?;
+                match comb_get(comb, n) {
+                    Some(item) => push(vec![item], rest),
+                    None => bad_stack(path, prim, &stack),
+                }
+            }
+            (I_UPDATE, [value, comb, rest @ ..]) if args.len() == 1 => {
+                let n = natural_argument(args, 0, path)?;
+                match comb_update(comb, n, value) {
+                    Some(comb) => push(vec![comb], rest),
+                    None => bad_stack(path, prim, &stack),
+                }
+            }
+
+            // Collections
+            (I_NIL, _) => {
+                let element = type_argument(args, 0, path)?;
+                push(vec![ty(T_list, vec![element])], &stack)
+            }
+            (I_CONS, [element, list, rest @ ..]) if args_of(list, &T_list) == Some(std::slice::from_ref(element)) => {
+                push(vec![list.clone()], rest)
+            }
+            (I_IF_CONS, [list, rest @ ..]) => match args_of(list, &T_list) {
+                Some([element]) => {
+                    let mut cons_stack = vec![element.clone(), list.clone()];
+                    cons_stack.extend_from_slice(rest);
+                    let cons = self.check_block(args, 0, cons_stack, path)?;
+                    let nil = self.check_block(args, 1, rest.to_vec(), path)?;
+                    merge(cons, nil, path)
+                }
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_SIZE, [collection, rest @ ..]) => match collection {
+                Node::Prim(T_list | T_set | T_map | T_string | T_bytes, _, _) => push(vec![ty(T_nat, vec![])], rest),
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_EMPTY_SET, _) => {
+                let element = type_argument(args, 0, path)?;
+                if !is_comparable(&element) {
+                    return with_index(path, 0, |path| error(path, TypeErrorKind::NotComparable));
+                }
+                push(vec![ty(T_set, vec![element])], &stack)
+            }
+            (I_EMPTY_MAP | I_EMPTY_BIG_MAP, _) => {
+                let key = type_argument(args, 0, path)?;
+                let value = type_argument(args, 1, path)?;
+                if !is_comparable(&key) {
+                    return with_index(path, 0, |path| error(path, TypeErrorKind::NotComparable));
+                }
+                let map = if *prim == I_EMPTY_MAP { T_map } else { T_big_map };
+                push(vec![ty(map, vec![key, value])], &stack)
+            }
+            (I_MEM, [key, collection, rest @ ..]) => match collection {
+                Node::Prim(T_set | T_map | T_big_map, args, _) if args[0] == *key => push(vec![ty(T_bool, vec![])], rest),
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_GET, [key, collection, rest @ ..]) => match collection {
+                Node::Prim(T_map | T_big_map, args, _) if args[0] == *key => {
+                    push(vec![ty(T_option, vec![args[1].clone()])], rest)
+                }
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_UPDATE, [key, value, collection, rest @ ..]) => match collection {
+                Node::Prim(T_set, args, _) if args[0] == *key && simple(value) == Some(&T_bool) => {
+                    push(vec![collection.clone()], rest)
+                }
+                Node::Prim(T_map | T_big_map, args, _)
+                    if args[0] == *key && args_of(value, &T_option) == Some(&args[1..]) => push(vec![collection.clone()], rest),
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_GET_AND_UPDATE, [key, value, collection, rest @ ..]) => match collection {
+                Node::Prim(T_map | T_big_map, args, _) if args[0] == *key && args_of(value, &T_option) == Some(&args[1..]) => {
+                    push(vec![value.clone(), collection.clone()], rest)
+                }
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_MAP, [collection, rest @ ..]) => {
+                let element = match collection {
+                    Node::Prim(T_list | T_option, args, _) => args[0].clone(),
+                    Node::Prim(T_map, args, _) => ty(T_pair, vec![args[0].clone(), args[1].clone()]),
+                    _ => return bad_stack(path, prim, &stack),
+                };
+                let mut body_stack = vec![element];
+                body_stack.extend_from_slice(rest);
+                match self.check_block(args, 0, body_stack, path)? {
+                    Stack::Typed(result) if !result.is_empty() && result[1..] == *rest => {
+                        let mapped = match collection {
+                            Node::Prim(T_map, args, _) => ty(T_map, vec![args[0].clone(), result[0].clone()]),
+                            Node::Prim(prim, _, _) => ty(*prim, vec![result[0].clone()]),
+                            _ => unreachable!("checked above"),
+                        };
+                        push(vec![mapped], rest)
+                    }
+                    Stack::Typed(result) => with_index(path, 0, |path| {
+                        error(path, TypeErrorKind::StackMismatch { expected: rest.to_vec(), actual: result })
+                    }),
+                    Stack::Failed => error(path, TypeErrorKind::FailNotInTail),
+                }
+            }
+            (I_ITER, [collection, rest @ ..]) => {
+                let element = match collection {
+                    Node::Prim(T_list | T_set, args, _) => args[0].clone(),
+                    Node::Prim(T_map, args, _) => ty(T_pair, vec![args[0].clone(), args[1].clone()]),
+                    _ => return bad_stack(path, prim, &stack),
+                };
+                let mut body_stack = vec![element];
+                body_stack.extend_from_slice(rest);
+                let body = self.check_block(args, 0, body_stack, path)?;
+                with_index(path, 0, |path| expect(rest, body, path))?;
+                push(vec![], rest)
+            }
+
+            // Control structures
+            (I_IF, [condition, rest @ ..]) if simple(condition) == Some(&T_bool) => {
+                let then = self.check_block(args, 0, rest.to_vec(), path)?;
+                let otherwise = self.check_block(args, 1, rest.to_vec(), path)?;
+                merge(then, otherwise, path)
+            }
+            (I_LOOP, [condition, rest @ ..]) if simple(condition) == Some(&T_bool) => {
+                let body = self.check_block(args, 0, rest.to_vec(), path)?;
+                with_index(path, 0, |path| expect(&stack, body, path))?;
+                push(vec![], rest)
+            }
+            (I_LOOP_LEFT, [or, rest @ ..]) => match args_of(or, &T_or) {
+                Some([left, right]) => {
+                    let mut body_stack = vec![left.clone()];
+                    body_stack.extend_from_slice(rest);
+                    let body = self.check_block(args, 0, body_stack, path)?;
+                    with_index(path, 0, |path| expect(&stack, body, path))?;
+                    push(vec![right.clone()], rest)
+                }
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_LAMBDA, _) => {
+                let argument = type_argument(args, 0, path)?;
+                let result = type_argument(args, 1, path)?;
+                with_index(path, 2, |path| check_lambda(&argument, &result, &args[2], path))?;
+                push(vec![ty(T_lambda, vec![argument, result])], &stack)
+            }
+            (I_LAMBDA_REC, _) => {
+                let argument = type_argument(args, 0, path)?;
+                let result = type_argument(args, 1, path)?;
+                with_index(path, 2, |path| check_lambda_rec(&argument, &result, &args[2], path))?;
+                push(vec![ty(T_lambda, vec![argument, result])], &stack)
+            }
+            (I_EXEC, [argument, lambda, rest @ ..]) => match args_of(lambda, &T_lambda) {
+                Some([expected, result]) if expected == argument => push(vec![result.clone()], rest),
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_APPLY, [captured, lambda, rest @ ..]) => match args_of(lambda, &T_lambda) {
+                Some([parameter, result]) => match args_of(parameter, &T_pair) {
+                    Some([left, right]) if left == captured && is_packable(captured) => {
+                        push(vec![ty(T_lambda, vec![right.clone(), result.clone()])], rest)
+                    }
+                    _ => bad_stack(path, prim, &stack),
+                },
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_FAILWITH, [value, ..]) => {
+                if !is_packable(value) {
+                    return error(path, TypeErrorKind::NotPackable);
+                }
+                Ok(Stack::Failed)
+            }
+            (I_NEVER, [never, ..]) if simple(never) == Some(&T_never) => Ok(Stack::Failed),
+            (I_CAST, [top, rest @ ..]) => {
+                let cast = type_argument(args, 0, path)?;
+                if cast != *top {
+                    return error(path, TypeErrorKind::StackMismatch { expected: vec![cast], actual: vec![top.clone()] });
+                }
+                push(vec![cast], rest)
+            }
+            (I_RENAME, [_, ..]) => Ok(Stack::Typed(stack)),
+
+            // Arithmetic, logic, comparison and cryptography
+            (I_ADD | I_SUB | I_SUB_MUTEZ | I_MUL | I_EDIV | I_LSL | I_LSR | I_OR | I_XOR | I_AND, [a, b, rest @ ..]) => {
+                match (simple(a), simple(b)) {
+                    (Some(a), Some(b)) => match arithmetic(prim, a, b) {
+                        Some(result) => push(vec![result], rest),
+                        None => bad_stack(path, prim, &stack),
+                    },
+                    _ => bad_stack(path, prim, &stack),
+                }
+            }
+            (I_NOT | I_NEG | I_ABS | I_ISNAT | I_INT | I_NAT | I_BYTES | I_EQ | I_NEQ | I_LT | I_GT | I_LE | I_GE
+                | I_BLAKE2B | I_SHA256 | I_SHA512 | I_KECCAK | I_SHA3 | I_HASH_KEY | I_VOTING_POWER
+                | I_IS_IMPLICIT_ACCOUNT, [a, rest @ ..]) => match simple(a).and_then(|a| unary(prim, a)) {
+                Some(result) => {
+                    if *prim == I_BYTES {
+                        self.record_operand(path, a);
+                    }
+                    push(vec![result], rest)
+                }
+                None => bad_stack(path, prim, &stack),
+            },
+            (I_COMPARE, [a, b, rest @ ..]) if a == b => {
+                if !is_comparable(a) {
+                    return error(path, TypeErrorKind::NotComparable);
+                }
+                push(vec![ty(T_int, vec![])], rest)
+            }
+            (I_CONCAT, [a, b, rest @ ..]) if a == b && matches!(simple(a), Some(T_string | T_bytes)) => push(vec![a.clone()], rest),
+            (I_CONCAT, [list, rest @ ..]) => match args_of(list, &T_list) {
+                Some([element]) if matches!(simple(element), Some(T_string | T_bytes)) => {
+                    self.record_operand(path, element);
+                    push(vec![element.clone()], rest)
+                }
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_SLICE, [offset, length, sliced, rest @ ..])
+                if simple(offset) == Some(&T_nat) && simple(length) == Some(&T_nat)
+                    && matches!(simple(sliced), Some(T_string | T_bytes)) => {
+                push(vec![ty(T_option, vec![sliced.clone()])], rest)
+            }
+            (I_PACK, [value, rest @ ..]) => {
+                if !is_packable(value) {
+                    return error(path, TypeErrorKind::NotPackable);
+                }
+                push(vec![ty(T_bytes, vec![])], rest)
+            }
+            (I_UNPACK, [bytes, rest @ ..]) if simple(bytes) == Some(&T_bytes) => {
+                let unpacked = type_argument(args, 0, path)?;
+                if !is_packable(&unpacked) {
+                    return with_index(path, 0, |path| error(path, TypeErrorKind::NotPackable));
+                }
+                push(vec![ty(T_option, vec![unpacked])], rest)
+            }
+            (I_CHECK_SIGNATURE, [key, signature, bytes, rest @ ..])
+                if simple(key) == Some(&T_key) && simple(signature) == Some(&T_signature) && simple(bytes) == Some(&T_bytes) => {
+                push(vec![ty(T_bool, vec![])], rest)
+            }
+            (I_PAIRING_CHECK, [list, rest @ ..]) => {
+                let expected = ty(T_list, vec![ty(T_pair, vec![ty(T_bls12_381_g1, vec![]), ty(T_bls12_381_g2, vec![])])]);
+                if *list != expected {
+                    return bad_stack(path, prim, &stack);
+                }
+                push(vec![ty(T_bool, vec![])], rest)
+            }
+
+            // Blockchain operations
+            (I_UNIT | I_AMOUNT | I_BALANCE | I_NOW | I_SOURCE | I_SENDER | I_SELF_ADDRESS | I_CHAIN_ID | I_LEVEL
+                | I_TOTAL_VOTING_POWER | I_MIN_BLOCK_TIME, _) => {
+                push(vec![constant(prim).expect("constant instruction")], &stack)
+            }
+            (I_SELF, _) => {
+                let parameter = match self.parameter {
+                    Some(parameter) => parameter,
+                    None => return error(path, TypeErrorKind::ForbiddenInstruction(*prim)),
+                };
+                let entrypoint = field_annotation(annot).unwrap_or("default");
+                match find_entrypoint(parameter, entrypoint) {
+                    Some(parameter) => push(vec![ty(T_contract, vec![normalize_type(parameter)])], &stack),
+                    None => error(path, TypeErrorKind::UndefinedEntrypoint(entrypoint.to_string())),
+                }
+            }
+            (I_CONTRACT, [address, rest @ ..]) if simple(address) == Some(&T_address) => {
+                let parameter = type_argument(args, 0, path)?;
+                if !is_passable(&parameter) {
+                    return with_index(path, 0, |path| error(path, TypeErrorKind::NotPassable));
+                }
+                push(vec![ty(T_option, vec![ty(T_contract, vec![parameter])])], rest)
+            }
+            (I_TRANSFER_TOKENS, [parameter, amount, contract, rest @ ..])
+                if simple(amount) == Some(&T_mutez) && args_of(contract, &T_contract) == Some(std::slice::from_ref(parameter)) => {
+                push(vec![ty(T_operation, vec![])], rest)
+            }
+            (I_SET_DELEGATE, [delegate, rest @ ..]) if *delegate == ty(T_option, vec![ty(T_key_hash, vec![])]) => {
+                push(vec![ty(T_operation, vec![])], rest)
+            }
+            (I_CREATE_CONTRACT, [delegate, amount, storage, rest @ ..])
+                if *delegate == ty(T_option, vec![ty(T_key_hash, vec![])]) && simple(amount) == Some(&T_mutez) => {
+                let storage_type = with_index(path, 0, |path| check_script_at(&args[0], path))?;
+                if normalize_type(&storage_type) != *storage {
+                    return error(path, TypeErrorKind::StackMismatch {
+                        expected: vec![delegate.clone(), amount.clone(), normalize_type(&storage_type)],
+                        actual: stack[..3].to_vec(),
+                    });
+                }
+                push(vec![ty(T_operation, vec![]), ty(T_address, vec![])], rest)
+            }
+            (I_IMPLICIT_ACCOUNT, [key_hash, rest @ ..]) if simple(key_hash) == Some(&T_key_hash) => {
+                push(vec![ty(T_contract, vec![ty(T_unit, vec![])])], rest)
+            }
+            (I_ADDRESS, [contract, rest @ ..]) if args_of(contract, &T_contract).is_some() => {
+                push(vec![ty(T_address, vec![])], rest)
+            }
+            (I_VIEW, [_, address, rest @ ..]) if simple(address) == Some(&T_address) => {
+                if !matches!(args[0], Node::String(_)) {
+                    return with_index(path, 0, |path| error(path, TypeErrorKind::UnexpectedValue(ty(T_string, vec![]))));
+                }
+                let result = type_argument(args, 1, path)?;
+                push(vec![ty(T_option, vec![result])], rest)
+            }
+            (I_EMIT, [event, rest @ ..]) => {
+                if let Some(declared) = args.first() {
+                    let declared = type_argument(std::slice::from_ref(declared), 0, path)?;
+                    if declared != *event {
+                        return error(path, TypeErrorKind::StackMismatch { expected: vec![declared], actual: vec![event.clone()] });
+                    }
+                }
+                if !is_packable(event) {
+                    return error(path, TypeErrorKind::NotPackable);
+                }
+                push(vec![ty(T_operation, vec![])], rest)
+            }
+
+            // Sapling, tickets and timelocks
+            (I_SAPLING_EMPTY_STATE, _) => match args[0] {
+                Node::Int(size) if size >= 0 => push(vec![ty(T_sapling_state, vec![Node::Int(size)])], &stack),
+                _ => with_index(path, 0, |path| error(path, TypeErrorKind::UnexpectedValue(ty(T_nat, vec![])))),
+            },
+            (I_SAPLING_VERIFY_UPDATE, [transaction, state, rest @ ..]) => match (transaction, state) {
+                (Node::Prim(T_sapling_transaction, a, _), Node::Prim(T_sapling_state, b, _)) if a == b => {
+                    let result = ty(T_pair, vec![ty(T_bytes, vec![]), ty(T_pair, vec![ty(T_int, vec![]), state.clone()])]);
+                    push(vec![ty(T_option, vec![result])], rest)
+                }
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_TICKET | I_TICKET_DEPRECATED, [content, amount, rest @ ..]) if simple(amount) == Some(&T_nat) => {
+                if !is_comparable(content) {
+                    return error(path, TypeErrorKind::NotComparable);
+                }
+                let ticket = ty(T_ticket, vec![content.clone()]);
+                match prim {
+                    I_TICKET => push(vec![ty(T_option, vec![ticket])], rest),
+                    _ => push(vec![ticket], rest),
+                }
+            }
+            (I_READ_TICKET, [ticket, rest @ ..]) => match args_of(ticket, &T_ticket) {
+                Some([content]) => {
+                    let read = ty(T_pair, vec![ty(T_address, vec![]), ty(T_pair, vec![content.clone(), ty(T_nat, vec![])])]);
+                    push(vec![read, ticket.clone()], rest)
+                }
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_SPLIT_TICKET, [ticket, amounts, rest @ ..])
+                if args_of(ticket, &T_ticket).is_some() && *amounts == ty(T_pair, vec![ty(T_nat, vec![]), ty(T_nat, vec![])]) => {
+                push(vec![ty(T_option, vec![ty(T_pair, vec![ticket.clone(), ticket.clone()])])], rest)
+            }
+            (I_JOIN_TICKETS, [tickets, rest @ ..]) => match args_of(tickets, &T_pair) {
+                Some([a, b]) if a == b && args_of(a, &T_ticket).is_some() => push(vec![ty(T_option, vec![a.clone()])], rest),
+                _ => bad_stack(path, prim, &stack),
+            },
+            (I_OPEN_CHEST, [key, chest, time, rest @ ..])
+                if simple(key) == Some(&T_chest_key) && simple(chest) == Some(&T_chest) && simple(time) == Some(&T_nat) => {
+                push(vec![ty(T_option, vec![ty(T_bytes, vec![])])], rest)
+            }
+
+            (I_INDEX_ADDRESS | I_GET_ADDRESS_INDEX, _) => error(path, TypeErrorKind::Unsupported(*prim)),
+
+            (prim, _) => bad_stack(path, prim, &stack),
+        }
+    }
+}
+
+/// Checks the body of a lambda of type `lambda argument result`.
+pub(crate) fn check_lambda(
+    argument: &Node<Primitive>,
+    result: &Node<Primitive>,
+    code: &Node<Primitive>,
+    path: &mut Vec<usize>,
+) -> Result<(), TypeError> {
+    let checker = Checker { parameter: None, in_lambda: true, in_view: false, operands: None };
+    let stack = checker.check_seq(code, vec![normalize_type(argument)], path)?;
+    expect(&[normalize_type(result)], stack, path)
+}
+
+/// Checks the body of a recursive lambda, which also receives itself below
+/// its argument.
+pub(crate) fn check_lambda_rec(
+    argument: &Node<Primitive>,
+    result: &Node<Primitive>,
+    code: &Node<Primitive>,
+    path: &mut Vec<usize>,
+) -> Result<(), TypeError> {
+    let checker = Checker { parameter: None, in_lambda: true, in_view: false, operands: None };
+    let lambda = ty(T_lambda, vec![normalize_type(argument), normalize_type(result)]);
+    let stack = checker.check_seq(code, vec![normalize_type(argument), lambda], path)?;
+    expect(&[normalize_type(result)], stack, path)
+}
+
+pub(crate) fn script_section(sections: &[Node<Primitive>], keyword: Primitive) -> Option<(usize, &Node<Primitive>)> {
+    sections.iter().enumerate().find_map(|(index, section)| match section {
+        Node::Prim(prim, args, _) if *prim == keyword && args.len() == 1 => Some((index, &args[0])),
+        _ => None,
+    })
+}
+
+/// Checks a whole script and returns its storage type.
+fn check_script_at(script: &Node<Primitive>, path: &mut Vec<usize>) -> Result<Node<Primitive>, TypeError> {
+    let sections = match script {
+        Node::Seq(sections) => sections,
+        _ => return error(path, TypeErrorKind::InvalidScript),
+    };
+
+    let mut seen = Vec::new();
+    for (index, section) in sections.iter().enumerate() {
+        if let Node::Prim(keyword @ (K_parameter | K_storage | K_code), _, _) = section {
+            if seen.contains(keyword) {
+                return with_index(path, index, |path| error(path, TypeErrorKind::InvalidScript));
+            }
+            seen.push(*keyword);
+        }
+    }
+
+    let (parameter_index, parameter) = script_section(sections, K_parameter)
+        .ok_or_else(|| TypeError { path: Path(path.clone()), kind: TypeErrorKind::InvalidScript })?;
+    let (storage_index, storage) = script_section(sections, K_storage)
+        .ok_or_else(|| TypeError { path: Path(path.clone()), kind: TypeErrorKind::InvalidScript })?;
+    let (code_index, code) = script_section(sections, K_code)
+        .ok_or_else(|| TypeError { path: Path(path.clone()), kind: TypeErrorKind::InvalidScript })?;
+
+    with_index(path, parameter_index, |path| {
+        with_index(path, 0, |path| {
+            check_type_at(parameter, path)?;
+            if is_passable(parameter) { Ok(()) } else { error(path, TypeErrorKind::NotPassable) }
+        })
+    })?;
+    with_index(path, storage_index, |path| {
+        with_index(path, 0, |path| {
+            check_type_at(storage, path)?;
+            if is_storable(storage) { Ok(()) } else { error(path, TypeErrorKind::NotStorable) }
+        })
+    })?;
+
+    let storage_type = normalize_type(storage);
+    let input = vec![ty(T_pair, vec![normalize_type(parameter), storage_type.clone()])];
+    let output = vec![ty(T_pair, vec![ty(T_list, vec![ty(T_operation, vec![])]), storage_type.clone()])];
+    let checker = Checker { parameter: Some(parameter), in_lambda: false, in_view: false, operands: None };
+    with_index(path, code_index, |path| {
+        with_index(path, 0, |path| {
+            let stack = checker.check_seq(code, input, path)?;
+            expect(&output, stack, path)
+        })
+    })?;
+
+    for (index, section) in sections.iter().enumerate() {
+        match section {
+            Node::Prim(K_parameter | K_storage | K_code, _, _) => {}
+            Node::Prim(K_view, args, _) => with_index(path, index, |path| match &args[..] {
+                [Node::String(_), input, output, code] => {
+                    with_index(path, 1, |path| check_type_at(input, path))?;
+                    with_index(path, 2, |path| check_type_at(output, path))?;
+                    let checker = Checker { parameter: Some(parameter), in_lambda: false, in_view: true, operands: None };
+                    let input = vec![ty(T_pair, vec![normalize_type(input), storage_type.clone()])];
+                    with_index(path, 3, |path| {
+                        let stack = checker.check_seq(code, input, path)?;
+                        expect(&[normalize_type(output)], stack, path)
+                    })
+                }
+                _ => error(path, TypeErrorKind::InvalidArity(K_view)),
+            })?,
+            _ => return with_index(path, index, |path| error(path, TypeErrorKind::InvalidScript)),
+        }
+    }
+
+    Ok(storage.clone())
+}
+
+/// Computes the stack type resulting from running `code` on a stack of
+/// type `input` (top first). `SELF` is rejected since no contract parameter
+/// is known; use [`typecheck_script`] for whole contracts.
+pub fn typecheck_code(code: &Node<Primitive>, input: &[Node<Primitive>]) -> Result<Stack, TypeError> {
+    let mut path = Vec::new();
+    let mut stack = Vec::new();
+    for (index, item) in input.iter().enumerate() {
+        with_index(&mut path, index, |path| check_type_at(item, path))?;
+        stack.push(normalize_type(item));
+    }
+
+    let checker = Checker { parameter: None, in_lambda: false, in_view: false, operands: None };
+    checker.check_instruction(code, stack, &mut path)
+}
+
+/// The paths, relative to `code`, and operand types of the `CONCAT`
+/// instructions on lists and of the `BYTES` instructions, when `code` runs on
+/// a stack of type `input` (top first) in a contract of parameter
+/// `parameter`. The types of list `CONCAT`s are those of the elements.
+/// `code` is assumed to be well-typed.
+pub(crate) fn operand_types(
+    code: &Node<Primitive>,
+    input: Vec<Node<Primitive>>,
+    parameter: Option<&Node<Primitive>>,
+) -> Operands {
+    let operands = RefCell::new(Vec::new());
+    let checker = Checker { parameter, in_lambda: false, in_view: false, operands: Some(&operands) };
+    let _ = checker.check_instruction(code, input, &mut Vec::new());
+    operands.into_inner()
+}
+
+/// Typechecks a script, given as the sequence of its `parameter`, `storage`,
+/// `code` and `view` sections.
+pub fn typecheck_script(script: &Node<Primitive>) -> Result<(), TypeError> {
+    check_script_at(script, &mut Vec::new()).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::typecheck::*;
+
+    fn t(prim: Primitive) -> Node<Primitive> {
+        Node::Prim(prim, vec![], vec![])
+    }
+
+    fn i(prim: Primitive, args: Vec<Node<Primitive>>) -> Node<Primitive> {
+        Node::Prim(prim, args, vec![])
+    }
+
+    #[test]
+    fn simple_sequences() {
+        let code = Node::Seq(vec![
+            i(I_PUSH, vec![t(T_nat), Node::Int(1)]),
+            t(I_ADD),
+            i(I_DUP, vec![]),
+            t(I_PAIR),
+        ]);
+        assert_eq!(
+            typecheck_code(&code, &[t(T_nat)]),
+            Ok(Stack::Typed(vec![i(T_pair, vec![t(T_nat), t(T_nat)])]))
+        );
+
+        assert_eq!(
+            typecheck_code(&code, &[t(T_string)]),
+            Err(TypeError {
+                path: Path(vec![1]),
+                kind: TypeErrorKind::BadStack { instruction: I_ADD, stack: vec![t(T_nat), t(T_string)] },
+            })
+        );
+
+        assert_eq!(
+            typecheck_code(&Node::Seq(vec![i(I_PUSH, vec![t(T_nat), Node::Int(-1)])]), &[]),
+            Err(TypeError { path: Path(vec![0, 1]), kind: TypeErrorKind::NegativeNat })
+        );
+    }
+
+    #[test]
+    fn numbered_instructions() {
+        let stack = [t(T_nat), t(T_int), t(T_string), t(T_bytes)];
+        assert_eq!(
+            typecheck_code(&i(I_DIG, vec![Node::Int(2)]), &stack),
+            Ok(Stack::Typed(vec![t(T_string), t(T_nat), t(T_int), t(T_bytes)]))
+        );
+        assert_eq!(
+            typecheck_code(&i(I_DUG, vec![Node::Int(3)]), &stack),
+            Ok(Stack::Typed(vec![t(T_int), t(T_string), t(T_bytes), t(T_nat)]))
+        );
+        assert_eq!(
+            typecheck_code(&i(I_DUP, vec![Node::Int(3)]), &stack),
+            Ok(Stack::Typed(vec![t(T_string), t(T_nat), t(T_int), t(T_string), t(T_bytes)]))
+        );
+        assert_eq!(
+            typecheck_code(&i(I_DIP, vec![Node::Int(2), Node::Seq(vec![t(I_DROP)])]), &stack),
+            Ok(Stack::Typed(vec![t(T_nat), t(T_int), t(T_bytes)]))
+        );
+
+        let comb = i(T_pair, vec![t(T_nat), t(T_int), t(T_string)]);
+        assert_eq!(
+            typecheck_code(&i(I_PAIR, vec![Node::Int(3)]), &stack),
+            Ok(Stack::Typed(vec![normalize_type(&comb), t(T_bytes)]))
+        );
+        assert_eq!(
+            typecheck_code(&i(I_UNPAIR, vec![Node::Int(3)]), std::slice::from_ref(&comb)),
+            Ok(Stack::Typed(vec![t(T_nat), t(T_int), t(T_string)]))
+        );
+        assert_eq!(typecheck_code(&i(I_GET, vec![Node::Int(3)]), std::slice::from_ref(&comb)), Ok(Stack::Typed(vec![t(T_int)])));
+        assert_eq!(typecheck_code(&i(I_GET, vec![Node::Int(4)]), std::slice::from_ref(&comb)), Ok(Stack::Typed(vec![t(T_string)])));
+        assert_eq!(
+            typecheck_code(&i(I_UPDATE, vec![Node::Int(4)]), &[t(T_bytes), comb.clone()]),
+            Ok(Stack::Typed(vec![normalize_type(&i(T_pair, vec![t(T_nat), t(T_int), t(T_bytes)]))]))
+        );
+        assert_eq!(
+            typecheck_code(&i(I_GET, vec![Node::Int(5)]), &[comb]).map_err(|error| error.path),
+            Err(Path::root())
+        );
+    }
+
+    #[test]
+    fn failwith_and_branches() {
+        let code = Node::Seq(vec![i(
+            I_IF,
+            vec![
+                Node::Seq(vec![i(I_PUSH, vec![t(T_string), Node::String(String::from("error"))]), t(I_FAILWITH)]),
+                Node::Seq(vec![i(I_PUSH, vec![t(T_nat), Node::Int(1)])]),
+            ],
+        )]);
+        assert_eq!(typecheck_code(&code, &[t(T_bool)]), Ok(Stack::Typed(vec![t(T_nat)])));
+
+        let unmatched = i(I_IF, vec![Node::Seq(vec![]), Node::Seq(vec![i(I_PUSH, vec![t(T_nat), Node::Int(1)])])]);
+        assert_eq!(
+            typecheck_code(&unmatched, &[t(T_bool)]),
+            Err(TypeError { path: Path(vec![]), kind: TypeErrorKind::UnmatchedBranches { left: vec![], right: vec![t(T_nat)] } })
+        );
+
+        let not_in_tail = Node::Seq(vec![t(I_FAILWITH), t(I_DROP)]);
+        assert_eq!(
+            typecheck_code(&not_in_tail, &[t(T_nat)]),
+            Err(TypeError { path: Path(vec![1]), kind: TypeErrorKind::FailNotInTail })
+        );
+        assert_eq!(typecheck_code(&t(I_FAILWITH), &[t(T_nat)]), Ok(Stack::Failed));
+    }
+
+    #[test]
+    fn lambdas() {
+        let lambda = i(T_lambda, vec![t(T_nat), t(T_int)]);
+        assert_eq!(typecheck_data(&lambda, &Node::Seq(vec![t(I_INT)])), Ok(()));
+        assert_eq!(
+            typecheck_data(&lambda, &Node::Seq(vec![])),
+            Err(TypeError { path: Path(vec![]), kind: TypeErrorKind::StackMismatch { expected: vec![t(T_int)], actual: vec![t(T_nat)] } })
+        );
+        assert_eq!(
+            typecheck_data(&lambda, &Node::Seq(vec![t(I_SELF), t(I_DROP), t(I_INT)])),
+            Err(TypeError { path: Path(vec![0]), kind: TypeErrorKind::ForbiddenInstruction(I_SELF) })
+        );
+    }
+
+    #[test]
+    fn newer_instructions() {
+        assert_eq!(typecheck_code(&t(I_LSL), &[t(T_bytes), t(T_nat)]), Ok(Stack::Typed(vec![t(T_bytes)])));
+        assert_eq!(typecheck_code(&t(I_XOR), &[t(T_bytes), t(T_bytes)]), Ok(Stack::Typed(vec![t(T_bytes)])));
+        assert_eq!(typecheck_code(&t(I_NAT), &[t(T_bytes)]), Ok(Stack::Typed(vec![t(T_nat)])));
+        assert_eq!(typecheck_code(&t(I_INT), &[t(T_bytes)]), Ok(Stack::Typed(vec![t(T_int)])));
+        assert_eq!(typecheck_code(&t(I_BYTES), &[t(T_int)]), Ok(Stack::Typed(vec![t(T_bytes)])));
+        assert_eq!(
+            typecheck_code(&t(I_INDEX_ADDRESS), &[t(T_address)]),
+            Err(TypeError { path: Path(vec![]), kind: TypeErrorKind::Unsupported(I_INDEX_ADDRESS) })
+        );
+        assert_eq!(
+            typecheck_code(&t(I_IS_IMPLICIT_ACCOUNT), &[t(T_address)]),
+            Ok(Stack::Typed(vec![i(T_option, vec![t(T_key_hash)])]))
+        );
+        assert_eq!(
+            typecheck_code(&t(I_TICKET), &[t(T_unit), t(T_nat)]),
+            Ok(Stack::Typed(vec![i(T_option, vec![i(T_ticket, vec![t(T_unit)])])]))
+        );
+        assert_eq!(
+            typecheck_code(&t(I_TICKET_DEPRECATED), &[t(T_unit), t(T_nat)]),
+            Ok(Stack::Typed(vec![i(T_ticket, vec![t(T_unit)])]))
+        );
+        assert_eq!(typecheck_code(&t(I_EMIT), &[t(T_nat)]), Ok(Stack::Typed(vec![t(T_operation)])));
+        assert_eq!(
+            typecheck_code(&i(I_EMIT, vec![i(T_big_map, vec![t(T_nat), t(T_nat)])]), &[i(T_big_map, vec![t(T_nat), t(T_nat)])]),
+            Err(TypeError { path: Path(vec![]), kind: TypeErrorKind::NotPackable })
+        );
+
+        // The body of a recursive lambda sees itself below its argument.
+        let lambda = i(T_lambda, vec![t(T_nat), t(T_nat)]);
+        let body = Node::Seq(vec![t(I_SWAP), t(I_DROP)]);
+        assert_eq!(typecheck_data(&lambda, &i(D_Lambda_rec, vec![body.clone()])), Ok(()));
+        assert_eq!(
+            typecheck_code(&i(I_LAMBDA_REC, vec![t(T_nat), t(T_nat), body]), &[]),
+            Ok(Stack::Typed(vec![lambda]))
+        );
+    }
+
+    #[test]
+    fn scripts() {
+        let script = Node::Seq(vec![
+            i(K_parameter, vec![Node::Prim(T_or, vec![
+                Node::Prim(T_nat, vec![], vec![String::from("%add")]),
+                Node::Prim(T_unit, vec![], vec![String::from("%reset")]),
+            ], vec![])]),
+            i(K_storage, vec![t(T_nat)]),
+            i(K_code, vec![Node::Seq(vec![
+                t(I_UNPAIR),
+                i(I_IF_LEFT, vec![
+                    Node::Seq(vec![t(I_ADD)]),
+                    Node::Seq(vec![i(I_DROP, vec![Node::Int(2)]), i(I_PUSH, vec![t(T_nat), Node::Int(0)])]),
+                ]),
+                Node::Prim(I_SELF, vec![], vec![String::from("%add")]),
+                t(I_DROP),
+                i(I_NIL, vec![t(T_operation)]),
+                t(I_PAIR),
+            ])]),
+        ]);
+        assert_eq!(typecheck_script(&script), Ok(()));
+
+        let mut broken = script.clone();
+        if let Node::Seq(sections) = &mut broken {
+            sections[1] = i(K_storage, vec![t(T_int)]);
+        }
+        assert_eq!(
+            typecheck_script(&broken).map_err(|error| error.path),
+            Err(Path(vec![2, 0, 1]))
+        );
+
+        let mut duplicated = script.clone();
+        if let Node::Seq(sections) = &mut duplicated {
+            sections.push(i(K_storage, vec![t(T_nat)]));
+        }
+        assert_eq!(
+            typecheck_script(&duplicated),
+            Err(TypeError { path: Path(vec![3]), kind: TypeErrorKind::InvalidScript })
+        );
+    }
+
+    #[test]
+    fn non_instructions() {
+        assert_eq!(
+            typecheck_code(&i(D_Pair, vec![Node::Int(1), Node::Int(2)]), &[]),
+            Err(TypeError { path: Path(vec![]), kind: TypeErrorKind::InvalidInstruction })
+        );
+        assert_eq!(
+            typecheck_code(&Node::Seq(vec![i(T_option, vec![t(T_nat)])]), &[]),
+            Err(TypeError { path: Path(vec![0]), kind: TypeErrorKind::InvalidInstruction })
+        );
+        assert_eq!(
+            typecheck_code(&i(I_SWAP, vec![Node::Int(1)]), &[t(T_nat), t(T_nat)]),
+            Err(TypeError { path: Path(vec![]), kind: TypeErrorKind::InvalidArity(I_SWAP) })
+        );
+    }
+}
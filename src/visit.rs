@@ -0,0 +1,299 @@
+// Generic traversals of `Node` trees.
+//
+// Traversals keep their own stack instead of recursing, so they handle
+// arbitrarily deep trees. Hooks receive the indices of the path of the
+// current node (see `path::Path`).
+
+use crate::{Encodable, Node};
+
+/// What to do after entering a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    /// Traverse the children of the node.
+    Continue,
+    /// Leave the node without traversing its children.
+    Skip,
+}
+
+/// Read-only traversal hooks. `leave` is called for every node entered,
+/// after its children unless they were skipped.
+pub trait Visitor<P: Encodable> {
+    fn enter(&mut self, _node: &Node<P>, _path: &[usize]) -> Visit {
+        Visit::Continue
+    }
+
+    fn leave(&mut self, _node: &Node<P>, _path: &[usize]) {}
+}
+
+/// Traversal hooks allowed to modify nodes. Changes made by `enter`,
+/// including to the children of the node, are seen by the rest of the
+/// traversal.
+pub trait VisitorMut<P: Encodable> {
+    fn enter(&mut self, _node: &mut Node<P>, _path: &[usize]) -> Visit {
+        Visit::Continue
+    }
+
+    fn leave(&mut self, _node: &mut Node<P>, _path: &[usize]) {}
+}
+
+/// Bottom-up computation over a tree: `leave` combines a node with the
+/// outputs of its children, which are empty if `enter` skipped them.
+pub trait Fold<P: Encodable> {
+    type Output;
+
+    fn enter(&mut self, _node: &Node<P>, _path: &[usize]) -> Visit {
+        Visit::Continue
+    }
+
+    fn leave(&mut self, node: &Node<P>, path: &[usize], children: Vec<Self::Output>) -> Self::Output;
+}
+
+pub(crate) fn children<P: Encodable>(node: &Node<P>) -> &[Node<P>] {
+    match node {
+        Node::Prim(_, args, _) => args,
+        Node::Seq(items) => items,
+        _ => &[],
+    }
+}
+
+pub(crate) fn children_mut<P: Encodable>(node: &mut Node<P>) -> Option<&mut Vec<Node<P>>> {
+    match node {
+        Node::Prim(_, args, _) => Some(args),
+        Node::Seq(items) => Some(items),
+        _ => None,
+    }
+}
+
+/// Traverses `node` in depth-first order.
+pub fn visit<P: Encodable, V: Visitor<P>>(node: &Node<P>, visitor: &mut V) {
+    let mut path = Vec::new();
+    if visitor.enter(node, &path) == Visit::Skip {
+        visitor.leave(node, &path);
+        return;
+    }
+    // Nodes being traversed, with the index of their next child.
+    let mut stack = vec![(node, 0)];
+    while let Some((node, index)) = stack.last_mut() {
+        let node: &Node<P> = node;
+        match children(node).get(*index) {
+            Some(child) => {
+                path.push(*index);
+                *index += 1;
+                if visitor.enter(child, &path) == Visit::Continue {
+                    stack.push((child, 0));
+                } else {
+                    visitor.leave(child, &path);
+                    path.pop();
+                }
+            }
+            None => {
+                stack.pop();
+                visitor.leave(node, &path);
+                path.pop();
+            }
+        }
+    }
+}
+
+fn take_children<P: Encodable>(node: &mut Node<P>) -> Vec<Node<P>> {
+    children_mut(node).map(std::mem::take).unwrap_or_default()
+}
+
+/// Traverses `node` in depth-first order, allowing the visitor to modify it.
+pub fn visit_mut<P: Encodable, V: VisitorMut<P>>(node: &mut Node<P>, visitor: &mut V) {
+    let mut path = Vec::new();
+    if visitor.enter(node, &path) == Visit::Skip {
+        visitor.leave(node, &path);
+        return;
+    }
+    // Children of the nodes being traversed, with the index of the next
+    // one. Their vectors are detached from the nodes while their
+    // descendants are traversed, and put back before leaving the nodes.
+    let mut stack = vec![(take_children(node), 0)];
+    while let Some((children, index)) = stack.last_mut() {
+        match children.get_mut(*index) {
+            Some(child) => {
+                path.push(*index);
+                *index += 1;
+                if visitor.enter(child, &path) == Visit::Continue {
+                    let grandchildren = take_children(child);
+                    stack.push((grandchildren, 0));
+                } else {
+                    visitor.leave(child, &path);
+                    path.pop();
+                }
+            }
+            None => {
+                let (children, _) = stack.pop().expect("frame");
+                let finished = match stack.last_mut() {
+                    Some((siblings, index)) => &mut siblings[*index - 1],
+                    None => &mut *node,
+                };
+                if let Some(slot) = children_mut(finished) {
+                    *slot = children;
+                }
+                visitor.leave(finished, &path);
+                path.pop();
+            }
+        }
+    }
+}
+
+/// Folds `node` bottom-up.
+pub fn fold<P: Encodable, F: Fold<P>>(node: &Node<P>, folder: &mut F) -> F::Output {
+    let mut path = Vec::new();
+    if folder.enter(node, &path) == Visit::Skip {
+        return folder.leave(node, &path, Vec::new());
+    }
+    // Nodes being folded, with the outputs of their children so far.
+    let mut stack = vec![(node, Vec::new())];
+    loop {
+        let (node, outputs) = stack.last_mut().expect("frame");
+        let node: &Node<P> = node;
+        match children(node).get(outputs.len()) {
+            Some(child) => {
+                path.push(outputs.len());
+                if folder.enter(child, &path) == Visit::Continue {
+                    stack.push((child, Vec::new()));
+                } else {
+                    let output = folder.leave(child, &path, Vec::new());
+                    outputs.push(output);
+                    path.pop();
+                }
+            }
+            None => {
+                let (node, outputs) = stack.pop().expect("frame");
+                let output = folder.leave(node, &path, outputs);
+                match stack.last_mut() {
+                    Some((_, parent)) => {
+                        parent.push(output);
+                        path.pop();
+                    }
+                    None => return output,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::michelson_v1_primitives::Primitive;
+    use crate::michelson_v1_primitives::Primitive::*;
+    use crate::visit::*;
+
+    fn prim(primitive: Primitive, args: Vec<Node<Primitive>>) -> Node<Primitive> {
+        Node::Prim(primitive, args, vec![])
+    }
+
+    fn sample() -> Node<Primitive> {
+        Node::Seq(vec![
+            prim(I_PUSH, vec![prim(T_nat, vec![]), Node::Int(1)]),
+            prim(I_DIP, vec![Node::Seq(vec![prim(I_DROP, vec![])])]),
+        ])
+    }
+
+    struct Trace(Vec<String>);
+
+    impl Visitor<Primitive> for Trace {
+        fn enter(&mut self, node: &Node<Primitive>, path: &[usize]) -> Visit {
+            self.0.push(format!("enter {:?}", path));
+            if let Node::Prim(I_DIP, _, _) = node { Visit::Skip } else { Visit::Continue }
+        }
+
+        fn leave(&mut self, _node: &Node<Primitive>, path: &[usize]) {
+            self.0.push(format!("leave {:?}", path));
+        }
+    }
+
+    #[test]
+    fn visitor() {
+        let mut trace = Trace(vec![]);
+        visit(&sample(), &mut trace);
+        assert_eq!(trace.0, vec![
+            "enter []",
+            "enter [0]",
+            "enter [0, 0]",
+            "leave [0, 0]",
+            "enter [0, 1]",
+            "leave [0, 1]",
+            "leave [0]",
+            "enter [1]",
+            "leave [1]",
+            "leave []",
+        ]);
+    }
+
+    struct Increment;
+
+    impl VisitorMut<Primitive> for Increment {
+        fn enter(&mut self, node: &mut Node<Primitive>, _path: &[usize]) -> Visit {
+            // Unfolds `DIP { code }` into its body before traversing it.
+            if let Node::Prim(I_DIP, args, _) = node {
+                *node = args.remove(0);
+            }
+            Visit::Continue
+        }
+
+        fn leave(&mut self, node: &mut Node<Primitive>, _path: &[usize]) {
+            if let Node::Int(value) = node {
+                *value += 1;
+            }
+        }
+    }
+
+    struct AppendDrop;
+
+    impl VisitorMut<Primitive> for AppendDrop {
+        fn leave(&mut self, node: &mut Node<Primitive>, _path: &[usize]) {
+            // Sequences are left with their children.
+            if let Node::Seq(items) = node {
+                items.push(prim(I_DROP, vec![]));
+            }
+        }
+    }
+
+    #[test]
+    fn visitor_mut() {
+        let mut node = sample();
+        visit_mut(&mut node, &mut Increment);
+        assert_eq!(node, Node::Seq(vec![
+            prim(I_PUSH, vec![prim(T_nat, vec![]), Node::Int(2)]),
+            Node::Seq(vec![prim(I_DROP, vec![])]),
+        ]));
+
+        visit_mut(&mut node, &mut AppendDrop);
+        assert_eq!(node, Node::Seq(vec![
+            prim(I_PUSH, vec![prim(T_nat, vec![]), Node::Int(2)]),
+            Node::Seq(vec![prim(I_DROP, vec![]), prim(I_DROP, vec![])]),
+            prim(I_DROP, vec![]),
+        ]));
+    }
+
+    struct Depth;
+
+    impl Fold<Primitive> for Depth {
+        type Output = usize;
+
+        fn leave(&mut self, _node: &Node<Primitive>, _path: &[usize], children: Vec<usize>) -> usize {
+            children.into_iter().max().unwrap_or(0) + 1
+        }
+    }
+
+    #[test]
+    fn deep_trees() {
+        let mut node = Node::Int(0);
+        for _ in 0..100_000 {
+            node = Node::Seq(vec![node]);
+        }
+        assert_eq!(fold(&node, &mut Depth), 100_001);
+        visit_mut(&mut node, &mut Increment);
+        // Unwinds the tree by hand, as dropping it would recurse.
+        let mut count = 0;
+        while let Node::Seq(mut items) = node {
+            node = items.pop().expect("child");
+            count += 1;
+        }
+        assert_eq!((count, node), (100_000, Node::Int(1)));
+    }
+}